@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_to_gif::video::ffmpeg::{probe, stream_frames, DecodeOptions};
+use rust_to_gif::video::video_gif::{is_gif, stream_gif};
+use std::time::{Duration, Instant};
+
+// The decoders only take paths, every input goes through one file per process
+fuzz_target!(|data: &[u8]| {
+  let path = std::env::temp_dir().join(format!("fuzz-decode-{}", std::process::id()));
+  std::fs::write(&path, data).unwrap();
+
+  let options = DecodeOptions {
+    fps: Some(10),
+    width: Some(64),
+    deadline: Some(Instant::now() + Duration::from_secs(2)),
+    ..Default::default()
+  };
+  // Errors are fine, panics and runaway decodes are not
+  let _ = probe(&path);
+  let _ = stream_frames(&path, &options, |_| Ok(()));
+  if is_gif(&path).unwrap_or(false) {
+    let _ = stream_gif(&path, &options, |_| Ok(()));
+  }
+});
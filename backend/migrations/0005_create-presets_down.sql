@@ -0,0 +1 @@
+DROP TABLE IF EXISTS presets;
\ No newline at end of file
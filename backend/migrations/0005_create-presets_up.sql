@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS presets (
+    id UUID PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    fps INTEGER,
+    width INTEGER,
+    palette INTEGER,
+    dithering BOOLEAN,
+    max_bytes BIGINT,
+    format TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, name)
+);
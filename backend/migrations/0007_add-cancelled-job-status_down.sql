@@ -0,0 +1,4 @@
+UPDATE conversion_jobs SET status = 'failed', error = 'Job cancelled' WHERE status = 'cancelled';
+ALTER TABLE conversion_jobs DROP CONSTRAINT IF EXISTS conversion_jobs_status_check;
+ALTER TABLE conversion_jobs ADD CONSTRAINT conversion_jobs_status_check
+    CHECK (status IN ('queued', 'running', 'succeeded', 'failed'));
\ No newline at end of file
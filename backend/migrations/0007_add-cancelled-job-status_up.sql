@@ -0,0 +1,3 @@
+ALTER TABLE conversion_jobs DROP CONSTRAINT IF EXISTS conversion_jobs_status_check;
+ALTER TABLE conversion_jobs ADD CONSTRAINT conversion_jobs_status_check
+    CHECK (status IN ('queued', 'running', 'succeeded', 'failed', 'cancelled'));
@@ -0,0 +1,8 @@
+DROP INDEX IF EXISTS conversion_jobs_dead_letter_idx;
+ALTER TABLE conversion_jobs
+    DROP COLUMN IF EXISTS run_after,
+    DROP COLUMN IF EXISTS failures;
+ALTER TABLE conversion_jobs DROP CONSTRAINT IF EXISTS conversion_jobs_status_check;
+UPDATE conversion_jobs SET status = 'failed' WHERE status = 'dead_lettered';
+ALTER TABLE conversion_jobs ADD CONSTRAINT conversion_jobs_status_check
+    CHECK (status IN ('queued', 'running', 'succeeded', 'failed', 'cancelled'));
\ No newline at end of file
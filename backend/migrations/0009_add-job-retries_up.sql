@@ -0,0 +1,13 @@
+ALTER TABLE conversion_jobs DROP CONSTRAINT IF EXISTS conversion_jobs_status_check;
+UPDATE conversion_jobs SET status = 'dead_lettered' WHERE status = 'failed';
+ALTER TABLE conversion_jobs ADD CONSTRAINT conversion_jobs_status_check
+    CHECK (status IN ('queued', 'running', 'succeeded', 'dead_lettered', 'cancelled'));
+
+-- Queued jobs waiting for a retry are not claimed before `run_after`, every
+-- failed attempt is kept in `failures` with its error chain
+ALTER TABLE conversion_jobs
+    ADD COLUMN IF NOT EXISTS run_after TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD COLUMN IF NOT EXISTS failures JSONB NOT NULL DEFAULT '[]';
+
+CREATE INDEX IF NOT EXISTS conversion_jobs_dead_letter_idx
+    ON conversion_jobs (finished_at) WHERE status = 'dead_lettered';
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS webhook_deliveries;
+ALTER TABLE conversion_jobs DROP COLUMN IF EXISTS callback_url;
+ALTER TABLE users DROP COLUMN IF EXISTS webhook_secret;
\ No newline at end of file
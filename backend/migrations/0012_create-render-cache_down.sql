@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS render_refs;
+DROP TABLE IF EXISTS render_cache;
\ No newline at end of file
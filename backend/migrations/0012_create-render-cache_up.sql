@@ -0,0 +1,21 @@
+-- One row per rendered output, found again by the SHA-256 of its source bytes
+-- and canonical recipe. Races between two identical renders may leave two rows
+-- for a key, the oldest is served
+CREATE TABLE IF NOT EXISTS render_cache (
+    output_id UUID PRIMARY KEY,
+    key TEXT NOT NULL,
+    format TEXT NOT NULL,
+    response JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS render_cache_key_idx ON render_cache (key, created_at);
+
+-- Users holding a cached output, its files are removed with the last reference.
+-- Outputs left without references by deleted users are swept with their files
+CREATE TABLE IF NOT EXISTS render_refs (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    output_id UUID NOT NULL REFERENCES render_cache(output_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (output_id, user_id)
+);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS conversion_jobs_user_status_idx;
+ALTER TABLE users DROP COLUMN IF EXISTS last_job_claimed_at;
+ALTER TABLE users DROP COLUMN IF EXISTS tier;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- `tier` sets the priority and limits of the jobs of a user, admins come
+-- before every tier. `last_job_claimed_at` lets users of the same class take
+-- turns at the queue
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS tier TEXT NOT NULL DEFAULT 'free' CHECK (tier IN ('free', 'paid')),
+    ADD COLUMN IF NOT EXISTS last_job_claimed_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS conversion_jobs_user_status_idx
+    ON conversion_jobs (user_id, status) WHERE status IN ('queued', 'running');
@@ -30,3 +30,16 @@ pub fn validate_jwt(token: &str) -> bool {
   )
   .is_ok()
 }
+
+// Claims of a valid token, `sub` is the email of the user
+pub fn decode_jwt(token: &str) -> Option<JwtClaims> {
+  let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+  let validation = Validation::default();
+  decode::<JwtClaims>(
+    token,
+    &DecodingKey::from_secret(secret.as_ref()),
+    &validation,
+  )
+  .ok()
+  .map(|data| data.claims)
+}
@@ -0,0 +1,99 @@
+use crate::auth::auth_jwt::decode_jwt;
+use crate::common::responses::ApiResponse;
+use crate::user::user::{User, UserMethods};
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use deadpool_postgres::Pool;
+use std::future::Future;
+use std::pin::Pin;
+
+// The user owning the token of a request, read from the `token` cookie or
+// an `Authorization: Bearer` header
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+  pub id: i32,
+}
+
+impl FromRequest for CurrentUser {
+  type Error = Error;
+  type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    let token = request_token(req);
+    let pool = req.app_data::<web::Data<Pool>>().cloned();
+
+    Box::pin(async move {
+      let claims = token.as_deref().and_then(decode_jwt).ok_or_else(unauthorized)?;
+      let pool = pool.ok_or_else(unavailable)?;
+      let client = pool.get().await.map_err(|_| unavailable())?;
+
+      match User::find_by_login_or_email(&client, &claims.sub, &claims.sub).await {
+        Ok(Some(user)) => Ok(CurrentUser {
+          id: user.id,
+        }),
+        Ok(None) => Err(unauthorized()),
+        Err(_) => Err(unavailable()),
+      }
+    })
+  }
+}
+
+// A signed-in user with `users.is_admin` set, others get a 403
+#[derive(Debug, Clone)]
+pub struct AdminUser;
+
+impl FromRequest for AdminUser {
+  type Error = Error;
+  type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+  fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+    let user = CurrentUser::from_request(req, payload);
+    let pool = req.app_data::<web::Data<Pool>>().cloned();
+
+    Box::pin(async move {
+      let user = user.await?;
+      let pool = pool.ok_or_else(unavailable)?;
+      let client = pool.get().await.map_err(|_| unavailable())?;
+
+      let row = client
+        .query_opt("SELECT is_admin FROM users WHERE id = $1", &[&user.id])
+        .await
+        .map_err(|_| unavailable())?;
+      match row.map(|row| row.try_get::<_, bool>("is_admin")) {
+        Some(Ok(true)) => Ok(AdminUser),
+        Some(Ok(false)) | None => Err(forbidden()),
+        Some(Err(_)) => Err(unavailable()),
+      }
+    })
+  }
+}
+
+fn request_token(req: &HttpRequest) -> Option<String> {
+  if let Some(cookie) = req.cookie("token") {
+    return Some(cookie.value().to_string());
+  }
+  req
+    .headers()
+    .get("Authorization")
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .map(str::to_string)
+}
+
+fn unauthorized() -> Error {
+  InternalError::from_response("unauthorized", ApiResponse::unauthorized("Authentication required"))
+    .into()
+}
+
+fn forbidden() -> Error {
+  InternalError::from_response("forbidden", ApiResponse::forbidden("Admin access required")).into()
+}
+
+fn unavailable() -> Error {
+  InternalError::from_response(
+    "database unavailable",
+    ApiResponse::internal_server_error("Database unavailable"),
+  )
+  .into()
+}
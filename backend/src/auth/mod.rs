@@ -1,5 +1,6 @@
 pub mod auth_controller;
 pub mod auth_service;
+pub mod auth_user;
 pub mod auth_dto;
 pub mod auth_jwt;
 pub mod auth_routes;
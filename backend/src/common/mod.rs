@@ -1,2 +1,5 @@
 pub mod errors;
 pub mod responses;
+// Catalogue of every HTTP status, most of them are not answered yet
+#[allow(dead_code)]
+pub mod responses_status_codes;
@@ -1,3 +1,4 @@
+use crate::common::responses_status_codes::ResponsesSuccessCodes;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 use serde::Serialize;
@@ -25,6 +26,15 @@ impl ApiResponse {
     })
   }
 
+  // 202 Accepted
+  pub fn accepted(message: &str, data: Option<serde_json::Value>) -> HttpResponse {
+    let status = StatusCode::from_u16(ResponsesSuccessCodes::Accepted.to_u16()).unwrap();
+    HttpResponse::build(status).json(ApiResponse {
+      message: message.to_string(),
+      data,
+    })
+  }
+
   // 204 No Content
   pub fn no_content() -> HttpResponse {
     HttpResponse::NoContent().finish()
@@ -92,6 +102,14 @@ impl ApiResponse {
     })
   }
 
+  // 413 Payload Too Large
+  pub fn payload_too_large(message: &str) -> HttpResponse {
+    HttpResponse::PayloadTooLarge().json(ApiResponse {
+      message: message.to_string(),
+      data: None,
+    })
+  }
+
   // 422 Unprocessable Entity
   pub fn unprocessable_entity(message: &str) -> HttpResponse {
     HttpResponse::UnprocessableEntity().json(ApiResponse {
@@ -100,6 +118,16 @@ impl ApiResponse {
     })
   }
 
+  // 429 Too Many Requests, with the seconds to wait before trying again
+  pub fn too_many_requests(message: &str, retry_after: u64) -> HttpResponse {
+    HttpResponse::TooManyRequests().insert_header(("Retry-After", retry_after.to_string())).json(
+      ApiResponse {
+        message: message.to_string(),
+        data: None,
+      },
+    )
+  }
+
   // 500 Internal Server Error
   pub fn internal_server_error(message: &str) -> HttpResponse {
     HttpResponse::InternalServerError().json(ApiResponse {
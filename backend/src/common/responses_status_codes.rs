@@ -1,3 +1,6 @@
+use actix_web::http::StatusCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResponsesInformationalCodes {
   // NOTE HTTP client informational
   Continue = 100,
@@ -7,8 +10,8 @@ pub enum ResponsesInformationalCodes {
 }
 
 impl ResponsesInformationalCodes {
-  pub fn to_u16(&self) -> u16 {
-    *self as u16
+  pub fn to_u16(self) -> u16 {
+    self as u16
   }
 
   pub fn description(&self) -> &'static str {
@@ -22,6 +25,7 @@ impl ResponsesInformationalCodes {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResponsesSuccessCodes {
   // NOTE HTTP Client Success
   Ok = 200,
@@ -39,8 +43,8 @@ pub enum ResponsesSuccessCodes {
 }
 
 impl ResponsesSuccessCodes {
-  pub fn to_u16(&self) -> u16 {
-    *self as u16
+  pub fn to_u16(self) -> u16 {
+    self as u16
   }
 
   pub fn description(&self) -> &'static str {
@@ -61,6 +65,7 @@ impl ResponsesSuccessCodes {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResponsesErrorCodes {
   // NOTE HTTP Client Error
   BadRequest = 400,
@@ -145,8 +150,8 @@ pub enum ResponsesErrorCodes {
 }
 
 impl ResponsesErrorCodes {
-  pub fn to_u16(&self) -> u16 {
-    *self as u16
+  pub fn to_u16(self) -> u16 {
+    self as u16
   }
 
   pub fn description(&self) -> &'static str {
@@ -234,3 +239,9 @@ impl ResponsesErrorCodes {
       }
   }
 }
+
+impl From<ResponsesErrorCodes> for StatusCode {
+  fn from(code: ResponsesErrorCodes) -> Self {
+    StatusCode::from_u16(code.to_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+  }
+}
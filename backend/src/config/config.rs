@@ -3,16 +3,27 @@ use std::env;
 use tokio_postgres::NoTls;
 
 pub fn create_pool() -> Pool {
+    let cfg = pg_config();
+  
+    // Using Runtime::Tokio1 and NoTls
+    cfg
+      .create_pool(Some(Runtime::Tokio1), NoTls)
+      .expect("Failed to create pool")
+  }
+
+// Settings of a connection of its own, for LISTEN which a pooled connection
+// cannot keep
+pub fn listener_config() -> tokio_postgres::Config {
+  pg_config().get_pg_config().expect("Invalid PostgreSQL settings")
+}
+
+fn pg_config() -> Config {
     let mut cfg = Config::new();
   
     cfg.host = Some(env::var("PG_HOST").expect("PG_HOST must be set"));
     cfg.user = Some(env::var("PG_USER").expect("PG_USER must be set"));
     cfg.password = Some(env::var("PG_PASSWORD").expect("PG_PASSWORD must be set"));
     cfg.dbname = Some(env::var("PG_DBNAME").expect("PG_DBNAME must be set"));
-  
-    // Using Runtime::Tokio1 and NoTls
     cfg
-      .create_pool(Some(Runtime::Tokio1), NoTls)
-      .expect("Failed to create pool")
   }
   
\ No newline at end of file
@@ -1,3 +1,3 @@
 mod config;
 
-pub use config::create_pool;
+pub use config::{create_pool, listener_config};
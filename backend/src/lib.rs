@@ -0,0 +1,9 @@
+// Only the upload decoders, so cargo-fuzz has a library to link against.
+// The server is the binary in main.rs
+pub mod video {
+  pub mod ffmpeg;
+  pub mod video_errors;
+  pub mod video_frame;
+  pub mod video_gif;
+  pub mod video_limits;
+}
@@ -2,13 +2,19 @@ use crate::postgres::lib::test_connection;
 use crate::protected::configure_protected_routes;
 use actix_web::{web, App, HttpServer, Responder};
 use auth::auth_routes::configure_auth_routes;
-use config::create_pool;
+use config::{create_pool, listener_config};
 use deadpool_postgres::Pool;
 use dotenvy::dotenv;
 use user::user_routes::configure_user_routes;
 use utils::password_routes::configure_password_routes;
-// use video::ffmpeg::extract_images;
-// use ffmpeg_next::format::context::input::Input;
+use video::video_routes::configure_video_routes;
+use video::video_idempotency::spawn_key_cleanup;
+use video::video_job_worker::{job_workers, JobWorker};
+use video::video_preview::PreviewCache;
+use video::video_progress_hub::ProgressHub;
+use video::video_render_cache::spawn_render_sweep;
+use video::video_storage::MediaStorage;
+use video::video_webhook_worker::{webhook_workers, WebhookWorker};
 
 mod auth;
 mod common;
@@ -18,7 +24,7 @@ mod protected;
 mod tests;
 mod user;
 mod utils;
-// mod video;
+mod video;
 
 // Route simple pour tester le serveur
 async fn index() -> impl Responder {
@@ -33,33 +39,34 @@ async fn db_check(pool: web::Data<Pool>) -> impl Responder {
   }
 }
 
-// Route pour extraire des images depuis une vidéo
-/* async fn extract_video() -> impl Responder {
-  let video_path = "/src/assets/video.mp4";
-  let output_dir = "/src/video/output";
-
-  match extract_images(video_path, output_dir) {
-    Ok(_) => HttpResponse::Ok().body("Images extracted successfully!"),
-    Err(e) => HttpResponse::In,
-  }
-}
- */
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
   dotenv().ok();
 
   let pool = create_pool();
+  let storage = MediaStorage::from_env();
+  // Shared by every worker
+  let previews = web::Data::new(PreviewCache::default());
+  let progress = web::Data::new(ProgressHub::default());
+  progress.listen(listener_config());
+  JobWorker::new(pool.clone(), storage.clone(), progress.get_ref().clone()).spawn(job_workers());
+  WebhookWorker::new(pool.clone()).spawn(webhook_workers());
+  spawn_key_cleanup(pool.clone());
+  spawn_render_sweep(pool.clone(), storage.clone());
 
   HttpServer::new(move || {
     App::new()
       .app_data(web::Data::new(pool.clone()))
+      .app_data(web::Data::new(storage.clone()))
+      .app_data(previews.clone())
+      .app_data(progress.clone())
       .route("/", web::get().to(index))
       .route("/db_check", web::get().to(db_check))
       .configure(|cfg| configure_auth_routes(cfg))
       .configure(|cfg| configure_user_routes(cfg))
       .configure(|cfg| configure_password_routes(cfg))
       .configure(|cfg| configure_protected_routes(cfg))
-    // .route("/extract_video", web::get().to(extract_video))
+      .configure(configure_video_routes)
   })
   .bind("127.0.0.1:8081")?
   .run()
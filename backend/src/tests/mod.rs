@@ -0,0 +1,52 @@
+#[cfg(test)]
+pub mod password_validator_tests;
+#[cfg(test)]
+pub mod responses_tests;
+#[cfg(test)]
+pub mod user_preset_tests;
+#[cfg(test)]
+pub mod video_concat_tests;
+#[cfg(test)]
+pub mod video_contact_sheet_tests;
+#[cfg(test)]
+pub mod video_cost_tests;
+#[cfg(test)]
+pub mod video_dedupe_tests;
+#[cfg(test)]
+pub mod video_encoder_tests;
+#[cfg(test)]
+pub mod video_filters_tests;
+#[cfg(test)]
+pub mod video_frame_edit_tests;
+#[cfg(test)]
+pub mod video_fuzz_tests;
+#[cfg(test)]
+pub mod video_idempotency_tests;
+#[cfg(test)]
+pub mod video_job_schedule_tests;
+#[cfg(test)]
+pub mod video_job_tests;
+#[cfg(test)]
+pub mod video_layout_tests;
+#[cfg(test)]
+pub mod video_limits_tests;
+#[cfg(test)]
+pub mod video_pipeline_tests;
+#[cfg(test)]
+pub mod video_preview_tests;
+#[cfg(test)]
+pub mod video_progress_tests;
+#[cfg(test)]
+pub mod video_recipe_tests;
+#[cfg(test)]
+pub mod video_render_cache_tests;
+#[cfg(test)]
+pub mod video_spritesheet_tests;
+#[cfg(test)]
+pub mod video_stream_tests;
+#[cfg(test)]
+pub mod video_timelapse_tests;
+#[cfg(test)]
+pub mod video_webhook_tests;
+#[cfg(test)]
+pub mod video_zip_tests;
@@ -1,6 +1,5 @@
 #[cfg(test)]
-mod password_validator_tests {
-  use super::*;
+mod tests {
   use crate::utils::password_generator::{generate_password, PASSWORD_LENGTH, SYMBOLS};
 
   #[test]
@@ -1,10 +1,15 @@
 #[cfg(test)]
 mod tests {
-  use super::ResponsesErrorCodes;
-  use super::*;
+  use crate::common::responses_status_codes::ResponsesErrorCodes;
   use actix_web::http::StatusCode;
+  use serde::Serialize;
   use serde_json::to_string;
 
+  #[derive(Serialize)]
+  struct MyResponse {
+    data: String,
+  }
+
   #[test]
   fn test_responses_error_codes() {
     assert_eq!(ResponsesErrorCodes::BadRequest.to_u16(), 400);
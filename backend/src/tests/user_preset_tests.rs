@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+  use crate::user::user_preset::{
+    system_preset, system_presets, PresetRequest, PresetSettings, SYSTEM_PRESET_NAMES,
+  };
+  use crate::video::video_dto::{ConversionRequest, OutputFormat};
+  use crate::video::video_errors::VideoError;
+  use serde_json::json;
+
+  fn request(value: serde_json::Value) -> PresetRequest {
+    serde_json::from_value(value).unwrap()
+  }
+
+  #[test]
+  fn test_system_presets_are_valid() {
+    assert_eq!(system_presets().len(), SYSTEM_PRESET_NAMES.len());
+    for preset in system_presets() {
+      assert!(preset.system);
+      preset.settings.validate().unwrap();
+      assert!(preset.settings.max_bytes.is_some(), "{} has no size limit", preset.name);
+    }
+    let emoji = system_preset("emoji-128").unwrap();
+    assert_eq!(emoji.settings.width, Some(128));
+    assert!(system_preset("myspace").is_none());
+  }
+
+  #[test]
+  fn test_request_options_win_over_the_preset() {
+    let mut conversion: ConversionRequest = serde_json::from_value(json!({
+      "preset": "slack",
+      "fps": 24,
+      "dithering": false
+    }))
+    .unwrap();
+    system_preset("slack").unwrap().settings.apply_to(&mut conversion);
+
+    assert_eq!(conversion.fps, Some(24));
+    assert_eq!(conversion.dithering, Some(false));
+    assert_eq!(conversion.width, Some(480));
+    assert_eq!(conversion.max_bytes, Some(2 * 1024 * 1024));
+    assert_eq!(conversion.format(), OutputFormat::Gif);
+  }
+
+  #[test]
+  fn test_preset_request_reads_flat_settings() {
+    let parsed = request(json!({ "name": "team-chat", "fps": 12, "palette": 64, "format": "gif" }));
+    parsed.validate().unwrap();
+    assert_eq!(
+      parsed.settings,
+      PresetSettings {
+        fps: Some(12),
+        palette: Some(64),
+        format: Some(OutputFormat::Gif),
+        ..Default::default()
+      }
+    );
+  }
+
+  #[test]
+  fn test_invalid_presets_are_rejected() {
+    let cases = [
+      json!({ "name": "" }),
+      json!({ "name": "Team Chat" }),
+      json!({ "name": "slack" }),
+      json!({ "name": "fast", "fps": 0 }),
+      json!({ "name": "tiny", "palette": 2 }),
+      json!({ "name": "small", "max_bytes": 10 }),
+      json!({ "name": "atlas", "format": "spritesheet", "max_bytes": 100000 }),
+    ];
+    for case in cases {
+      let result = request(case.clone()).validate();
+      assert!(matches!(result, Err(VideoError::InvalidOption(_))), "{} should be invalid", case);
+    }
+  }
+}
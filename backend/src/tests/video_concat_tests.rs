@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_canvas::{fit_to_canvas, parse_color, BLACK};
+  use crate::video::video_concat::{concat_clips, Clip, Transition};
+  use crate::video::video_frame::{total_duration_ms, Frame};
+  use image::{Rgba, RgbaImage};
+
+  fn solid_clip(value: u8, width: u32, height: u32, count: usize, transition: Transition) -> Clip {
+    let frames = (0..count)
+      .map(|i| {
+        let image = RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]));
+        Frame::new(image, i as i64 * 100, 100)
+      })
+      .collect();
+    Clip {
+      frames,
+      transition,
+    }
+  }
+
+  #[test]
+  fn test_cut_keeps_every_frame() {
+    let clips =
+      vec![solid_clip(0, 16, 16, 5, Transition::Cut), solid_clip(255, 16, 16, 5, Transition::Cut)];
+
+    let frames = concat_clips(clips, BLACK).unwrap();
+
+    assert_eq!(frames.len(), 10);
+    assert_eq!(frames[5].pts_ms, 500, "Timestamps should continue across clips");
+    assert_eq!(frames[5].image.get_pixel(0, 0).0[0], 255);
+  }
+
+  #[test]
+  fn test_crossfade_overlaps_clips() {
+    let clips = vec![
+      solid_clip(
+        0,
+        16,
+        16,
+        10,
+        Transition::Crossfade {
+          duration: 0.3,
+        },
+      ),
+      solid_clip(255, 16, 16, 10, Transition::Cut),
+    ];
+
+    let frames = concat_clips(clips, BLACK).unwrap();
+
+    assert_eq!(frames.len(), 17, "Three frames should overlap");
+    assert_eq!(total_duration_ms(&frames), 1700);
+    let ramp: Vec<u8> = frames[6..10].iter().map(|f| f.image.get_pixel(0, 0).0[0]).collect();
+    assert_eq!(ramp[0], 0);
+    assert!(ramp[1] > 0 && ramp[1] < ramp[2] && ramp[2] < ramp[3] && ramp[3] < 255);
+    assert_eq!(frames[10].image.get_pixel(0, 0).0[0], 255);
+  }
+
+  #[test]
+  fn test_dip_to_color_fades_through_the_color() {
+    let clips = vec![
+      solid_clip(
+        0,
+        16,
+        16,
+        10,
+        Transition::DipToColor {
+          duration: 0.4,
+          color: Some("#ff0000".to_string()),
+        },
+      ),
+      solid_clip(0, 16, 16, 10, Transition::Cut),
+    ];
+
+    let frames = concat_clips(clips, BLACK).unwrap();
+
+    assert_eq!(frames.len(), 20, "A dip should not change the length");
+    assert_eq!(frames[7].image.get_pixel(0, 0).0[0], 0);
+    assert_eq!(frames[9].image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    let fade_in = frames[10].image.get_pixel(0, 0).0[0];
+    assert!(fade_in > 0 && fade_in < 255);
+    assert_eq!(frames[12].image.get_pixel(0, 0).0[0], 0);
+  }
+
+  #[test]
+  fn test_clips_are_letterboxed_on_the_first_canvas() {
+    let clips =
+      vec![solid_clip(0, 32, 16, 2, Transition::Cut), solid_clip(255, 16, 16, 2, Transition::Cut)];
+
+    let frames = concat_clips(clips, parse_color("#00ff00").unwrap()).unwrap();
+
+    let letterboxed = &frames[2].image;
+    assert_eq!(letterboxed.dimensions(), (32, 16));
+    assert_eq!(letterboxed.get_pixel(0, 8).0, [0, 255, 0, 255]);
+    assert_eq!(letterboxed.get_pixel(16, 8).0, [255, 255, 255, 255]);
+  }
+
+  #[test]
+  fn test_color_parsing() {
+    assert_eq!(parse_color("#102030").unwrap().0, [16, 32, 48, 255]);
+    assert_eq!(parse_color("10203080").unwrap().0, [16, 32, 48, 128]);
+    assert!(parse_color("#12345").is_err());
+    assert!(parse_color("#zzzzzz").is_err());
+
+    let image = RgbaImage::from_pixel(4, 4, Rgba([9, 9, 9, 255]));
+    assert_eq!(fit_to_canvas(&image, 4, 4, BLACK), image);
+  }
+}
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_contact_sheet::{
+    format_label, format_vtt_time, render, sample_timestamps, webvtt, SheetGrid,
+  };
+  use crate::video::video_dto::{ContactSheetQuery, SheetFormat};
+  use crate::video::video_frame::Frame;
+  use image::{Rgba, RgbaImage};
+
+  fn grid(columns: u32, rows: u32, labels: bool) -> SheetGrid {
+    SheetGrid {
+      columns,
+      rows,
+      tile_width: 160,
+      labels,
+    }
+  }
+
+  #[test]
+  fn test_timestamps_are_spread_evenly() {
+    assert_eq!(sample_timestamps(10_000, 4), vec![1250, 3750, 6250, 8750]);
+  }
+
+  #[test]
+  fn test_sheet_tiles_frames_row_by_row() {
+    let frames: Vec<Frame> = (0..3)
+      .map(|i| {
+        let image = RgbaImage::from_pixel(40, 20, Rgba([100 * i as u8, 0, 0, 255]));
+        Frame::new(image, i as i64 * 1000, 0)
+      })
+      .collect();
+
+    let sheet = render(&frames, &grid(2, 2, false)).unwrap();
+
+    assert_eq!(sheet.dimensions(), (80, 40));
+    assert_eq!(sheet.get_pixel(50, 5).0, [100, 0, 0, 255]);
+    assert_eq!(sheet.get_pixel(10, 25).0, [200, 0, 0, 255]);
+    assert_eq!(sheet.get_pixel(50, 25).0, [16, 16, 16, 255], "Missing frames leave the tile empty");
+
+    let labelled = render(&frames, &grid(2, 2, true)).unwrap();
+    assert_ne!(labelled, sheet, "Timestamps should be drawn on the tiles");
+  }
+
+  #[test]
+  fn test_webvtt_points_at_tiles() {
+    let track = webvtt(4000, &grid(2, 1, true), (160, 90), "/sheet.png");
+
+    assert!(track.starts_with("WEBVTT\n"));
+    assert!(track.contains("00:00:00.000 --> 00:00:02.000\n/sheet.png#xywh=0,0,160,90\n"));
+    assert!(track.contains("00:00:02.000 --> 00:00:04.000\n/sheet.png#xywh=160,0,160,90\n"));
+  }
+
+  #[test]
+  fn test_time_formatting() {
+    assert_eq!(format_vtt_time(3_723_004), "01:02:03.004");
+    assert_eq!(format_label(65_000), "1:05");
+    assert_eq!(format_label(3_725_000), "1:02:05");
+  }
+
+  #[test]
+  fn test_grid_limits() {
+    let query = ContactSheetQuery {
+      cols: Some(11),
+      ..Default::default()
+    };
+    assert!(SheetGrid::from_query(&query).is_err());
+
+    let grid = SheetGrid::from_query(&ContactSheetQuery::default()).unwrap();
+    assert_eq!(grid.count(), 20);
+    assert_eq!(
+      grid.image_query(SheetFormat::Vtt),
+      "cols=5&rows=4&width=160&labels=true&format=png"
+    );
+  }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::ffmpeg::VideoInfo;
+  use crate::video::video_controller::video_error_response;
+  use crate::video::video_cost::{CostEstimate, EstimateResponse, BASE_WEIGHT};
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_job_schedule::UserTier;
+  use crate::video::video_limits::MAX_SOURCE_DURATION_MS;
+  use crate::video::video_recipe::Recipe;
+  use actix_web::http::StatusCode;
+  use serde_json::json;
+
+  fn recipe(value: serde_json::Value) -> Recipe {
+    serde_json::from_value(value).unwrap()
+  }
+
+  fn video(width: u32, height: u32, duration_ms: u64) -> VideoInfo {
+    VideoInfo {
+      width,
+      height,
+      duration_ms,
+      fps: 30.0,
+      frame_count: 0,
+      codec: "h264".to_string(),
+      format: "mp4".to_string(),
+    }
+  }
+
+  #[test]
+  fn test_cost_is_pixels_times_frames_times_weight() {
+    let plain = recipe(json!({ "version": 1, "sources": [{ "start": 2.0, "end": 12.0 }] }));
+    let estimate = CostEstimate::of(&plain, &[video(1000, 500, 60_000)]);
+    assert_eq!((estimate.width, estimate.height, estimate.frames), (1000, 500, 100));
+    assert_eq!(estimate.weight, BASE_WEIGHT);
+    assert_eq!(estimate.cost, 50);
+
+    // Trims past the end stop with the video, every source adds its frames
+    let joined = recipe(json!({
+      "version": 1,
+      "sources": [{ "start": 55.0, "end": 90.0 }, {}],
+      "output": { "fps": 20 },
+    }));
+    let estimate = CostEstimate::of(&joined, &[video(1000, 500, 60_000), video(640, 360, 3_000)]);
+    assert_eq!((estimate.width, estimate.frames), (1000, 100 + 60));
+  }
+
+  #[test]
+  fn test_operations_change_the_size_and_add_weight() {
+    let edited = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [
+        { "op": "scale", "width": 480 },
+        { "op": "crop", "x": 0, "y": 60, "width": 480, "height": 400 },
+        { "op": "filter", "type": "blur", "sigma": 2.0 },
+      ],
+    }));
+    let estimate = CostEstimate::of(&edited, &[video(1920, 1080, 10_000)]);
+    assert_eq!((estimate.width, estimate.height, estimate.frames), (480, 210, 100));
+    assert_eq!(estimate.weight, BASE_WEIGHT + 0.5 + 0.1 + 3.0);
+
+    // Frames faster than the shortest GIF delay are dropped
+    let fast = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [{ "op": "speed", "factor": 10.0 }],
+      "output": { "fps": 50 },
+    }));
+    assert_eq!(CostEstimate::of(&fast, &[video(100, 100, 10_000)]).frames, 50);
+  }
+
+  #[test]
+  fn test_unknown_durations_are_not_free() {
+    let plain = recipe(json!({ "version": 1, "sources": [{}] }));
+    let counted = VideoInfo {
+      frame_count: 300,
+      ..video(1000, 500, 0)
+    };
+    assert_eq!(CostEstimate::of(&plain, &[counted]).frames, 100);
+
+    let unknown = CostEstimate::of(&plain, &[video(1000, 500, 0)]);
+    assert_eq!(unknown.frames, MAX_SOURCE_DURATION_MS / 100);
+    assert!(unknown.check(UserTier::Free, &plain).is_err());
+    // A trim still bounds the source
+    let trimmed = recipe(json!({ "version": 1, "sources": [{ "start": 1.0, "end": 3.0 }] }));
+    assert_eq!(CostEstimate::of(&trimmed, &[video(1000, 500, 0)]).frames, 20);
+  }
+
+  #[test]
+  fn test_costly_jobs_say_what_to_reduce() {
+    let blurred = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [{ "op": "filter", "type": "blur", "sigma": 2.0 }],
+      "output": { "fps": 25 },
+    }));
+    let estimate = CostEstimate::of(&blurred, &[video(1920, 1080, 60_000)]);
+    assert!(estimate.cost > UserTier::Free.max_cost());
+    assert!(estimate.check(UserTier::Admin, &blurred).is_ok());
+
+    let err = estimate.check(UserTier::Free, &blurred).unwrap_err();
+    let message = err.to_string();
+    assert!(matches!(err, VideoError::CostTooHigh(_)));
+    assert!(message.contains("limit of 1000 for the free tier"), "{}", message);
+    assert!(message.contains("1920x1080 pixels × 1500 frames × weight 4"), "{}", message);
+    assert!(message.contains("reduce the frames to"), "{}", message);
+    assert!(message.contains("remove the blur operation"), "{}", message);
+    assert_eq!(video_error_response(err).status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let dry_run = json!(EstimateResponse::new(estimate.clone(), UserTier::Free, &blurred));
+    assert_eq!(dry_run["cost"], estimate.cost);
+    assert_eq!(dry_run["tier"], "free");
+    assert_eq!(dry_run["limit"], 1000);
+    assert_eq!(dry_run["allowed"], false);
+    assert_eq!(dry_run["reason"].as_str(), Some(message.as_str()));
+  }
+}
@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_dedupe::dedupe_frames;
+  use crate::video::video_frame::{total_duration_ms, Frame};
+  use image::{Rgba, RgbaImage};
+
+  fn solid_frame(value: u8, pts_ms: i64, delay_ms: u32) -> Frame {
+    let image = RgbaImage::from_pixel(64, 36, Rgba([value, value, value, 255]));
+    Frame::new(image, pts_ms, delay_ms)
+  }
+
+  #[test]
+  fn test_identical_frames_are_merged() {
+    let frames = vec![
+      solid_frame(10, 0, 100),
+      solid_frame(10, 100, 100),
+      solid_frame(10, 200, 100),
+      solid_frame(200, 300, 100),
+    ];
+
+    let (kept, stats) = dedupe_frames(frames, 0.01);
+
+    assert_eq!(kept.len(), 2);
+    assert_eq!(kept[0].delay_ms, 300, "Merged delays should be summed");
+    assert_eq!(kept[1].delay_ms, 100);
+    assert_eq!(stats.input_frames, 4);
+    assert_eq!(stats.output_frames, 2);
+    assert_eq!(stats.dropped_frames, 2);
+  }
+
+  #[test]
+  fn test_total_duration_is_preserved() {
+    let frames: Vec<Frame> = (0..20)
+      .map(|i| {
+        solid_frame(
+          if i < 15 {
+            50
+          } else {
+            52
+          },
+          i * 40,
+          40,
+        )
+      })
+      .collect();
+    let duration = total_duration_ms(&frames);
+
+    let (kept, _) = dedupe_frames(frames, 0.05);
+
+    assert_eq!(kept.len(), 1);
+    assert_eq!(total_duration_ms(&kept), duration);
+  }
+
+  #[test]
+  fn test_slow_fade_is_not_collapsed() {
+    // Each step is below the threshold but the drift from the kept frame is not
+    let frames: Vec<Frame> = (0..10).map(|i| solid_frame(i as u8 * 5, i * 100, 100)).collect();
+
+    let (kept, stats) = dedupe_frames(frames, 0.03);
+
+    assert!(kept.len() > 1, "Gradual changes should keep intermediate frames");
+    assert_eq!(stats.input_frames, 10);
+  }
+
+  #[test]
+  fn test_zero_threshold_keeps_every_frame() {
+    let frames = vec![solid_frame(10, 0, 100), solid_frame(10, 100, 100)];
+
+    let (kept, stats) = dedupe_frames(frames, 0.0);
+
+    assert_eq!(kept.len(), 2);
+    assert_eq!(stats.dropped_frames, 0);
+  }
+}
@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_encoder::{
+    encode_gif, encode_gif_tracked, encode_gif_with, encode_within, EncoderOptions,
+  };
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_frame::Frame;
+  use image::codecs::gif::GifDecoder;
+  use image::{AnimationDecoder, Rgba, RgbaImage};
+  use std::collections::HashSet;
+  use std::io::Cursor;
+
+  // Smooth gradient with more than 256 colours
+  fn gradient(width: u32, height: u32, count: usize) -> Vec<Frame> {
+    (0..count)
+      .map(|i| {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+          Rgba([(x * 255 / width) as u8, (y * 255 / height) as u8, (i * 30) as u8, 255])
+        });
+        Frame::new(image, i as i64 * 100, 100)
+      })
+      .collect()
+  }
+
+  fn decoded_colors(bytes: &[u8]) -> Vec<usize> {
+    let decoder = GifDecoder::new(Cursor::new(bytes)).unwrap();
+    decoder
+      .into_frames()
+      .map(|frame| {
+        let image = frame.unwrap().into_buffer();
+        image.pixels().map(|pixel| pixel.0).collect::<HashSet<_>>().len()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_default_options_match_encode_gif() {
+    let frames = gradient(32, 32, 2);
+    let (mut plain, mut with_options) = (Vec::new(), Vec::new());
+    encode_gif(&frames, &mut plain, 10).unwrap();
+    encode_gif_with(&frames, &mut with_options, &EncoderOptions::default()).unwrap();
+    assert_eq!(plain, with_options);
+  }
+
+  #[test]
+  fn test_palette_limits_the_colours_of_every_frame() {
+    let options = EncoderOptions {
+      colors: 16,
+      ..Default::default()
+    };
+    let mut bytes = Vec::new();
+    encode_gif_with(&gradient(64, 64, 2), &mut bytes, &options).unwrap();
+    for colors in decoded_colors(&bytes) {
+      assert!(colors <= 16, "{} colours", colors);
+    }
+  }
+
+  #[test]
+  fn test_dithering_keeps_the_palette_and_mixes_colours() {
+    let frames = gradient(64, 64, 1);
+    let flat = EncoderOptions {
+      colors: 16,
+      ..Default::default()
+    };
+    let dithered = EncoderOptions {
+      dithering: true,
+      ..flat
+    };
+    let (mut flat_bytes, mut dithered_bytes) = (Vec::new(), Vec::new());
+    encode_gif_with(&frames, &mut flat_bytes, &flat).unwrap();
+    encode_gif_with(&frames, &mut dithered_bytes, &dithered).unwrap();
+
+    assert!(decoded_colors(&dithered_bytes)[0] <= 16);
+    assert_ne!(flat_bytes, dithered_bytes);
+  }
+
+  #[test]
+  fn test_transparent_pixels_stay_transparent() {
+    let mut image =
+      RgbaImage::from_fn(16, 16, |x, y| Rgba([(x * 16) as u8, (y * 16) as u8, 0, 255]));
+    image.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+    let options = EncoderOptions {
+      colors: 32,
+      dithering: true,
+      ..Default::default()
+    };
+    let mut bytes = Vec::new();
+    encode_gif_with(&[Frame::new(image, 0, 100)], &mut bytes, &options).unwrap();
+
+    let decoder = GifDecoder::new(Cursor::new(bytes)).unwrap();
+    let frame = decoder.into_frames().next().unwrap().unwrap().into_buffer();
+    assert_eq!(frame.get_pixel(0, 0).0[3], 0);
+  }
+
+  #[test]
+  fn test_frames_are_scaled_down_until_the_gif_fits() {
+    let frames = gradient(256, 128, 3);
+    let mut full = Vec::new();
+    encode_gif(&frames, &mut full, 10).unwrap();
+
+    let limit = full.len() as u64 / 3;
+    let (scaled, bytes) = encode_within(frames, &EncoderOptions::default(), limit).unwrap();
+    assert!(bytes.len() as u64 <= limit);
+    assert!(scaled[0].width() < 256);
+    assert_eq!(scaled[0].height() * 2, scaled[0].width() & !1);
+    assert_eq!(scaled.len(), 3);
+    assert_eq!(scaled[1].delay_ms, 100);
+  }
+
+  #[test]
+  fn test_impossible_limit_fails() {
+    let result = encode_within(gradient(64, 64, 2), &EncoderOptions::default(), 10);
+    assert!(matches!(result, Err(VideoError::OutputTooLarge(10))));
+  }
+
+  #[test]
+  fn test_tracked_encoding_reports_and_stops_between_frames() {
+    let mut written = Vec::new();
+    let result =
+      encode_gif_tracked(&gradient(8, 8, 5), Vec::new(), &EncoderOptions::default(), |count| {
+        written.push(count);
+        match count {
+          3 => Err(VideoError::Cancelled),
+          _ => Ok(()),
+        }
+      });
+    assert!(matches!(result, Err(VideoError::Cancelled)));
+    assert_eq!(written, vec![1, 2, 3]);
+  }
+}
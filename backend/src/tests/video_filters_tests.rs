@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_filters::{ChannelCurve, FrameFilter, Grayscale, Invert};
+  use crate::video::video_lut::Lut3d;
+  use image::{Rgba, RgbaImage};
+
+  const IDENTITY_CUBE: &str = "TITLE \"identity\"
+LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+  fn pixel_after(filter: &dyn FrameFilter, color: [u8; 4]) -> [u8; 4] {
+    let mut image = RgbaImage::from_pixel(2, 2, Rgba(color));
+    filter.apply(&mut image);
+    image.get_pixel(0, 0).0
+  }
+
+  #[test]
+  fn test_identity_lut_keeps_colors() {
+    let lut = Lut3d::parse(IDENTITY_CUBE).expect("Identity LUT should parse");
+    assert_eq!(lut.apply([12, 128, 250]), [12, 128, 250]);
+  }
+
+  #[test]
+  fn test_resolve_input_range_and_vendor_keywords() {
+    let resolve = IDENTITY_CUBE.replace(
+      "LUT_3D_SIZE 2\n",
+      "LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0.0 0.5\nLUT_IN_VIDEO_RANGE\n",
+    );
+    let lut = Lut3d::parse(&resolve).expect("Resolve LUT should parse");
+    assert_eq!(lut.apply([64, 0, 200]), [128, 0, 255]);
+  }
+
+  #[test]
+  fn test_lut_with_missing_entries_is_rejected() {
+    let truncated = IDENTITY_CUBE.lines().take(6).collect::<Vec<_>>().join("\n");
+    assert!(Lut3d::parse(&truncated).is_err());
+  }
+
+  #[test]
+  fn test_grayscale_and_invert() {
+    let [r, g, b, a] = pixel_after(&Grayscale, [255, 0, 0, 200]);
+    assert!(r == g && g == b, "Grayscale channels should be equal");
+    assert_eq!(a, 200, "Alpha should be preserved");
+
+    assert_eq!(pixel_after(&Invert, [10, 20, 30, 255]), [245, 235, 225, 255]);
+  }
+
+  #[test]
+  fn test_neutral_curves_are_identity() {
+    let color = [17, 99, 201, 255];
+    assert_eq!(pixel_after(&ChannelCurve::contrast(1.0), color), color);
+    assert_eq!(pixel_after(&ChannelCurve::gamma(1.0), color), color);
+    assert_eq!(pixel_after(&ChannelCurve::brightness(0.0), color), color);
+  }
+}
@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_controller::video_error_response;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_frame_edit::{apply_operations, FrameEditRequest, FrameOperation};
+  use crate::video::video_frame_store::{write_frames, FrameEntry};
+  use crate::video::video_service::VideoService;
+  use crate::video::video_storage::MediaStorage;
+  use actix_web::http::StatusCode;
+  use image::{Rgba, RgbaImage};
+  use uuid::Uuid;
+
+  fn entries(count: usize) -> Vec<FrameEntry> {
+    (0..count)
+      .map(|index| FrameEntry {
+        index,
+        file: format!("frame_{:05}.png", index),
+        pts_ms: index as i64 * 100,
+        delay_ms: 100,
+      })
+      .collect()
+  }
+
+  fn files(frames: &[FrameEntry]) -> Vec<&str> {
+    frames.iter().map(|frame| &frame.file[6..11]).collect()
+  }
+
+  #[test]
+  fn test_operations_apply_in_order() {
+    let mut frames = entries(5);
+    let operations = vec![
+      FrameOperation::Drop {
+        index: 1,
+      },
+      FrameOperation::Duplicate {
+        index: 0,
+        count: Some(2),
+      },
+      FrameOperation::Move {
+        from: 5,
+        to: 0,
+      },
+      FrameOperation::SetDelay {
+        index: 0,
+        delay_ms: 500,
+      },
+    ];
+
+    apply_operations(&mut frames, &operations).unwrap();
+
+    assert_eq!(files(&frames), vec!["00004", "00000", "00000", "00000", "00002", "00003"]);
+    assert_eq!(frames[1].pts_ms, 500, "Timestamps should follow the new delays");
+    assert_eq!(frames[5].index, 5);
+  }
+
+  #[test]
+  fn test_range_delay_is_inclusive() {
+    let mut frames = entries(5);
+    let operations = vec![FrameOperation::SetRangeDelay {
+      start: 1,
+      end: 3,
+      delay_ms: 40,
+    }];
+
+    apply_operations(&mut frames, &operations).unwrap();
+
+    let delays: Vec<u32> = frames.iter().map(|frame| frame.delay_ms).collect();
+    assert_eq!(delays, vec![100, 40, 40, 40, 100]);
+  }
+
+  #[test]
+  fn test_invalid_operations_are_rejected() {
+    let reject = |operation: FrameOperation, count: usize| {
+      apply_operations(&mut entries(count), &[operation]).is_err()
+    };
+
+    assert!(reject(
+      FrameOperation::Drop {
+        index: 0,
+      },
+      1
+    ));
+    assert!(reject(
+      FrameOperation::Move {
+        from: 0,
+        to: 3,
+      },
+      3
+    ));
+    assert!(reject(
+      FrameOperation::SetDelay {
+        index: 0,
+        delay_ms: 5,
+      },
+      3
+    ));
+    assert!(reject(
+      FrameOperation::SetRangeDelay {
+        start: 2,
+        end: 1,
+        delay_ms: 100,
+      },
+      3
+    ));
+    assert!(apply_operations(&mut entries(3), &[]).is_err());
+  }
+
+  #[test]
+  fn test_each_edit_creates_a_version() {
+    let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let storage = MediaStorage::new(&root);
+    let gif_id = Uuid::new_v4();
+    let frames: Vec<Frame> = (0..4)
+      .map(|i| Frame::new(RgbaImage::from_pixel(8, 8, Rgba([i * 60, 0, 0, 255])), 0, 100))
+      .collect();
+    write_frames(&storage.frames_dir(&gif_id), &frames).unwrap();
+
+    let drop_first = FrameEditRequest {
+      base_version: None,
+      operations: vec![FrameOperation::Drop {
+        index: 0,
+      }],
+    };
+    let second = VideoService::edit_frames(&storage, gif_id, &drop_first).unwrap();
+    let from_original = FrameEditRequest {
+      base_version: Some(1),
+      ..drop_first.clone()
+    };
+    let third = VideoService::edit_frames(&storage, gif_id, &from_original).unwrap();
+    let versions = VideoService::list_versions(&storage, gif_id).unwrap();
+    let latest = VideoService::latest_gif(&storage, gif_id).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!((second.version, second.frame_count), (2, 3));
+    assert_eq!((third.version, third.frame_count), (3, 3));
+    assert_eq!(versions.len(), 2, "Version 1 has no GIF file in this test");
+    assert_eq!(latest, storage.gif_version_path(&gif_id, 3));
+  }
+
+  #[test]
+  fn test_unknown_base_version_is_not_found() {
+    let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let storage = MediaStorage::new(&root);
+    let gif_id = Uuid::new_v4();
+    let frames = vec![Frame::new(RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255])), 0, 100)];
+    write_frames(&storage.frames_dir(&gif_id), &frames).unwrap();
+
+    let edit = FrameEditRequest {
+      base_version: Some(7),
+      operations: vec![FrameOperation::SetDelay {
+        index: 0,
+        delay_ms: 50,
+      }],
+    };
+    let edited = VideoService::edit_frames(&storage, gif_id, &edit);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    let err = edited.unwrap_err();
+    assert!(matches!(err, VideoError::VersionNotFound(7)));
+    assert_eq!(video_error_response(err).status(), StatusCode::NOT_FOUND);
+  }
+}
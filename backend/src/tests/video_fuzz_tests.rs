@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::ffmpeg::{probe, stream_frames, DecodeOptions};
+  use crate::video::video_encoder::encode_gif;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_gif::{is_gif, stream_gif};
+  use image::{Rgba, RgbaImage};
+  use std::panic::{catch_unwind, AssertUnwindSafe};
+  use std::path::{Path, PathBuf};
+  use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+  use uuid::Uuid;
+
+  // Mutation fuzzer for the probe and decode entry points, a quick stand-in
+  // for the cargo-fuzz target in fuzz/ that needs no nightly toolchain:
+  // `FUZZ_ITERATIONS=100000 cargo test --release fuzz_decoders -- --ignored --nocapture`
+  // Inputs that panic are kept in the temporary directory
+  const SMOKE_ITERATIONS: u64 = 50;
+
+  // xorshift64*, the same seed always gives the same inputs
+  struct Rng(u64);
+
+  impl Rng {
+    fn next(&mut self) -> u64 {
+      self.0 ^= self.0 >> 12;
+      self.0 ^= self.0 << 25;
+      self.0 ^= self.0 >> 27;
+      self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+      (self.next() % bound.max(1) as u64) as usize
+    }
+  }
+
+  fn seeds() -> Vec<Vec<u8>> {
+    let frames: Vec<Frame> = (0..4)
+      .map(|i| {
+        let image =
+          RgbaImage::from_fn(24, 12, |x, y| Rgba([(x * 10) as u8, (y * 20) as u8, i, 255]));
+        Frame::new(image, i as i64 * 100, 100)
+      })
+      .collect();
+    let mut gif = Vec::new();
+    encode_gif(&frames, &mut gif, 10).unwrap();
+
+    // The cargo-fuzz seeds, one tiny valid file per container
+    let mp4 = include_bytes!("../../fuzz/seeds/decode/tiny.mp4").to_vec();
+    let webm = include_bytes!("../../fuzz/seeds/decode/tiny.webm").to_vec();
+    let small_gif = include_bytes!("../../fuzz/seeds/decode/tiny.gif").to_vec();
+    vec![gif, mp4, webm, small_gif]
+  }
+
+  fn mutate(rng: &mut Rng, seed: &[u8]) -> Vec<u8> {
+    let mut bytes = seed.to_vec();
+    for _ in 0..1 + rng.below(8) {
+      match rng.below(5) {
+        0 if !bytes.is_empty() => {
+          let index = rng.below(bytes.len());
+          bytes[index] ^= 1 << rng.below(8);
+        },
+        1 if !bytes.is_empty() => {
+          let index = rng.below(bytes.len());
+          bytes[index] = [0x00, 0xFF, 0x7F, 0x80][rng.below(4)];
+        },
+        2 => bytes.truncate(rng.below(bytes.len() + 1)),
+        3 => {
+          let index = rng.below(bytes.len() + 1);
+          let extra: Vec<u8> = (0..rng.below(32)).map(|_| rng.next() as u8).collect();
+          bytes.splice(index..index, extra);
+        },
+        _ if bytes.len() > 1 => {
+          // Repeats a slice, the usual way to grow counts and sizes
+          let start = rng.below(bytes.len());
+          let end = start + rng.below(bytes.len() - start);
+          let copy = bytes[start..end].to_vec();
+          bytes.extend_from_slice(&copy);
+        },
+        _ => {},
+      }
+    }
+    bytes
+  }
+
+  // Every entry point must fail with an error, never panic or run away
+  fn exercise(path: &Path) {
+    let options = DecodeOptions {
+      fps: Some(10),
+      width: Some(64),
+      deadline: Some(Instant::now() + Duration::from_secs(2)),
+      ..Default::default()
+    };
+    let _ = probe(path);
+    let _ = stream_frames(path, &options, |_| Ok(()));
+    if is_gif(path).unwrap_or(false) {
+      let _ = stream_gif(path, &options, |_| Ok(()));
+    }
+  }
+
+  fn run(iterations: u64, seed: u64) {
+    let seeds = seeds();
+    let mut rng = Rng(seed);
+    let directory = std::env::temp_dir().join(format!("fuzz-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("input");
+
+    for iteration in 0..iterations {
+      let seed = &seeds[rng.below(seeds.len())];
+      let input = mutate(&mut rng, seed);
+      std::fs::write(&path, &input).unwrap();
+      if catch_unwind(AssertUnwindSafe(|| exercise(&path))).is_err() {
+        let kept: PathBuf = directory.join(format!("crash-{}", iteration));
+        std::fs::write(&kept, &input).unwrap();
+        panic!("decoding panicked on iteration {}, input kept in {}", iteration, kept.display());
+      }
+    }
+    let _ = std::fs::remove_dir_all(&directory);
+  }
+
+  #[test]
+  fn test_fuzz_decoders_smoke() {
+    run(SMOKE_ITERATIONS, 0x5EED);
+  }
+
+  #[test]
+  #[ignore]
+  fn test_fuzz_decoders() {
+    let iterations =
+      std::env::var("FUZZ_ITERATIONS").ok().and_then(|value| value.parse().ok()).unwrap_or(10_000);
+    let seed =
+      std::env::var("FUZZ_SEED").ok().and_then(|value| value.parse().ok()).unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64 | 1
+      });
+    println!("fuzzing {} inputs with FUZZ_SEED={}", iterations, seed);
+    run(iterations, seed);
+  }
+}
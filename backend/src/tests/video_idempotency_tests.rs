@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+  use crate::common::responses::ApiResponse;
+  use crate::video::video_controller::video_error_response;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_idempotency::{
+    fingerprint, idempotency_key, replay, StoredResponse, IDEMPOTENCY_HEADER, REPLAYED_HEADER,
+  };
+  use actix_web::body::to_bytes;
+  use actix_web::http::header::LOCATION;
+  use actix_web::http::StatusCode;
+  use actix_web::test::TestRequest;
+  use serde_json::json;
+
+  #[test]
+  fn test_keys_are_read_from_the_header() {
+    let req = TestRequest::default().to_http_request();
+    assert_eq!(idempotency_key(&req).unwrap(), None);
+
+    let req =
+      TestRequest::default().insert_header((IDEMPOTENCY_HEADER, " 4b1d-retry ")).to_http_request();
+    assert_eq!(idempotency_key(&req).unwrap().as_deref(), Some("4b1d-retry"));
+
+    for key in ["", "two words", &"k".repeat(256)] {
+      let req = TestRequest::default().insert_header((IDEMPOTENCY_HEADER, key)).to_http_request();
+      assert!(matches!(idempotency_key(&req), Err(VideoError::InvalidOption(_))), "{:?}", key);
+    }
+  }
+
+  #[test]
+  fn test_fingerprints_cover_the_endpoint_and_the_body() {
+    let body = br#"{"recipe_id":"7c9e6679-7425-40de-944b-e07fc1f90ae7"}"#;
+    assert_eq!(fingerprint("POST /jobs", body), fingerprint("POST /jobs", body));
+    assert_ne!(fingerprint("POST /jobs", body), fingerprint("POST /media", body));
+    assert_ne!(fingerprint("POST /jobs", body), fingerprint("POST /jobs", b"{}"));
+    assert_eq!(fingerprint("POST /jobs", body).len(), 64);
+  }
+
+  #[actix_web::test]
+  async fn test_retries_replay_the_original_response() {
+    let mut response = ApiResponse::accepted("Job queued", Some(json!({ "id": 7 })));
+    response.headers_mut().insert(LOCATION, "/jobs/7".parse().unwrap());
+    let stored = StoredResponse::capture(response).await.unwrap();
+    assert_eq!(stored.status, 202);
+
+    let original = fingerprint("POST /jobs", b"{}");
+    let replayed = replay(&original, &original, &stored).unwrap();
+    assert_eq!(replayed.status(), StatusCode::ACCEPTED);
+    assert_eq!(replayed.headers().get(LOCATION).unwrap(), "/jobs/7");
+    assert_eq!(replayed.headers().get(REPLAYED_HEADER).unwrap(), "true");
+    let body: serde_json::Value =
+      serde_json::from_slice(&to_bytes(replayed.into_body()).await.unwrap()).unwrap();
+    assert_eq!(body, json!({ "message": "Job queued", "data": { "id": 7 } }));
+
+    // The first response is rebuilt from what is stored, without the header
+    assert!(stored.respond(false).headers().get(REPLAYED_HEADER).is_none());
+  }
+
+  #[actix_web::test]
+  async fn test_a_different_request_with_the_same_key_is_refused() {
+    let stored =
+      StoredResponse::capture(ApiResponse::created("Media uploaded", None)).await.unwrap();
+    let err = replay(&fingerprint("POST /media", b"a"), &fingerprint("POST /media", b"b"), &stored)
+      .unwrap_err();
+    assert!(matches!(err, VideoError::IdempotencyKeyReused));
+    assert_eq!(video_error_response(err).status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let status = video_error_response(VideoError::IdempotencyKeyInUse).status();
+    assert_eq!(status, StatusCode::CONFLICT);
+  }
+
+  #[actix_web::test]
+  async fn test_failures_are_not_kept() {
+    let response = video_error_response(VideoError::MediaNotFound);
+    let response = StoredResponse::capture(response).await.unwrap_err();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+  }
+}
@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_controller::video_error_response;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_job_schedule::{check_queue, QueueLoad, UserTier, QUEUE_RETRY_AFTER};
+  use actix_web::http::header::RETRY_AFTER;
+  use actix_web::http::StatusCode;
+
+  fn load(tier: UserTier, pending: i64, queued: i64) -> QueueLoad {
+    QueueLoad {
+      tier,
+      pending,
+      queued,
+    }
+  }
+
+  #[test]
+  fn test_admins_come_before_every_tier() {
+    assert_eq!(UserTier::of("free", false), UserTier::Free);
+    assert_eq!(UserTier::of("paid", false), UserTier::Paid);
+    assert_eq!(UserTier::of("paid", true), UserTier::Admin);
+    assert_eq!(UserTier::of("unknown", false), UserTier::Free);
+    assert!(UserTier::Admin.priority() > UserTier::Paid.priority());
+    assert!(UserTier::Paid.priority() > UserTier::Free.priority());
+    for tier in UserTier::ALL {
+      assert!(tier.max_running() >= 1 && tier.max_running() < tier.max_pending(), "{:?}", tier);
+    }
+  }
+
+  #[test]
+  fn test_jobs_are_admitted_below_the_limits() {
+    assert!(check_queue(&load(UserTier::Free, 0, 0), 1000).is_ok());
+    let max_pending = UserTier::Free.max_pending();
+    assert!(check_queue(&load(UserTier::Free, max_pending - 1, 999), 1000).is_ok());
+    // A paid user still has room where a free one has none
+    assert!(check_queue(&load(UserTier::Paid, max_pending, 0), 1000).is_ok());
+  }
+
+  #[test]
+  fn test_a_full_queue_is_retried_later() {
+    let max_pending = UserTier::Free.max_pending();
+    for load in [load(UserTier::Free, max_pending, 0), load(UserTier::Admin, 0, 1000)] {
+      let err = check_queue(&load, 1000).unwrap_err();
+      assert!(matches!(err, VideoError::QueueFull(_, 30)), "{:?}", load);
+
+      let response = video_error_response(err);
+      assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+      let retry_after = response.headers().get(RETRY_AFTER).unwrap();
+      assert_eq!(retry_after.to_str().unwrap(), QUEUE_RETRY_AFTER.as_secs().to_string());
+    }
+  }
+}
@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests {
+  use crate::common::responses::ApiResponse;
+  use crate::video::video_controller::video_error_response;
+  use crate::video::video_errors::{FailureKind, VideoError};
+  use crate::video::video_job::{ConversionJob, JobFailure, JobStatus};
+  use crate::video::video_job_worker::{retry_delay, MAX_ATTEMPTS, MAX_RETRY_DELAY};
+  use crate::video::video_progress::{ProgressEvent, Stage};
+  use crate::video::video_recipe::Recipe;
+  use actix_web::http::StatusCode;
+  use chrono::Utc;
+  use serde_json::json;
+  use std::io::{Error, ErrorKind};
+  use std::time::Duration;
+  use uuid::Uuid;
+
+  fn job(status: JobStatus) -> ConversionJob {
+    let recipe: Recipe =
+      serde_json::from_value(json!({ "version": 1, "sources": [{ "end": 2.0 }] })).unwrap();
+    ConversionJob {
+      id: Uuid::new_v4(),
+      user_id: 1,
+      media_id: Some(Uuid::new_v4()),
+      recipe,
+      callback_url: None,
+      status,
+      attempts: 0,
+      result: None,
+      error: None,
+      failures: Vec::new(),
+      run_after: Utc::now(),
+      created_at: Utc::now(),
+      updated_at: Utc::now(),
+      started_at: None,
+      finished_at: None,
+    }
+  }
+
+  #[test]
+  fn test_job_status_round_trips() {
+    for status in [
+      JobStatus::Queued,
+      JobStatus::Running,
+      JobStatus::Succeeded,
+      JobStatus::DeadLettered,
+      JobStatus::Cancelled,
+    ] {
+      let name = json!(status);
+      assert_eq!(JobStatus::parse(name.as_str().unwrap()), Some(status));
+    }
+    assert_eq!(JobStatus::parse("done"), None);
+  }
+
+  #[test]
+  fn test_job_is_serialized_with_its_recipe() {
+    let value = json!(job(JobStatus::Queued));
+    assert_eq!(value["status"], "queued");
+    assert_eq!(value["recipe"]["sources"][0]["end"], 2.0);
+    assert!(value["result"].is_null());
+  }
+
+  #[test]
+  fn test_queued_jobs_are_accepted() {
+    let response = ApiResponse::accepted("Job queued", Some(json!(job(JobStatus::Queued))));
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+  }
+
+  #[test]
+  fn test_cancelling_a_finished_job_conflicts() {
+    assert_eq!(video_error_response(VideoError::JobFinished).status(), StatusCode::CONFLICT);
+    assert_eq!(video_error_response(VideoError::JobNotFound).status(), StatusCode::NOT_FOUND);
+  }
+
+  #[test]
+  fn test_cancelled_jobs_end_their_progress() {
+    let event = ProgressEvent::from_job(&job(JobStatus::Cancelled));
+    assert_eq!(event.stage, Stage::Cancelled);
+    assert!(event.stage.is_finished());
+    assert!(!Stage::from(JobStatus::Running).is_finished());
+  }
+
+  #[test]
+  fn test_failures_are_split_into_transient_and_permanent() {
+    let transient = [
+      VideoError::IoError(Error::new(ErrorKind::StorageFull, "disk full")),
+      VideoError::FfmpegError(ffmpeg_next::Error::Other {
+        errno: 12, // ENOMEM
+      }),
+      VideoError::Abandoned(5),
+    ];
+    for err in transient {
+      assert_eq!(err.failure_kind(), FailureKind::Transient, "{}", err);
+    }
+    let permanent = [
+      VideoError::IoError(Error::new(ErrorKind::UnexpectedEof, "truncated")),
+      VideoError::NoVideoStream,
+      VideoError::TimedOut(300),
+      VideoError::UnsupportedMedia("video/x-unknown".to_string()),
+      VideoError::FfmpegError(ffmpeg_next::Error::InvalidData),
+      VideoError::FfmpegError(ffmpeg_next::Error::Other {
+        errno: 22, // EINVAL
+      }),
+    ];
+    for err in permanent {
+      assert_eq!(err.failure_kind(), FailureKind::Permanent, "{}", err);
+    }
+  }
+
+  #[test]
+  fn test_failures_keep_the_error_chain() {
+    let err = VideoError::ImageError(image::ImageError::IoError(Error::new(
+      ErrorKind::PermissionDenied,
+      "frame unreadable",
+    )));
+    let failure = JobFailure::new(2, &err);
+    assert_eq!(failure.kind, FailureKind::Transient);
+    assert_eq!(failure.errors[0], "Image error: frame unreadable");
+    assert_eq!(failure.errors.last().unwrap(), "frame unreadable");
+
+    let value = json!(failure);
+    assert_eq!(value["kind"], "transient");
+    assert_eq!(serde_json::from_value::<JobFailure>(value).unwrap(), failure);
+  }
+
+  #[test]
+  fn test_retries_back_off_until_the_last_attempt() {
+    assert_eq!(retry_delay(FailureKind::Transient, 1), Some(Duration::from_secs(10)));
+    assert_eq!(retry_delay(FailureKind::Transient, 3), Some(Duration::from_secs(40)));
+    assert_eq!(retry_delay(FailureKind::Transient, MAX_ATTEMPTS), None);
+    assert_eq!(retry_delay(FailureKind::Permanent, 1), None);
+    for attempt in 1..MAX_ATTEMPTS {
+      assert!(retry_delay(FailureKind::Transient, attempt).unwrap() <= MAX_RETRY_DELAY);
+    }
+  }
+
+  #[test]
+  fn test_dead_lettered_jobs_report_their_error() {
+    let mut job = job(JobStatus::DeadLettered);
+    job.error = Some("No video stream found".to_string());
+    let event = ProgressEvent::from_job(&job);
+    assert_eq!(event.stage, Stage::Failed);
+    assert_eq!(event.error.as_deref(), Some("No video stream found"));
+    assert_eq!(json!(JobStatus::DeadLettered), "dead_lettered");
+
+    let status = video_error_response(VideoError::NotDeadLettered).status();
+    assert_eq!(status, StatusCode::CONFLICT);
+  }
+}
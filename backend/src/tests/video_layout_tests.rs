@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_canvas::Rect;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_layout::{
+    frame_at, CellSpec, FillMode, LayoutClip, LayoutKind, LayoutSpec, PipPosition,
+  };
+  use crate::video::video_text::{draw_label, label_size};
+  use image::{Rgba, RgbaImage};
+
+  fn layout(kind: LayoutKind, cells: usize, gutter: u32, fill: FillMode) -> LayoutSpec {
+    LayoutSpec {
+      kind,
+      columns: None,
+      gutter,
+      background: Some("#ff00ff".to_string()),
+      fill,
+      position: PipPosition::BottomRight,
+      scale: None,
+      cells: (0..cells)
+        .map(|_| CellSpec {
+          source: None,
+          start: None,
+          end: None,
+          label: None,
+        })
+        .collect(),
+    }
+  }
+
+  fn solid_clip(value: u8, width: u32, height: u32, count: usize) -> LayoutClip {
+    let frames = (0..count)
+      .map(|i| {
+        let image = RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]));
+        Frame::new(image, i as i64 * 100, 100)
+      })
+      .collect();
+    LayoutClip {
+      frames,
+      label: None,
+    }
+  }
+
+  #[test]
+  fn test_grid_geometry_includes_gutters() {
+    let spec = layout(LayoutKind::Grid, 4, 4, FillMode::Loop);
+    assert!(spec.validate().is_ok());
+    assert_eq!(spec.decode_width(0, 212), 100);
+
+    let clips: Vec<LayoutClip> = (0..4).map(|_| solid_clip(0, 100, 50, 1)).collect();
+    let ((width, height), rects) = spec.geometry(&clips, 212).unwrap();
+
+    assert_eq!((width, height), (212, 112));
+    assert_eq!(
+      rects[3],
+      Rect {
+        x: 108,
+        y: 58,
+        width: 100,
+        height: 50,
+      }
+    );
+  }
+
+  #[test]
+  fn test_shorter_clips_loop_or_hold() {
+    let frames = solid_clip(0, 4, 4, 3).frames;
+
+    let looped = frame_at(&frames, 450, FillMode::Loop).unwrap();
+    assert_eq!(looped.pts_ms, 100);
+    let held = frame_at(&frames, 450, FillMode::Hold).unwrap();
+    assert_eq!(held.pts_ms, 200);
+  }
+
+  #[test]
+  fn test_compose_plays_clips_in_sync() {
+    let spec = layout(LayoutKind::Grid, 2, 2, FillMode::Hold);
+    let clips = vec![solid_clip(0, 20, 20, 10), solid_clip(255, 20, 20, 4)];
+
+    let frames = spec.compose(clips, 46, 100).unwrap();
+
+    assert_eq!(frames.len(), 10, "Output should last as long as the longest clip");
+    assert_eq!(frames[0].image.dimensions(), (46, 24));
+    assert_eq!(frames[9].image.get_pixel(30, 10).0, [255, 255, 255, 255]);
+    assert_eq!(
+      frames[9].image.get_pixel(0, 0).0,
+      [255, 0, 255, 255],
+      "Gutters show the background"
+    );
+  }
+
+  #[test]
+  fn test_pip_inset_is_in_the_corner() {
+    let mut spec = layout(LayoutKind::Pip, 2, 8, FillMode::Loop);
+    spec.scale = Some(0.25);
+    let clips = vec![solid_clip(0, 200, 100, 1), solid_clip(255, 50, 50, 1)];
+
+    let ((width, height), rects) = spec.geometry(&clips, 200).unwrap();
+
+    assert_eq!((width, height), (200, 100));
+    assert_eq!(
+      rects[1],
+      Rect {
+        x: 142,
+        y: 42,
+        width: 50,
+        height: 50,
+      }
+    );
+  }
+
+  #[test]
+  fn test_invalid_layouts_are_rejected() {
+    assert!(layout(LayoutKind::Grid, 1, 0, FillMode::Loop).validate().is_err());
+    assert!(layout(LayoutKind::Grid, 10, 0, FillMode::Loop).validate().is_err());
+    assert!(layout(LayoutKind::Pip, 3, 0, FillMode::Loop).validate().is_err());
+  }
+
+  #[test]
+  fn test_labels_are_drawn() {
+    let mut image = RgbaImage::from_pixel(64, 32, Rgba([0, 0, 0, 255]));
+    draw_label(&mut image, "A1", 0, 0, 1);
+
+    let (width, height) = label_size("A1", 1);
+    assert_eq!((width, height), (15, 11));
+    let lit = image.pixels().filter(|pixel| pixel.0[0] == 255).count();
+    assert!(lit > 10, "Glyph pixels should be white");
+    assert_eq!(image.get_pixel(40, 20).0, [0, 0, 0, 255], "Pixels outside the label are untouched");
+  }
+}
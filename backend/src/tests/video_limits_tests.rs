@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::ffmpeg::DecodeOptions;
+  use crate::video::video_controller::video_error_response;
+  use crate::video::video_encoder::encode_gif;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_gif::decode_gif;
+  use crate::video::video_limits::{
+    check_container, check_duration, check_resolution, DecodeGuard, MAX_SOURCE_DURATION_MS,
+  };
+  use actix_web::http::StatusCode;
+  use image::{Rgba, RgbaImage};
+  use std::path::PathBuf;
+  use std::time::{Duration, Instant};
+  use uuid::Uuid;
+
+  fn write_file(bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{}.gif", Uuid::new_v4()));
+    std::fs::write(&path, bytes).unwrap();
+    path
+  }
+
+  fn gif_bytes(count: usize) -> Vec<u8> {
+    let frames: Vec<Frame> = (0..count)
+      .map(|i| Frame::new(RgbaImage::from_pixel(16, 8, Rgba([0, 0, 0, 255])), i as i64 * 100, 100))
+      .collect();
+    let mut bytes = Vec::new();
+    encode_gif(&frames, &mut bytes, 10).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn test_only_whitelisted_containers_and_codecs_are_opened() {
+    assert!(check_container("mov,mp4,m4a,3gp,3g2,mj2", "h264").is_ok());
+    assert!(check_container("matroska,webm", "vp9").is_ok());
+    assert!(check_container("gif", "gif").is_ok());
+    assert!(matches!(check_container("image2", "png"), Err(VideoError::UnsupportedMedia(_))));
+    assert!(matches!(check_container("hls", "h264"), Err(VideoError::UnsupportedMedia(_))));
+    assert!(matches!(check_container("avi", "cinepak"), Err(VideoError::UnsupportedMedia(_))));
+  }
+
+  #[test]
+  fn test_resolution_and_duration_limits() {
+    assert!(check_resolution(7680, 4320).is_ok());
+    assert!(check_resolution(8192, 1024).is_ok());
+    assert!(matches!(check_resolution(8192, 4320), Err(VideoError::MediaTooLarge(_))));
+    assert!(matches!(check_resolution(65535, 2), Err(VideoError::MediaTooLarge(_))));
+    assert!(matches!(check_resolution(0, 480), Err(VideoError::InvalidMedia(_))));
+
+    assert!(check_duration(0).is_ok());
+    assert!(check_duration(MAX_SOURCE_DURATION_MS).is_ok());
+    assert!(matches!(
+      check_duration(MAX_SOURCE_DURATION_MS + 1),
+      Err(VideoError::MediaTooLarge(_))
+    ));
+  }
+
+  #[test]
+  fn test_guard_counts_every_decoded_frame() {
+    let mut frames = DecodeGuard::with_limits(3, u64::MAX, None);
+    for _ in 0..3 {
+      frames.admit(16, 16).unwrap();
+    }
+    assert!(matches!(frames.admit(16, 16), Err(VideoError::MediaTooLarge(_))));
+
+    let mut pixels = DecodeGuard::with_limits(u64::MAX, 1000, None);
+    pixels.admit(20, 25).unwrap();
+    pixels.admit(20, 25).unwrap();
+    assert!(matches!(pixels.admit(1, 1), Err(VideoError::MediaTooLarge(_))));
+
+    // A stream that grows mid-way is stopped at the frame that grew
+    let mut resized = DecodeGuard::new(None);
+    resized.admit(640, 360).unwrap();
+    assert!(matches!(resized.admit(16384, 16384), Err(VideoError::MediaTooLarge(_))));
+  }
+
+  #[test]
+  fn test_guard_stops_at_the_deadline() {
+    let mut guard = DecodeGuard::new(Some(Instant::now() + Duration::from_secs(60)));
+    guard.admit(16, 16).unwrap();
+    let mut late = DecodeGuard::new(Some(Instant::now()));
+    assert!(matches!(late.admit(16, 16), Err(VideoError::TimedOut(_))));
+  }
+
+  #[test]
+  fn test_gif_bomb_is_rejected_before_decoding() {
+    // 65535x65535 logical screen, a single 1x1 frame would be composed on it
+    let mut bytes = b"GIF89a".to_vec();
+    bytes.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00]);
+    bytes.extend_from_slice(&[0x2C, 0, 0, 0, 0, 1, 0, 1, 0, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00]);
+    bytes.push(0x3B);
+
+    let result = decode_gif(&write_file(&bytes), &DecodeOptions::default());
+    assert!(matches!(result, Err(VideoError::MediaTooLarge(_))));
+  }
+
+  #[test]
+  fn test_broken_gifs_are_invalid_media() {
+    let mut bytes = gif_bytes(3);
+    bytes.truncate(bytes.len() / 2);
+    bytes.extend_from_slice(&[0xA5; 64]);
+    let result = decode_gif(&write_file(&bytes), &DecodeOptions::default());
+    assert!(matches!(result, Err(VideoError::InvalidMedia(_))), "{:?}", result);
+
+    let result = decode_gif(&write_file(b"GIF89a"), &DecodeOptions::default());
+    assert!(matches!(result, Err(VideoError::InvalidMedia(_))), "{:?}", result);
+  }
+
+  #[test]
+  fn test_gif_decoding_stops_at_the_deadline() {
+    let path = write_file(&gif_bytes(3));
+    let options = DecodeOptions {
+      deadline: Some(Instant::now()),
+      ..Default::default()
+    };
+    assert!(matches!(decode_gif(&path, &options), Err(VideoError::TimedOut(_))));
+    assert_eq!(decode_gif(&path, &DecodeOptions::default()).unwrap().len(), 3);
+  }
+
+  #[test]
+  fn test_limit_violations_are_client_errors() {
+    let status = |err| video_error_response(err).status();
+    assert_eq!(status(VideoError::MediaTooLarge("8K".to_string())), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+      status(VideoError::UnsupportedMedia("codec".to_string())),
+      StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+      status(VideoError::InvalidMedia("gif".to_string())),
+      StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(status(VideoError::TimedOut(300)), StatusCode::UNPROCESSABLE_ENTITY);
+  }
+}
@@ -0,0 +1,273 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_dedupe::dedupe_frames;
+  use crate::video::video_encoder::{encode_gif_with, EncoderOptions};
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_filters::{FilterChain, FilterSpec};
+  use crate::video::video_frame::Frame;
+  use crate::video::video_frame_store::read_manifest;
+  use crate::video::video_pipeline::{
+    frame_ceiling, worker_count, StreamingPipeline, STAGE_CAPACITY,
+  };
+  use crate::video::video_storage::MediaStorage;
+  use image::{Rgba, RgbaImage};
+  use std::io::{self, Write};
+  use std::time::{Duration, Instant};
+  use uuid::Uuid;
+
+  // Every third frame repeats the previous one so dedupe has work to do
+  fn frame(index: usize) -> Frame {
+    let value = ((index - index / 3) * 7) as u8;
+    let image = RgbaImage::from_fn(24, 16, |x, y| Rgba([value, x as u8 * 10, y as u8 * 15, 255]));
+    Frame::new(image, index as i64 * 50, 50)
+  }
+
+  fn filters(specs: &[FilterSpec]) -> FilterChain {
+    let storage = MediaStorage::new(std::env::temp_dir().join(Uuid::new_v4().to_string()));
+    FilterChain::build(specs, &storage).unwrap()
+  }
+
+  fn pipeline(
+    filters: &FilterChain,
+    dedupe_threshold: Option<f32>,
+    workers: usize,
+  ) -> StreamingPipeline<'_> {
+    StreamingPipeline {
+      filters,
+      dedupe_threshold,
+      scale_width: None,
+      encoder: EncoderOptions::default(),
+      capacity: STAGE_CAPACITY,
+      workers,
+    }
+  }
+
+  // Slow sink so the queues before it fill up
+  #[derive(Default)]
+  struct SlowWriter {
+    bytes: Vec<u8>,
+    writes: usize,
+  }
+
+  impl Write for SlowWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.writes += 1;
+      if self.writes.is_multiple_of(16) {
+        std::thread::sleep(Duration::from_micros(100));
+      }
+      self.bytes.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  struct FailingWriter;
+
+  impl Write for FailingWriter {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+      Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn test_streamed_gif_matches_the_buffered_one() {
+    let chain = filters(&[FilterSpec::Invert]);
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let mut streamed = Vec::new();
+    let summary = pipeline(&chain, Some(0.001), 3)
+      .run(|sink| (0..30).try_for_each(|index| sink(frame(index))), &mut streamed, Some(&directory))
+      .unwrap();
+
+    let (mut frames, stats) = dedupe_frames((0..30).map(frame).collect(), 0.001);
+    chain.apply(&mut frames);
+    let mut buffered = Vec::new();
+    encode_gif_with(&frames, &mut buffered, &EncoderOptions::default()).unwrap();
+
+    assert_eq!(streamed, buffered);
+    assert_eq!(summary.dedupe, Some(stats));
+    assert_eq!(summary.frame_count, frames.len());
+    assert_eq!(summary.duration_ms, 1500);
+    assert_eq!((summary.width, summary.height), (24, 16));
+
+    let manifest = read_manifest(&directory, 1).unwrap();
+    assert_eq!(manifest.frames.len(), frames.len());
+    assert_eq!(manifest.frames[0].delay_ms, frames[0].delay_ms);
+  }
+
+  #[test]
+  fn test_output_order_does_not_depend_on_the_workers() {
+    let chain = filters(&[FilterSpec::Sepia]);
+    let encoder = EncoderOptions {
+      colors: 64,
+      dithering: true,
+      ..Default::default()
+    };
+
+    let mut frames: Vec<Frame> = (0..24).map(frame).collect();
+    for frame in frames.iter_mut() {
+      frame.scale_to_width(Some(12));
+    }
+    chain.apply(&mut frames);
+    let mut buffered = Vec::new();
+    encode_gif_with(&frames, &mut buffered, &encoder).unwrap();
+
+    for workers in [1, 2, 5, 8] {
+      let pipeline = StreamingPipeline {
+        scale_width: Some(12),
+        encoder,
+        ..pipeline(&chain, None, workers)
+      };
+      let mut streamed = Vec::new();
+      let summary = pipeline
+        .run(|sink| (0..24).try_for_each(|index| sink(frame(index))), &mut streamed, None)
+        .unwrap();
+      assert_eq!((summary.width, summary.height), (12, 8));
+      assert!(streamed == buffered, "{} workers changed the GIF", workers);
+    }
+  }
+
+  #[test]
+  fn test_frames_in_flight_stay_under_the_ceiling() {
+    let chain = filters(&[FilterSpec::Grayscale]);
+    let cases = [(40, None, 1), (300, None, 1), (300, Some(0.001), 1), (300, None, 4)];
+    for (count, dedupe, workers) in cases {
+      let mut writer = SlowWriter::default();
+      let summary = pipeline(&chain, dedupe, workers)
+        .run(|sink| (0..count).try_for_each(|index| sink(frame(index))), &mut writer, None)
+        .unwrap();
+
+      // Constant in the clip length, the source is much faster than the sink
+      assert!(
+        summary.peak_frames_in_flight <= frame_ceiling(STAGE_CAPACITY, workers),
+        "{} frames in flight for a {} frame clip",
+        summary.peak_frames_in_flight,
+        count
+      );
+      assert!(summary.peak_frames_in_flight > STAGE_CAPACITY);
+    }
+  }
+
+  #[test]
+  fn test_decode_errors_are_reported() {
+    let chain = filters(&[]);
+    let result = pipeline(&chain, None, 2).run(
+      |sink| {
+        (0..3).try_for_each(|index| sink(frame(index)))?;
+        Err(VideoError::InvalidOption("corrupt packet".to_string()))
+      },
+      Vec::new(),
+      None,
+    );
+    assert!(matches!(result, Err(VideoError::InvalidOption(_))));
+  }
+
+  #[test]
+  fn test_encode_errors_stop_the_decoder() {
+    let chain = filters(&[]);
+    let mut pushed = 0;
+    let result = pipeline(&chain, None, 2).run(
+      |sink| {
+        (0..10_000).try_for_each(|index| {
+          pushed += 1;
+          sink(frame(index))
+        })
+      },
+      FailingWriter,
+      None,
+    );
+    assert!(matches!(result, Err(VideoError::EncodingError(_)) | Err(VideoError::IoError(_))));
+    assert!(pushed < 100, "{} frames decoded after the encoder failed", pushed);
+  }
+
+  #[test]
+  fn test_stages_stop_at_the_deadline() {
+    let chain = filters(&[]);
+    let pipeline = StreamingPipeline {
+      encoder: EncoderOptions {
+        deadline: Some(Instant::now() + Duration::from_millis(50)),
+        ..Default::default()
+      },
+      ..pipeline(&chain, None, 2)
+    };
+    let started = Instant::now();
+    let result = pipeline.run(
+      |sink| {
+        (0..1000).try_for_each(|index| {
+          std::thread::sleep(Duration::from_millis(5));
+          sink(frame(index))
+        })
+      },
+      io::sink(),
+      None,
+    );
+    assert!(matches!(result, Err(VideoError::TimedOut(_))), "{:?}", result.map(|_| ()));
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    // Buffered encodes stop between frames too
+    let late = EncoderOptions {
+      deadline: Some(Instant::now()),
+      ..Default::default()
+    };
+    let encoded = encode_gif_with(&[frame(0)], io::sink(), &late);
+    assert!(matches!(encoded, Err(VideoError::TimedOut(_))));
+  }
+
+  #[test]
+  fn test_empty_source_fails() {
+    let chain = filters(&[]);
+    let result = pipeline(&chain, None, 2).run(|_| Ok(()), Vec::new(), None);
+    assert!(matches!(result, Err(VideoError::NoFrames)));
+  }
+
+  // Benchmark fixture, 1080p frames scaled to 480p, dithered and stored as PNG
+  // like a streamed conversion, with one worker then with one worker per core:
+  // cargo test --release pipeline_benchmark -- --ignored --nocapture
+  #[test]
+  #[ignore]
+  fn test_pipeline_benchmark() {
+    let chain = filters(&[FilterSpec::Contrast {
+      value: 1.2,
+    }]);
+    let source = |sink: &mut dyn FnMut(Frame) -> Result<(), VideoError>| {
+      (0..48).try_for_each(|index: u32| {
+        let image = RgbaImage::from_fn(1920, 1080, |x, y| {
+          let noise = (x.wrapping_mul(31) ^ y.wrapping_mul(17) ^ index.wrapping_mul(13)) % 32;
+          Rgba([(x / 8) as u8, (y / 5) as u8, (noise * 8 + index) as u8, 255])
+        });
+        sink(Frame::new(image, index as i64 * 100, 100))
+      })
+    };
+
+    let cores = worker_count();
+    let mut timings = Vec::new();
+    for workers in [1, cores] {
+      let pipeline = StreamingPipeline {
+        scale_width: Some(480),
+        encoder: EncoderOptions {
+          colors: 128,
+          dithering: true,
+          ..Default::default()
+        },
+        ..pipeline(&chain, None, workers)
+      };
+      let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+      let started = Instant::now();
+      pipeline.run(source, io::sink(), Some(&directory)).unwrap();
+      timings.push(started.elapsed());
+      std::fs::remove_dir_all(&directory).unwrap();
+      println!("{:>2} worker(s): {:?}", workers, started.elapsed());
+    }
+
+    let speedup = timings[0].as_secs_f64() / timings[1].as_secs_f64();
+    println!("speedup with {} workers: {:.2}x", cores, speedup);
+    if cores >= 4 {
+      assert!(speedup > 2.0, "{:.2}x with {} workers", speedup, cores);
+    }
+  }
+}
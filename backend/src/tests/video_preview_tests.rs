@@ -0,0 +1,174 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::ffmpeg::DecodeOptions;
+  use crate::video::video_dto::ConversionRequest;
+  use crate::video::video_encoder::{encode_gif, quantize_fixed};
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_preview::{Preview, PreviewBudget, PreviewCache, PREVIEW_WIDTH};
+  use crate::video::video_recipe::Recipe;
+  use crate::video::video_service::VideoService;
+  use crate::video::video_storage::MediaStorage;
+  use actix_web::web::Bytes;
+  use image::codecs::gif::GifDecoder;
+  use image::{AnimationDecoder, Rgba, RgbaImage};
+  use serde_json::json;
+  use std::fs::File;
+  use std::io::Cursor;
+  use std::time::Duration;
+  use uuid::Uuid;
+
+  fn frames(count: usize, width: u32, height: u32, delay_ms: u32) -> Vec<Frame> {
+    (0..count)
+      .map(|i| {
+        let image = RgbaImage::from_pixel(width, height, Rgba([(i * 40) as u8, 0, 0, 255]));
+        Frame::new(image, i as i64 * delay_ms as i64, delay_ms)
+      })
+      .collect()
+  }
+
+  // Storage holding one animated GIF upload
+  fn upload(frames: &[Frame]) -> (MediaStorage, Uuid) {
+    let storage = MediaStorage::new(std::env::temp_dir().join(Uuid::new_v4().to_string()));
+    let media_id = Uuid::new_v4();
+    std::fs::create_dir_all(storage.media_path(&media_id).parent().unwrap()).unwrap();
+    encode_gif(frames, File::create(storage.media_path(&media_id)).unwrap(), 10).unwrap();
+    (storage, media_id)
+  }
+
+  fn decoded(bytes: &[u8]) -> Vec<RgbaImage> {
+    let decoder = GifDecoder::new(Cursor::new(bytes)).unwrap();
+    let frames = decoder.into_frames().collect::<Result<Vec<_>, _>>().unwrap();
+    frames.into_iter().map(|frame| frame.into_buffer()).collect()
+  }
+
+  fn preview(frame_count: usize) -> Preview {
+    Preview {
+      bytes: Bytes::from(vec![0; 4]),
+      frame_count,
+      truncated: false,
+    }
+  }
+
+  #[test]
+  fn test_fixed_palette_keeps_cube_colours_and_transparency() {
+    let mut image = RgbaImage::from_pixel(4, 2, Rgba([255, 0, 51, 255]));
+    image.put_pixel(0, 0, Rgba([250, 250, 250, 0]));
+    image.put_pixel(1, 0, Rgba([104, 150, 5, 255]));
+    let gif_frame = quantize_fixed(&Frame::new(image, 0, 100)).unwrap();
+
+    let palette = gif_frame.palette.as_ref().unwrap();
+    let colour = |index: u8| &palette[index as usize * 3..index as usize * 3 + 3];
+    assert_eq!(gif_frame.transparent, Some(gif_frame.buffer[0]));
+    assert_eq!(colour(gif_frame.buffer[1]), &[102, 153, 0]);
+    assert_eq!(colour(gif_frame.buffer[2]), &[255, 0, 51]);
+  }
+
+  #[test]
+  fn test_preview_is_scaled_down_and_not_saved() {
+    let (storage, media_id) = upload(&frames(4, 480, 240, 100));
+    let request = ConversionRequest {
+      fps: Some(30),
+      width: Some(1000),
+      dithering: Some(true),
+      ..Default::default()
+    };
+
+    let preview = VideoService::preview(&storage, media_id, &request).unwrap();
+    let images = decoded(&preview.bytes);
+    assert_eq!(images.len(), 4);
+    assert_eq!(preview.frame_count, 4);
+    assert!(!preview.truncated);
+    assert_eq!(images[0].dimensions(), (PREVIEW_WIDTH, 120));
+    // Nothing is written next to the upload
+    assert_eq!(
+      std::fs::read_dir(storage.media_path(&media_id).parent().unwrap()).unwrap().count(),
+      1
+    );
+  }
+
+  #[test]
+  fn test_preview_rejects_multi_clip_conversions() {
+    let (storage, media_id) = upload(&frames(2, 16, 8, 100));
+    let request: ConversionRequest = serde_json::from_value(json!({
+      "segments": [{ "start": 0.0, "end": 0.1 }, { "start": 0.1 }]
+    }))
+    .unwrap();
+    let result = VideoService::preview(&storage, media_id, &request);
+    assert!(matches!(result, Err(VideoError::InvalidOption(_))));
+  }
+
+  #[test]
+  fn test_recipe_preview_runs_its_operations() {
+    let (storage, media_id) = upload(&frames(4, 480, 240, 100));
+    let recipe: Recipe = serde_json::from_value(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [
+        { "op": "crop", "x": 0, "y": 0, "width": 400, "height": 200 },
+        { "op": "filter", "type": "grayscale" }
+      ]
+    }))
+    .unwrap();
+
+    let preview = VideoService::preview_recipe(&storage, Some(media_id), &recipe).unwrap();
+    let images = decoded(&preview.bytes);
+    assert_eq!(images.len(), 4);
+    assert_eq!(images[1].dimensions(), (PREVIEW_WIDTH, 120));
+    let pixel = images[1].get_pixel(10, 10);
+    assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+  }
+
+  #[test]
+  fn test_spent_budget_stops_decoding() {
+    let (storage, media_id) = upload(&frames(3, 16, 8, 100));
+    let mut budget = PreviewBudget::with_duration(Duration::ZERO);
+    let decoded = budget.decode(&storage.media_path(&media_id), &DecodeOptions::default()).unwrap();
+    assert!(decoded.is_empty());
+    assert!(matches!(budget.encode(decoded, PREVIEW_WIDTH), Err(VideoError::NoFrames)));
+
+    let mut budget = PreviewBudget::with_duration(Duration::from_secs(60));
+    let decoded = budget.decode(&storage.media_path(&media_id), &DecodeOptions::default()).unwrap();
+    assert_eq!(decoded.len(), 3);
+    assert!(!budget.encode(decoded, PREVIEW_WIDTH).unwrap().truncated);
+  }
+
+  #[test]
+  fn test_budget_bounds_stretches_without_kept_frames() {
+    let (storage, media_id) = upload(&frames(3000, 16, 8, 20));
+    // Every frame is before the trim, none ever reaches the sink
+    let options = DecodeOptions {
+      start_ms: Some(3_600_000),
+      ..Default::default()
+    };
+    let mut budget = PreviewBudget::with_duration(Duration::from_millis(1));
+    let decoded = budget.decode(&storage.media_path(&media_id), &options).unwrap();
+    assert!(decoded.is_empty());
+    assert!(budget.is_spent());
+  }
+
+  #[test]
+  fn test_cache_expires_entries() {
+    let cache = PreviewCache::new(Duration::from_secs(60), 4);
+    cache.insert("a".to_string(), preview(1));
+    assert_eq!(cache.get("a").map(|preview| preview.frame_count), Some(1));
+    assert!(cache.get("b").is_none());
+
+    let expired = PreviewCache::new(Duration::ZERO, 4);
+    expired.insert("a".to_string(), preview(1));
+    assert!(expired.get("a").is_none());
+  }
+
+  #[test]
+  fn test_full_cache_drops_the_oldest_entry() {
+    let cache = PreviewCache::new(Duration::from_secs(60), 2);
+    cache.insert("a".to_string(), preview(1));
+    std::thread::sleep(Duration::from_millis(2));
+    cache.insert("b".to_string(), preview(2));
+    cache.insert("c".to_string(), preview(3));
+
+    assert!(cache.get("a").is_none());
+    assert_eq!(cache.get("b").map(|preview| preview.frame_count), Some(2));
+    assert_eq!(cache.get("c").map(|preview| preview.frame_count), Some(3));
+  }
+}
@@ -0,0 +1,221 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_progress::{eta_ms, CancelToken, ProgressEvent, ProgressTracker, Stage};
+  use crate::video::video_progress_hub::{
+    job_events, sse_message, ws_stream, ProgressHub, Relay, MAX_CLIENT_FRAME,
+  };
+  use actix_http::ws::{CloseCode, CloseReason, OpCode, Parser};
+  use actix_web::error::PayloadError;
+  use actix_web::web::{Bytes, BytesMut};
+  use futures_util::{stream, StreamExt};
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+  use uuid::Uuid;
+
+  fn tracker(interval: Duration) -> (ProgressTracker, Arc<Mutex<Vec<ProgressEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sent = events.clone();
+    let tracker = ProgressTracker::with_interval(Uuid::new_v4(), interval, move |event| {
+      sent.lock().unwrap().push(event)
+    });
+    (tracker, events)
+  }
+
+  // Frames the server sent, as (opcode, payload)
+  fn server_frames(bytes: &[u8]) -> Vec<(OpCode, Vec<u8>)> {
+    let mut buffer = BytesMut::from(bytes);
+    let mut frames = Vec::new();
+    while let Some((_, opcode, payload)) = Parser::parse(&mut buffer, false, 1 << 20).unwrap() {
+      frames.push((opcode, payload.map(|payload| payload.to_vec()).unwrap_or_default()));
+    }
+    frames
+  }
+
+  // Clients must mask their frames
+  fn client_frame(opcode: OpCode, payload: &[u8]) -> Result<Bytes, PayloadError> {
+    let mut frame = BytesMut::new();
+    Parser::write_message(&mut frame, payload, opcode, true, true);
+    Ok(frame.freeze())
+  }
+
+  #[test]
+  fn test_tracker_throttles_events_within_a_stage() {
+    let (mut tracker, events) = tracker(Duration::from_secs(3600));
+    tracker.start(Stage::Decoding, 10);
+    for _ in 0..9 {
+      tracker.advance(1);
+    }
+    assert_eq!(events.lock().unwrap().len(), 1);
+
+    // The last frame of a stage always goes out
+    tracker.advance(1);
+    tracker.start(Stage::Encoding, 10);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(
+      (events[1].stage, events[1].frames_done, events[1].frames_total),
+      (Stage::Decoding, 10, 10)
+    );
+    assert_eq!((events[2].stage, events[2].frames_done), (Stage::Encoding, 0));
+  }
+
+  #[test]
+  fn test_tracker_total_follows_frames_beyond_the_estimate() {
+    let (mut tracker, _) = tracker(Duration::ZERO);
+    tracker.start(Stage::Decoding, 4);
+    tracker.advance(6);
+    let event = tracker.event();
+    assert_eq!((event.frames_done, event.frames_total), (6, 6));
+
+    tracker.start(Stage::Decoding, 0);
+    tracker.advance(3);
+    assert_eq!(tracker.event().frames_total, 0);
+  }
+
+  #[test]
+  fn test_eta_is_weighted_by_stage() {
+    let elapsed = Duration::from_secs(10);
+    assert_eq!(eta_ms(Stage::Running, 0, 0, elapsed), None);
+    assert_eq!(eta_ms(Stage::Decoding, 0, 100, elapsed), None);
+    // Half of decoding is a quarter of the conversion
+    assert_eq!(eta_ms(Stage::Decoding, 50, 100, elapsed), Some(30_000));
+    assert_eq!(eta_ms(Stage::Encoding, 10, 10, elapsed), Some(0));
+    assert_eq!(eta_ms(Stage::Succeeded, 0, 0, elapsed), Some(0));
+  }
+
+  #[test]
+  fn test_failed_events_keep_a_short_error() {
+    let event = ProgressEvent::failed(Uuid::new_v4(), &"x".repeat(10_000));
+    assert_eq!(event.stage, Stage::Failed);
+    assert_eq!(event.error.unwrap().len(), 1000);
+
+    // A retried job goes back to the queue with the error of its last attempt
+    let event = ProgressEvent::retrying(Uuid::new_v4(), "Connection reset");
+    assert_eq!((event.stage, event.error.as_deref()), (Stage::Queued, Some("Connection reset")));
+    assert!(!event.stage.is_finished());
+
+    let value = serde_json::json!(ProgressEvent::new(Uuid::new_v4(), Stage::Encoding));
+    assert_eq!(value["stage"], "encoding");
+    assert!(value.get("error").is_none());
+  }
+
+  #[actix_web::test]
+  async fn test_job_events_follow_one_job_until_it_finishes() {
+    let hub = ProgressHub::new(16);
+    let job_id = Uuid::new_v4();
+    let events = job_events(
+      ProgressEvent::new(job_id, Stage::Queued),
+      hub.subscribe(),
+      Duration::from_secs(60),
+    );
+    hub.publish(ProgressEvent::new(Uuid::new_v4(), Stage::Decoding));
+    hub.publish(ProgressEvent::new(job_id, Stage::Decoding));
+    hub.publish(ProgressEvent::new(job_id, Stage::Succeeded));
+    hub.publish(ProgressEvent::new(job_id, Stage::Encoding));
+
+    let stages: Vec<_> = events
+      .map(|relay| match relay {
+        Relay::Event(event) => event.stage,
+        Relay::KeepAlive => panic!("unexpected keep-alive"),
+      })
+      .collect()
+      .await;
+    assert_eq!(stages, vec![Stage::Queued, Stage::Decoding, Stage::Succeeded]);
+  }
+
+  #[actix_web::test]
+  async fn test_job_events_keep_quiet_connections_alive() {
+    let hub = ProgressHub::new(16);
+    let first = ProgressEvent::new(Uuid::new_v4(), Stage::Running);
+    let mut events = Box::pin(job_events(first, hub.subscribe(), Duration::from_millis(10)));
+    assert!(matches!(events.next().await, Some(Relay::Event(_))));
+    assert_eq!(events.next().await, Some(Relay::KeepAlive));
+  }
+
+  #[actix_web::test]
+  async fn test_job_events_drop_subscribers_that_fell_behind() {
+    let hub = ProgressHub::new(2);
+    let job_id = Uuid::new_v4();
+    let first = ProgressEvent::new(job_id, Stage::Running);
+    let events = job_events(first, hub.subscribe(), Duration::from_secs(60));
+    for _ in 0..4 {
+      hub.publish(ProgressEvent::new(job_id, Stage::Decoding));
+    }
+    assert_eq!(events.count().await, 1);
+  }
+
+  #[test]
+  fn test_sse_messages() {
+    let event = ProgressEvent::new(Uuid::nil(), Stage::Decoding);
+    let message = sse_message(&Relay::Event(event));
+    let text = std::str::from_utf8(&message).unwrap();
+    assert!(text.starts_with("event: progress\ndata: {"));
+    assert!(text.ends_with("}\n\n"));
+    // The JSON must stay on one data line
+    assert_eq!(text.trim_end().lines().count(), 2);
+    assert_eq!(sse_message(&Relay::KeepAlive), Bytes::from_static(b": keep-alive\n\n"));
+  }
+
+  #[actix_web::test]
+  async fn test_socket_sends_events_then_closes() {
+    let job_id = Uuid::new_v4();
+    let relay = stream::iter(vec![
+      Relay::Event(ProgressEvent::new(job_id, Stage::Encoding)),
+      Relay::KeepAlive,
+      Relay::Event(ProgressEvent::new(job_id, Stage::Succeeded)),
+    ]);
+    let bytes: Vec<u8> = ws_stream(stream::pending::<Result<Bytes, PayloadError>>(), relay)
+      .map(|chunk| chunk.unwrap().to_vec())
+      .concat()
+      .await;
+
+    let frames = server_frames(&bytes);
+    let opcodes: Vec<_> = frames.iter().map(|(opcode, _)| *opcode).collect();
+    assert_eq!(opcodes, vec![OpCode::Text, OpCode::Ping, OpCode::Text, OpCode::Close]);
+    let event: ProgressEvent = serde_json::from_slice(&frames[2].1).unwrap();
+    assert_eq!((event.job_id, event.stage), (job_id, Stage::Succeeded));
+  }
+
+  #[actix_web::test]
+  async fn test_socket_answers_pings_and_client_close() {
+    let payload = stream::iter(vec![
+      client_frame(OpCode::Ping, b"hello"),
+      client_frame(OpCode::Text, b"ignored"),
+      client_frame(OpCode::Close, &1000u16.to_be_bytes()),
+    ]);
+    let bytes: Vec<u8> = ws_stream(payload, stream::pending::<Relay>())
+      .map(|chunk| chunk.unwrap().to_vec())
+      .concat()
+      .await;
+
+    let frames = server_frames(&bytes);
+    assert_eq!(frames[0], (OpCode::Pong, b"hello".to_vec()));
+    assert_eq!(frames[1].0, OpCode::Close);
+    assert_eq!(frames.len(), 2);
+  }
+
+  #[actix_web::test]
+  async fn test_socket_closes_on_oversized_client_frames() {
+    let payload = stream::iter(vec![client_frame(OpCode::Text, &vec![0; MAX_CLIENT_FRAME + 1])]);
+    let bytes: Vec<u8> = ws_stream(payload, stream::pending::<Relay>())
+      .map(|chunk| chunk.unwrap().to_vec())
+      .concat()
+      .await;
+
+    let mut expected = BytesMut::new();
+    Parser::write_close(&mut expected, Some(CloseReason::from(CloseCode::Protocol)), false);
+    assert_eq!(bytes, expected.to_vec());
+  }
+
+  #[test]
+  fn test_checkpoint_fails_once_cancelled() {
+    let cancel = CancelToken::default();
+    let (tracker, _) = tracker(Duration::ZERO);
+    let tracker = tracker.with_cancel(cancel.clone());
+    assert!(tracker.checkpoint().is_ok());
+    cancel.cancel();
+    assert!(matches!(tracker.checkpoint(), Err(VideoError::Cancelled)));
+    assert!(ProgressTracker::none().checkpoint().is_ok());
+  }
+}
@@ -0,0 +1,304 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_encoder::encode_gif;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_limits::MAX_SOURCE_DIMENSION;
+  use crate::video::video_progress::{CancelToken, ProgressEvent, ProgressTracker, Stage};
+  use crate::video::video_recipe::{change_speed, Recipe, RecipeOperation, RecipePipeline};
+  use crate::video::video_service::VideoService;
+  use crate::video::video_storage::MediaStorage;
+  use crate::video::video_text::draw_label;
+  use image::{Rgba, RgbaImage};
+  use serde_json::json;
+  use std::fs::File;
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+  use uuid::Uuid;
+
+  fn frames(count: usize, width: u32, height: u32, delay_ms: u32) -> Vec<Frame> {
+    (0..count)
+      .map(|i| {
+        let image = RgbaImage::from_pixel(width, height, Rgba([(i * 40) as u8, 0, 0, 255]));
+        Frame::new(image, i as i64 * delay_ms as i64, delay_ms)
+      })
+      .collect()
+  }
+
+  fn recipe(value: serde_json::Value) -> Recipe {
+    serde_json::from_value(value).unwrap()
+  }
+
+  fn storage() -> MediaStorage {
+    MediaStorage::new(std::env::temp_dir().join(Uuid::new_v4().to_string()))
+  }
+
+  #[test]
+  fn test_recipe_round_trips_in_canonical_form() {
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [
+        { "start": 1.5, "end": 4.0, "transition": { "type": "crossfade", "duration": 0.5 } },
+        { "media_id": "7f1d7c1e-5a0c-4d5e-9d8e-0a4b3c2d1e0f" }
+      ],
+      "operations": [
+        { "op": "scale", "width": 320 },
+        { "op": "crop", "x": 0, "y": 10, "width": 320, "height": 160 },
+        { "op": "filter", "type": "sepia" },
+        { "op": "filter", "type": "brightness", "value": 0.2 },
+        { "op": "overlay", "type": "text", "text": "HELLO", "x": 4, "y": 4, "scale": 2 },
+        { "op": "speed", "factor": 1.5 }
+      ],
+      "output": { "fps": 12 }
+    }));
+    parsed.validate().unwrap();
+    assert_eq!(parsed.decode_width(), Some(320));
+    assert!(matches!(parsed.operations[2], RecipeOperation::Filter(_)));
+
+    let canonical = serde_json::to_value(&parsed).unwrap();
+    let reparsed = serde_json::to_value(recipe(canonical.clone())).unwrap();
+    assert_eq!(canonical, reparsed);
+    assert_eq!(canonical["operations"][3]["op"], "filter");
+    assert_eq!(canonical["operations"][3]["type"], "brightness");
+    assert_eq!(canonical["output"]["format"], "gif");
+  }
+
+  #[test]
+  fn test_unknown_fields_and_operations_are_rejected() {
+    let typo = json!({ "version": 1, "sources": [{}], "operation": [] });
+    assert!(serde_json::from_value::<Recipe>(typo).is_err());
+
+    let unknown = json!({ "version": 1, "sources": [{}], "operations": [{ "op": "rotate" }] });
+    assert!(serde_json::from_value::<Recipe>(unknown).is_err());
+  }
+
+  #[test]
+  fn test_invalid_recipes_are_rejected() {
+    let cases = [
+      json!({ "version": 2, "sources": [{}] }),
+      json!({ "version": 1, "sources": [] }),
+      json!({ "version": 1, "sources": [{ "start": 3.0, "end": 1.0 }] }),
+      json!({ "version": 1, "sources": [{}], "operations": [{ "op": "speed", "factor": 0.0 }] }),
+      json!({ "version": 1, "sources": [{}], "operations": [{ "op": "scale", "width": 1 }] }),
+      json!({ "version": 1, "sources": [{}], "operations": [
+        { "op": "crop", "x": 0, "y": 0, "width": 0, "height": 4 }
+      ] }),
+      json!({ "version": 1, "sources": [{}], "operations": [
+        { "op": "overlay", "type": "text", "text": "", "x": 0, "y": 0 }
+      ] }),
+      json!({ "version": 1, "sources": [{}], "operations": [
+        { "op": "overlay", "type": "text", "text": "HI", "x": 4294967295u32, "y": 0 }
+      ] }),
+      json!({ "version": 1, "sources": [{}], "operations": [
+        { "op": "overlay", "type": "image", "media_id": Uuid::nil(), "x": 0, "y": 1921 }
+      ] }),
+      json!({ "version": 1, "sources": [{}], "output": { "fps": 0 } }),
+      json!({ "version": 1, "sources": [{}], "output": { "background": "blue" } }),
+    ];
+    for case in cases {
+      let parsed = recipe(case.clone());
+      assert!(
+        matches!(parsed.validate(), Err(VideoError::InvalidOption(_))),
+        "{} should be invalid",
+        case
+      );
+    }
+  }
+
+  #[test]
+  fn test_pipeline_runs_operations_in_order() {
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [
+        { "op": "scale", "width": 40, "height": 20 },
+        { "op": "crop", "x": 10, "y": 5, "width": 20, "height": 10 },
+        { "op": "filter", "type": "invert" },
+        { "op": "dedupe", "threshold": 0.0 }
+      ]
+    }));
+    let pipeline = RecipePipeline::build(&parsed, &storage()).unwrap();
+    assert_eq!(pipeline.filter_names(), vec!["invert"]);
+
+    let (output, dedupe) = pipeline.apply(frames(3, 80, 40, 100)).unwrap();
+    assert_eq!(output.len(), 3);
+    assert_eq!(output[0].image.dimensions(), (20, 10));
+    assert_eq!(output[1].image.get_pixel(0, 0), &Rgba([215, 255, 255, 255]));
+    assert_eq!(dedupe.unwrap().input_frames, 3);
+  }
+
+  #[test]
+  fn test_crop_outside_the_frames_fails() {
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [{ "op": "crop", "x": 8, "y": 0, "width": 8, "height": 8 }]
+    }));
+    let pipeline = RecipePipeline::build(&parsed, &storage()).unwrap();
+    let result = pipeline.apply(frames(1, 10, 10, 100));
+    assert!(matches!(result, Err(VideoError::InvalidOption(_))));
+  }
+
+  #[test]
+  fn test_overlays_are_drawn_on_every_frame() {
+    let storage = storage();
+    let logo_id = Uuid::new_v4();
+    std::fs::create_dir_all(storage.media_path(&logo_id).parent().unwrap()).unwrap();
+    let logo = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+    logo.save_with_format(storage.media_path(&logo_id), image::ImageFormat::Png).unwrap();
+
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [
+        { "op": "overlay", "type": "image", "media_id": logo_id, "x": 10, "y": 10, "opacity": 0.5 },
+        { "op": "overlay", "type": "text", "text": "HI", "x": 0, "y": 0 }
+      ]
+    }));
+    let pipeline = RecipePipeline::build(&parsed, &storage).unwrap();
+    let (output, _) = pipeline.apply(frames(2, 20, 20, 100)).unwrap();
+    for frame in &output {
+      let blended = frame.image.get_pixel(11, 11);
+      assert!(blended.0[2] > 100 && blended.0[2] < 150, "{:?}", blended);
+      assert_eq!(frame.image.get_pixel(2, 2), &Rgba([255, 255, 255, 255]));
+    }
+  }
+
+  #[test]
+  fn test_labels_far_outside_the_frame_are_clipped() {
+    let mut image = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255]));
+    draw_label(&mut image, "FAR AWAY", u32::MAX - 3, u32::MAX - 3, 8);
+    draw_label(&mut image, "EDGE", 6, 6, 8);
+    assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+  }
+
+  #[test]
+  fn test_huge_overlay_images_are_refused() {
+    let storage = storage();
+    let logo_id = Uuid::new_v4();
+    std::fs::create_dir_all(storage.media_path(&logo_id).parent().unwrap()).unwrap();
+    let banner = RgbaImage::from_pixel(MAX_SOURCE_DIMENSION + 1, 1, Rgba([0, 0, 255, 255]));
+    banner.save_with_format(storage.media_path(&logo_id), image::ImageFormat::Png).unwrap();
+
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [{ "op": "overlay", "type": "image", "media_id": logo_id, "x": 0, "y": 0 }]
+    }));
+    let result = RecipePipeline::build(&parsed, &storage);
+    std::fs::remove_dir_all(storage.media_path(&logo_id).parent().unwrap()).unwrap();
+    assert!(matches!(result, Err(VideoError::MediaTooLarge(_))));
+  }
+
+  #[test]
+  fn test_missing_overlay_image_fails() {
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [{}],
+      "operations": [{ "op": "overlay", "type": "image", "media_id": Uuid::new_v4(), "x": 0, "y": 0 }]
+    }));
+    let result = RecipePipeline::build(&parsed, &storage());
+    assert!(matches!(result, Err(VideoError::MediaNotFound)));
+  }
+
+  #[test]
+  fn test_speed_keeps_the_shortest_gif_delay() {
+    let faster = change_speed(frames(4, 2, 2, 100), 2.0);
+    assert_eq!(faster.iter().map(|f| f.delay_ms).collect::<Vec<_>>(), vec![50, 50, 50, 50]);
+    assert_eq!(faster[3].pts_ms, 150);
+
+    // At 8x every other frame is dropped and its time given to the next one
+    let fastest = change_speed(frames(5, 2, 2, 100), 8.0);
+    assert_eq!(fastest.iter().map(|f| f.delay_ms).collect::<Vec<_>>(), vec![25, 38]);
+    assert_eq!(fastest.iter().map(|f| f.pts_ms).collect::<Vec<_>>(), vec![0, 25]);
+
+    let slower = change_speed(frames(2, 2, 2, 100), 0.5);
+    assert_eq!(slower.iter().map(|f| f.delay_ms).collect::<Vec<_>>(), vec![200, 200]);
+
+    let single = change_speed(frames(1, 2, 2, 40), 10.0);
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].delay_ms, 20);
+  }
+
+  #[test]
+  fn test_recipe_output_is_reproducible() {
+    let storage = storage();
+    let media_id = Uuid::new_v4();
+    std::fs::create_dir_all(storage.media_path(&media_id).parent().unwrap()).unwrap();
+    encode_gif(&frames(6, 32, 16, 100), File::create(storage.media_path(&media_id)).unwrap(), 10)
+      .unwrap();
+
+    let parsed = recipe(json!({
+      "version": 1,
+      "sources": [
+        { "end": 0.3, "transition": { "type": "crossfade", "duration": 0.1 } },
+        { "start": 0.3 }
+      ],
+      "operations": [
+        { "op": "scale", "width": 16 },
+        { "op": "filter", "type": "grayscale" },
+        { "op": "speed", "factor": 2.0 }
+      ]
+    }));
+
+    let mut outputs = Vec::new();
+    for _ in 0..2 {
+      let id = Uuid::new_v4();
+      let destination = storage.gif_path(&id);
+      std::fs::create_dir_all(destination.parent().unwrap()).unwrap();
+      let events = Arc::new(Mutex::new(Vec::new()));
+      let sent = events.clone();
+      let mut progress =
+        ProgressTracker::new(id, move |event: ProgressEvent| sent.lock().unwrap().push(event));
+      let response = VideoService::run_recipe(
+        &storage,
+        id,
+        Some(media_id),
+        &destination,
+        &parsed,
+        &mut progress,
+      )
+      .unwrap();
+      assert_eq!((response.width, response.height), (16, 8));
+      assert_eq!(response.filters, vec!["grayscale"]);
+      outputs.push(std::fs::read(destination).unwrap());
+
+      let events = events.lock().unwrap();
+      let mut stages: Vec<_> = events.iter().map(|event| event.stage).collect();
+      stages.dedup();
+      assert_eq!(stages, vec![Stage::Decoding, Stage::Processing, Stage::Encoding]);
+      let last = events.last().unwrap();
+      assert_eq!(last.frames_done, response.frame_count as u64);
+      assert_eq!(last.eta_ms, Some(0));
+    }
+    assert_eq!(outputs[0], outputs[1]);
+  }
+
+  #[test]
+  fn test_cancelled_recipe_stops_at_the_first_frame() {
+    let storage = storage();
+    let media_id = Uuid::new_v4();
+    std::fs::create_dir_all(storage.media_path(&media_id).parent().unwrap()).unwrap();
+    encode_gif(&frames(6, 32, 16, 100), File::create(storage.media_path(&media_id)).unwrap(), 10)
+      .unwrap();
+    let parsed = recipe(json!({ "version": 1, "sources": [{}] }));
+
+    let cancel = CancelToken::default();
+    cancel.cancel();
+    let decoded = Arc::new(Mutex::new(0));
+    let seen = decoded.clone();
+    let mut progress = ProgressTracker::with_interval(
+      Uuid::new_v4(),
+      Duration::ZERO,
+      move |event: ProgressEvent| *seen.lock().unwrap() = event.frames_done,
+    )
+    .with_cancel(cancel);
+    let id = Uuid::new_v4();
+    let destination = storage.gif_path(&id);
+    let result =
+      VideoService::run_recipe(&storage, id, Some(media_id), &destination, &parsed, &mut progress);
+    assert!(matches!(result, Err(VideoError::Cancelled)));
+    assert_eq!(*decoded.lock().unwrap(), 1);
+    assert!(!destination.exists());
+  }
+}
@@ -0,0 +1,228 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_dto::ConversionRequest;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_frame_edit::{FrameEditRequest, FrameOperation};
+  use crate::video::video_frame_store::{latest_version, write_frames};
+  use crate::video::video_job::JobStatus;
+  use crate::video::video_recipe::Recipe;
+  use crate::video::video_render_cache::{
+    conversion_key, recipe_key, recipe_media, render_key, Owner,
+  };
+  use crate::video::video_service::VideoService;
+  use crate::video::video_storage::MediaStorage;
+  use image::{Rgba, RgbaImage};
+  use serde_json::json;
+  use std::collections::HashMap;
+  use uuid::Uuid;
+
+  const CLIP: &str = "7f1d7c1e-5a0c-4d5e-9d8e-0a4b3c2d1e0f";
+  const LUT: &str = "0b8f2f4e-3c6a-4f0e-8a55-6f3e2d1c0b9a";
+
+  fn recipe(value: serde_json::Value) -> Recipe {
+    serde_json::from_value(value).unwrap()
+  }
+
+  fn digests(entries: &[(Uuid, &str)]) -> HashMap<Uuid, String> {
+    entries.iter().map(|(id, digest)| (*id, digest.to_string())).collect()
+  }
+
+  #[test]
+  fn test_every_media_of_a_recipe_is_found() {
+    let graded = recipe(json!({
+      "version": 1,
+      "sources": [{ "start": 1.0 }, { "media_id": CLIP }],
+      "operations": [
+        { "op": "filter", "type": "lut", "media_id": LUT },
+        { "op": "overlay", "type": "image", "media_id": CLIP, "x": 0, "y": 0 },
+      ],
+    }));
+    let media_id = Uuid::new_v4();
+    let (clip, lut) = (Uuid::parse_str(CLIP).unwrap(), Uuid::parse_str(LUT).unwrap());
+    let mut media = recipe_media(&graded, Some(media_id)).unwrap();
+    media.sort();
+    let mut expected = vec![media_id, clip, lut, clip];
+    expected.sort();
+    assert_eq!(media, expected);
+    assert!(matches!(recipe_media(&graded, None), Err(VideoError::InvalidOption(_))));
+  }
+
+  #[test]
+  fn test_keys_follow_the_bytes_rather_than_the_ids() {
+    let trimmed = |media_id: Uuid| {
+      recipe(json!({
+        "version": 1,
+        "sources": [{ "media_id": media_id, "start": 1, "end": 2.5 }],
+      }))
+    };
+    let (first, again) = (Uuid::new_v4(), Uuid::new_v4());
+    let known = digests(&[(first, "aa"), (again, "aa")]);
+    let key = render_key(&trimmed(first), None, &known).unwrap();
+    assert_eq!(key.len(), 64);
+    // The same video uploaded twice
+    assert_eq!(render_key(&trimmed(again), None, &known).unwrap(), key);
+    // Run on the media of the request rather than named
+    let unnamed = recipe(json!({ "version": 1, "sources": [{ "start": 1.0, "end": 2.5 }] }));
+    assert_eq!(render_key(&unnamed, Some(again), &known).unwrap(), key);
+
+    let changed = digests(&[(first, "bb")]);
+    assert_ne!(render_key(&trimmed(first), None, &changed).unwrap(), key);
+    assert!(matches!(
+      render_key(&trimmed(first), None, &HashMap::new()),
+      Err(VideoError::MediaNotFound)
+    ));
+  }
+
+  #[test]
+  fn test_keys_ignore_how_the_recipe_is_written() {
+    let media_id = Uuid::new_v4();
+    let known = digests(&[(media_id, "aa")]);
+    let short = recipe(json!({ "version": 1, "sources": [{ "media_id": media_id }] }));
+    let spelled_out = recipe(json!({
+      "output": { "format": "gif" },
+      "operations": [],
+      "sources": [{ "transition": { "type": "cut" }, "media_id": media_id }],
+      "version": 1,
+    }));
+    let key = render_key(&short, None, &known).unwrap();
+    assert_eq!(render_key(&spelled_out, None, &known).unwrap(), key);
+
+    let faster = recipe(json!({
+      "version": 1,
+      "sources": [{ "media_id": media_id }],
+      "operations": [{ "op": "speed", "factor": 2.0 }],
+    }));
+    assert_ne!(render_key(&faster, None, &known).unwrap(), key);
+  }
+
+  #[actix_web::test]
+  async fn test_digests_are_kept_with_the_upload() {
+    let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let storage = MediaStorage::new(&root);
+    let media_id = Uuid::new_v4();
+    std::fs::create_dir_all(root.join("media")).unwrap();
+    std::fs::write(storage.media_path(&media_id), b"abc").unwrap();
+
+    let unnamed = recipe(json!({ "version": 1, "sources": [{}] }));
+    let key = recipe_key(&storage, &unnamed, Some(media_id)).await;
+    let digest = std::fs::read_to_string(storage.media_digest_path(&media_id));
+    let missing = storage.media_digest(&Uuid::new_v4()).await;
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(digest.unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(key.unwrap().len(), 64);
+    assert!(matches!(missing, Err(VideoError::MediaNotFound)));
+  }
+
+  #[actix_web::test]
+  async fn test_conversions_are_keyed_by_their_sources_and_luts() {
+    let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let storage = MediaStorage::new(&root);
+    let (first, again, lut) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    std::fs::create_dir_all(root.join("media")).unwrap();
+    for (id, bytes) in [(first, "clip"), (again, "clip"), (lut, "LUT_3D_SIZE 2")] {
+      std::fs::write(storage.media_path(&id), bytes).unwrap();
+    }
+    let request = |source: Option<Uuid>, fps: u32| -> ConversionRequest {
+      serde_json::from_value(json!({
+        "fps": fps,
+        "segments": [{ "source": source, "start": 1.0 }, { "source": first }],
+        "filters": [{ "type": "lut", "media_id": lut }],
+      }))
+      .unwrap()
+    };
+
+    let key = conversion_key(&storage, &request(Some(first), 10), None).await.unwrap();
+    // The same video uploaded twice, or the media of the URL
+    let copy = conversion_key(&storage, &request(Some(again), 10), None).await.unwrap();
+    let unnamed = conversion_key(&storage, &request(None, 10), Some(first)).await.unwrap();
+    let faster = conversion_key(&storage, &request(Some(first), 20), None).await.unwrap();
+    let without_source = conversion_key(&storage, &request(None, 10), None).await;
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(key.len(), 64);
+    assert_eq!(copy, key);
+    assert_eq!(unnamed, key);
+    assert_ne!(faster, key);
+    assert!(matches!(without_source, Err(VideoError::InvalidOption(_))));
+  }
+
+  #[test]
+  fn test_only_the_user_of_a_job_deletes_its_gif() {
+    let (alice, bob) = (1, 2);
+    let done = Owner::Job(alice, JobStatus::Succeeded);
+    assert!(done.may_delete(alice).is_ok());
+    assert!(matches!(done.may_delete(bob), Err(VideoError::NotGifOwner)));
+    let running = Owner::Job(alice, JobStatus::Running);
+    assert!(matches!(running.may_delete(alice), Err(VideoError::JobNotFinished)));
+    assert!(matches!(running.may_delete(bob), Err(VideoError::NotGifOwner)));
+    // Anonymous renders, streamed GIFs and edited copies
+    assert!(matches!(Owner::Nobody.may_delete(alice), Err(VideoError::NotGifOwner)));
+    assert!(matches!(Owner::Shared.may_delete(bob), Err(VideoError::NotGifOwner)));
+  }
+
+  #[test]
+  fn test_gifs_of_others_are_edited_as_a_copy() {
+    let (alice, bob) = (1, 2);
+    let done = Owner::Job(alice, JobStatus::Succeeded);
+    assert!(done.edits_in_place(alice).unwrap());
+    assert!(!done.edits_in_place(bob).unwrap());
+    let queued = Owner::Job(alice, JobStatus::Queued);
+    assert!(matches!(queued.edits_in_place(alice), Err(VideoError::JobNotFinished)));
+    assert!(!Owner::Nobody.edits_in_place(alice).unwrap());
+    assert!(!Owner::Shared.edits_in_place(alice).unwrap());
+  }
+
+  #[actix_web::test]
+  async fn test_uncached_gifs_are_removed_from_disk() {
+    let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let storage = MediaStorage::new(&root);
+    let (gif_id, edited_id) = (Uuid::new_v4(), Uuid::new_v4());
+    std::fs::create_dir_all(root.join("gifs")).unwrap();
+    std::fs::write(storage.gif_path(&gif_id), b"GIF89a").unwrap();
+    // An edited copy whose first version is gone still has its later ones
+    std::fs::create_dir_all(storage.versions_dir(&edited_id)).unwrap();
+
+    let found = (storage.has_gif(&gif_id).await, storage.has_gif(&edited_id).await);
+    storage.remove_gif(&gif_id).await.unwrap();
+    storage.remove_gif(&edited_id).await.unwrap();
+    let removed = (storage.has_gif(&gif_id).await, storage.has_gif(&edited_id).await);
+    let missing = storage.has_gif(&Uuid::new_v4()).await;
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!((found.0.unwrap(), found.1.unwrap()), (true, true));
+    assert_eq!((removed.0.unwrap(), removed.1.unwrap()), (false, false));
+    assert!(!missing.unwrap());
+  }
+
+  #[test]
+  fn test_shared_gifs_are_edited_as_a_copy() {
+    let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let storage = MediaStorage::new(&root);
+    let gif_id = Uuid::new_v4();
+    let frames: Vec<Frame> = (0..3)
+      .map(|i| Frame::new(RgbaImage::from_pixel(8, 8, Rgba([i * 80, 0, 0, 255])), 0, 100))
+      .collect();
+    write_frames(&storage.frames_dir(&gif_id), &frames).unwrap();
+    std::fs::create_dir_all(root.join("gifs")).unwrap();
+    std::fs::write(storage.gif_path(&gif_id), b"GIF89a").unwrap();
+
+    let copy_id = VideoService::copy_gif(&storage, gif_id).unwrap();
+    let edit = FrameEditRequest {
+      base_version: None,
+      operations: vec![FrameOperation::Drop {
+        index: 0,
+      }],
+    };
+    let edited = VideoService::edit_frames(&storage, copy_id, &edit).unwrap();
+    let original = latest_version(&storage.frames_dir(&gif_id)).unwrap();
+    let copied = std::fs::read(storage.gif_path(&copy_id)).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_ne!(copy_id, gif_id);
+    assert_eq!((edited.id, edited.version, edited.frame_count), (copy_id, 2, 2));
+    assert_eq!(original, Some(1));
+    assert_eq!(copied, b"GIF89a");
+  }
+}
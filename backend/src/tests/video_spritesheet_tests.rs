@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::ffmpeg::DecodeOptions;
+  use crate::video::video_encoder::encode_gif;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_gif::{decode_gif, is_gif};
+  use crate::video::video_spritesheet::{
+    descriptor, is_atlas_file, pack, Packing, SpritesheetOptions,
+  };
+  use image::{Rgba, RgbaImage};
+  use std::fs::File;
+  use uuid::Uuid;
+
+  fn frames(count: usize, width: u32, height: u32) -> Vec<Frame> {
+    (0..count)
+      .map(|i| {
+        let image = RgbaImage::from_pixel(width, height, Rgba([i as u8 * 20, 0, 0, 255]));
+        Frame::new(image, i as i64 * 100, 100)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_grid_packing_splits_atlases() {
+    let options = SpritesheetOptions {
+      packing: Packing::Grid,
+      max_size: Some(64),
+      padding: Some(0),
+      columns: None,
+    };
+
+    let atlases = pack(&frames(10, 32, 32), &options).unwrap();
+
+    assert_eq!(atlases.len(), 3, "Four 32x32 frames fit in a 64x64 atlas");
+    assert_eq!(atlases[0].image.dimensions(), (64, 64));
+    assert_eq!(atlases[2].sprites.len(), 2);
+    assert_eq!(atlases[2].sprites[1].index, 9);
+    assert_eq!(atlases[0].image.get_pixel(40, 8).0, [20, 0, 0, 255]);
+  }
+
+  #[test]
+  fn test_tight_packing_trims_transparent_borders() {
+    let mut frame = Frame::new(RgbaImage::new(40, 40), 0, 80);
+    for x in 10..20 {
+      for y in 5..25 {
+        frame.image.put_pixel(x, y, Rgba([0, 255, 0, 255]));
+      }
+    }
+    let options = SpritesheetOptions {
+      packing: Packing::Tight,
+      ..Default::default()
+    };
+
+    let atlases = pack(&[frame], &options).unwrap();
+    let data = serde_json::to_value(descriptor(&atlases[0], "atlas_0.png")).unwrap();
+
+    assert_eq!(atlases[0].image.dimensions(), (10, 20));
+    let entry = &data["frames"][0];
+    assert_eq!(entry["filename"], "frame_0000");
+    assert_eq!(entry["trimmed"], true);
+    assert_eq!(entry["spriteSourceSize"]["x"], 10);
+    assert_eq!(entry["spriteSourceSize"]["y"], 5);
+    assert_eq!(entry["sourceSize"]["w"], 40);
+    assert_eq!(entry["duration"], 80);
+    assert_eq!(data["meta"]["size"]["w"], 10);
+  }
+
+  #[test]
+  fn test_oversized_frames_are_rejected() {
+    let options = SpritesheetOptions {
+      max_size: Some(64),
+      ..Default::default()
+    };
+    assert!(pack(&frames(1, 100, 10), &options).is_err());
+  }
+
+  #[test]
+  fn test_gif_input_keeps_its_delays() {
+    let path = std::env::temp_dir().join(format!("{}.gif", Uuid::new_v4()));
+    let mut source = frames(3, 8, 8);
+    source[1].delay_ms = 250;
+    encode_gif(&source, File::create(&path).unwrap(), 10).unwrap();
+
+    assert!(is_gif(&path).unwrap());
+    let decoded = decode_gif(&path, &DecodeOptions::default()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let delays: Vec<u32> = decoded.iter().map(|frame| frame.delay_ms).collect();
+    assert_eq!(delays, vec![100, 250, 100]);
+    assert_eq!(decoded[2].pts_ms, 350);
+  }
+
+  #[test]
+  fn test_only_atlas_files_are_served() {
+    assert!(is_atlas_file("atlas_0.png"));
+    assert!(is_atlas_file("atlas_12.json"));
+    assert!(!is_atlas_file("atlas_.png"));
+    assert!(!is_atlas_file("../atlas_0.png"));
+    assert!(!is_atlas_file("atlas_0.gif"));
+  }
+}
@@ -0,0 +1,193 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_encoder::EncoderOptions;
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_filters::FilterChain;
+  use crate::video::video_frame::Frame;
+  use crate::video::video_pipeline::{StreamingPipeline, STAGE_CAPACITY};
+  use crate::video::video_storage::MediaStorage;
+  use crate::video::video_stream::{gif_chunks, ChunkWriter, Tee, STREAM_CHUNKS};
+  use actix_web::web::Bytes;
+  use futures_util::TryStreamExt;
+  use image::codecs::gif::GifDecoder;
+  use image::{AnimationDecoder, Rgba, RgbaImage};
+  use std::io::{Cursor, ErrorKind, Write};
+  use std::path::PathBuf;
+  use std::thread;
+  use std::time::{Duration, Instant};
+  use tokio::sync::mpsc;
+  use uuid::Uuid;
+
+  fn frame(index: usize) -> Frame {
+    let image = RgbaImage::from_pixel(16, 8, Rgba([(index * 40) as u8, 0, 0, 255]));
+    Frame::new(image, index as i64 * 100, 100)
+  }
+
+  type Source = Box<
+    dyn FnOnce(&mut dyn FnMut(Frame) -> Result<(), VideoError>) -> Result<(), VideoError> + Send,
+  >;
+
+  // How the pipeline runs, and how slowly the client reads its chunks
+  #[derive(Default)]
+  struct Client {
+    deadline: Option<Instant>,
+    frames_dir: Option<PathBuf>,
+    read_delay: Duration,
+  }
+
+  // Runs the pipeline on its own thread and collects the chunks it streams
+  fn stream(
+    source: impl FnOnce(&mut dyn FnMut(Frame) -> Result<(), VideoError>) -> Result<(), VideoError>
+      + Send
+      + 'static,
+  ) -> (Vec<Bytes>, Result<usize, VideoError>) {
+    stream_to(Box::new(source), Client::default())
+  }
+
+  fn stream_to(source: Source, client: Client) -> (Vec<Bytes>, Result<usize, VideoError>) {
+    let capacity = match client.read_delay.is_zero() {
+      true => STREAM_CHUNKS,
+      false => 1,
+    };
+    let (sender, mut receiver) = mpsc::channel(capacity);
+    let encoding = thread::spawn(move || {
+      let storage = MediaStorage::new(std::env::temp_dir().join(Uuid::new_v4().to_string()));
+      let filters = FilterChain::build(&[], &storage)?;
+      let pipeline = StreamingPipeline {
+        filters: &filters,
+        dedupe_threshold: None,
+        scale_width: None,
+        encoder: EncoderOptions {
+          deadline: client.deadline,
+          ..Default::default()
+        },
+        capacity: STAGE_CAPACITY,
+        workers: 2,
+      };
+      let writer = ChunkWriter::new(sender);
+      let result = pipeline.run(source, writer, client.frames_dir.as_deref());
+      result.map(|summary| summary.frame_count)
+    });
+
+    let mut chunks = Vec::new();
+    thread::sleep(client.read_delay);
+    while let Some(chunk) = receiver.blocking_recv() {
+      chunks.push(chunk);
+      thread::sleep(client.read_delay);
+    }
+    (chunks, encoding.join().unwrap())
+  }
+
+  fn decoded_frames(bytes: &[u8]) -> usize {
+    let decoder = GifDecoder::new(Cursor::new(bytes)).unwrap();
+    decoder.into_frames().collect::<Result<Vec<_>, _>>().unwrap().len()
+  }
+
+  #[test]
+  fn test_every_frame_is_sent_as_it_is_encoded() {
+    let (chunks, result) = stream(|sink| (0..5).try_for_each(|index| sink(frame(index))));
+
+    assert_eq!(result.unwrap(), 5);
+    // The header goes with the first frame, the trailer comes last on its own
+    assert_eq!(chunks.len(), 6);
+    assert_eq!(&chunks[0][..6], b"GIF89a");
+    assert_eq!(&chunks[5][..], &[0x3B]);
+    assert_eq!(decoded_frames(&chunks.concat()), 5);
+  }
+
+  #[test]
+  fn test_failures_mid_stream_end_with_a_valid_gif() {
+    let (chunks, result) = stream(|sink| {
+      (0..3).try_for_each(|index| sink(frame(index)))?;
+      Err(VideoError::NoVideoStream)
+    });
+
+    assert!(matches!(result, Err(VideoError::NoVideoStream)));
+    assert_eq!(chunks.last().map(|chunk| chunk.to_vec()), Some(vec![0x3B]));
+    assert_eq!(decoded_frames(&chunks.concat()), 3);
+  }
+
+  #[test]
+  fn test_deadlines_hit_while_encoding_end_with_a_valid_gif() {
+    // The frames are ready at once, the client takes the first ones after the deadline
+    let client = Client {
+      deadline: Some(Instant::now() + Duration::from_millis(50)),
+      read_delay: Duration::from_millis(100),
+      ..Default::default()
+    };
+    let source = |sink: &mut dyn FnMut(Frame) -> Result<(), VideoError>| {
+      (0..4).try_for_each(|index| sink(frame(index)))
+    };
+    let (chunks, result) = stream_to(Box::new(source), client);
+
+    assert!(matches!(result, Err(VideoError::TimedOut(_))));
+    assert_eq!(chunks.last().map(|chunk| chunk.to_vec()), Some(vec![0x3B]));
+    assert_eq!(decoded_frames(&chunks.concat()), 2);
+  }
+
+  #[test]
+  fn test_frame_store_failures_end_with_a_valid_gif() {
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    // The third frame cannot be stored where a directory already is
+    std::fs::create_dir_all(directory.join("frame_00002.png")).unwrap();
+    let client = Client {
+      frames_dir: Some(directory.clone()),
+      ..Default::default()
+    };
+    let source = |sink: &mut dyn FnMut(Frame) -> Result<(), VideoError>| {
+      (0..5).try_for_each(|index| sink(frame(index)))
+    };
+    let (chunks, result) = stream_to(Box::new(source), client);
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert!(result.is_err());
+    assert_eq!(chunks.last().map(|chunk| chunk.to_vec()), Some(vec![0x3B]));
+    assert_eq!(decoded_frames(&chunks.concat()), 2);
+  }
+
+  #[test]
+  fn test_failures_before_the_first_frame_send_nothing() {
+    let (chunks, result) = stream(|_| Err(VideoError::NoVideoStream));
+    assert!(chunks.is_empty());
+    assert!(matches!(result, Err(VideoError::NoVideoStream)));
+  }
+
+  #[test]
+  fn test_writer_reports_a_client_that_went_away() {
+    let (sender, receiver) = mpsc::channel(1);
+    let mut writer = ChunkWriter::new(sender);
+    drop(receiver);
+
+    writer.write_all(b"GIF89a").unwrap();
+    assert_eq!(writer.flush().unwrap_err().kind(), ErrorKind::BrokenPipe);
+    // The encoder can still write its trailer
+    writer.write_all(&[0x3B]).unwrap();
+    assert!(writer.flush().is_err());
+  }
+
+  #[test]
+  fn test_tee_writes_both_sides() {
+    let (sender, mut receiver) = mpsc::channel(1);
+    let mut file = Vec::new();
+    let mut tee = Tee(&mut file, ChunkWriter::new(sender));
+    tee.write_all(b"GIF").unwrap();
+    tee.write_all(b"89a").unwrap();
+    tee.flush().unwrap();
+    drop(tee);
+
+    assert_eq!(file, b"GIF89a");
+    assert_eq!(receiver.blocking_recv().unwrap(), Bytes::from_static(b"GIF89a"));
+  }
+
+  #[actix_web::test]
+  async fn test_body_starts_with_the_first_chunk() {
+    let (sender, receiver) = mpsc::channel(2);
+    sender.send(Bytes::from_static(b"b")).await.unwrap();
+    sender.send(Bytes::from_static(b"c")).await.unwrap();
+    drop(sender);
+
+    let body: Vec<Bytes> =
+      gif_chunks(Bytes::from_static(b"a"), receiver).try_collect().await.unwrap();
+    assert_eq!(body.concat(), b"abc");
+  }
+}
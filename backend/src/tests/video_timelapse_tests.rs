@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_errors::VideoError;
+  use crate::video::video_service::{timelapse_timestamps, MAX_TIMELAPSE_FRAMES};
+
+  #[test]
+  fn test_every_samples_at_a_fixed_interval() {
+    let timestamps = timelapse_timestamps(10_000, 10, Some(2.5), None).unwrap();
+    assert_eq!(timestamps, vec![0, 2500, 5000, 7500]);
+    // A video shorter than the interval still gives its first frame
+    assert_eq!(timelapse_timestamps(1_000, 10, Some(5.0), None).unwrap(), vec![0]);
+  }
+
+  #[test]
+  fn test_target_duration_spreads_frames_over_the_video() {
+    // 2 seconds at 5 fps is 10 frames over 60 seconds
+    let timestamps = timelapse_timestamps(60_000, 5, None, Some(2.0)).unwrap();
+    assert_eq!(timestamps.len(), 10);
+    assert_eq!(timestamps[1], 6000);
+    assert_eq!(*timestamps.last().unwrap(), 54_000);
+    // Too short for a single frame at the output fps, one frame is still taken
+    assert_eq!(timelapse_timestamps(60_000, 5, None, Some(0.01)).unwrap(), vec![0]);
+  }
+
+  #[test]
+  fn test_frames_are_capped() {
+    let interval = 0.1;
+    let duration_ms = MAX_TIMELAPSE_FRAMES * 100;
+    let timestamps = timelapse_timestamps(duration_ms, 10, Some(interval), None).unwrap();
+    assert_eq!(timestamps.len() as u64, MAX_TIMELAPSE_FRAMES);
+
+    let err = timelapse_timestamps(duration_ms + 100, 10, Some(interval), None).unwrap_err();
+    assert!(err.to_string().contains(&format!("the maximum is {}", MAX_TIMELAPSE_FRAMES)));
+  }
+
+  #[test]
+  fn test_small_and_non_positive_intervals_are_rejected() {
+    for every in [0.0005, 0.0, -2.0] {
+      assert!(
+        matches!(
+          timelapse_timestamps(10_000, 10, Some(every), None),
+          Err(VideoError::InvalidOption(_))
+        ),
+        "every {} should be rejected",
+        every
+      );
+    }
+    // More frames than milliseconds
+    assert!(timelapse_timestamps(100, 50, None, Some(10.0)).is_err());
+    assert!(timelapse_timestamps(10_000, 10, None, None).is_err());
+  }
+}
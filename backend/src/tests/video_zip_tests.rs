@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+  use crate::video::video_frame::Frame;
+  use crate::video::video_frame_store::{read_manifest, write_frames};
+  use crate::video::video_zip::{zip_stream, ZipSource, ZipWriter};
+  use chrono::NaiveDate;
+  use futures_util::TryStreamExt;
+  use image::{Rgba, RgbaImage};
+  use uuid::Uuid;
+
+  fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+  }
+
+  fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+  }
+
+  fn modified() -> chrono::NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 5, 17).unwrap().and_hms_opt(13, 45, 30).unwrap()
+  }
+
+  #[test]
+  fn test_archive_layout() {
+    let mut writer = ZipWriter::new(modified());
+    let mut archive = writer.entry("a.txt", b"hello").unwrap();
+    let second_offset = archive.len();
+    archive.extend(writer.entry("b.txt", b"world!").unwrap());
+    let directory_offset = archive.len();
+    archive.extend(writer.finish().unwrap());
+
+    assert_eq!(read_u32(&archive, 0), 0x04034b50);
+    assert_eq!(read_u32(&archive, 14), crc32fast::hash(b"hello"));
+    assert_eq!(&archive[30..35], b"a.txt");
+    assert_eq!(&archive[35..40], b"hello");
+    assert_eq!(read_u16(&archive, 12), (44 << 9) | (5 << 5) | 17, "DOS date");
+
+    let end = archive.len() - 22;
+    assert_eq!(read_u32(&archive, end), 0x06054b50);
+    assert_eq!(read_u16(&archive, end + 10), 2);
+    assert_eq!(read_u32(&archive, end + 16) as usize, directory_offset);
+    assert_eq!(read_u32(&archive, directory_offset), 0x02014b50);
+    let second_entry = directory_offset + 46 + 5;
+    assert_eq!(read_u32(&archive, second_entry + 42) as usize, second_offset);
+  }
+
+  #[actix_web::test]
+  async fn test_stream_matches_the_writer() {
+    let chunks: Vec<_> = zip_stream(
+      vec![
+        ("a.txt".to_string(), ZipSource::Data(b"hello".to_vec())),
+        ("b.txt".to_string(), ZipSource::Data(b"world!".to_vec())),
+      ],
+      modified(),
+    )
+    .try_collect()
+    .await
+    .unwrap();
+
+    let mut writer = ZipWriter::new(modified());
+    let mut expected = writer.entry("a.txt", b"hello").unwrap();
+    expected.extend(writer.entry("b.txt", b"world!").unwrap());
+    expected.extend(writer.finish().unwrap());
+
+    assert_eq!(chunks.len(), 3, "One chunk per entry plus the central directory");
+    assert_eq!(chunks.concat(), expected);
+  }
+
+  #[actix_web::test]
+  async fn test_missing_files_end_the_stream_with_an_error() {
+    let missing = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let result: Result<Vec<_>, _> =
+      zip_stream(vec![("a.png".to_string(), ZipSource::File(missing))], modified())
+        .try_collect()
+        .await;
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_frame_store_round_trip() {
+    let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    let frames: Vec<Frame> = (0..3)
+      .map(|i| Frame::new(RgbaImage::from_pixel(4, 2, Rgba([i, 0, 0, 255])), i as i64 * 80, 80))
+      .collect();
+
+    let written = write_frames(&directory, &frames).unwrap();
+    let manifest = read_manifest(&directory, 1).unwrap();
+    let second = image::open(directory.join(&manifest.frames[1].file)).unwrap().to_rgba8();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(manifest.frames, written.frames);
+    assert_eq!((manifest.width, manifest.height), (4, 2));
+    assert_eq!(manifest.frames[2].pts_ms, 160);
+    assert_eq!(second.get_pixel(0, 0).0, [1, 0, 0, 255]);
+  }
+}
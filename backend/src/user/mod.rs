@@ -1,3 +1,4 @@
 pub mod user;
 pub mod user_controller;
+pub mod user_preset;
 pub mod user_service;
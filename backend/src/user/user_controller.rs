@@ -1,10 +1,15 @@
+use crate::auth::auth_user::CurrentUser;
 use crate::auth::dto::UpdatePasswordRequest;
 use crate::common::errors::UserServiceError;
 use crate::common::responses::ApiResponse;
 use crate::user::user::{User, UserMethods};
+use crate::user::user_preset::{
+  system_preset, system_presets, Preset, PresetMethods, PresetRequest,
+};
 use crate::user::user_service::UserService;
 use actix_web::{web, HttpResponse, Responder};
 use deadpool_postgres::Pool;
+use tokio_postgres::error::SqlState;
 
 pub async fn all_users(pool: web::Data<Pool>) -> impl Responder {
   let client = pool.get().await.expect("Error connecting with database");
@@ -71,3 +76,113 @@ pub async fn delete_user(req: web::Path<String>, pool: web::Data<Pool>) -> impl
     }
   }
 }
+
+// System presets followed by the presets of the user
+pub async fn list_presets(user: CurrentUser, pool: web::Data<Pool>) -> impl Responder {
+  let client = match pool.get().await {
+    Ok(client) => client,
+    Err(_) => return ApiResponse::internal_server_error("Database unavailable"),
+  };
+
+  match Preset::all(&client, user.id).await {
+    Ok(presets) => {
+      let mut all = system_presets();
+      all.extend(presets);
+      ApiResponse::success("Presets retrieved", Some(serde_json::json!(all)))
+    },
+    Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+  }
+}
+
+pub async fn get_preset(
+  user: CurrentUser,
+  path: web::Path<String>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let name = path.into_inner();
+  if let Some(preset) = system_preset(&name) {
+    return ApiResponse::success("Preset retrieved", Some(serde_json::json!(preset)));
+  }
+  let client = match pool.get().await {
+    Ok(client) => client,
+    Err(_) => return ApiResponse::internal_server_error("Database unavailable"),
+  };
+
+  match Preset::find(&client, user.id, &name).await {
+    Ok(Some(preset)) => ApiResponse::success("Preset retrieved", Some(serde_json::json!(preset))),
+    Ok(None) => ApiResponse::not_found("Preset not found"),
+    Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+  }
+}
+
+pub async fn create_preset(
+  user: CurrentUser,
+  body: web::Json<PresetRequest>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  if let Err(err) = body.validate() {
+    return ApiResponse::bad_request(&err.to_string());
+  }
+  let client = match pool.get().await {
+    Ok(client) => client,
+    Err(_) => return ApiResponse::internal_server_error("Database unavailable"),
+  };
+
+  match Preset::create(&client, user.id, &body).await {
+    Ok(preset) => ApiResponse::created("Preset created", Some(serde_json::json!(preset))),
+    Err(err) if err.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+      ApiResponse::conflict("A preset with this name already exists")
+    },
+    Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+  }
+}
+
+// Replaces every setting of a preset, the name can change too
+pub async fn update_preset(
+  user: CurrentUser,
+  path: web::Path<String>,
+  body: web::Json<PresetRequest>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let name = path.into_inner();
+  if system_preset(&name).is_some() {
+    return ApiResponse::forbidden("System presets cannot be changed");
+  }
+  if let Err(err) = body.validate() {
+    return ApiResponse::bad_request(&err.to_string());
+  }
+  let client = match pool.get().await {
+    Ok(client) => client,
+    Err(_) => return ApiResponse::internal_server_error("Database unavailable"),
+  };
+
+  match Preset::update(&client, user.id, &name, &body).await {
+    Ok(Some(preset)) => ApiResponse::success("Preset updated", Some(serde_json::json!(preset))),
+    Ok(None) => ApiResponse::not_found("Preset not found"),
+    Err(err) if err.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+      ApiResponse::conflict("A preset with this name already exists")
+    },
+    Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+  }
+}
+
+pub async fn delete_preset(
+  user: CurrentUser,
+  path: web::Path<String>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let name = path.into_inner();
+  if system_preset(&name).is_some() {
+    return ApiResponse::forbidden("System presets cannot be deleted");
+  }
+  let client = match pool.get().await {
+    Ok(client) => client,
+    Err(_) => return ApiResponse::internal_server_error("Database unavailable"),
+  };
+
+  match Preset::delete(&client, user.id, &name).await {
+    Ok(true) => ApiResponse::no_content(),
+    Ok(false) => ApiResponse::not_found("Preset not found"),
+    Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+  }
+}
@@ -0,0 +1,294 @@
+use crate::video::video_dto::{ConversionRequest, OutputFormat};
+use crate::video::video_errors::VideoError;
+use crate::video::video_service::VideoService;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Error;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+pub const MAX_PRESET_NAME: usize = 50;
+
+// Conversion settings a preset stores, all of them optional
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresetSettings {
+  pub fps: Option<u32>,
+  pub width: Option<u32>,
+  pub palette: Option<u16>, // Colours per GIF frame
+  pub dithering: Option<bool>,
+  pub max_bytes: Option<u64>,
+  pub format: Option<OutputFormat>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Preset {
+  pub id: Option<Uuid>, // System presets are not stored
+  pub name: String,
+  pub system: bool,
+  #[serde(flatten)]
+  pub settings: PresetSettings,
+  pub created_at: Option<DateTime<Utc>>,
+}
+
+// DTO for creating or replacing a preset
+#[derive(Debug, Deserialize)]
+pub struct PresetRequest {
+  pub name: String,
+  #[serde(flatten)]
+  pub settings: PresetSettings,
+}
+
+// Built-in presets sized for the upload limits of each platform
+pub const SYSTEM_PRESET_NAMES: [&str; 5] =
+  ["slack", "discord", "twitter", "github-readme", "emoji-128"];
+
+pub fn system_preset(name: &str) -> Option<Preset> {
+  let settings = match name {
+    "slack" => PresetSettings {
+      fps: Some(15),
+      width: Some(480),
+      palette: Some(256),
+      dithering: Some(true),
+      max_bytes: Some(2 * 1024 * 1024),
+      format: Some(OutputFormat::Gif),
+    },
+    "discord" => PresetSettings {
+      fps: Some(15),
+      width: Some(640),
+      palette: Some(256),
+      dithering: Some(true),
+      max_bytes: Some(10 * 1024 * 1024),
+      format: Some(OutputFormat::Gif),
+    },
+    "twitter" => PresetSettings {
+      fps: Some(15),
+      width: Some(1280),
+      palette: Some(256),
+      dithering: Some(true),
+      max_bytes: Some(15 * 1024 * 1024),
+      format: Some(OutputFormat::Gif),
+    },
+    "github-readme" => PresetSettings {
+      fps: Some(10),
+      width: Some(800),
+      palette: Some(128),
+      dithering: Some(true),
+      max_bytes: Some(10 * 1024 * 1024),
+      format: Some(OutputFormat::Gif),
+    },
+    "emoji-128" => PresetSettings {
+      fps: Some(10),
+      width: Some(128),
+      palette: Some(64),
+      dithering: Some(false),
+      max_bytes: Some(128 * 1024),
+      format: Some(OutputFormat::Gif),
+    },
+    _ => return None,
+  };
+  Some(Preset {
+    id: None,
+    name: name.to_string(),
+    system: true,
+    settings,
+    created_at: None,
+  })
+}
+
+pub fn system_presets() -> Vec<Preset> {
+  SYSTEM_PRESET_NAMES.iter().filter_map(|name| system_preset(name)).collect()
+}
+
+impl PresetSettings {
+  // Options of the request win over the preset
+  pub fn apply_to(&self, request: &mut ConversionRequest) {
+    request.fps = request.fps.or(self.fps);
+    request.width = request.width.or(self.width);
+    request.palette = request.palette.or(self.palette);
+    request.dithering = request.dithering.or(self.dithering);
+    request.max_bytes = request.max_bytes.or(self.max_bytes);
+    request.format = request.format.or(self.format);
+  }
+
+  // Same limits as the options of a conversion request
+  pub fn validate(&self) -> Result<(), VideoError> {
+    let mut request = ConversionRequest::default();
+    self.apply_to(&mut request);
+    VideoService::validate(&request)
+  }
+}
+
+impl PresetRequest {
+  // Names are referenced from conversion requests, system names are reserved
+  pub fn validate(&self) -> Result<(), VideoError> {
+    let valid_name = !self.name.is_empty()
+      && self.name.len() <= MAX_PRESET_NAME
+      && self.name.bytes().all(|byte| {
+        byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_'
+      });
+    if !valid_name {
+      return Err(VideoError::InvalidOption(format!(
+        "preset names use 1 to {} lowercase letters, digits, '-' or '_'",
+        MAX_PRESET_NAME
+      )));
+    }
+    if system_preset(&self.name).is_some() {
+      return Err(VideoError::InvalidOption(format!(
+        "{:?} is the name of a system preset",
+        self.name
+      )));
+    }
+    self.settings.validate()
+  }
+}
+
+// Convert a database Row into a Preset instance
+impl From<Row> for Preset {
+  fn from(row: Row) -> Self {
+    let unsigned = |column: &str| row.get::<_, Option<i32>>(column).map(|value| value as u32);
+    Preset {
+      id: Some(row.get("id")),
+      name: row.get("name"),
+      system: false,
+      settings: PresetSettings {
+        fps: unsigned("fps"),
+        width: unsigned("width"),
+        palette: unsigned("palette").map(|value| value as u16),
+        dithering: row.get("dithering"),
+        max_bytes: row.get::<_, Option<i64>>("max_bytes").map(|value| value as u64),
+        format: row.get::<_, Option<&str>>("format").and_then(OutputFormat::parse),
+      },
+      created_at: Some(row.get("created_at")),
+    }
+  }
+}
+
+const PRESET_COLUMNS: &str =
+  "id, name, fps, width, palette, dithering, max_bytes, format, created_at";
+
+// Presets of one user, system presets never reach the database
+#[async_trait]
+pub trait PresetMethods {
+  async fn all(client: &Client, user_id: i32) -> Result<Vec<Preset>, Error>;
+
+  async fn find(client: &Client, user_id: i32, name: &str) -> Result<Option<Preset>, Error>;
+
+  async fn create(client: &Client, user_id: i32, request: &PresetRequest) -> Result<Preset, Error>;
+
+  async fn update(
+    client: &Client,
+    user_id: i32,
+    name: &str,
+    request: &PresetRequest,
+  ) -> Result<Option<Preset>, Error>;
+
+  async fn delete(client: &Client, user_id: i32, name: &str) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl PresetMethods for Preset {
+  async fn all(client: &Client, user_id: i32) -> Result<Vec<Preset>, Error> {
+    let stmt = client
+      .prepare(&format!("SELECT {} FROM presets WHERE user_id = $1 ORDER BY name", PRESET_COLUMNS))
+      .await?;
+    let rows = client.query(&stmt, &[&user_id]).await?;
+    Ok(rows.into_iter().map(Preset::from).collect())
+  }
+
+  async fn find(client: &Client, user_id: i32, name: &str) -> Result<Option<Preset>, Error> {
+    let stmt = client
+      .prepare(&format!("SELECT {} FROM presets WHERE user_id = $1 AND name = $2", PRESET_COLUMNS))
+      .await?;
+    let row = client.query_opt(&stmt, &[&user_id, &name]).await?;
+    Ok(row.map(Preset::from))
+  }
+
+  async fn create(client: &Client, user_id: i32, request: &PresetRequest) -> Result<Preset, Error> {
+    let stmt = client
+      .prepare(&format!(
+        "INSERT INTO presets (id, user_id, name, fps, width, palette, dithering, max_bytes, format)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+        PRESET_COLUMNS
+      ))
+      .await?;
+    let columns = SettingColumns::from(&request.settings);
+    let row = client
+      .query_one(
+        &stmt,
+        &[
+          &Uuid::new_v4(),
+          &user_id,
+          &request.name,
+          &columns.fps,
+          &columns.width,
+          &columns.palette,
+          &request.settings.dithering,
+          &columns.max_bytes,
+          &columns.format,
+        ],
+      )
+      .await?;
+    Ok(Preset::from(row))
+  }
+
+  async fn update(
+    client: &Client,
+    user_id: i32,
+    name: &str,
+    request: &PresetRequest,
+  ) -> Result<Option<Preset>, Error> {
+    let stmt = client
+      .prepare(&format!(
+        "UPDATE presets SET name = $3, fps = $4, width = $5, palette = $6, dithering = $7,
+         max_bytes = $8, format = $9 WHERE user_id = $1 AND name = $2 RETURNING {}",
+        PRESET_COLUMNS
+      ))
+      .await?;
+    let columns = SettingColumns::from(&request.settings);
+    let row = client
+      .query_opt(
+        &stmt,
+        &[
+          &user_id,
+          &name,
+          &request.name,
+          &columns.fps,
+          &columns.width,
+          &columns.palette,
+          &request.settings.dithering,
+          &columns.max_bytes,
+          &columns.format,
+        ],
+      )
+      .await?;
+    Ok(row.map(Preset::from))
+  }
+
+  async fn delete(client: &Client, user_id: i32, name: &str) -> Result<bool, Error> {
+    let stmt = client.prepare("DELETE FROM presets WHERE user_id = $1 AND name = $2").await?;
+    Ok(client.execute(&stmt, &[&user_id, &name]).await? > 0)
+  }
+}
+
+// Settings in their SQL column types
+struct SettingColumns {
+  fps: Option<i32>,
+  width: Option<i32>,
+  palette: Option<i32>,
+  max_bytes: Option<i64>,
+  format: Option<&'static str>,
+}
+
+impl From<&PresetSettings> for SettingColumns {
+  fn from(settings: &PresetSettings) -> Self {
+    SettingColumns {
+      fps: settings.fps.map(|value| value as i32),
+      width: settings.width.map(|value| value as i32),
+      palette: settings.palette.map(|value| value as i32),
+      max_bytes: settings.max_bytes.map(|value| value as i64),
+      format: settings.format.map(|format| format.as_str()),
+    }
+  }
+}
@@ -1,4 +1,8 @@
-use crate::user::user_controller::{all_users, create_user, delete_user, update_password};
+use crate::user::user_controller::{
+  all_users, create_preset, create_user, delete_preset, delete_user, get_preset, list_presets,
+  update_password, update_preset,
+};
+use crate::video::video_routes::{configure_recipe_routes, configure_webhook_routes};
 use actix_web::web;
 
 pub fn configure_user_routes(cfg: &mut web::ServiceConfig) {
@@ -7,6 +11,16 @@ pub fn configure_user_routes(cfg: &mut web::ServiceConfig) {
       .route("/", web::get().to(all_users))
       .route("/", web::post().to(create_user))
       .route("/{login}", web::delete().to(delete_user))
-      .route("/password", web::put().to(update_password)),
+      .route("/password", web::put().to(update_password))
+      .service(
+        web::scope("/me/presets")
+          .route("", web::get().to(list_presets))
+          .route("", web::post().to(create_preset))
+          .route("/{name}", web::get().to(get_preset))
+          .route("/{name}", web::put().to(update_preset))
+          .route("/{name}", web::delete().to(delete_preset)),
+      )
+      .configure(configure_recipe_routes)
+      .configure(configure_webhook_routes),
   );
 }
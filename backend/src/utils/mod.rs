@@ -2,6 +2,3 @@ pub mod password_controller;
 pub mod password_generator;
 pub mod password_routes;
 pub mod password_validator;
-
-#[cfg(test)]
-pub mod password_validator_tests;
@@ -49,7 +49,11 @@ pub fn generate_password() -> String {
     }
   }
 
+  // The shuffle may bring two equal characters together again
   password.shuffle(&mut rng);
+  while password.windows(2).any(|pair| pair[0] == pair[1]) {
+    password.shuffle(&mut rng);
+  }
 
   String::from_utf8(password).expect("Error generating password")
 }
@@ -0,0 +1,482 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_limits::{
+  check_container, check_deadline, check_duration, check_resolution, DecodeGuard,
+};
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::format::context::Input;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::media::Type;
+use ffmpeg_next::software::scaling::{Context as Scaler, Flags};
+use ffmpeg_next::{codec, frame, Discard, Packet, Rational};
+use image::RgbaImage;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+
+// Read errors in a row before a file is given up on
+const MAX_READ_ERRORS: usize = 16;
+
+// Options applied while decoding a video into frames
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+  pub fps: Option<u32>,   // Sampling rate, every decoded frame is kept when unset
+  pub width: Option<u32>, // Output width, the height keeps the aspect ratio
+  pub start_ms: Option<u64>, // Frames before this timestamp are dropped
+  pub end_ms: Option<u64>, // Decoding stops after this timestamp
+  pub deadline: Option<Instant>, // Decoding fails with `TimedOut` after this
+}
+
+// Metadata of the best video stream of a file
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoInfo {
+  pub width: u32,
+  pub height: u32,
+  pub duration_ms: u64,
+  pub fps: f64,
+  pub frame_count: u64, // Reported by the container, 0 when unknown
+  pub codec: String,
+  pub format: String,
+}
+
+pub fn probe(path: &Path) -> Result<VideoInfo, VideoError> {
+  let source = VideoSource::open(path, None, None)?;
+  let stream = source.input.stream(source.stream_index).ok_or(VideoError::NoVideoStream)?;
+
+  let rate = stream.avg_frame_rate();
+  let fps = match rate.denominator() {
+    0 => 0.0,
+    _ => f64::from(rate),
+  };
+
+  Ok(VideoInfo {
+    width: source.decoder.width(),
+    height: source.decoder.height(),
+    duration_ms: source.duration_ms,
+    fps,
+    frame_count: stream.frames().max(0) as u64,
+    codec: source.codec.name().to_string(),
+    format: source.input.format().name().to_string(),
+  })
+}
+
+// Decode the best video stream of a file into RGBA frames
+pub fn extract_frames(path: &Path, options: &DecodeOptions) -> Result<Vec<Frame>, VideoError> {
+  let mut frames = Vec::new();
+  stream_frames(path, options, |frame| {
+    frames.push(frame);
+    Ok(())
+  })?;
+  Ok(frames)
+}
+
+// Same as `extract_frames` but every frame goes to `sink` as soon as its delay
+// is known, so only one decoded frame is held at a time
+pub fn stream_frames(
+  path: &Path,
+  options: &DecodeOptions,
+  mut sink: impl FnMut(Frame) -> Result<(), VideoError>,
+) -> Result<(), VideoError> {
+  let mut source = VideoSource::open(path, options.width, options.deadline)?;
+  let mut sampler = FrameSampler::new(options.fps, options.start_ms, options.end_ms);
+  let mut delays = DelayAssigner::new(options.fps);
+
+  if let Some(start_ms) = options.start_ms.filter(|&start| start > 0) {
+    source.seek(start_ms)?;
+  }
+
+  while let Some(packet) = source.next_packet()? {
+    if sampler.finished {
+      break;
+    }
+    source.decoder.send_packet(&packet)?;
+    receive_frames(
+      &mut source.decoder,
+      &mut source.scaler,
+      &mut source.guard,
+      source.time_base,
+      &mut sampler,
+      &mut delays,
+      &mut sink,
+    )?;
+  }
+
+  source.decoder.send_eof()?;
+  receive_frames(
+    &mut source.decoder,
+    &mut source.scaler,
+    &mut source.guard,
+    source.time_base,
+    &mut sampler,
+    &mut delays,
+    &mut sink,
+  )?;
+
+  match delays.finish(&mut sink)? {
+    0 => Err(VideoError::NoFrames),
+    _ => Ok(()),
+  }
+}
+
+// Pull one frame near each timestamp by seeking to the closest keyframe,
+// only keyframes are decoded so long videos are never decoded in full
+pub fn extract_frames_at(
+  path: &Path,
+  timestamps_ms: &[u64],
+  width: Option<u32>,
+  deadline: Option<Instant>,
+) -> Result<Vec<Frame>, VideoError> {
+  let mut source = VideoSource::open(path, width, deadline)?;
+  source.decoder.skip_frame(Discard::NonKey);
+
+  let mut frames: Vec<Frame> = Vec::with_capacity(timestamps_ms.len());
+  let mut decoded = frame::Video::empty();
+
+  for &timestamp_ms in timestamps_ms {
+    let last_pts = frames.last().map(|frame| frame.pts_ms);
+    if last_pts.is_some_and(|pts| pts >= timestamp_ms as i64) {
+      continue; // The previous keyframe already covers this timestamp
+    }
+
+    source.seek(timestamp_ms)?;
+    source.decoder.flush();
+
+    let mut found = None;
+    'packets: while let Some(packet) = source.next_packet()? {
+      source.decoder.send_packet(&packet)?;
+
+      while source.decoder.receive_frame(&mut decoded).is_ok() {
+        source.guard.admit(decoded.width(), decoded.height())?;
+        let pts_ms = frame_pts_ms(&decoded, source.time_base).unwrap_or(timestamp_ms as i64);
+        // Seeking back can land on the keyframe that was already taken
+        if last_pts.is_some_and(|pts| pts_ms <= pts) {
+          continue;
+        }
+        let mut rgba = frame::Video::empty();
+        source.scaler.run(&decoded, &mut rgba)?;
+        found = Some(Frame::new(to_rgba_image(&rgba), pts_ms, 0));
+        break 'packets;
+      }
+    }
+
+    match found {
+      Some(frame) => frames.push(frame),
+      None => break, // No keyframe left after this timestamp
+    }
+  }
+
+  if frames.is_empty() {
+    return Err(VideoError::NoFrames);
+  }
+  Ok(frames)
+}
+
+// Pull the first frame at or after each timestamp, seeking to the keyframe
+// before it and decoding forward. Slower than keyframe seeking but every
+// timestamp gets its own frame
+pub fn extract_frames_exact(
+  path: &Path,
+  timestamps_ms: &[u64],
+  width: Option<u32>,
+) -> Result<Vec<Frame>, VideoError> {
+  let mut source = VideoSource::open(path, width, None)?;
+  let mut frames = Vec::with_capacity(timestamps_ms.len());
+  let mut decoded = frame::Video::empty();
+
+  for &timestamp_ms in timestamps_ms {
+    source.seek(timestamp_ms)?;
+    source.decoder.flush();
+
+    let mut found = None;
+    'packets: while let Some(packet) = source.next_packet()? {
+      source.decoder.send_packet(&packet)?;
+
+      while source.decoder.receive_frame(&mut decoded).is_ok() {
+        source.guard.admit(decoded.width(), decoded.height())?;
+        let pts_ms = frame_pts_ms(&decoded, source.time_base).unwrap_or(timestamp_ms as i64);
+        if pts_ms < timestamp_ms as i64 {
+          continue; // Frames between the keyframe and the timestamp
+        }
+        let mut rgba = frame::Video::empty();
+        source.scaler.run(&decoded, &mut rgba)?;
+        found = Some(Frame::new(to_rgba_image(&rgba), pts_ms, 0));
+        break 'packets;
+      }
+    }
+
+    match found {
+      Some(frame) => frames.push(frame),
+      None => break, // No frame left after this timestamp
+    }
+  }
+
+  if frames.is_empty() {
+    return Err(VideoError::NoFrames);
+  }
+  Ok(frames)
+}
+
+// An opened input with the decoder and scaler of its best video stream
+struct VideoSource {
+  input: Input,
+  stream_index: usize,
+  time_base: Rational,
+  duration_ms: u64,
+  codec: codec::Id,
+  decoder: ffmpeg::decoder::Video,
+  scaler: Scaler,
+  guard: DecodeGuard,
+  deadline: Option<Instant>,
+}
+
+impl VideoSource {
+  // The container, codec, resolution and duration are checked before anything
+  // is decoded
+  fn open(path: &Path, width: Option<u32>, deadline: Option<Instant>) -> Result<Self, VideoError> {
+    ffmpeg::init()?;
+
+    let input = open_input(path, deadline)?;
+    let (stream_index, time_base, stream_duration, parameters) = {
+      let stream = input.streams().best(Type::Video).ok_or(VideoError::NoVideoStream)?;
+      (stream.index(), stream.time_base(), stream.duration(), stream.parameters())
+    };
+    let duration_ms = match stream_duration {
+      duration if duration > 0 => to_millis(duration, time_base),
+      _ => input.duration().max(0) / 1000, // Container duration is in microseconds
+    };
+
+    let codec = parameters.id();
+    check_container(input.format().name(), codec.name())?;
+    check_duration(duration_ms.max(0) as u64)?;
+    let context = ffmpeg::codec::context::Context::from_parameters(parameters)?;
+    let decoder = context.decoder().video()?;
+    check_resolution(decoder.width(), decoder.height())?;
+
+    let (out_width, out_height) = output_size(decoder.width(), decoder.height(), width);
+    let scaler = Scaler::get(
+      decoder.format(),
+      decoder.width(),
+      decoder.height(),
+      Pixel::RGBA,
+      out_width,
+      out_height,
+      Flags::BILINEAR,
+    )?;
+
+    Ok(VideoSource {
+      input,
+      stream_index,
+      time_base,
+      duration_ms: duration_ms.max(0) as u64,
+      codec,
+      decoder,
+      scaler,
+      guard: DecodeGuard::new(deadline),
+      deadline,
+    })
+  }
+
+  // Next packet of the video stream, `None` at the end of the file. Unlike
+  // `Input::packets` read errors are not retried forever, and every read fails
+  // once the interrupt callback sees the deadline
+  fn next_packet(&mut self) -> Result<Option<Packet>, VideoError> {
+    let mut errors = 0;
+    loop {
+      check_deadline(self.deadline)?;
+      let mut packet = Packet::empty();
+      match packet.read(&mut self.input) {
+        Ok(()) if packet.stream() == self.stream_index => return Ok(Some(packet)),
+        Ok(()) => errors = 0,
+        Err(ffmpeg::Error::Eof) => return Ok(None),
+        Err(_) if errors < MAX_READ_ERRORS => errors += 1,
+        Err(err) => return Err(err.into()),
+      }
+    }
+  }
+
+  // To the keyframe before `timestamp_ms`
+  fn seek(&mut self, timestamp_ms: u64) -> Result<(), VideoError> {
+    let target = timestamp_ms as i64 * 1000; // Seek positions are in microseconds
+    self.input.seek(target, ..target).map_err(|err| interrupted(self.deadline, err))
+  }
+}
+
+// Blocking reads of the demuxer give up once the deadline has passed, so a
+// stalled file or a slow seek never outlives the conversion
+fn open_input(path: &Path, deadline: Option<Instant>) -> Result<Input, VideoError> {
+  let input = match deadline {
+    Some(deadline) => {
+      ffmpeg::format::input_with_interrupt(path, move || Instant::now() >= deadline)
+    },
+    None => ffmpeg::format::input(path),
+  };
+  input.map_err(|err| interrupted(deadline, err))
+}
+
+// Reads cut short by the interrupt callback are reported as a timeout
+fn interrupted(deadline: Option<Instant>, err: ffmpeg::Error) -> VideoError {
+  match check_deadline(deadline) {
+    Err(timed_out) => timed_out,
+    Ok(()) => err.into(),
+  }
+}
+
+fn receive_frames(
+  decoder: &mut ffmpeg::decoder::Video,
+  scaler: &mut Scaler,
+  guard: &mut DecodeGuard,
+  time_base: Rational,
+  sampler: &mut FrameSampler,
+  delays: &mut DelayAssigner,
+  sink: &mut impl FnMut(Frame) -> Result<(), VideoError>,
+) -> Result<(), VideoError> {
+  let mut decoded = frame::Video::empty();
+
+  while decoder.receive_frame(&mut decoded).is_ok() {
+    guard.admit(decoded.width(), decoded.height())?;
+    let pts_ms = frame_pts_ms(&decoded, time_base)
+      .unwrap_or_else(|| delays.last_pts_ms().map(|last| last + 1).unwrap_or(0));
+
+    if !sampler.accept(pts_ms) {
+      continue;
+    }
+
+    let mut rgba = frame::Video::empty();
+    scaler.run(&decoded, &mut rgba)?;
+    delays.push(Frame::new(to_rgba_image(&rgba), pts_ms, 0), sink)?;
+  }
+
+  Ok(())
+}
+
+fn frame_pts_ms(decoded: &frame::Video, time_base: Rational) -> Option<i64> {
+  decoded.timestamp().or(decoded.pts()).map(|pts| to_millis(pts, time_base))
+}
+
+// Copy an RGBA ffmpeg frame into an image buffer, dropping the line padding
+fn to_rgba_image(rgba: &frame::Video) -> RgbaImage {
+  let (width, height) = (rgba.width(), rgba.height());
+  let stride = rgba.stride(0);
+  let row_len = width as usize * 4;
+  let data = rgba.data(0);
+
+  let mut pixels = Vec::with_capacity(row_len * height as usize);
+  for row in 0..height as usize {
+    pixels.extend_from_slice(&data[row * stride..row * stride + row_len]);
+  }
+
+  RgbaImage::from_raw(width, height, pixels).expect("RGBA buffer has the frame dimensions")
+}
+
+// Each frame lasts until the next one, the last one gets the sampling interval.
+// Frames are held back by one so their delay is known when they are emitted
+struct DelayAssigner {
+  pending: Option<Frame>,
+  fallback_ms: u32,
+  emitted: usize,
+}
+
+impl DelayAssigner {
+  fn new(fps: Option<u32>) -> Self {
+    DelayAssigner {
+      pending: None,
+      fallback_ms: fps.map(|fps| 1000 / fps.max(1)).unwrap_or(100),
+      emitted: 0,
+    }
+  }
+
+  fn last_pts_ms(&self) -> Option<i64> {
+    self.pending.as_ref().map(|frame| frame.pts_ms)
+  }
+
+  fn push(
+    &mut self,
+    frame: Frame,
+    sink: &mut impl FnMut(Frame) -> Result<(), VideoError>,
+  ) -> Result<(), VideoError> {
+    let next_pts_ms = frame.pts_ms;
+    if let Some(mut previous) = self.pending.replace(frame) {
+      previous.delay_ms = (next_pts_ms - previous.pts_ms).max(0) as u32;
+      self.emitted += 1;
+      sink(previous)?;
+    }
+    Ok(())
+  }
+
+  // Emits the last frame, returns the number of frames emitted
+  fn finish(
+    &mut self,
+    sink: &mut impl FnMut(Frame) -> Result<(), VideoError>,
+  ) -> Result<usize, VideoError> {
+    if let Some(mut last) = self.pending.take() {
+      last.delay_ms = self.fallback_ms;
+      self.emitted += 1;
+      sink(last)?;
+    }
+    Ok(self.emitted)
+  }
+}
+
+pub fn to_millis(timestamp: i64, time_base: Rational) -> i64 {
+  (timestamp as f64 * f64::from(time_base) * 1000.0).round() as i64
+}
+
+// Scale to the requested width while keeping the aspect ratio and even dimensions
+pub fn output_size(width: u32, height: u32, target_width: Option<u32>) -> (u32, u32) {
+  match target_width {
+    Some(target) if target > 0 && target < width => {
+      let scaled_height = (height as u64 * target as u64 / width as u64) as u32;
+      (target, scaled_height.max(2) & !1)
+    },
+    _ => (width, height),
+  }
+}
+
+// Keeps frames inside the trim window at a fixed rate based on their presentation timestamp
+struct FrameSampler {
+  interval_ms: Option<f64>,
+  next_ms: Option<f64>,
+  start_ms: i64,
+  end_ms: Option<i64>,
+  finished: bool, // Set once a frame past the trim window was seen
+}
+
+impl FrameSampler {
+  fn new(fps: Option<u32>, start_ms: Option<u64>, end_ms: Option<u64>) -> Self {
+    FrameSampler {
+      interval_ms: fps.map(|fps| 1000.0 / fps.max(1) as f64),
+      next_ms: None,
+      start_ms: start_ms.unwrap_or(0) as i64,
+      end_ms: end_ms.map(|end| end as i64),
+      finished: false,
+    }
+  }
+
+  fn accept(&mut self, pts_ms: i64) -> bool {
+    if pts_ms < self.start_ms {
+      return false;
+    }
+    if self.end_ms.is_some_and(|end| pts_ms >= end) {
+      self.finished = true;
+      return false;
+    }
+    let Some(interval) = self.interval_ms else {
+      return true;
+    };
+
+    let pts = pts_ms as f64;
+    match self.next_ms {
+      Some(next) if pts < next => false,
+      Some(next) => {
+        // Skip the slots that were missed so the rate never bursts
+        let missed = ((pts - next) / interval).floor();
+        self.next_ms = Some(next + (missed + 1.0) * interval);
+        true
+      },
+      None => {
+        self.next_ms = Some(pts + interval);
+        true
+      },
+    }
+  }
+}
@@ -0,0 +1,44 @@
+pub mod ffmpeg;
+pub mod video_canvas;
+pub mod video_concat;
+pub mod video_contact_sheet;
+pub mod video_controller;
+pub mod video_cost;
+pub mod video_dedupe;
+pub mod video_dto;
+pub mod video_encoder;
+pub mod video_errors;
+pub mod video_filters;
+pub mod video_frame;
+pub mod video_frame_edit;
+pub mod video_frame_store;
+pub mod video_gif;
+pub mod video_idempotency;
+pub mod video_idempotency_repository;
+pub mod video_job;
+pub mod video_job_controller;
+pub mod video_job_repository;
+pub mod video_job_schedule;
+pub mod video_job_worker;
+pub mod video_layout;
+pub mod video_limits;
+pub mod video_lut;
+pub mod video_pipeline;
+pub mod video_preview;
+pub mod video_progress;
+pub mod video_progress_hub;
+pub mod video_recipe;
+pub mod video_recipe_repository;
+pub mod video_render_cache;
+pub mod video_render_cache_repository;
+pub mod video_routes;
+pub mod video_service;
+pub mod video_spritesheet;
+pub mod video_storage;
+pub mod video_stream;
+pub mod video_text;
+pub mod video_webhook;
+pub mod video_webhook_controller;
+pub mod video_webhook_repository;
+pub mod video_webhook_worker;
+pub mod video_zip;
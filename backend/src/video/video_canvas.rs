@@ -0,0 +1,71 @@
+use crate::video::video_errors::VideoError;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+
+pub const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+// A pixel area of a canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+// Parse a `#rrggbb` or `#rrggbbaa` colour
+pub fn parse_color(value: &str) -> Result<Rgba<u8>, VideoError> {
+  let invalid = || VideoError::InvalidOption(format!("invalid colour {:?}", value));
+  let hex = value.strip_prefix('#').unwrap_or(value);
+  if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+    return Err(invalid());
+  }
+
+  let mut channels = [255u8; 4];
+  for (index, channel) in channels.iter_mut().take(hex.len() / 2).enumerate() {
+    *channel = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+  }
+  Ok(Rgba(channels))
+}
+
+// Scale an image to fit inside the canvas and center it, the rest is filled with the background
+pub fn fit_to_canvas(
+  image: &RgbaImage,
+  width: u32,
+  height: u32,
+  background: Rgba<u8>,
+) -> RgbaImage {
+  if image.dimensions() == (width, height) {
+    return image.clone();
+  }
+
+  let scale = f64::min(width as f64 / image.width() as f64, height as f64 / image.height() as f64);
+  let scaled_width = ((image.width() as f64 * scale).round() as u32).clamp(1, width);
+  let scaled_height = ((image.height() as f64 * scale).round() as u32).clamp(1, height);
+  let scaled = imageops::resize(image, scaled_width, scaled_height, FilterType::Triangle);
+
+  let mut canvas = RgbaImage::from_pixel(width, height, background);
+  let x = (width - scaled_width) / 2;
+  let y = (height - scaled_height) / 2;
+  imageops::replace(&mut canvas, &scaled, x as i64, y as i64);
+  canvas
+}
+
+// Linear mix of two images of the same size, `amount` 0.0 is `from` and 1.0 is `to`
+pub fn blend(from: &RgbaImage, to: &RgbaImage, amount: f32) -> RgbaImage {
+  let amount = amount.clamp(0.0, 1.0);
+  let mut result = from.clone();
+  for (pixel, target) in result.pixels_mut().zip(to.pixels()) {
+    for channel in 0..4 {
+      let value = pixel.0[channel] as f32 * (1.0 - amount) + target.0[channel] as f32 * amount;
+      pixel.0[channel] = value.round() as u8;
+    }
+  }
+  result
+}
+
+// Linear mix of an image with a solid colour
+pub fn blend_color(image: &RgbaImage, color: Rgba<u8>, amount: f32) -> RgbaImage {
+  let solid = RgbaImage::from_pixel(image.width(), image.height(), color);
+  blend(image, &solid, amount)
+}
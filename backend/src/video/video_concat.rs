@@ -0,0 +1,177 @@
+use crate::video::video_canvas::{blend, blend_color, fit_to_canvas, parse_color, BLACK};
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use image::Rgba;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const MAX_SEGMENTS: usize = 20;
+pub const MAX_TRANSITION_SECONDS: f64 = 5.0;
+
+// DTO for one clip of a multi-clip conversion, times are in seconds
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SegmentSpec {
+  pub source: Option<Uuid>, // Uploaded media, defaults to the media of the URL
+  pub start: Option<f64>,
+  pub end: Option<f64>,
+  #[serde(default)]
+  pub transition: Transition, // Into the next segment, ignored on the last one
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transition {
+  #[default]
+  Cut,
+  Crossfade {
+    duration: f64, // The two clips overlap for this long
+  },
+  DipToColor {
+    duration: f64,         // Fade out then fade in, half of it on each side
+    color: Option<String>, // `#rrggbb`, black by default
+  },
+}
+
+impl SegmentSpec {
+  pub fn validate(&self) -> Result<(), VideoError> {
+    let start = self.start.unwrap_or(0.0);
+    if !start.is_finite() || start < 0.0 {
+      return Err(VideoError::InvalidOption("segment start must be positive".to_string()));
+    }
+    if let Some(end) = self.end {
+      if !end.is_finite() || end <= start {
+        return Err(VideoError::InvalidOption("segment end must be after its start".to_string()));
+      }
+    }
+
+    let (duration, color) = match &self.transition {
+      Transition::Cut => return Ok(()),
+      Transition::Crossfade {
+        duration,
+      } => (*duration, None),
+      Transition::DipToColor {
+        duration,
+        color,
+      } => (*duration, color.as_deref()),
+    };
+    if !(duration > 0.0 && duration <= MAX_TRANSITION_SECONDS) {
+      return Err(VideoError::InvalidOption(format!(
+        "transition duration must be between 0 and {} seconds",
+        MAX_TRANSITION_SECONDS
+      )));
+    }
+    if let Some(color) = color {
+      parse_color(color)?;
+    }
+    Ok(())
+  }
+
+  pub fn start_ms(&self) -> Option<u64> {
+    self.start.map(|start| (start * 1000.0).round() as u64)
+  }
+
+  pub fn end_ms(&self) -> Option<u64> {
+    self.end.map(|end| (end * 1000.0).round() as u64)
+  }
+}
+
+// The decoded frames of a segment and how it leads into the next one
+pub struct Clip {
+  pub frames: Vec<Frame>,
+  pub transition: Transition,
+}
+
+// Join clips on the canvas of the first one, smaller or differently shaped
+// clips are letterboxed with the background colour
+pub fn concat_clips(clips: Vec<Clip>, background: Rgba<u8>) -> Result<Vec<Frame>, VideoError> {
+  let first = clips.first().and_then(|clip| clip.frames.first()).ok_or(VideoError::NoFrames)?;
+  let (width, height) = (first.width(), first.height());
+
+  let mut output: Vec<Frame> = Vec::new();
+  let mut pending = Transition::Cut;
+
+  for clip in clips {
+    let mut frames = clip.frames;
+    for frame in frames.iter_mut() {
+      frame.image = fit_to_canvas(&frame.image, width, height, background);
+    }
+
+    match pending {
+      Transition::Cut => output.extend(frames),
+      Transition::Crossfade {
+        duration,
+      } => crossfade(&mut output, frames, to_millis(duration)),
+      Transition::DipToColor {
+        duration,
+        ref color,
+      } => {
+        let color = match color {
+          Some(color) => parse_color(color)?,
+          None => BLACK,
+        };
+        dip_to_color(&mut output, frames, to_millis(duration), color);
+      },
+    }
+    pending = clip.transition;
+  }
+
+  // Timestamps restart from zero on the joined timeline
+  let mut pts_ms = 0i64;
+  for frame in output.iter_mut() {
+    frame.pts_ms = pts_ms;
+    pts_ms += frame.delay_ms as i64;
+  }
+  Ok(output)
+}
+
+fn to_millis(seconds: f64) -> u64 {
+  (seconds * 1000.0).round() as u64
+}
+
+// Number of frames at the edge of a clip that fit in `duration_ms`
+fn frames_within<'a>(frames: impl Iterator<Item = &'a Frame>, duration_ms: u64) -> usize {
+  let mut elapsed = 0u64;
+  frames
+    .take_while(|frame| {
+      elapsed += frame.delay_ms as u64;
+      elapsed <= duration_ms
+    })
+    .count()
+}
+
+// The tail of the output and the head of the next clip are mixed into one overlap
+fn crossfade(output: &mut Vec<Frame>, next: Vec<Frame>, duration_ms: u64) {
+  let overlap =
+    frames_within(output.iter().rev(), duration_ms).min(frames_within(next.iter(), duration_ms));
+  if overlap == 0 {
+    output.extend(next);
+    return;
+  }
+
+  let start = output.len() - overlap;
+  for (index, incoming) in next.iter().take(overlap).enumerate() {
+    let amount = (index + 1) as f32 / (overlap + 1) as f32;
+    let frame = &mut output[start + index];
+    frame.image = blend(&frame.image, &incoming.image, amount);
+  }
+  output.extend(next.into_iter().skip(overlap));
+}
+
+// The output fades out to the colour and the next clip fades in from it
+fn dip_to_color(output: &mut Vec<Frame>, mut next: Vec<Frame>, duration_ms: u64, color: Rgba<u8>) {
+  let half_ms = duration_ms / 2;
+
+  let fade_out = frames_within(output.iter().rev(), half_ms);
+  let start = output.len() - fade_out;
+  for (index, frame) in output[start..].iter_mut().enumerate() {
+    let amount = (index + 1) as f32 / fade_out as f32;
+    frame.image = blend_color(&frame.image, color, amount);
+  }
+
+  let fade_in = frames_within(next.iter(), half_ms);
+  for (index, frame) in next[..fade_in].iter_mut().enumerate() {
+    let amount = (fade_in - index) as f32 / (fade_in + 1) as f32;
+    frame.image = blend_color(&frame.image, color, amount);
+  }
+  output.extend(next);
+}
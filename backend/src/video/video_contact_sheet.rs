@@ -0,0 +1,164 @@
+use crate::video::video_canvas::{fit_to_canvas, Rect};
+use crate::video::video_dto::{ContactSheetQuery, SheetFormat};
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_text::{draw_label, label_scale, label_size};
+use image::{imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+
+pub const DEFAULT_COLUMNS: u32 = 5;
+pub const DEFAULT_ROWS: u32 = 4;
+pub const MAX_GRID_SIZE: u32 = 10;
+pub const DEFAULT_TILE_WIDTH: u32 = 160;
+pub const MAX_TILE_WIDTH: u32 = 640;
+
+const SHEET_BACKGROUND: Rgba<u8> = Rgba([16, 16, 16, 255]);
+
+// Resolved contact sheet options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetGrid {
+  pub columns: u32,
+  pub rows: u32,
+  pub tile_width: u32,
+  pub labels: bool,
+}
+
+impl SheetGrid {
+  pub fn from_query(query: &ContactSheetQuery) -> Result<Self, VideoError> {
+    let grid = SheetGrid {
+      columns: query.cols.unwrap_or(DEFAULT_COLUMNS),
+      rows: query.rows.unwrap_or(DEFAULT_ROWS),
+      tile_width: query.width.unwrap_or(DEFAULT_TILE_WIDTH),
+      labels: query.labels.unwrap_or(true),
+    };
+    if !(1..=MAX_GRID_SIZE).contains(&grid.columns) || !(1..=MAX_GRID_SIZE).contains(&grid.rows) {
+      return Err(VideoError::InvalidOption(format!(
+        "cols and rows must be between 1 and {}",
+        MAX_GRID_SIZE
+      )));
+    }
+    if !(16..=MAX_TILE_WIDTH).contains(&grid.tile_width) {
+      return Err(VideoError::InvalidOption(format!(
+        "width must be between 16 and {}",
+        MAX_TILE_WIDTH
+      )));
+    }
+    Ok(grid)
+  }
+
+  pub fn count(&self) -> usize {
+    (self.columns * self.rows) as usize
+  }
+
+  // Identifies the rendered sheet among the cached ones of a video
+  pub fn cache_key(&self) -> String {
+    format!("{}x{}_{}_{}", self.columns, self.rows, self.tile_width, self.labels as u8)
+  }
+
+  // Query string of the sheet image, used by the WebVTT track
+  pub fn image_query(&self, format: SheetFormat) -> String {
+    let format = match format {
+      SheetFormat::Jpeg => "jpeg",
+      _ => "png",
+    };
+    format!(
+      "cols={}&rows={}&width={}&labels={}&format={}",
+      self.columns, self.rows, self.tile_width, self.labels, format
+    )
+  }
+
+  pub fn tile(&self, index: usize, tile_width: u32, tile_height: u32) -> Rect {
+    let index = index as u32;
+    Rect {
+      x: (index % self.columns) * tile_width,
+      y: (index / self.columns) * tile_height,
+      width: tile_width,
+      height: tile_height,
+    }
+  }
+}
+
+// One timestamp in the middle of each of `count` equal slices of the video
+pub fn sample_timestamps(duration_ms: u64, count: usize) -> Vec<u64> {
+  let slice = duration_ms as f64 / count.max(1) as f64;
+  (0..count).map(|index| ((index as f64 + 0.5) * slice) as u64).collect()
+}
+
+// Tile the frames row by row, missing frames leave their tile empty
+pub fn render(frames: &[Frame], grid: &SheetGrid) -> Result<RgbaImage, VideoError> {
+  let first = frames.first().ok_or(VideoError::NoFrames)?;
+  let (tile_width, tile_height) = (first.width(), first.height());
+  let mut sheet =
+    RgbaImage::from_pixel(grid.columns * tile_width, grid.rows * tile_height, SHEET_BACKGROUND);
+
+  let scale = label_scale(tile_height);
+  for (index, frame) in frames.iter().take(grid.count()).enumerate() {
+    let mut tile = fit_to_canvas(&frame.image, tile_width, tile_height, SHEET_BACKGROUND);
+    if grid.labels {
+      let text = format_label(frame.pts_ms.max(0) as u64);
+      let (_, label_height) = label_size(&text, scale);
+      let y = tile_height.saturating_sub(label_height + scale);
+      draw_label(&mut tile, &text, scale, y, scale);
+    }
+    let rect = grid.tile(index, tile_width, tile_height);
+    imageops::replace(&mut sheet, &tile, rect.x as i64, rect.y as i64);
+  }
+  Ok(sheet)
+}
+
+pub fn encode(sheet: RgbaImage, format: SheetFormat) -> Result<Vec<u8>, VideoError> {
+  let mut bytes = Cursor::new(Vec::new());
+  match format {
+    // JPEG has no alpha channel
+    SheetFormat::Jpeg => {
+      DynamicImage::ImageRgba8(sheet).to_rgb8().write_to(&mut bytes, ImageFormat::Jpeg)?
+    },
+    _ => sheet.write_to(&mut bytes, ImageFormat::Png)?,
+  }
+  Ok(bytes.into_inner())
+}
+
+// WebVTT thumbnails track, each cue covers one slice of the video and points
+// at its tile with a media fragment
+pub fn webvtt(
+  duration_ms: u64,
+  grid: &SheetGrid,
+  tile_size: (u32, u32),
+  image_url: &str,
+) -> String {
+  let count = grid.count();
+  let slice = duration_ms as f64 / count as f64;
+  let mut track = String::from("WEBVTT\n");
+
+  for index in 0..count {
+    let start = (index as f64 * slice) as u64;
+    let end = ((index + 1) as f64 * slice) as u64;
+    let rect = grid.tile(index, tile_size.0, tile_size.1);
+    track.push_str(&format!(
+      "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+      format_vtt_time(start),
+      format_vtt_time(end),
+      image_url,
+      rect.x,
+      rect.y,
+      rect.width,
+      rect.height
+    ));
+  }
+  track
+}
+
+// `HH:MM:SS.mmm` as required by WebVTT
+pub fn format_vtt_time(ms: u64) -> String {
+  let seconds = ms / 1000;
+  format!("{:02}:{:02}:{:02}.{:03}", seconds / 3600, seconds / 60 % 60, seconds % 60, ms % 1000)
+}
+
+// `M:SS`, or `H:MM:SS` for long videos
+pub fn format_label(ms: u64) -> String {
+  let seconds = ms / 1000;
+  match seconds / 3600 {
+    0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+    hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+  }
+}
@@ -0,0 +1,817 @@
+use crate::auth::auth_user::CurrentUser;
+use crate::common::responses::ApiResponse;
+use crate::user::user_preset::{system_preset, Preset, PresetMethods};
+use crate::video::video_contact_sheet::SheetGrid;
+use crate::video::video_dto::{
+  ContactSheetQuery, ConversionQuery, ConversionRequest, ConversionResponse, MediaResponse,
+  OutputFormat, RecipeQuery, RunRecipeRequest, SaveRecipeRequest, SheetFormat,
+};
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame_edit::FrameEditRequest;
+use crate::video::video_idempotency::{
+  complete, fingerprint, idempotency_key, payload_digest, replay, reserve, Reservation,
+};
+use crate::video::video_preview::{Preview, PreviewCache};
+use crate::video::video_progress::ProgressTracker;
+use crate::video::video_recipe::Recipe;
+use crate::video::video_recipe_repository::{validate_name, RecipeRepository, SavedRecipe};
+use crate::video::video_render_cache::{
+  conversion_key, find_render, output_owner, recipe_key, release_render, store_render, Release,
+  CACHE_HEADER,
+};
+use crate::video::video_service::VideoService;
+use crate::video::video_spritesheet::{is_atlas_file, SpritesheetOptions};
+use crate::video::video_storage::MediaStorage;
+use crate::video::video_stream::{gif_chunks, ChunkWriter, STREAM_CHUNKS};
+use crate::video::video_zip::{zip_stream, ZipSource};
+use actix_web::error::BlockingError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{error, web, HttpRequest, HttpResponse, Responder};
+use deadpool_postgres::Pool;
+use futures_util::TryStreamExt;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+// An `Idempotency-Key` needs a signed-in user, keys are kept per user. A retry
+// is read to its end to compare it with the first upload, then dropped
+pub async fn upload_media(
+  req: HttpRequest,
+  user: Option<CurrentUser>,
+  payload: web::Payload,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let (key, user) = match (idempotency_key(&req), user) {
+    (Ok(None), _) => return save_media(payload, &storage).await.0,
+    (Ok(Some(_)), None) => {
+      return ApiResponse::unauthorized("An Idempotency-Key requires authentication")
+    },
+    (Ok(Some(key)), Some(user)) => (key, user),
+    (Err(err), _) => return video_error_response(err),
+  };
+  let uploaded = match reserve(&pool, user.id, &key).await {
+    // A failed upload answers an error, which releases the key
+    Ok(Reservation::Reserved) => {
+      let (response, digest) = save_media(payload, &storage).await;
+      let fingerprint = fingerprint("POST /media", digest.unwrap_or_default().as_bytes());
+      complete(&pool, user.id, &key, &fingerprint, response).await
+    },
+    Ok(Reservation::InProgress) => Err(VideoError::IdempotencyKeyInUse),
+    Ok(Reservation::Completed(original, response)) => match payload_digest(payload).await {
+      Ok(digest) => replay(&original, &fingerprint("POST /media", digest.as_bytes()), &response),
+      Err(err) => Err(err),
+    },
+    Err(err) => Err(err),
+  };
+  match uploaded {
+    Ok(response) => response,
+    Err(err) => video_error_response(err),
+  }
+}
+
+// The response with the SHA-256 of the upload once it is saved
+async fn save_media(
+  payload: web::Payload,
+  storage: &MediaStorage,
+) -> (HttpResponse, Option<String>) {
+  match storage.save_upload(payload).await {
+    Ok((id, size_bytes, digest)) => {
+      let media = MediaResponse {
+        id,
+        size_bytes,
+      };
+      let response =
+        ApiResponse::created("Media uploaded successfully", Some(serde_json::json!(media)));
+      (response, Some(digest))
+    },
+    Err(err) => (video_error_response(err), None),
+  }
+}
+
+pub async fn convert_media(
+  path: web::Path<Uuid>,
+  query: web::Query<ConversionQuery>,
+  user: Option<CurrentUser>,
+  body: web::Json<ConversionRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+  previews: web::Data<PreviewCache>,
+) -> impl Responder {
+  let media_id = path.into_inner();
+  if let Err(err) = storage.find_media(&media_id).await {
+    return video_error_response(err);
+  }
+  let mut request = body.into_inner();
+  if let Err(err) = apply_preset(&mut request, user.as_ref(), &pool).await {
+    return video_error_response(err);
+  }
+  let user_id = user.as_ref().map(|user| user.id);
+  match (query.stream, query.preview) {
+    (true, true) => ApiResponse::bad_request("stream and preview cannot be combined"),
+    (true, false) => stream_conversion(storage, media_id, request).await,
+    (false, true) => preview_conversion(storage, previews, media_id, request).await,
+    (false, false) => run_conversion(storage, Some(media_id), request, user_id, &pool).await,
+  }
+}
+
+// Multi-clip conversion where every segment or layout cell names its own source
+pub async fn create_gif(
+  user: Option<CurrentUser>,
+  body: web::Json<ConversionRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let mut request = body.into_inner();
+  if request.segments.is_empty() && request.layout.is_none() {
+    return ApiResponse::bad_request("Segments or a layout are required");
+  }
+  if let Err(err) = apply_preset(&mut request, user.as_ref(), &pool).await {
+    return video_error_response(err);
+  }
+  let user_id = user.as_ref().map(|user| user.id);
+  run_conversion(storage, None, request, user_id, &pool).await
+}
+
+// Options left out of the request come from its preset, system presets are
+// available without authentication
+async fn apply_preset(
+  request: &mut ConversionRequest,
+  user: Option<&CurrentUser>,
+  pool: &Pool,
+) -> Result<(), VideoError> {
+  let Some(name) = request.preset.clone() else {
+    return Ok(());
+  };
+  let preset = match (system_preset(&name), user) {
+    (Some(preset), _) => preset,
+    (None, Some(user)) => {
+      let client = pool.get().await?;
+      Preset::find(&client, user.id, &name).await?.ok_or(VideoError::PresetNotFound(name))?
+    },
+    (None, None) => return Err(VideoError::PresetNotFound(name)),
+  };
+  preset.settings.apply_to(request);
+  Ok(())
+}
+
+// Cached like recipes, see `run_recipe`
+async fn run_conversion(
+  storage: web::Data<MediaStorage>,
+  media_id: Option<Uuid>,
+  request: ConversionRequest,
+  user_id: Option<i32>,
+  pool: &Pool,
+) -> HttpResponse {
+  let cache = match user_id {
+    Some(user_id) => match conversion_key(&storage, &request, media_id).await {
+      Ok(key) => Some((user_id, key)),
+      Err(err) => return video_error_response(err),
+    },
+    None => None,
+  };
+  match cached_render(pool, cache.as_ref()).await {
+    Ok(Some(response)) => return cached_response(response),
+    Ok(None) => {},
+    Err(err) => return video_error_response(err),
+  }
+
+  let output_id = Uuid::new_v4();
+  let format = request.format();
+  let destination = match storage.prepare_output(&output_id, format).await {
+    Ok(destination) => destination,
+    Err(err) => return video_error_response(err),
+  };
+  let result = {
+    let storage = storage.clone();
+    web::block(move || VideoService::convert(&storage, output_id, media_id, &destination, &request))
+      .await
+  };
+
+  if let (Some(cache), Ok(Ok(response))) = (&cache, &result) {
+    if let Err(err) = cache_render(pool, &storage, cache, &output_id, format, response).await {
+      return video_error_response(err);
+    }
+  }
+  conversion_response(result, format)
+}
+
+// Sends the GIF while it is encoded. Errors before the first frame get the
+// usual JSON response, later ones end the GIF early with its trailer and the
+// GIF is not kept
+async fn stream_conversion(
+  storage: web::Data<MediaStorage>,
+  media_id: Uuid,
+  request: ConversionRequest,
+) -> HttpResponse {
+  let output_id = Uuid::new_v4();
+  let destination = match storage.prepare_gif(&output_id).await {
+    Ok(destination) => destination,
+    Err(err) => return video_error_response(err),
+  };
+
+  let (sender, mut receiver) = mpsc::channel(STREAM_CHUNKS);
+  let conversion = {
+    let storage = storage.clone();
+    web::block(move || {
+      let writer = ChunkWriter::new(sender);
+      VideoService::convert_streaming(&storage, output_id, media_id, &destination, &request, writer)
+    })
+  };
+
+  let Some(first) = receiver.recv().await else {
+    // The writer was dropped without a single frame
+    let result = conversion.await;
+    if !matches!(result, Ok(Ok(_))) {
+      let _ = storage.remove_gif(&output_id).await;
+    }
+    return conversion_response(result, OutputFormat::Gif);
+  };
+
+  actix_web::rt::spawn(async move {
+    if !matches!(conversion.await, Ok(Ok(_))) {
+      let _ = storage.remove_gif(&output_id).await;
+    }
+  });
+  HttpResponse::Ok()
+    .content_type("image/gif")
+    .insert_header(("X-Gif-Id", output_id.to_string()))
+    .streaming(gif_chunks(first, receiver))
+}
+
+// Previews are cached by request for a short while, the same request made
+// again while tweaking other options is answered right away
+async fn preview_conversion(
+  storage: web::Data<MediaStorage>,
+  previews: web::Data<PreviewCache>,
+  media_id: Uuid,
+  request: ConversionRequest,
+) -> HttpResponse {
+  let key = format!("gif:{}:{:?}", media_id, request);
+  if let Some(preview) = previews.get(&key) {
+    return preview_response(preview);
+  }
+  let result = web::block(move || VideoService::preview(&storage, media_id, &request)).await;
+  cached_preview_response(result, &previews, key)
+}
+
+fn cached_preview_response(
+  result: Result<Result<Preview, VideoError>, BlockingError>,
+  previews: &PreviewCache,
+  key: String,
+) -> HttpResponse {
+  match result {
+    Ok(Ok(preview)) => {
+      previews.insert(key, preview.clone());
+      preview_response(preview)
+    },
+    Ok(Err(err)) => video_error_response(err),
+    Err(err) => ApiResponse::from_error(err),
+  }
+}
+
+fn preview_response(preview: Preview) -> HttpResponse {
+  HttpResponse::Ok()
+    .content_type("image/gif")
+    .insert_header(("Cache-Control", "no-store"))
+    .insert_header(("X-Preview-Frames", preview.frame_count.to_string()))
+    .insert_header(("X-Preview-Truncated", preview.truncated.to_string()))
+    .body(preview.bytes)
+}
+
+fn conversion_response(
+  result: Result<Result<ConversionResponse, VideoError>, BlockingError>,
+  format: OutputFormat,
+) -> HttpResponse {
+  match (result, format) {
+    (Ok(Ok(response)), OutputFormat::Gif) => {
+      ApiResponse::created("GIF created successfully", Some(serde_json::json!(response)))
+    },
+    (Ok(Ok(response)), OutputFormat::Spritesheet) => {
+      ApiResponse::created("Sprite sheet created successfully", Some(serde_json::json!(response)))
+    },
+    (Ok(Err(err)), _) => video_error_response(err),
+    (Err(err), _) => ApiResponse::from_error(err),
+  }
+}
+
+// Sprite sheet from a GIF that was already generated
+pub async fn gif_to_spritesheet(
+  path: web::Path<Uuid>,
+  body: web::Json<SpritesheetOptions>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let gif_id = path.into_inner();
+  let latest = {
+    let storage = storage.clone();
+    web::block(move || VideoService::latest_gif(&storage, gif_id)).await
+  };
+  let source = match latest {
+    Ok(Ok(source)) => source,
+    Ok(Err(err)) => return video_error_response(err),
+    Err(err) => return ApiResponse::from_error(err),
+  };
+  let sheet_id = Uuid::new_v4();
+  let destination = match storage.prepare_sheet(&sheet_id).await {
+    Ok(destination) => destination,
+    Err(err) => return video_error_response(err),
+  };
+
+  let options = body.into_inner();
+  let result = web::block(move || {
+    VideoService::spritesheet_from_gif(sheet_id, &source, &destination, &options)
+  })
+  .await;
+
+  match result {
+    Ok(Ok(response)) => {
+      ApiResponse::created("Sprite sheet created successfully", Some(serde_json::json!(response)))
+    },
+    Ok(Err(err)) => video_error_response(err),
+    Err(err) => ApiResponse::from_error(err),
+  }
+}
+
+// Storyboard of a video as an image, or as a WebVTT track for hover previews
+pub async fn get_contact_sheet(
+  path: web::Path<Uuid>,
+  query: web::Query<ContactSheetQuery>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let media_id = path.into_inner();
+  let grid = match SheetGrid::from_query(&query) {
+    Ok(grid) => grid,
+    Err(err) => return video_error_response(err),
+  };
+  let source = match storage.find_media(&media_id).await {
+    Ok(source) => source,
+    Err(err) => return video_error_response(err),
+  };
+
+  let format = query.format;
+  let result = match format {
+    SheetFormat::Vtt => {
+      let image_url =
+        format!("/media/{}/contact-sheet?{}", media_id, grid.image_query(SheetFormat::Png));
+      web::block(move || VideoService::thumbnails_track(&source, &grid, &image_url))
+        .await
+        .map(|track| track.map(String::into_bytes))
+    },
+    SheetFormat::Png | SheetFormat::Jpeg => {
+      let extension = match format {
+        SheetFormat::Jpeg => "jpg",
+        _ => "png",
+      };
+      let cache = storage.contact_sheet_path(&media_id, &grid.cache_key(), extension);
+      web::block(move || VideoService::contact_sheet(&source, &cache, &grid, format)).await
+    },
+  };
+
+  let content_type = match format {
+    SheetFormat::Png => "image/png",
+    SheetFormat::Jpeg => "image/jpeg",
+    SheetFormat::Vtt => "text/vtt",
+  };
+  match result {
+    Ok(Ok(bytes)) => HttpResponse::Ok().content_type(content_type).body(bytes),
+    Ok(Err(err)) => video_error_response(err),
+    Err(err) => ApiResponse::from_error(err),
+  }
+}
+
+// Latest version of a GIF
+pub async fn get_gif(path: web::Path<Uuid>, storage: web::Data<MediaStorage>) -> impl Responder {
+  let gif_id = path.into_inner();
+  let gif = match web::block(move || VideoService::latest_gif(&storage, gif_id)).await {
+    Ok(Ok(gif)) => gif,
+    Ok(Err(err)) => return video_error_response(err),
+    Err(err) => return ApiResponse::from_error(err),
+  };
+  match tokio::fs::read(gif).await {
+    Ok(bytes) => HttpResponse::Ok().content_type("image/gif").body(bytes),
+    Err(_) => ApiResponse::not_found("GIF not found"),
+  }
+}
+
+// Drops the reference of the user to a render, its files are removed once no
+// other user references it. Job outputs outside the cache go with their job
+pub async fn delete_gif(
+  path: web::Path<Uuid>,
+  user: CurrentUser,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let gif_id = path.into_inner();
+  match release_render(&pool, &storage, user.id, &gif_id).await {
+    Ok(Release::NotReferenced) => {
+      match delete_unreferenced(&pool, &storage, user.id, &gif_id).await {
+        Ok(()) => ApiResponse::no_content(),
+        Err(err) => video_error_response(err),
+      }
+    },
+    Ok(Release::Kept | Release::Removed(_)) => ApiResponse::no_content(),
+    Err(err) => video_error_response(err),
+  }
+}
+
+// Job outputs are deleted by the user of the job once it finished
+async fn delete_unreferenced(
+  pool: &Pool,
+  storage: &MediaStorage,
+  user_id: i32,
+  gif_id: &Uuid,
+) -> Result<(), VideoError> {
+  if !storage.has_gif(gif_id).await? {
+    return Err(VideoError::GifNotFound);
+  }
+  output_owner(pool, gif_id).await?.may_delete(user_id)?;
+  storage.remove_gif(gif_id).await
+}
+
+pub async fn get_gif_version(
+  path: web::Path<(Uuid, u32)>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let (gif_id, version) = path.into_inner();
+  match tokio::fs::read(storage.gif_version_path(&gif_id, version)).await {
+    Ok(bytes) => HttpResponse::Ok().content_type("image/gif").body(bytes),
+    Err(_) => ApiResponse::not_found("GIF version not found"),
+  }
+}
+
+pub async fn list_gif_versions(
+  path: web::Path<Uuid>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let gif_id = path.into_inner();
+  match web::block(move || VideoService::list_versions(&storage, gif_id)).await {
+    Ok(Ok(versions)) => {
+      ApiResponse::success("GIF versions retrieved", Some(serde_json::json!(versions)))
+    },
+    Ok(Err(err)) => video_error_response(err),
+    Err(err) => ApiResponse::from_error(err),
+  }
+}
+
+// Drop, duplicate, move and retime frames, the result is saved as a new version.
+// Only the user of a job edits its GIF, other GIFs are edited as a copy with an
+// id of its own so their owners never see the edit
+pub async fn edit_gif_frames(
+  path: web::Path<Uuid>,
+  body: web::Json<FrameEditRequest>,
+  user: CurrentUser,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let gif_id = path.into_inner();
+  let request = body.into_inner();
+  let owner = output_owner(&pool, &gif_id).await;
+  let in_place = match owner.and_then(|owner| owner.edits_in_place(user.id)) {
+    Ok(in_place) => in_place,
+    Err(err) => return video_error_response(err),
+  };
+  let edited = web::block(move || {
+    let gif_id = match in_place {
+      true => gif_id,
+      false => VideoService::copy_gif(&storage, gif_id)?,
+    };
+    VideoService::edit_frames(&storage, gif_id, &request)
+  })
+  .await;
+  match edited {
+    Ok(Ok(version)) => {
+      ApiResponse::created("GIF version created successfully", Some(serde_json::json!(version)))
+    },
+    Ok(Err(err)) => video_error_response(err),
+    Err(err) => ApiResponse::from_error(err),
+  }
+}
+
+pub async fn get_sheet_file(
+  path: web::Path<(Uuid, String)>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let (id, file) = path.into_inner();
+  if !is_atlas_file(&file) {
+    return ApiResponse::not_found("File not found");
+  }
+  let content_type = match file.ends_with(".png") {
+    true => "image/png",
+    false => "application/json",
+  };
+  match tokio::fs::read(storage.sheet_dir(&id).join(&file)).await {
+    Ok(bytes) => HttpResponse::Ok().content_type(content_type).body(bytes),
+    Err(_) => ApiResponse::not_found("File not found"),
+  }
+}
+
+// Every frame as a PNG plus a manifest with the index, pts and delay of each one
+pub async fn download_frames(
+  path: web::Path<Uuid>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let gif_id = path.into_inner();
+  let directory = storage.frames_dir(&gif_id);
+  let manifest = match web::block(move || VideoService::frame_manifest(&storage, gif_id)).await {
+    Ok(Ok(manifest)) => manifest,
+    Ok(Err(err)) => return video_error_response(err),
+    Err(err) => return ApiResponse::from_error(err),
+  };
+  let manifest_json = match serde_json::to_vec_pretty(&manifest) {
+    Ok(json) => json,
+    Err(err) => return ApiResponse::from_error(err),
+  };
+
+  let mut entries: Vec<(String, ZipSource)> = manifest
+    .frames
+    .iter()
+    .map(|frame| (frame.file.clone(), ZipSource::File(directory.join(&frame.file))))
+    .collect();
+  entries.push(("manifest.json".to_string(), ZipSource::Data(manifest_json)));
+
+  let archive = zip_stream(entries, chrono::Utc::now().naive_utc());
+  HttpResponse::Ok()
+    .content_type("application/zip")
+    .insert_header((
+      "Content-Disposition",
+      format!("attachment; filename=\"{}-frames.zip\"", gif_id),
+    ))
+    .streaming(archive.map_err(error::ErrorInternalServerError))
+}
+
+// Run a recipe on an uploaded video, its sources without a media_id use this one
+pub async fn run_media_recipe(
+  path: web::Path<Uuid>,
+  query: web::Query<RecipeQuery>,
+  user: Option<CurrentUser>,
+  body: web::Json<RunRecipeRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+  previews: web::Data<PreviewCache>,
+) -> impl Responder {
+  let media_id = path.into_inner();
+  if let Err(err) = storage.find_media(&media_id).await {
+    return video_error_response(err);
+  }
+  let user_id = user.as_ref().map(|user| user.id);
+  let recipe = match resolve_recipe(user, body.into_inner(), &pool).await {
+    Ok(recipe) => recipe,
+    Err(response) => return response,
+  };
+  match query.preview {
+    true => preview_recipe(storage, previews, Some(media_id), recipe).await,
+    false => run_recipe(Some(media_id), recipe, user_id, &pool, storage).await,
+  }
+}
+
+// Run a recipe whose sources all name their media
+pub async fn create_gif_from_recipe(
+  query: web::Query<RecipeQuery>,
+  user: Option<CurrentUser>,
+  body: web::Json<RunRecipeRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+  previews: web::Data<PreviewCache>,
+) -> impl Responder {
+  let user_id = user.as_ref().map(|user| user.id);
+  let recipe = match resolve_recipe(user, body.into_inner(), &pool).await {
+    Ok(recipe) => recipe,
+    Err(response) => return response,
+  };
+  match query.preview {
+    true => preview_recipe(storage, previews, None, recipe).await,
+    false => run_recipe(None, recipe, user_id, &pool, storage).await,
+  }
+}
+
+// The saved recipe named by the request, or the one given inline
+pub async fn resolve_recipe(
+  user: Option<CurrentUser>,
+  request: RunRecipeRequest,
+  pool: &Pool,
+) -> Result<Recipe, HttpResponse> {
+  match (request.recipe_id, request.recipe) {
+    (Some(recipe_id), None) => {
+      let Some(user) = user else {
+        return Err(ApiResponse::unauthorized("Saved recipes require authentication"));
+      };
+      match find_recipe(pool, user.id, &recipe_id).await {
+        Ok(saved) => Ok(saved.recipe),
+        Err(err) => Err(video_error_response(err)),
+      }
+    },
+    (None, Some(recipe)) => Ok(recipe),
+    _ => Err(ApiResponse::bad_request("Either recipe_id or recipe is required")),
+  }
+}
+
+async fn preview_recipe(
+  storage: web::Data<MediaStorage>,
+  previews: web::Data<PreviewCache>,
+  media_id: Option<Uuid>,
+  recipe: Recipe,
+) -> HttpResponse {
+  let key = match serde_json::to_string(&recipe) {
+    Ok(json) => format!("recipe:{:?}:{}", media_id, json),
+    Err(err) => return ApiResponse::from_error(err),
+  };
+  if let Some(preview) = previews.get(&key) {
+    return preview_response(preview);
+  }
+  let result = web::block(move || VideoService::preview_recipe(&storage, media_id, &recipe)).await;
+  cached_preview_response(result, &previews, key)
+}
+
+// Renders of signed-in users are cached by their source bytes and recipe, the
+// same render asked again is answered with the first one. Anonymous renders
+// are not cached, no one could delete them
+async fn run_recipe(
+  media_id: Option<Uuid>,
+  recipe: Recipe,
+  user_id: Option<i32>,
+  pool: &Pool,
+  storage: web::Data<MediaStorage>,
+) -> HttpResponse {
+  let cache = match user_id {
+    Some(user_id) => match recipe_key(&storage, &recipe, media_id).await {
+      Ok(key) => Some((user_id, key)),
+      Err(err) => return video_error_response(err),
+    },
+    None => None,
+  };
+  match cached_render(pool, cache.as_ref()).await {
+    Ok(Some(response)) => return cached_response(response),
+    Ok(None) => {},
+    Err(err) => return video_error_response(err),
+  }
+
+  let output_id = Uuid::new_v4();
+  let format = recipe.output.format;
+  let destination = match storage.prepare_output(&output_id, format).await {
+    Ok(destination) => destination,
+    Err(err) => return video_error_response(err),
+  };
+  let result = {
+    let storage = storage.clone();
+    web::block(move || {
+      let mut progress = ProgressTracker::none();
+      VideoService::run_recipe(&storage, output_id, media_id, &destination, &recipe, &mut progress)
+    })
+    .await
+  };
+
+  if let (Some(cache), Ok(Ok(response))) = (&cache, &result) {
+    if let Err(err) = cache_render(pool, &storage, cache, &output_id, format, response).await {
+      return video_error_response(err);
+    }
+  }
+  conversion_response(result, format)
+}
+
+// The response of the cached render for the user and key, none for anonymous
+// renders
+async fn cached_render(
+  pool: &Pool,
+  cache: Option<&(i32, String)>,
+) -> Result<Option<serde_json::Value>, VideoError> {
+  match cache {
+    Some((user_id, key)) => find_render(pool, key, *user_id).await,
+    None => Ok(None),
+  }
+}
+
+async fn cache_render(
+  pool: &Pool,
+  storage: &MediaStorage,
+  (user_id, key): &(i32, String),
+  output_id: &Uuid,
+  format: OutputFormat,
+  response: &ConversionResponse,
+) -> Result<(), VideoError> {
+  let response = serde_json::json!(response);
+  if let Err(err) = store_render(pool, key, output_id, format, *user_id, &response).await {
+    // Not cached, it could never be deleted
+    let _ = storage.remove_output(output_id, format).await;
+    return Err(err);
+  }
+  Ok(())
+}
+
+fn cached_response(response: serde_json::Value) -> HttpResponse {
+  let mut cached = ApiResponse::success("Cached render returned", Some(response));
+  if let Ok(name) = HeaderName::try_from(CACHE_HEADER) {
+    cached.headers_mut().insert(name, HeaderValue::from_static("hit"));
+  }
+  cached
+}
+
+async fn find_recipe(pool: &Pool, user_id: i32, id: &Uuid) -> Result<SavedRecipe, VideoError> {
+  let client = pool.get().await?;
+  RecipeRepository::find(&client, user_id, id).await
+}
+
+// Recipes are validated before they are saved so a saved recipe always runs
+pub async fn save_recipe(
+  user: CurrentUser,
+  body: web::Json<SaveRecipeRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let SaveRecipeRequest {
+    name,
+    recipe,
+  } = body.into_inner();
+  if let Err(err) = validate_name(&name) {
+    return video_error_response(err);
+  }
+  let checked = web::block(move || {
+    VideoService::check_recipe(&storage, &recipe)?;
+    Ok::<_, VideoError>(recipe)
+  })
+  .await;
+  let recipe = match checked {
+    Ok(Ok(recipe)) => recipe,
+    Ok(Err(err)) => return video_error_response(err),
+    Err(err) => return ApiResponse::from_error(err),
+  };
+
+  let saved = match pool.get().await {
+    Ok(client) => RecipeRepository::create(&client, user.id, name.trim(), &recipe).await,
+    Err(err) => Err(err.into()),
+  };
+  match saved {
+    Ok(saved) => ApiResponse::created("Recipe saved successfully", Some(serde_json::json!(saved))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+pub async fn list_recipes(user: CurrentUser, pool: web::Data<Pool>) -> impl Responder {
+  let recipes = match pool.get().await {
+    Ok(client) => RecipeRepository::all(&client, user.id).await,
+    Err(err) => Err(err.into()),
+  };
+  match recipes {
+    Ok(recipes) => ApiResponse::success("Recipes retrieved", Some(serde_json::json!(recipes))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+pub async fn get_recipe(
+  user: CurrentUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  match find_recipe(&pool, user.id, &path.into_inner()).await {
+    Ok(saved) => ApiResponse::success("Recipe retrieved", Some(serde_json::json!(saved))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+pub async fn delete_recipe(
+  user: CurrentUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let deleted = match pool.get().await {
+    Ok(client) => RecipeRepository::delete(&client, user.id, &path.into_inner()).await,
+    Err(err) => Err(err.into()),
+  };
+  match deleted {
+    Ok(()) => ApiResponse::no_content(),
+    Err(err) => video_error_response(err),
+  }
+}
+
+pub fn video_error_response(err: VideoError) -> HttpResponse {
+  match err {
+    VideoError::MediaNotFound
+    | VideoError::GifNotFound
+    | VideoError::VersionNotFound(_)
+    | VideoError::RecipeNotFound
+    | VideoError::JobNotFound
+    | VideoError::PresetNotFound(_) => ApiResponse::not_found(&err.to_string()),
+    VideoError::InvalidOption(_) | VideoError::InvalidLut(_) | VideoError::ArchiveTooLarge(_) => {
+      ApiResponse::bad_request(&err.to_string())
+    },
+    VideoError::VersionConflict(_)
+    | VideoError::RecipeNameTaken(_)
+    | VideoError::JobFinished
+    | VideoError::JobNotFinished
+    | VideoError::NotDeadLettered
+    | VideoError::IdempotencyKeyInUse => ApiResponse::conflict(&err.to_string()),
+    VideoError::NoVideoStream
+    | VideoError::NoFrames
+    | VideoError::FfmpegError(_)
+    | VideoError::OutputTooLarge(_)
+    | VideoError::UnsupportedMedia(_)
+    | VideoError::InvalidMedia(_)
+    | VideoError::TimedOut(_)
+    | VideoError::IdempotencyKeyReused
+    | VideoError::CostTooHigh(_) => ApiResponse::unprocessable_entity(&err.to_string()),
+    VideoError::MediaTooLarge(_) => ApiResponse::payload_too_large(&err.to_string()),
+    VideoError::QueueFull(_, retry_after) => {
+      ApiResponse::too_many_requests(&err.to_string(), retry_after)
+    },
+    VideoError::NotGifOwner => ApiResponse::forbidden(&err.to_string()),
+    _ => ApiResponse::from_error(err),
+  }
+}
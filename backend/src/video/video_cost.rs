@@ -0,0 +1,200 @@
+use crate::video::ffmpeg::{output_size, VideoInfo};
+use crate::video::video_errors::VideoError;
+use crate::video::video_filters::FilterSpec;
+use crate::video::video_frame_edit::MIN_DELAY_MS;
+use crate::video::video_job_schedule::UserTier;
+use crate::video::video_limits::MAX_SOURCE_DURATION_MS;
+use crate::video::video_recipe::{scaled_size, OverlaySpec, Recipe, RecipeOperation};
+use serde::Serialize;
+
+// Decoding, letterboxing and encoding a frame, operations add their weight to it
+pub const BASE_WEIGHT: f64 = 1.0;
+const PIXELS_PER_UNIT: f64 = 1_000_000.0;
+
+// Name and weight of an operation, the work it adds per output pixel of every
+// frame compared to a plain conversion
+pub fn operation_weight(operation: &RecipeOperation) -> (&'static str, f64) {
+  match operation {
+    RecipeOperation::Scale {
+      ..
+    } => ("scale", 0.5),
+    RecipeOperation::Crop {
+      ..
+    } => ("crop", 0.1),
+    RecipeOperation::Filter(spec) => match spec {
+      FilterSpec::Blur {
+        ..
+      } => ("blur", 3.0),
+      FilterSpec::Sharpen {
+        ..
+      } => ("sharpen", 3.0),
+      FilterSpec::Lut {
+        ..
+      } => ("lut", 1.0),
+      _ => ("filter", 0.25),
+    },
+    RecipeOperation::Overlay(OverlaySpec::Text {
+      ..
+    }) => ("text overlay", 0.1),
+    RecipeOperation::Overlay(OverlaySpec::Image {
+      ..
+    }) => ("image overlay", 0.5),
+    RecipeOperation::Speed {
+      ..
+    } => ("speed", 0.0),
+    RecipeOperation::Dedupe {
+      ..
+    } => ("dedupe", 1.0),
+  }
+}
+
+// Length of a source for the estimate. Containers that leave the duration out
+// are counted from their frames, or as long as a source may be
+fn source_duration_ms(info: &VideoInfo) -> u64 {
+  match info.duration_ms {
+    0 if info.frame_count > 0 && info.fps > 0.0 => {
+      ((info.frame_count as f64 * 1000.0 / info.fps).ceil() as u64).min(MAX_SOURCE_DURATION_MS)
+    },
+    0 => MAX_SOURCE_DURATION_MS,
+    duration_ms => duration_ms,
+  }
+}
+
+// Work of a recipe before it runs, output pixels × frames × weights in
+// millions. Transitions overlapping clips are not subtracted, the estimate
+// errs high
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CostEstimate {
+  pub width: u32,
+  pub height: u32,
+  pub frames: u64,
+  pub weight: f64,
+  pub cost: u64,
+}
+
+impl CostEstimate {
+  // `sources` are the probes of the recipe sources, in order
+  pub fn of(recipe: &Recipe, sources: &[VideoInfo]) -> Self {
+    let fps = recipe.output.fps();
+    let mut frames: f64 = recipe
+      .sources
+      .iter()
+      .zip(sources)
+      .map(|(source, info)| {
+        let segment = source.segment();
+        let duration_ms = source_duration_ms(info);
+        let end_ms = segment.end_ms().map_or(duration_ms, |end| end.min(duration_ms));
+        let length_ms = end_ms.saturating_sub(segment.start_ms().unwrap_or(0));
+        (length_ms as f64 * fps as f64 / 1000.0).ceil()
+      })
+      .sum();
+    // Clips are joined on the canvas of the first one
+    let (mut width, mut height) = sources
+      .first()
+      .map_or((0, 0), |info| output_size(info.width, info.height, recipe.decode_width()));
+
+    let mut weight = BASE_WEIGHT;
+    for operation in &recipe.operations {
+      match *operation {
+        RecipeOperation::Scale {
+          width: scaled,
+          height: Some(scaled_height),
+        } => (width, height) = (scaled, scaled_height),
+        RecipeOperation::Scale {
+          width: scaled,
+          height: None,
+        } if width > 0 => (width, height) = scaled_size(width, height, scaled),
+        RecipeOperation::Crop {
+          x,
+          y,
+          width: cropped,
+          height: cropped_height,
+        } => {
+          width = cropped.min(width.saturating_sub(x));
+          height = cropped_height.min(height.saturating_sub(y));
+        },
+        // Frames shown for less than the shortest GIF delay are dropped
+        RecipeOperation::Speed {
+          factor,
+        } => frames *= (1000.0 / (fps as f64 * factor * MIN_DELAY_MS as f64)).min(1.0),
+        _ => {},
+      }
+      weight += operation_weight(operation).1;
+    }
+
+    let frames = frames.ceil() as u64;
+    let pixels = width as f64 * height as f64 * frames as f64;
+    CostEstimate {
+      width,
+      height,
+      frames,
+      weight,
+      cost: (pixels * weight / PIXELS_PER_UNIT).ceil() as u64,
+    }
+  }
+
+  // Explains which parameters to reduce when the estimate is above the limit
+  // of the tier
+  pub fn check(&self, tier: UserTier, recipe: &Recipe) -> Result<(), VideoError> {
+    let limit = tier.max_cost();
+    if self.cost <= limit {
+      return Ok(());
+    }
+    let share = limit as f64 / self.cost as f64;
+    let mut reductions = vec![
+      format!(
+        "reduce the frames to {} with a lower fps or shorter sources",
+        (self.frames as f64 * share).floor() as u64
+      ),
+      format!(
+        "scale the output to at most {} pixels wide",
+        (self.width as f64 * share.sqrt()).floor() as u32
+      ),
+    ];
+    let heaviest = recipe
+      .operations
+      .iter()
+      .map(operation_weight)
+      .filter(|(_, weight)| *weight > 0.0)
+      .max_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((name, weight)) = heaviest {
+      reductions.push(format!("remove the {} operation (weight {})", name, weight));
+    }
+    Err(VideoError::CostTooHigh(format!(
+      "estimated cost {} is above the limit of {} for the {} tier ({}x{} pixels × {} frames × \
+       weight {}), {}",
+      self.cost,
+      limit,
+      tier.as_str(),
+      self.width,
+      self.height,
+      self.frames,
+      self.weight,
+      reductions.join(", or ")
+    )))
+  }
+}
+
+// Answer of the dry run, with what the job would be refused for
+#[derive(Debug, Clone, Serialize)]
+pub struct EstimateResponse {
+  #[serde(flatten)]
+  pub estimate: CostEstimate,
+  pub tier: UserTier,
+  pub limit: u64,
+  pub allowed: bool,
+  pub reason: Option<String>,
+}
+
+impl EstimateResponse {
+  pub fn new(estimate: CostEstimate, tier: UserTier, recipe: &Recipe) -> Self {
+    let reason = estimate.check(tier, recipe).err().map(|err| err.to_string());
+    EstimateResponse {
+      estimate,
+      tier,
+      limit: tier.max_cost(),
+      allowed: reason.is_none(),
+      reason,
+    }
+  }
+}
@@ -0,0 +1,102 @@
+use crate::video::video_frame::Frame;
+use image::RgbaImage;
+use serde::Serialize;
+
+// Size of the luma grid used to compare two frames
+const SIGNATURE_SIZE: u32 = 32;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DedupeStats {
+  pub input_frames: usize,
+  pub output_frames: usize,
+  pub dropped_frames: usize,
+}
+
+// Collapse consecutive frames whose difference is below the threshold into
+// a single frame that lasts as long as all of the merged ones
+pub fn dedupe_frames(frames: Vec<Frame>, threshold: f32) -> (Vec<Frame>, DedupeStats) {
+  let mut deduper = Deduper::new(threshold);
+  let mut kept: Vec<Frame> = Vec::with_capacity(frames.len());
+
+  for frame in frames {
+    match (deduper.is_duplicate(&frame), kept.last_mut()) {
+      (true, Some(last)) => last.delay_ms += frame.delay_ms,
+      _ => kept.push(frame),
+    }
+  }
+  (kept, deduper.stats())
+}
+
+// Frame by frame dedupe for streamed frames, the caller adds the delay of a
+// duplicate to the last frame it kept
+pub struct Deduper {
+  threshold: f32,
+  reference: Option<Vec<f32>>, // Signature of the last kept frame
+  stats: DedupeStats,
+}
+
+impl Deduper {
+  pub fn new(threshold: f32) -> Self {
+    Deduper {
+      threshold,
+      reference: None,
+      stats: DedupeStats::default(),
+    }
+  }
+
+  pub fn is_duplicate(&mut self, frame: &Frame) -> bool {
+    let signature = luma_signature(&frame.image);
+    self.stats.input_frames += 1;
+
+    // Always compare with the kept frame so slow fades are not swallowed
+    if let Some(reference) = &self.reference {
+      if signature_difference(reference, &signature) < self.threshold {
+        self.stats.dropped_frames += 1;
+        return true;
+      }
+    }
+
+    self.reference = Some(signature);
+    self.stats.output_frames += 1;
+    false
+  }
+
+  pub fn stats(&self) -> DedupeStats {
+    self.stats
+  }
+}
+
+// Mean luma difference between two signatures, from 0.0 (identical) to 1.0
+fn signature_difference(a: &[f32], b: &[f32]) -> f32 {
+  let total: f32 = a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum();
+  total / (a.len().max(1) as f32 * 255.0)
+}
+
+// Average luma of each cell of a SIGNATURE_SIZE x SIGNATURE_SIZE grid
+fn luma_signature(image: &RgbaImage) -> Vec<f32> {
+  let (width, height) = image.dimensions();
+  let cells = (SIGNATURE_SIZE * SIGNATURE_SIZE) as usize;
+  let mut sums = vec![0f32; cells];
+  let mut counts = vec![0u32; cells];
+
+  for (x, y, pixel) in image.enumerate_pixels() {
+    let cell_x = (x * SIGNATURE_SIZE / width.max(1)) as usize;
+    let cell_y = (y * SIGNATURE_SIZE / height.max(1)) as usize;
+    let cell = cell_y * SIGNATURE_SIZE as usize + cell_x;
+    let [r, g, b, _] = pixel.0;
+    sums[cell] += 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    counts[cell] += 1;
+  }
+
+  sums
+    .iter()
+    .zip(&counts)
+    .map(|(sum, &count)| {
+      if count == 0 {
+        0.0
+      } else {
+        sum / count as f32
+      }
+    })
+    .collect()
+}
@@ -0,0 +1,176 @@
+use crate::video::video_concat::SegmentSpec;
+use crate::video::video_dedupe::DedupeStats;
+use crate::video::video_filters::FilterSpec;
+use crate::video::video_layout::LayoutSpec;
+use crate::video::video_recipe::Recipe;
+use crate::video::video_spritesheet::{AtlasFile, SpritesheetOptions};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionMode {
+  #[default]
+  Normal,
+  Timelapse, // Sparse frames pulled with keyframe seeking
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+  #[default]
+  Gif,
+  Spritesheet, // PNG atlases with TexturePacker JSON descriptors
+}
+
+impl OutputFormat {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      OutputFormat::Gif => "gif",
+      OutputFormat::Spritesheet => "spritesheet",
+    }
+  }
+
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "gif" => Some(OutputFormat::Gif),
+      "spritesheet" => Some(OutputFormat::Spritesheet),
+      _ => None,
+    }
+  }
+}
+
+// DTO for video to GIF conversion requests
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ConversionRequest {
+  pub fps: Option<u32>,
+  pub width: Option<u32>,
+  pub dedupe_threshold: Option<f32>, // 0.0 to 1.0, frames closer than this are merged
+  #[serde(default)]
+  pub mode: ConversionMode,
+  pub every: Option<f64>, // Timelapse: seconds of video between two frames
+  pub target_duration: Option<f64>, // Timelapse: length of the GIF in seconds
+  #[serde(default)]
+  pub filters: Vec<FilterSpec>, // Applied to every frame in this order
+  #[serde(default)]
+  pub segments: Vec<SegmentSpec>, // Clips joined in this order, the whole video when empty
+  pub background: Option<String>, // Letterbox colour when segments differ in size
+  pub layout: Option<LayoutSpec>, // Several clips side by side instead of one after the other
+  pub format: Option<OutputFormat>, // GIF by default
+  #[serde(default)]
+  pub spritesheet: SpritesheetOptions,
+  pub palette: Option<u16>, // Colours per GIF frame
+  pub dithering: Option<bool>,
+  pub max_bytes: Option<u64>, // The GIF is scaled down until it fits
+  pub preset: Option<String>, // System or user preset, explicit options take precedence
+}
+
+impl ConversionRequest {
+  pub fn format(&self) -> OutputFormat {
+    self.format.unwrap_or_default()
+  }
+}
+
+// DTO for the result of a conversion
+#[derive(Debug, Serialize)]
+pub struct ConversionResponse {
+  pub id: Uuid,
+  pub width: u32,
+  pub height: u32,
+  pub frame_count: usize,
+  pub duration_ms: u64,
+  pub size_bytes: u64,
+  pub dedupe: Option<DedupeStats>,
+  pub filters: Vec<&'static str>,
+  pub format: OutputFormat,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub atlases: Option<Vec<AtlasFile>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SheetFormat {
+  #[default]
+  Png,
+  Jpeg,
+  Vtt, // WebVTT thumbnails track pointing at the tiles of the PNG sheet
+}
+
+// Query of the conversion endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConversionQuery {
+  #[serde(default)]
+  pub stream: bool, // Send the GIF while it is encoded instead of a JSON response
+  #[serde(default)]
+  pub preview: bool, // Quick low resolution GIF that is not saved
+}
+
+// Query of the recipe endpoints
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RecipeQuery {
+  #[serde(default)]
+  pub preview: bool,
+}
+
+// Query of the contact sheet endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ContactSheetQuery {
+  pub cols: Option<u32>,
+  pub rows: Option<u32>,
+  pub width: Option<u32>, // Width of one tile
+  #[serde(default)]
+  pub format: SheetFormat,
+  pub labels: Option<bool>, // Timestamp on every tile, on by default
+}
+
+// DTO for one saved version of a GIF
+#[derive(Debug, Serialize)]
+pub struct GifVersionResponse {
+  pub id: Uuid,
+  pub version: u32,
+  pub frame_count: usize,
+  pub duration_ms: u64,
+  pub size_bytes: u64,
+}
+
+// DTO for saving a recipe under a name
+#[derive(Debug, Deserialize)]
+pub struct SaveRecipeRequest {
+  pub name: String,
+  pub recipe: Recipe,
+}
+
+// DTO for running either a saved recipe or one given inline
+#[derive(Debug, Deserialize)]
+pub struct RunRecipeRequest {
+  pub recipe_id: Option<Uuid>,
+  pub recipe: Option<Recipe>,
+}
+
+// DTO for queueing a recipe as a conversion job, same recipe fields as `RunRecipeRequest`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateJobRequest {
+  pub media_id: Option<Uuid>, // Source of the recipe clips that do not name one
+  pub recipe_id: Option<Uuid>,
+  pub recipe: Option<Recipe>,
+  pub callback_url: Option<String>, // Gets a signed POST once the job succeeds or fails
+}
+
+// Query of the webhook deliveries endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WebhookDeliveriesQuery {
+  pub job_id: Option<Uuid>,
+}
+
+// DTO for the secret signing the webhooks of a user
+#[derive(Debug, Serialize)]
+pub struct WebhookSecretResponse {
+  pub secret: String,
+}
+
+// DTO for uploaded media
+#[derive(Debug, Serialize)]
+pub struct MediaResponse {
+  pub id: Uuid,
+  pub size_bytes: u64,
+}
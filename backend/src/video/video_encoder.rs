@@ -0,0 +1,284 @@
+use crate::video::ffmpeg::output_size;
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_limits::check_deadline;
+use color_quant::NeuQuant;
+use gif::{Encoder, Repeat};
+use image::imageops::{self, FilterType};
+use std::borrow::Cow;
+use std::io::Write;
+use std::time::Instant;
+
+// NeuQuant sampling factor, 1 is the best quality and 30 the fastest
+pub const DEFAULT_QUANTIZER_SPEED: i32 = 10;
+pub const MIN_PALETTE_SIZE: u16 = 16;
+pub const MAX_PALETTE_SIZE: u16 = 256;
+// Levels per channel of the fixed colour cube, 6 * 6 * 6 colours plus transparency
+const CUBE_LEVELS: u8 = 6;
+const CUBE_TRANSPARENT: u8 = 216;
+const MAX_FIT_ATTEMPTS: usize = 8;
+const MIN_FIT_WIDTH: u32 = 16;
+
+// Quantizer settings, the defaults give the palettes of `gif::Frame::from_rgba_speed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderOptions {
+  pub speed: i32,
+  pub colors: u16,               // Palette size of every frame
+  pub dithering: bool,           // Floyd-Steinberg error diffusion
+  pub deadline: Option<Instant>, // Quantizing fails with `TimedOut` after this
+}
+
+impl Default for EncoderOptions {
+  fn default() -> Self {
+    EncoderOptions {
+      speed: DEFAULT_QUANTIZER_SPEED,
+      colors: MAX_PALETTE_SIZE,
+      dithering: false,
+      deadline: None,
+    }
+  }
+}
+
+// Encode frames into an infinitely looping GIF
+pub fn encode_gif<W: Write>(frames: &[Frame], writer: W, speed: i32) -> Result<(), VideoError> {
+  let options = EncoderOptions {
+    speed,
+    ..Default::default()
+  };
+  encode_gif_with(frames, writer, &options)
+}
+
+pub fn encode_gif_with<W: Write>(
+  frames: &[Frame],
+  writer: W,
+  options: &EncoderOptions,
+) -> Result<(), VideoError> {
+  encode_gif_tracked(frames, writer, options, |_| Ok(()))
+}
+
+// Same as `encode_gif_with`, `on_frame` gets the number of frames written so
+// far and stops the encoding with an error
+pub fn encode_gif_tracked<W: Write>(
+  frames: &[Frame],
+  writer: W,
+  options: &EncoderOptions,
+  mut on_frame: impl FnMut(usize) -> Result<(), VideoError>,
+) -> Result<(), VideoError> {
+  let first = frames.first().ok_or(VideoError::NoFrames)?;
+  let mut gif = GifWriter::new(writer, first.width(), first.height())?;
+  for (index, frame) in frames.iter().enumerate() {
+    gif.write(quantize(frame, options)?, frame.delay_ms)?;
+    on_frame(index + 1)?;
+  }
+  gif.finish()?;
+  Ok(())
+}
+
+// Palette and indexed pixels of one frame, the slow part of the encoding
+pub fn quantize(
+  frame: &Frame,
+  options: &EncoderOptions,
+) -> Result<gif::Frame<'static>, VideoError> {
+  check_deadline(options.deadline)?;
+  let (width, height) = gif_dimensions(frame.width(), frame.height())?;
+  let mut pixels = frame.image.as_raw().clone();
+  let gif_frame = match options.colors < MAX_PALETTE_SIZE || options.dithering {
+    true => quantize_frame(width, height, pixels, options),
+    false => gif::Frame::from_rgba_speed(width, height, &mut pixels, options.speed),
+  };
+  Ok(gif_frame)
+}
+
+// Maps every pixel to the nearest colour of a fixed cube, no quantizer pass and
+// no dithering. Much faster than `quantize` and good enough for previews
+pub fn quantize_fixed(frame: &Frame) -> Result<gif::Frame<'static>, VideoError> {
+  let (width, height) = gif_dimensions(frame.width(), frame.height())?;
+  let level = |value: u8| (value as u16 * (CUBE_LEVELS as u16 - 1) + 127) / 255;
+  let pixels: Vec<u8> = frame
+    .image
+    .pixels()
+    .map(|pixel| match pixel[3] {
+      0..=127 => CUBE_TRANSPARENT,
+      _ => (level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2])) as u8,
+    })
+    .collect();
+  Ok(gif::Frame::from_palette_pixels(width, height, pixels, cube_palette(), Some(CUBE_TRANSPARENT)))
+}
+
+fn cube_palette() -> Vec<u8> {
+  let step = 255 / (CUBE_LEVELS - 1);
+  let mut palette = Vec::with_capacity((CUBE_TRANSPARENT as usize + 1) * 3);
+  for r in 0..CUBE_LEVELS {
+    for g in 0..CUBE_LEVELS {
+      for b in 0..CUBE_LEVELS {
+        palette.extend_from_slice(&[r * step, g * step, b * step]);
+      }
+    }
+  }
+  palette.extend_from_slice(&[0, 0, 0]);
+  palette
+}
+
+// Writes quantized frames one at a time into an infinitely looping GIF
+pub struct GifWriter<W: Write> {
+  encoder: Encoder<W>,
+  timeline: DelayTimeline,
+}
+
+impl<W: Write> GifWriter<W> {
+  pub fn new(writer: W, width: u32, height: u32) -> Result<Self, VideoError> {
+    let (width, height) = gif_dimensions(width, height)?;
+    let mut encoder = Encoder::new(writer, width, height, &[])?;
+    encoder.set_repeat(Repeat::Infinite)?;
+    Ok(GifWriter {
+      encoder,
+      timeline: DelayTimeline::default(),
+    })
+  }
+
+  // Every frame is flushed so a streamed response receives it right away
+  pub fn write(&mut self, mut frame: gif::Frame<'_>, delay_ms: u32) -> Result<(), VideoError> {
+    frame.delay = self.timeline.next_delay(delay_ms);
+    self.encoder.write_frame(&frame)?;
+    self.encoder.get_mut().flush()?;
+    Ok(())
+  }
+
+  // Writes the trailer, the GIF is valid even when frames are missing
+  pub fn finish(self) -> Result<W, VideoError> {
+    let mut writer = self.encoder.into_inner()?;
+    writer.flush()?;
+    Ok(writer)
+  }
+}
+
+// Encode in memory, frames are scaled down from their original size until the
+// GIF fits in `max_bytes`. Returns the frames that were encoded with the GIF
+pub fn encode_within(
+  frames: Vec<Frame>,
+  options: &EncoderOptions,
+  max_bytes: u64,
+) -> Result<(Vec<Frame>, Vec<u8>), VideoError> {
+  let first = frames.first().ok_or(VideoError::NoFrames)?;
+  let (source_width, source_height) = (first.width(), first.height());
+
+  let mut current = frames.clone();
+  for _ in 0..MAX_FIT_ATTEMPTS {
+    let mut bytes = Vec::new();
+    encode_gif_with(&current, &mut bytes, options)?;
+    if bytes.len() as u64 <= max_bytes {
+      return Ok((current, bytes));
+    }
+
+    // The size of a GIF grows roughly with its pixel count
+    let ratio = (max_bytes as f64 / bytes.len() as f64).sqrt() * 0.95;
+    let width = (current[0].width() as f64 * ratio.clamp(0.5, 0.95)) as u32;
+    if width < MIN_FIT_WIDTH {
+      break;
+    }
+    let (width, height) = output_size(source_width, source_height, Some(width));
+    current = frames
+      .iter()
+      .map(|frame| Frame {
+        image: imageops::resize(&frame.image, width, height, FilterType::Triangle),
+        ..frame.clone()
+      })
+      .collect();
+  }
+  Err(VideoError::OutputTooLarge(max_bytes))
+}
+
+// Same transparency rules as `gif::Frame::from_rgba_speed`, any non zero alpha is opaque
+fn quantize_frame(
+  width: u16,
+  height: u16,
+  mut pixels: Vec<u8>,
+  options: &EncoderOptions,
+) -> gif::Frame<'static> {
+  let mut transparent = None;
+  for pixel in pixels.chunks_exact_mut(4) {
+    match pixel[3] {
+      0 => transparent = Some([pixel[0], pixel[1], pixel[2], 0]),
+      _ => pixel[3] = 0xFF,
+    }
+  }
+
+  let quantizer = NeuQuant::new(options.speed, options.colors as usize, &pixels);
+  let buffer = match options.dithering {
+    true => dither(&pixels, width as usize, &quantizer),
+    false => pixels.chunks_exact(4).map(|pixel| quantizer.index_of(pixel) as u8).collect(),
+  };
+
+  gif::Frame {
+    width,
+    height,
+    buffer: Cow::Owned(buffer),
+    palette: Some(quantizer.color_map_rgb()),
+    transparent: transparent.map(|pixel| quantizer.index_of(&pixel) as u8),
+    ..gif::Frame::default()
+  }
+}
+
+// Floyd-Steinberg, the rounding error of every pixel is spread over the
+// neighbours that are not mapped yet
+fn dither(pixels: &[u8], width: usize, quantizer: &NeuQuant) -> Vec<u8> {
+  let palette = quantizer.color_map_rgb();
+  let count = pixels.len() / 4;
+  let mut errors = vec![[0f32; 3]; count];
+  let mut indices = Vec::with_capacity(count);
+
+  for (position, pixel) in pixels.chunks_exact(4).enumerate() {
+    if pixel[3] == 0 {
+      indices.push(quantizer.index_of(pixel) as u8);
+      continue;
+    }
+    let mut target = [0, 0, 0, 0xFF];
+    for channel in 0..3 {
+      let value = pixel[channel] as f32 + errors[position][channel];
+      target[channel] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    let index = quantizer.index_of(&target);
+    indices.push(index as u8);
+
+    let (x, y) = (position % width, position / width);
+    let neighbours = [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)];
+    for (dx, dy, weight) in neighbours {
+      let nx = x as isize + dx;
+      let ny = y + dy;
+      if nx < 0 || nx as usize >= width || ny * width >= count {
+        continue;
+      }
+      let neighbour = &mut errors[ny * width + nx as usize];
+      for channel in 0..3 {
+        let error = target[channel] as f32 - palette[index * 3 + channel] as f32;
+        neighbour[channel] += error * weight / 16.0;
+      }
+    }
+  }
+  indices
+}
+
+pub fn gif_dimensions(width: u32, height: u32) -> Result<(u16, u16), VideoError> {
+  match (u16::try_from(width), u16::try_from(height)) {
+    (Ok(width), Ok(height)) => Ok((width, height)),
+    _ => Err(VideoError::InvalidOption(format!("{}x{} exceeds the GIF size limit", width, height))),
+  }
+}
+
+// GIF delays are in centiseconds, rounding each one separately would drift
+// so delays are computed from the rounded cumulative timestamps instead
+#[derive(Default)]
+pub struct DelayTimeline {
+  elapsed_ms: u64,
+  elapsed_cs: u64,
+}
+
+impl DelayTimeline {
+  pub fn next_delay(&mut self, delay_ms: u32) -> u16 {
+    self.elapsed_ms += delay_ms as u64;
+    let end_cs = (self.elapsed_ms + 5) / 10;
+    let delay = end_cs - self.elapsed_cs;
+    self.elapsed_cs = end_cs;
+    delay.min(u16::MAX as u64) as u16
+  }
+}
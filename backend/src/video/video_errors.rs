@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error as _;
+use std::io::ErrorKind;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum VideoError {
+  #[error("FFmpeg error: {0}")]
+  FfmpegError(#[from] ffmpeg_next::Error),
+
+  #[error("GIF encoding error: {0}")]
+  EncodingError(#[from] gif::EncodingError),
+
+  #[error("Image error: {0}")]
+  ImageError(#[from] image::ImageError),
+
+  #[error("I/O error: {0}")]
+  IoError(#[from] std::io::Error),
+
+  #[error("Serialization error: {0}")]
+  SerializationError(#[from] serde_json::Error),
+
+  #[error("Database error: {0}")]
+  DatabaseError(#[from] tokio_postgres::Error),
+
+  #[error("Database pool error: {0}")]
+  PoolError(#[from] deadpool_postgres::PoolError),
+
+  #[error("Conversion thread error: {0}")]
+  BlockingError(#[from] actix_web::error::BlockingError),
+
+  #[error("Media not found")]
+  MediaNotFound,
+
+  #[error("GIF not found")]
+  GifNotFound,
+
+  #[error("GIF version {0} not found")]
+  VersionNotFound(u32),
+
+  #[error("GIF version {0} was saved by another edit")]
+  VersionConflict(u32),
+
+  #[error("Recipe not found")]
+  RecipeNotFound,
+
+  #[error("Job not found")]
+  JobNotFound,
+
+  #[error("Job already finished")]
+  JobFinished,
+
+  #[error("Job still running")]
+  JobNotFinished,
+
+  #[error("GIF belongs to another user")]
+  NotGifOwner,
+
+  #[error("Job cancelled")]
+  Cancelled,
+
+  #[error("Only dead-lettered jobs can be requeued")]
+  NotDeadLettered,
+
+  #[error("Job was claimed {0} times without finishing")]
+  Abandoned(i32),
+
+  #[error("Idempotency-Key was already used for a different request")]
+  IdempotencyKeyReused,
+
+  #[error("A request with this Idempotency-Key is still in progress")]
+  IdempotencyKeyInUse,
+
+  #[error("Too many jobs, {0}")]
+  QueueFull(String, u64), // With the seconds to wait before trying again
+
+  #[error("Job too costly: {0}")]
+  CostTooHigh(String),
+
+  #[error("A recipe named {0:?} already exists")]
+  RecipeNameTaken(String),
+
+  #[error("No video stream found")]
+  NoVideoStream,
+
+  #[error("No frames could be decoded")]
+  NoFrames,
+
+  #[error("Invalid conversion option: {0}")]
+  InvalidOption(String),
+
+  #[error("Invalid LUT file: {0}")]
+  InvalidLut(String),
+
+  #[error("Archive too large: {0}")]
+  ArchiveTooLarge(&'static str),
+
+  #[error("GIF does not fit in {0} bytes")]
+  OutputTooLarge(u64),
+
+  #[error("Preset {0:?} not found")]
+  PresetNotFound(String),
+
+  #[error("A later stage of the conversion pipeline stopped")]
+  StageClosed,
+
+  #[error("Unsupported media: {0}")]
+  UnsupportedMedia(String),
+
+  #[error("Invalid media: {0}")]
+  InvalidMedia(String),
+
+  #[error("Media exceeds the decoding limits: {0}")]
+  MediaTooLarge(String),
+
+  #[error("Conversion took longer than {0} seconds")]
+  TimedOut(u64),
+}
+
+// Whether a failed job is worth running again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+  Transient, // The server, the database or the disk, the same input may succeed later
+  Permanent, // The input or the recipe, running it again gives the same error
+}
+
+impl VideoError {
+  // Every variant is listed so a new one has to be classified
+  pub fn failure_kind(&self) -> FailureKind {
+    match self {
+      VideoError::IoError(err) => io_failure_kind(err.kind()),
+      VideoError::ImageError(image::ImageError::IoError(err)) => io_failure_kind(err.kind()),
+      VideoError::EncodingError(gif::EncodingError::Io(err)) => io_failure_kind(err.kind()),
+      // Errors other than the decoding ones are the errno of a failed call
+      VideoError::FfmpegError(ffmpeg_next::Error::Other {
+        errno,
+      }) => io_failure_kind(std::io::Error::from_raw_os_error(*errno).kind()),
+      VideoError::DatabaseError(_)
+      | VideoError::PoolError(_)
+      | VideoError::BlockingError(_)
+      | VideoError::Abandoned(_)
+      | VideoError::QueueFull(..) => FailureKind::Transient,
+      VideoError::FfmpegError(_)
+      | VideoError::EncodingError(gif::EncodingError::Format(_))
+      | VideoError::ImageError(_)
+      | VideoError::SerializationError(_)
+      | VideoError::MediaNotFound
+      | VideoError::GifNotFound
+      | VideoError::VersionNotFound(_)
+      | VideoError::VersionConflict(_)
+      | VideoError::RecipeNotFound
+      | VideoError::JobNotFound
+      | VideoError::JobFinished
+      | VideoError::JobNotFinished
+      | VideoError::NotGifOwner
+      | VideoError::Cancelled
+      | VideoError::NotDeadLettered
+      | VideoError::IdempotencyKeyReused
+      | VideoError::IdempotencyKeyInUse
+      | VideoError::RecipeNameTaken(_)
+      | VideoError::NoVideoStream
+      | VideoError::NoFrames
+      | VideoError::InvalidOption(_)
+      | VideoError::InvalidLut(_)
+      | VideoError::ArchiveTooLarge(_)
+      | VideoError::OutputTooLarge(_)
+      | VideoError::PresetNotFound(_)
+      | VideoError::StageClosed
+      | VideoError::UnsupportedMedia(_)
+      | VideoError::InvalidMedia(_)
+      | VideoError::MediaTooLarge(_)
+      // The same input and recipe take as long again
+      | VideoError::TimedOut(_)
+      | VideoError::CostTooHigh(_) => FailureKind::Permanent,
+    }
+  }
+
+  // The error and the errors that caused it, outermost first
+  pub fn chain(&self) -> Vec<String> {
+    let mut chain = vec![self.to_string()];
+    let mut source = self.source();
+    while let Some(err) = source {
+      chain.push(err.to_string());
+      source = err.source();
+    }
+    chain
+  }
+}
+
+// Reads of a corrupt upload fail with these, anything else is the machine
+fn io_failure_kind(kind: ErrorKind) -> FailureKind {
+  match kind {
+    ErrorKind::InvalidData | ErrorKind::InvalidInput | ErrorKind::UnexpectedEof => {
+      FailureKind::Permanent
+    },
+    _ => FailureKind::Transient,
+  }
+}
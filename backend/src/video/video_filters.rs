@@ -0,0 +1,328 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_lut::Lut3d;
+use crate::video::video_storage::MediaStorage;
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const MAX_FILTERS: usize = 16;
+
+// A per-frame effect, new effects only need a new implementation and a FilterSpec variant
+pub trait FrameFilter: Send + Sync {
+  fn name(&self) -> &'static str;
+
+  fn apply(&self, image: &mut RgbaImage);
+}
+
+// DTO describing one filter of the chain, applied in the order given by the user
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterSpec {
+  Grayscale,
+  Sepia,
+  Brightness {
+    value: f32, // -1.0 to 1.0
+  },
+  Contrast {
+    value: f32, // 1.0 keeps the image unchanged
+  },
+  Saturation {
+    value: f32, // 0.0 is grayscale, 1.0 keeps the image unchanged
+  },
+  Gamma {
+    value: f32,
+  },
+  Blur {
+    sigma: f32,
+  },
+  Sharpen {
+    sigma: f32,
+    threshold: Option<i32>,
+  },
+  Invert,
+  Lut {
+    media_id: Uuid, // An uploaded `.cube` file
+  },
+}
+
+pub struct FilterChain {
+  filters: Vec<Box<dyn FrameFilter>>,
+}
+
+impl FilterChain {
+  // Blocking: LUT filters read their `.cube` file from the storage
+  pub fn build(specs: &[FilterSpec], storage: &MediaStorage) -> Result<Self, VideoError> {
+    if specs.len() > MAX_FILTERS {
+      return Err(VideoError::InvalidOption(format!(
+        "at most {} filters are allowed",
+        MAX_FILTERS
+      )));
+    }
+
+    let filters =
+      specs.iter().map(|spec| build_filter(spec, storage)).collect::<Result<Vec<_>, _>>()?;
+    Ok(FilterChain {
+      filters,
+    })
+  }
+
+  pub fn names(&self) -> Vec<&'static str> {
+    self.filters.iter().map(|filter| filter.name()).collect()
+  }
+
+  pub fn apply(&self, frames: &mut [Frame]) {
+    for frame in frames.iter_mut() {
+      self.apply_frame(frame);
+    }
+  }
+
+  pub fn apply_frame(&self, frame: &mut Frame) {
+    for filter in &self.filters {
+      filter.apply(&mut frame.image);
+    }
+  }
+}
+
+fn build_filter(
+  spec: &FilterSpec,
+  storage: &MediaStorage,
+) -> Result<Box<dyn FrameFilter>, VideoError> {
+  let filter: Box<dyn FrameFilter> = match *spec {
+    FilterSpec::Grayscale => Box::new(Grayscale),
+    FilterSpec::Sepia => Box::new(Sepia),
+    FilterSpec::Brightness {
+      value,
+    } => {
+      check_range("brightness", value, -1.0, 1.0)?;
+      Box::new(ChannelCurve::brightness(value))
+    },
+    FilterSpec::Contrast {
+      value,
+    } => {
+      check_range("contrast", value, 0.0, 4.0)?;
+      Box::new(ChannelCurve::contrast(value))
+    },
+    FilterSpec::Saturation {
+      value,
+    } => {
+      check_range("saturation", value, 0.0, 4.0)?;
+      Box::new(Saturation {
+        factor: value,
+      })
+    },
+    FilterSpec::Gamma {
+      value,
+    } => {
+      check_range("gamma", value, 0.1, 10.0)?;
+      Box::new(ChannelCurve::gamma(value))
+    },
+    FilterSpec::Blur {
+      sigma,
+    } => {
+      check_range("blur sigma", sigma, 0.1, 50.0)?;
+      Box::new(GaussianBlur {
+        sigma,
+      })
+    },
+    FilterSpec::Sharpen {
+      sigma,
+      threshold,
+    } => {
+      check_range("sharpen sigma", sigma, 0.1, 20.0)?;
+      Box::new(Sharpen {
+        sigma,
+        threshold: threshold.unwrap_or(0).clamp(0, 255),
+      })
+    },
+    FilterSpec::Invert => Box::new(Invert),
+    FilterSpec::Lut {
+      media_id,
+    } => {
+      let path = storage.media_path(&media_id);
+      let content = std::fs::read_to_string(&path).map_err(|_| VideoError::MediaNotFound)?;
+      Box::new(LutFilter {
+        lut: Lut3d::parse(&content)?,
+      })
+    },
+  };
+  Ok(filter)
+}
+
+fn check_range(name: &str, value: f32, min: f32, max: f32) -> Result<(), VideoError> {
+  if (min..=max).contains(&value) {
+    Ok(())
+  } else {
+    Err(VideoError::InvalidOption(format!("{} must be between {} and {}", name, min, max)))
+  }
+}
+
+fn luma(r: u8, g: u8, b: u8) -> f32 {
+  0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32
+}
+
+fn to_channel(value: f32) -> u8 {
+  value.round().clamp(0.0, 255.0) as u8
+}
+
+pub struct Grayscale;
+
+impl FrameFilter for Grayscale {
+  fn name(&self) -> &'static str {
+    "grayscale"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+      let [r, g, b, a] = pixel.0;
+      let gray = to_channel(luma(r, g, b));
+      pixel.0 = [gray, gray, gray, a];
+    }
+  }
+}
+
+pub struct Sepia;
+
+impl FrameFilter for Sepia {
+  fn name(&self) -> &'static str {
+    "sepia"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+      let [r, g, b, a] = pixel.0;
+      let (r, g, b) = (r as f32, g as f32, b as f32);
+      pixel.0 = [
+        to_channel(0.393 * r + 0.769 * g + 0.189 * b),
+        to_channel(0.349 * r + 0.686 * g + 0.168 * b),
+        to_channel(0.272 * r + 0.534 * g + 0.131 * b),
+        a,
+      ];
+    }
+  }
+}
+
+// Filters that map every colour channel independently through a lookup table
+pub struct ChannelCurve {
+  name: &'static str,
+  table: [u8; 256],
+}
+
+impl ChannelCurve {
+  fn from_fn(name: &'static str, curve: impl Fn(f32) -> f32) -> Self {
+    let mut table = [0u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+      *entry = to_channel(curve(value as f32));
+    }
+    ChannelCurve {
+      name,
+      table,
+    }
+  }
+
+  pub fn brightness(value: f32) -> Self {
+    Self::from_fn("brightness", |channel| channel + value * 255.0)
+  }
+
+  pub fn contrast(value: f32) -> Self {
+    Self::from_fn("contrast", |channel| (channel - 128.0) * value + 128.0)
+  }
+
+  pub fn gamma(value: f32) -> Self {
+    Self::from_fn("gamma", |channel| 255.0 * (channel / 255.0).powf(1.0 / value))
+  }
+}
+
+impl FrameFilter for ChannelCurve {
+  fn name(&self) -> &'static str {
+    self.name
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+      let [r, g, b, a] = pixel.0;
+      pixel.0 = [self.table[r as usize], self.table[g as usize], self.table[b as usize], a];
+    }
+  }
+}
+
+pub struct Saturation {
+  factor: f32,
+}
+
+impl FrameFilter for Saturation {
+  fn name(&self) -> &'static str {
+    "saturation"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+      let [r, g, b, a] = pixel.0;
+      let gray = luma(r, g, b);
+      let saturate = |channel: u8| to_channel(gray + (channel as f32 - gray) * self.factor);
+      pixel.0 = [saturate(r), saturate(g), saturate(b), a];
+    }
+  }
+}
+
+pub struct GaussianBlur {
+  sigma: f32,
+}
+
+impl FrameFilter for GaussianBlur {
+  fn name(&self) -> &'static str {
+    "blur"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    *image = imageops::blur(image, self.sigma);
+  }
+}
+
+pub struct Sharpen {
+  sigma: f32,
+  threshold: i32,
+}
+
+impl FrameFilter for Sharpen {
+  fn name(&self) -> &'static str {
+    "sharpen"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    *image = imageops::unsharpen(image, self.sigma, self.threshold);
+  }
+}
+
+pub struct Invert;
+
+impl FrameFilter for Invert {
+  fn name(&self) -> &'static str {
+    "invert"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+      let [r, g, b, a] = pixel.0;
+      pixel.0 = [255 - r, 255 - g, 255 - b, a];
+    }
+  }
+}
+
+pub struct LutFilter {
+  lut: Lut3d,
+}
+
+impl FrameFilter for LutFilter {
+  fn name(&self) -> &'static str {
+    "lut"
+  }
+
+  fn apply(&self, image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+      let [r, g, b, a] = pixel.0;
+      let [r, g, b] = self.lut.apply([r, g, b]);
+      pixel.0 = [r, g, b, a];
+    }
+  }
+}
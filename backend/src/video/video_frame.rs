@@ -0,0 +1,42 @@
+use crate::video::ffmpeg::output_size;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+// A decoded frame ready to be processed and encoded
+#[derive(Debug, Clone)]
+pub struct Frame {
+  pub image: RgbaImage,
+  pub pts_ms: i64,   // Presentation timestamp in milliseconds
+  pub delay_ms: u32, // How long the frame stays on screen
+}
+
+impl Frame {
+  pub fn new(image: RgbaImage, pts_ms: i64, delay_ms: u32) -> Self {
+    Frame {
+      image,
+      pts_ms,
+      delay_ms,
+    }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.image.width()
+  }
+
+  pub fn height(&self) -> u32 {
+    self.image.height()
+  }
+
+  // Scale down to `width` keeping the aspect ratio, smaller frames are kept as they are
+  pub fn scale_to_width(&mut self, width: Option<u32>) {
+    let (width, height) = output_size(self.width(), self.height(), width);
+    if (width, height) != self.image.dimensions() {
+      self.image = imageops::resize(&self.image, width, height, FilterType::Triangle);
+    }
+  }
+}
+
+// Total duration of a list of frames in milliseconds
+pub fn total_duration_ms(frames: &[Frame]) -> u64 {
+  frames.iter().map(|frame| frame.delay_ms as u64).sum()
+}
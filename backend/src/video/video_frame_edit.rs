@@ -0,0 +1,144 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame_store::FrameEntry;
+use serde::Deserialize;
+
+pub const MAX_OPERATIONS: usize = 500;
+pub const MAX_EDITED_FRAMES: usize = 5000;
+pub const MAX_DUPLICATES: u32 = 100;
+pub const MIN_DELAY_MS: u32 = 20; // Shorter delays are slowed down by browsers
+pub const MAX_DELAY_MS: u32 = 655_350; // Largest delay a GIF frame can hold
+
+// One edit of the frame list, indices refer to the list as left by the previous operations
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FrameOperation {
+  Drop {
+    index: usize,
+  },
+  Duplicate {
+    index: usize,
+    count: Option<u32>, // Copies inserted after the frame, 1 by default
+  },
+  Move {
+    from: usize,
+    to: usize,
+  },
+  SetDelay {
+    index: usize,
+    delay_ms: u32,
+  },
+  SetRangeDelay {
+    start: usize,
+    end: usize, // Inclusive
+    delay_ms: u32,
+  },
+}
+
+// DTO for a frame edit, applied to `base_version` or the latest version
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameEditRequest {
+  pub base_version: Option<u32>,
+  pub operations: Vec<FrameOperation>,
+}
+
+// Apply the operations in order then renumber the frames and rebuild their timestamps
+pub fn apply_operations(
+  frames: &mut Vec<FrameEntry>,
+  operations: &[FrameOperation],
+) -> Result<(), VideoError> {
+  if operations.is_empty() || operations.len() > MAX_OPERATIONS {
+    return Err(VideoError::InvalidOption(format!(
+      "between 1 and {} operations are allowed",
+      MAX_OPERATIONS
+    )));
+  }
+
+  for (position, operation) in operations.iter().enumerate() {
+    apply_operation(frames, operation).map_err(|message| {
+      VideoError::InvalidOption(format!("operation {}: {}", position, message))
+    })?;
+  }
+
+  let mut pts_ms = 0i64;
+  for (index, frame) in frames.iter_mut().enumerate() {
+    frame.index = index;
+    frame.pts_ms = pts_ms;
+    pts_ms += frame.delay_ms as i64;
+  }
+  Ok(())
+}
+
+fn apply_operation(frames: &mut Vec<FrameEntry>, operation: &FrameOperation) -> Result<(), String> {
+  let count = frames.len();
+  let check_index = |index: usize| match index < count {
+    true => Ok(()),
+    false => Err(format!("frame {} does not exist, there are {} frames", index, count)),
+  };
+
+  match *operation {
+    FrameOperation::Drop {
+      index,
+    } => {
+      check_index(index)?;
+      if count == 1 {
+        return Err("the last frame cannot be dropped".to_string());
+      }
+      frames.remove(index);
+    },
+    FrameOperation::Duplicate {
+      index,
+      count: copies,
+    } => {
+      check_index(index)?;
+      let copies = copies.unwrap_or(1);
+      if !(1..=MAX_DUPLICATES).contains(&copies) {
+        return Err(format!("count must be between 1 and {}", MAX_DUPLICATES));
+      }
+      if count + copies as usize > MAX_EDITED_FRAMES {
+        return Err(format!("a GIF can have at most {} frames", MAX_EDITED_FRAMES));
+      }
+      let frame = frames[index].clone();
+      frames.splice(index + 1..index + 1, std::iter::repeat_n(frame, copies as usize));
+    },
+    FrameOperation::Move {
+      from,
+      to,
+    } => {
+      check_index(from)?;
+      check_index(to)?;
+      let frame = frames.remove(from);
+      frames.insert(to, frame);
+    },
+    FrameOperation::SetDelay {
+      index,
+      delay_ms,
+    } => {
+      check_index(index)?;
+      check_delay(delay_ms)?;
+      frames[index].delay_ms = delay_ms;
+    },
+    FrameOperation::SetRangeDelay {
+      start,
+      end,
+      delay_ms,
+    } => {
+      check_index(start)?;
+      check_index(end)?;
+      if end < start {
+        return Err("end must not be before start".to_string());
+      }
+      check_delay(delay_ms)?;
+      for frame in &mut frames[start..=end] {
+        frame.delay_ms = delay_ms;
+      }
+    },
+  }
+  Ok(())
+}
+
+fn check_delay(delay_ms: u32) -> Result<(), String> {
+  match (MIN_DELAY_MS..=MAX_DELAY_MS).contains(&delay_ms) {
+    true => Ok(()),
+    false => Err(format!("delay_ms must be between {} and {}", MIN_DELAY_MS, MAX_DELAY_MS)),
+  }
+}
@@ -0,0 +1,173 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::ImageEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+// The processed frames of a GIF kept as PNG files next to a JSON manifest,
+// so frames can be exported or edited without decoding the GIF again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameManifest {
+  pub version: u32,
+  pub width: u32,
+  pub height: u32,
+  pub frames: Vec<FrameEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameEntry {
+  pub index: usize,
+  pub file: String, // PNG file of the frame directory
+  pub pts_ms: i64,
+  pub delay_ms: u32,
+}
+
+pub fn manifest_path(directory: &Path, version: u32) -> PathBuf {
+  directory.join(format!("v{}.json", version))
+}
+
+// Blocking: writes every frame and the first manifest version
+pub fn write_frames(directory: &Path, frames: &[Frame]) -> Result<FrameManifest, VideoError> {
+  if frames.is_empty() {
+    return Err(VideoError::NoFrames);
+  }
+  let mut store = FrameStoreWriter::create(directory)?;
+  for frame in frames {
+    store.push(frame)?;
+  }
+  store.finish()
+}
+
+// PNG file of frame `index`, any thread may write it with `write_frame`
+pub fn frame_file(index: usize) -> String {
+  format!("frame_{:05}.png", index)
+}
+
+pub fn write_frame(directory: &Path, index: usize, frame: &Frame) -> Result<(), VideoError> {
+  write_png(&directory.join(frame_file(index)), frame)
+}
+
+// Writes frames one at a time as they are encoded, the manifest is written last
+pub struct FrameStoreWriter {
+  directory: PathBuf,
+  entries: Vec<FrameEntry>,
+  size: Option<(u32, u32)>, // Dimensions of the first frame
+}
+
+impl FrameStoreWriter {
+  pub fn create(directory: &Path) -> Result<Self, VideoError> {
+    fs::create_dir_all(directory)?;
+    Ok(FrameStoreWriter {
+      directory: directory.to_path_buf(),
+      entries: Vec::new(),
+      size: None,
+    })
+  }
+
+  pub fn push(&mut self, frame: &Frame) -> Result<(), VideoError> {
+    let index = self.entries.len();
+    write_frame(&self.directory, index, frame)?;
+    self.record(index, frame);
+    Ok(())
+  }
+
+  // Adds a frame whose PNG was already written with `write_frame`, in order
+  pub fn record(&mut self, index: usize, frame: &Frame) {
+    debug_assert_eq!(index, self.entries.len());
+    self.size.get_or_insert((frame.width(), frame.height()));
+    self.entries.push(FrameEntry {
+      index,
+      file: frame_file(index),
+      pts_ms: frame.pts_ms,
+      delay_ms: frame.delay_ms,
+    });
+  }
+
+  pub fn finish(self) -> Result<FrameManifest, VideoError> {
+    let (width, height) = self.size.ok_or(VideoError::NoFrames)?;
+    let manifest = FrameManifest {
+      version: 1,
+      width,
+      height,
+      frames: self.entries,
+    };
+    write_manifest(&self.directory, &manifest)?;
+    Ok(manifest)
+  }
+}
+
+pub fn write_manifest(directory: &Path, manifest: &FrameManifest) -> Result<(), VideoError> {
+  let json = serde_json::to_vec_pretty(manifest)?;
+  fs::write(manifest_path(directory, manifest.version), json)?;
+  Ok(())
+}
+
+// Fails with a conflict when another edit already saved this version
+pub fn create_manifest(directory: &Path, manifest: &FrameManifest) -> Result<(), VideoError> {
+  let json = serde_json::to_vec_pretty(manifest)?;
+  let file = OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(manifest_path(directory, manifest.version));
+  match file {
+    Ok(mut file) => Ok(file.write_all(&json)?),
+    Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+      Err(VideoError::VersionConflict(manifest.version))
+    },
+    Err(err) => Err(err.into()),
+  }
+}
+
+// Highest saved version, None when the frames were never stored
+pub fn latest_version(directory: &Path) -> Result<Option<u32>, VideoError> {
+  let entries = match fs::read_dir(directory) {
+    Ok(entries) => entries,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+
+  let mut latest = None;
+  for entry in entries {
+    let name = entry?.file_name();
+    let version = name
+      .to_str()
+      .and_then(|name| name.strip_prefix('v'))
+      .and_then(|name| name.strip_suffix(".json"))
+      .and_then(|number| number.parse::<u32>().ok());
+    latest = latest.max(version);
+  }
+  Ok(latest)
+}
+
+// Blocking: decode the PNG files of a manifest back into frames
+pub fn load_frames(directory: &Path, manifest: &FrameManifest) -> Result<Vec<Frame>, VideoError> {
+  manifest
+    .frames
+    .iter()
+    .map(|entry| {
+      let image = image::open(directory.join(&entry.file))?.to_rgba8();
+      Ok(Frame::new(image, entry.pts_ms, entry.delay_ms))
+    })
+    .collect()
+}
+
+pub fn read_manifest(directory: &Path, version: u32) -> Result<FrameManifest, VideoError> {
+  let content = fs::read(manifest_path(directory, version))?;
+  Ok(serde_json::from_slice(&content)?)
+}
+
+// Frames are written once per conversion so speed matters more than size
+fn write_png(path: &Path, frame: &Frame) -> Result<(), VideoError> {
+  let writer = BufWriter::new(File::create(path)?);
+  let encoder = PngEncoder::new_with_quality(writer, CompressionType::Fast, FilterType::Adaptive);
+  encoder.write_image(
+    frame.image.as_raw(),
+    frame.width(),
+    frame.height(),
+    image::ExtendedColorType::Rgba8,
+  )?;
+  Ok(())
+}
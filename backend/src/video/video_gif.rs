@@ -0,0 +1,79 @@
+use crate::video::ffmpeg::DecodeOptions;
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_limits::{check_gif_screen, image_error, image_limits, DecodeGuard};
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, ImageDecoder};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// GIF delays under 2 centiseconds are played at 100ms by browsers
+const MIN_GIF_DELAY_MS: u32 = 20;
+const BROWSER_DELAY_MS: u32 = 100;
+
+pub fn is_gif(path: &Path) -> Result<bool, VideoError> {
+  let mut signature = [0u8; 6];
+  let mut file = File::open(path)?;
+  match file.read_exact(&mut signature) {
+    Ok(()) => Ok(&signature == b"GIF87a" || &signature == b"GIF89a"),
+    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+    Err(err) => Err(err.into()),
+  }
+}
+
+// Decode an animated GIF keeping its own frame delays, the fps option is
+// ignored since the frames are already sampled
+pub fn decode_gif(path: &Path, options: &DecodeOptions) -> Result<Vec<Frame>, VideoError> {
+  let mut frames = Vec::new();
+  stream_gif(path, options, |frame| {
+    frames.push(frame);
+    Ok(())
+  })?;
+  Ok(frames)
+}
+
+// Same as `decode_gif` but every frame goes to `sink` as soon as it is decoded.
+// Uploads go through the same limits as videos, a tiny file can describe a
+// huge canvas or thousands of frames
+pub fn stream_gif(
+  path: &Path,
+  options: &DecodeOptions,
+  mut sink: impl FnMut(Frame) -> Result<(), VideoError>,
+) -> Result<(), VideoError> {
+  check_gif_screen(path)?;
+  let mut decoder = GifDecoder::new(BufReader::new(File::open(path)?)).map_err(image_error)?;
+  decoder.set_limits(image_limits()).map_err(image_error)?;
+  let mut guard = DecodeGuard::new(options.deadline);
+  let start_ms = options.start_ms.unwrap_or(0) as i64;
+  let end_ms = options.end_ms.map(|end| end as i64);
+
+  let mut emitted = 0;
+  let mut pts_ms = 0i64;
+  for frame in decoder.into_frames() {
+    let frame = frame.map_err(image_error)?;
+    let (width, height) = frame.buffer().dimensions();
+    guard.admit(width, height)?;
+    let (numerator, denominator) = frame.delay().numer_denom_ms();
+    let delay_ms = match numerator / denominator.max(1) {
+      delay if delay < MIN_GIF_DELAY_MS => BROWSER_DELAY_MS,
+      delay => delay,
+    };
+
+    if end_ms.is_some_and(|end| pts_ms >= end) {
+      break;
+    }
+    if pts_ms + delay_ms as i64 > start_ms {
+      let mut frame = Frame::new(frame.into_buffer(), pts_ms, delay_ms);
+      frame.scale_to_width(options.width);
+      sink(frame)?;
+      emitted += 1;
+    }
+    pts_ms += delay_ms as i64;
+  }
+
+  match emitted {
+    0 => Err(VideoError::NoFrames),
+    _ => Ok(()),
+  }
+}
@@ -0,0 +1,186 @@
+use crate::common::responses::ApiResponse;
+use crate::video::video_errors::VideoError;
+use crate::video::video_idempotency_repository::IdempotencyRepository;
+use actix_web::body::to_bytes;
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::time::Duration;
+
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+// Set on responses replayed from a key
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+pub const MAX_KEY_LENGTH: usize = 255;
+// How long a retry gets the original response
+pub const KEY_TTL: Duration = Duration::from_secs(24 * 3600);
+// A request holding its key longer is assumed to have died with its server,
+// a retry may then run again
+pub const KEY_LOCK: Duration = Duration::from_secs(600);
+pub const KEY_CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+// The `Idempotency-Key` header, printable ASCII of at most 255 characters
+pub fn idempotency_key(req: &HttpRequest) -> Result<Option<String>, VideoError> {
+  let value = match req.headers().get(IDEMPOTENCY_HEADER) {
+    Some(value) => value,
+    None => return Ok(None),
+  };
+  let key = value.to_str().unwrap_or_default().trim();
+  if key.is_empty() || key.len() > MAX_KEY_LENGTH || !key.bytes().all(|b| b.is_ascii_graphic()) {
+    return Err(VideoError::InvalidOption(format!(
+      "{} must be 1 to {} printable ASCII characters",
+      IDEMPOTENCY_HEADER, MAX_KEY_LENGTH
+    )));
+  }
+  Ok(Some(key.to_string()))
+}
+
+// Identifies a request, the same key sent to another endpoint or with another
+// body does not match
+pub fn fingerprint(endpoint: &str, body: &[u8]) -> String {
+  let mut digest = Sha256::new();
+  digest.update(endpoint.as_bytes());
+  digest.update(b"\n");
+  digest.update(body);
+  hex::encode(digest.finalize())
+}
+
+// SHA-256 of a body that is not kept
+pub async fn payload_digest(mut payload: web::Payload) -> Result<String, VideoError> {
+  let mut digest = Sha256::new();
+  while let Some(chunk) = payload.next().await {
+    let chunk = chunk.map_err(|err| VideoError::IoError(std::io::Error::other(err.to_string())))?;
+    digest.update(&chunk);
+  }
+  Ok(hex::encode(digest.finalize()))
+}
+
+// What a retry gets back, the endpoints behind keys all answer JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredResponse {
+  pub status: u16,
+  pub location: Option<String>,
+  pub body: serde_json::Value,
+}
+
+impl StoredResponse {
+  // Only successes are kept. After a failure the key is released and a retry
+  // runs again, the failure may not happen twice
+  pub async fn capture(response: HttpResponse) -> Result<Self, HttpResponse> {
+    if !response.status().is_success() {
+      return Err(response);
+    }
+    let status = response.status().as_u16();
+    let location =
+      response.headers().get(LOCATION).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let bytes = to_bytes(response.into_body()).await.map_err(ApiResponse::from_error)?;
+    let body = serde_json::from_slice(&bytes).map_err(ApiResponse::from_error)?;
+    Ok(StoredResponse {
+      status,
+      location,
+      body,
+    })
+  }
+
+  pub fn respond(&self, replayed: bool) -> HttpResponse {
+    let mut response = HttpResponse::build(StatusCode::from_u16(self.status).unwrap_or_default());
+    if let Some(location) = &self.location {
+      response.insert_header((LOCATION, location.as_str()));
+    }
+    if replayed {
+      response.insert_header((REPLAYED_HEADER, "true"));
+    }
+    response.json(&self.body)
+  }
+}
+
+// State of a key when a request arrives with it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reservation {
+  Reserved, // First use, or the previous one failed, the request runs
+  InProgress,
+  Completed(String, StoredResponse), // Fingerprint and response of the first request
+}
+
+// Answer to a retry of a completed request
+pub fn replay(
+  original: &str,
+  fingerprint: &str,
+  response: &StoredResponse,
+) -> Result<HttpResponse, VideoError> {
+  match original == fingerprint {
+    true => Ok(response.respond(true)),
+    false => Err(VideoError::IdempotencyKeyReused),
+  }
+}
+
+pub async fn reserve(pool: &Pool, user_id: i32, key: &str) -> Result<Reservation, VideoError> {
+  let client = pool.get().await?;
+  IdempotencyRepository::reserve(&client, user_id, key).await
+}
+
+// Runs `handler` once per key, retries get its response back. The key is
+// reserved first so a retry sent while the request runs is turned away
+pub async fn idempotent(
+  pool: &Pool,
+  user_id: i32,
+  key: Option<String>,
+  fingerprint: String,
+  handler: impl Future<Output = HttpResponse>,
+) -> Result<HttpResponse, VideoError> {
+  let key = match key {
+    Some(key) => key,
+    None => return Ok(handler.await),
+  };
+  match reserve(pool, user_id, &key).await? {
+    Reservation::Reserved => {},
+    Reservation::InProgress => return Err(VideoError::IdempotencyKeyInUse),
+    Reservation::Completed(original, response) => {
+      return replay(&original, &fingerprint, &response)
+    },
+  }
+  complete(pool, user_id, &key, &fingerprint, handler.await).await
+}
+
+// Keeps the response of a request that reserved its key, or releases the key
+// when it failed
+pub async fn complete(
+  pool: &Pool,
+  user_id: i32,
+  key: &str,
+  fingerprint: &str,
+  response: HttpResponse,
+) -> Result<HttpResponse, VideoError> {
+  let client = pool.get().await?;
+  match StoredResponse::capture(response).await {
+    Ok(stored) => {
+      IdempotencyRepository::complete(&client, user_id, key, fingerprint, &stored).await?;
+      Ok(stored.respond(false))
+    },
+    Err(response) => {
+      IdempotencyRepository::release(&client, user_id, key).await?;
+      Ok(response)
+    },
+  }
+}
+
+// Must be called from the server runtime. Every server runs it, deleting the
+// same rows twice is harmless
+pub fn spawn_key_cleanup(pool: Pool) {
+  actix_web::rt::spawn(async move {
+    loop {
+      let deleted = match pool.get().await {
+        Ok(client) => IdempotencyRepository::delete_expired(&client).await,
+        Err(err) => Err(err.into()),
+      };
+      if let Err(err) = deleted {
+        eprintln!("Idempotency key cleanup: {}", err);
+      }
+      actix_web::rt::time::sleep(KEY_CLEANUP_INTERVAL).await;
+    }
+  });
+}
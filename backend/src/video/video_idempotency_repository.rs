@@ -0,0 +1,79 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_idempotency::{Reservation, StoredResponse, KEY_LOCK, KEY_TTL};
+use deadpool_postgres::Client;
+use tokio_postgres::types::Json;
+
+pub struct IdempotencyRepository;
+
+impl IdempotencyRepository {
+  // Takes the key unless a request holds it or it completed. Expired keys and
+  // keys whose request stopped without releasing them are taken over
+  pub async fn reserve(
+    client: &Client,
+    user_id: i32,
+    key: &str,
+  ) -> Result<Reservation, VideoError> {
+    let stmt = client
+      .prepare(
+        "INSERT INTO idempotency_keys (user_id, key, locked_until, expires_at)
+         VALUES ($1, $2, now() + make_interval(secs => $3), now() + make_interval(secs => $4))
+         ON CONFLICT (user_id, key) DO UPDATE
+         SET fingerprint = NULL, response = NULL, locked_until = EXCLUDED.locked_until,
+             expires_at = EXCLUDED.expires_at, created_at = now()
+         WHERE idempotency_keys.expires_at < now()
+            OR (idempotency_keys.response IS NULL AND idempotency_keys.locked_until < now())
+         RETURNING key",
+      )
+      .await?;
+    let (lock, ttl) = (KEY_LOCK.as_secs_f64(), KEY_TTL.as_secs_f64());
+    if client.query_opt(&stmt, &[&user_id, &key, &lock, &ttl]).await?.is_some() {
+      return Ok(Reservation::Reserved);
+    }
+
+    let stmt = client
+      .prepare("SELECT fingerprint, response FROM idempotency_keys WHERE user_id = $1 AND key = $2")
+      .await?;
+    let row = match client.query_opt(&stmt, &[&user_id, &key]).await? {
+      Some(row) => row,
+      // Released in between, the caller may try again
+      None => return Ok(Reservation::InProgress),
+    };
+    let fingerprint: Option<String> = row.try_get("fingerprint")?;
+    let response: Option<Json<StoredResponse>> = row.try_get("response")?;
+    match (fingerprint, response) {
+      (Some(fingerprint), Some(response)) => Ok(Reservation::Completed(fingerprint, response.0)),
+      _ => Ok(Reservation::InProgress),
+    }
+  }
+
+  pub async fn complete(
+    client: &Client,
+    user_id: i32,
+    key: &str,
+    fingerprint: &str,
+    response: &StoredResponse,
+  ) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE idempotency_keys SET fingerprint = $3, response = $4
+         WHERE user_id = $1 AND key = $2",
+      )
+      .await?;
+    client.execute(&stmt, &[&user_id, &key, &fingerprint, &Json(response)]).await?;
+    Ok(())
+  }
+
+  pub async fn release(client: &Client, user_id: i32, key: &str) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2 AND response IS NULL")
+      .await?;
+    client.execute(&stmt, &[&user_id, &key]).await?;
+    Ok(())
+  }
+
+  // Keys only matter until they expire
+  pub async fn delete_expired(client: &Client) -> Result<u64, VideoError> {
+    let stmt = client.prepare("DELETE FROM idempotency_keys WHERE expires_at < now()").await?;
+    Ok(client.execute(&stmt, &[]).await?)
+  }
+}
@@ -0,0 +1,96 @@
+use crate::video::video_errors::{FailureKind, VideoError};
+use crate::video::video_recipe::Recipe;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::types::Json;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+  Queued,  // Also waiting for a retry until `run_after`
+  Running, // Claimed by a worker until `locked_until`
+  Succeeded,
+  DeadLettered, // Failed for good, an admin may requeue it
+  Cancelled,
+}
+
+impl JobStatus {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "queued" => Some(JobStatus::Queued),
+      "running" => Some(JobStatus::Running),
+      "succeeded" => Some(JobStatus::Succeeded),
+      "dead_lettered" => Some(JobStatus::DeadLettered),
+      "cancelled" => Some(JobStatus::Cancelled),
+      _ => None,
+    }
+  }
+}
+
+// One failed attempt, kept with the job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobFailure {
+  pub attempt: i32,
+  pub kind: FailureKind,
+  pub errors: Vec<String>, // The error chain, outermost first
+  pub failed_at: DateTime<Utc>,
+}
+
+impl JobFailure {
+  pub fn new(attempt: i32, err: &VideoError) -> Self {
+    JobFailure {
+      attempt,
+      kind: err.failure_kind(),
+      errors: err.chain(),
+      failed_at: Utc::now(),
+    }
+  }
+}
+
+// A recipe queued by a user, its output is saved under the id of the job
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionJob {
+  pub id: Uuid,
+  pub user_id: i32,
+  pub media_id: Option<Uuid>, // Source of the recipe clips that do not name one
+  pub recipe: Recipe,
+  pub callback_url: Option<String>, // Receives a webhook once the job succeeds or fails
+  pub status: JobStatus,
+  pub attempts: i32,
+  pub result: Option<serde_json::Value>, // The conversion response once it succeeded
+  pub error: Option<String>,             // Last error, `failures` has every attempt
+  pub failures: Vec<JobFailure>,
+  pub run_after: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+  pub started_at: Option<DateTime<Utc>>,
+  pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<Row> for ConversionJob {
+  type Error = VideoError;
+
+  fn try_from(row: Row) -> Result<Self, Self::Error> {
+    let status: String = row.try_get("status")?;
+    Ok(ConversionJob {
+      id: row.try_get("id")?,
+      user_id: row.try_get("user_id")?,
+      media_id: row.try_get("media_id")?,
+      recipe: row.try_get::<_, Json<Recipe>>("recipe")?.0,
+      callback_url: row.try_get("callback_url")?,
+      status: JobStatus::parse(&status)
+        .ok_or_else(|| VideoError::InvalidOption(format!("unknown job status {:?}", status)))?,
+      attempts: row.try_get("attempts")?,
+      result: row.try_get::<_, Option<Json<serde_json::Value>>>("result")?.map(|json| json.0),
+      error: row.try_get("error")?,
+      failures: row.try_get::<_, Json<Vec<JobFailure>>>("failures")?.0,
+      run_after: row.try_get("run_after")?,
+      created_at: row.try_get("created_at")?,
+      updated_at: row.try_get("updated_at")?,
+      started_at: row.try_get("started_at")?,
+      finished_at: row.try_get("finished_at")?,
+    })
+  }
+}
@@ -0,0 +1,314 @@
+use crate::auth::auth_user::{AdminUser, CurrentUser};
+use crate::common::responses::ApiResponse;
+use crate::video::video_controller::{resolve_recipe, video_error_response};
+use crate::video::video_cost::{CostEstimate, EstimateResponse};
+use crate::video::video_dto::{CreateJobRequest, RunRecipeRequest};
+use crate::video::video_errors::VideoError;
+use crate::video::video_idempotency::{fingerprint, idempotency_key, idempotent};
+use crate::video::video_job::ConversionJob;
+use crate::video::video_job_repository::JobRepository;
+use crate::video::video_job_schedule::{check_queue, max_queued_jobs};
+use crate::video::video_progress::{ProgressEvent, Stage};
+use crate::video::video_progress_hub::{
+  job_events, sse_stream, ws_stream, ProgressHub, Relay, KEEP_ALIVE,
+};
+use crate::video::video_recipe::Recipe;
+use crate::video::video_service::VideoService;
+use crate::video::video_storage::MediaStorage;
+use crate::video::video_webhook::{check_callback_url, private_callbacks_allowed};
+use crate::video::video_webhook_repository::WebhookRepository;
+use actix_http::ws::{hash_key, verify_handshake};
+use actix_web::body::BoxBody;
+use actix_web::http::header::{
+  HeaderValue, CACHE_CONTROL, LOCATION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use deadpool_postgres::{Client, Pool};
+use futures_util::Stream;
+use uuid::Uuid;
+
+// Queues a recipe, it runs on a job worker and `GET /jobs/{id}` reports its
+// progress. With an `Idempotency-Key`, a retry of the request gets the job
+// queued the first time
+pub async fn create_job(
+  req: HttpRequest,
+  user: CurrentUser,
+  body: web::Json<CreateJobRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let key = match idempotency_key(&req) {
+    Ok(key) => key,
+    Err(err) => return video_error_response(err),
+  };
+  let request = body.into_inner();
+  let fingerprint = fingerprint("POST /jobs", &serde_json::to_vec(&request).unwrap_or_default());
+  let user_id = user.id;
+  let queued = queue_job(user, request, pool.clone(), storage);
+  match idempotent(&pool, user_id, key, fingerprint, queued).await {
+    Ok(response) => response,
+    Err(err) => video_error_response(err),
+  }
+}
+
+// The recipe is checked and its cost estimated first so a queued job never
+// fails on input that could be refused right away
+async fn queue_job(
+  user: CurrentUser,
+  request: CreateJobRequest,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> HttpResponse {
+  if let Some(url) = request.callback_url.as_deref() {
+    if let Err(err) = check_callback_url(url, private_callbacks_allowed()).await {
+      return video_error_response(err);
+    }
+  }
+  let media_id = request.media_id;
+  let callback_url = request.callback_url.clone();
+  let (recipe, estimate) = match estimate_recipe(&user, request, &pool, storage).await {
+    Ok(estimated) => estimated,
+    Err(response) => return response,
+  };
+
+  let created = match pool.get().await {
+    Ok(client) => match admit(&client, user.id, &estimate, &recipe).await {
+      Ok(()) => create_with_callback(&client, user.id, media_id, &recipe, callback_url).await,
+      Err(err) => Err(err),
+    },
+    Err(err) => Err(err.into()),
+  };
+  match created {
+    Ok(job) => {
+      let mut response = ApiResponse::accepted("Job queued", Some(serde_json::json!(job)));
+      if let Ok(location) = HeaderValue::from_str(&format!("/jobs/{}", job.id)) {
+        response.headers_mut().insert(LOCATION, location);
+      }
+      response
+    },
+    Err(err) => video_error_response(err),
+  }
+}
+
+// Dry run of `POST /jobs`: the cost of the job and whether the tier of the
+// user allows it, nothing is queued
+pub async fn estimate_job(
+  user: CurrentUser,
+  body: web::Json<CreateJobRequest>,
+  pool: web::Data<Pool>,
+  storage: web::Data<MediaStorage>,
+) -> impl Responder {
+  let (recipe, estimate) = match estimate_recipe(&user, body.into_inner(), &pool, storage).await {
+    Ok(estimated) => estimated,
+    Err(response) => return response,
+  };
+  let tier = match pool.get().await {
+    Ok(client) => JobRepository::tier(&client, user.id).await,
+    Err(err) => Err(err.into()),
+  };
+  match tier {
+    Ok(tier) => {
+      let estimate = EstimateResponse::new(estimate, tier, &recipe);
+      ApiResponse::success("Job cost estimated", Some(serde_json::json!(estimate)))
+    },
+    Err(err) => video_error_response(err),
+  }
+}
+
+// The checked recipe of a job request with its cost, from the probes of its sources
+async fn estimate_recipe(
+  user: &CurrentUser,
+  request: CreateJobRequest,
+  pool: &Pool,
+  storage: web::Data<MediaStorage>,
+) -> Result<(Recipe, CostEstimate), HttpResponse> {
+  let CreateJobRequest {
+    media_id,
+    recipe_id,
+    recipe,
+    ..
+  } = request;
+  if let Some(media_id) = media_id {
+    storage.find_media(&media_id).await.map_err(video_error_response)?;
+  }
+  let request = RunRecipeRequest {
+    recipe_id,
+    recipe,
+  };
+  let recipe = resolve_recipe(Some(user.clone()), request, pool).await?;
+  let estimated = web::block(move || {
+    VideoService::check_recipe(&storage, &recipe)?;
+    let estimate = VideoService::estimate_recipe(&storage, media_id, &recipe)?;
+    Ok::<_, VideoError>((recipe, estimate))
+  })
+  .await;
+  match estimated {
+    Ok(Ok(estimated)) => Ok(estimated),
+    Ok(Err(err)) => Err(video_error_response(err)),
+    Err(err) => Err(ApiResponse::from_error(err)),
+  }
+}
+
+pub async fn get_job(
+  user: CurrentUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let job = match pool.get().await {
+    Ok(client) => JobRepository::find(&client, user.id, &path.into_inner()).await,
+    Err(err) => Err(err.into()),
+  };
+  match job {
+    Ok(job) => ApiResponse::success("Job retrieved", Some(serde_json::json!(job))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+pub async fn list_jobs(user: CurrentUser, pool: web::Data<Pool>) -> impl Responder {
+  let jobs = match pool.get().await {
+    Ok(client) => JobRepository::all(&client, user.id).await,
+    Err(err) => Err(err.into()),
+  };
+  match jobs {
+    Ok(jobs) => ApiResponse::success("Jobs retrieved", Some(serde_json::json!(jobs))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+// A queued job never starts. A running job stops at its next frame and drops
+// what it wrote, its worker hears of it through the progress channel
+pub async fn cancel_job(
+  user: CurrentUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let id = path.into_inner();
+  let client = match pool.get().await {
+    Ok(client) => client,
+    Err(err) => return video_error_response(err.into()),
+  };
+  let job = match JobRepository::cancel(&client, user.id, &id).await {
+    Ok(job) => job,
+    Err(err) => return video_error_response(err),
+  };
+  // Best effort, the worker also stops once it fails to renew its claim
+  let _ = JobRepository::notify(&client, &ProgressEvent::new(id, Stage::Cancelled)).await;
+  ApiResponse::success("Job cancelled", Some(serde_json::json!(job)))
+}
+
+// Refused with a 422 when the job costs more than the tier of the user allows,
+// and with a 429 while the queue or the user's share of it is full
+async fn admit(
+  client: &Client,
+  user_id: i32,
+  estimate: &CostEstimate,
+  recipe: &Recipe,
+) -> Result<(), VideoError> {
+  let load = JobRepository::queue_load(client, user_id).await?;
+  estimate.check(load.tier, recipe)?;
+  check_queue(&load, max_queued_jobs())
+}
+
+// The webhook secret is created before the job so the user can fetch it
+// before the first webhook arrives
+async fn create_with_callback(
+  client: &Client,
+  user_id: i32,
+  media_id: Option<Uuid>,
+  recipe: &Recipe,
+  callback_url: Option<String>,
+) -> Result<ConversionJob, VideoError> {
+  if callback_url.is_some() {
+    WebhookRepository::secret(client, user_id).await?;
+  }
+  JobRepository::create(client, user_id, media_id, recipe, callback_url.as_deref()).await
+}
+
+// Jobs of every user that failed for good, with the errors of each attempt
+pub async fn list_dead_letters(_: AdminUser, pool: web::Data<Pool>) -> impl Responder {
+  let jobs = match pool.get().await {
+    Ok(client) => JobRepository::dead_lettered(&client).await,
+    Err(err) => Err(err.into()),
+  };
+  match jobs {
+    Ok(jobs) => ApiResponse::success("Dead-lettered jobs retrieved", Some(serde_json::json!(jobs))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+// Once the cause is fixed, the job runs again with a fresh set of retries
+pub async fn requeue_job(
+  _: AdminUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let job = match pool.get().await {
+    Ok(client) => JobRepository::requeue(&client, &path.into_inner()).await,
+    Err(err) => Err(err.into()),
+  };
+  match job {
+    Ok(job) => ApiResponse::success("Job requeued", Some(serde_json::json!(job))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+// Subscribed before the job is read so nothing published in between is missed
+async fn follow_job(
+  user: &CurrentUser,
+  id: &Uuid,
+  pool: &Pool,
+  hub: &ProgressHub,
+) -> Result<impl Stream<Item = Relay>, VideoError> {
+  let receiver = hub.subscribe();
+  let job = {
+    let client = pool.get().await?;
+    JobRepository::find(&client, user.id, id).await?
+  };
+  Ok(job_events(ProgressEvent::from_job(&job), receiver, KEEP_ALIVE))
+}
+
+// Server-Sent Events: the current state of the job, then every progress event
+// until it succeeds or fails
+pub async fn job_events_stream(
+  user: CurrentUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+  hub: web::Data<ProgressHub>,
+) -> HttpResponse {
+  match follow_job(&user, &path.into_inner(), &pool, &hub).await {
+    Ok(events) => HttpResponse::Ok()
+      .content_type("text/event-stream")
+      .insert_header((CACHE_CONTROL, "no-cache"))
+      .streaming(sse_stream(Box::pin(events))),
+    Err(err) => video_error_response(err),
+  }
+}
+
+// Same events as `job_events_stream`, one text frame each over a WebSocket
+pub async fn job_events_socket(
+  req: HttpRequest,
+  payload: web::Payload,
+  user: CurrentUser,
+  path: web::Path<Uuid>,
+  pool: web::Data<Pool>,
+  hub: web::Data<ProgressHub>,
+) -> HttpResponse {
+  if let Err(err) = verify_handshake(req.head()) {
+    return HttpResponse::from(actix_http::Response::<BoxBody>::from(err));
+  }
+  let accept = match req.headers().get(SEC_WEBSOCKET_KEY) {
+    Some(key) => hash_key(key.as_bytes()),
+    None => return ApiResponse::bad_request("Sec-WebSocket-Key is required"),
+  };
+  let accept = match HeaderValue::from_bytes(&accept) {
+    Ok(accept) => accept,
+    Err(err) => return ApiResponse::from_error(err),
+  };
+  match follow_job(&user, &path.into_inner(), &pool, &hub).await {
+    Ok(events) => HttpResponse::SwitchingProtocols()
+      .upgrade("websocket")
+      .insert_header((SEC_WEBSOCKET_ACCEPT, accept))
+      .streaming(ws_stream(payload, Box::pin(events))),
+    Err(err) => video_error_response(err),
+  }
+}
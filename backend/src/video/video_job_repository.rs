@@ -0,0 +1,307 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_job::{ConversionJob, JobFailure, JobStatus};
+use crate::video::video_job_schedule::{QueueLoad, UserTier};
+use crate::video::video_progress::ProgressEvent;
+use crate::video::video_progress_hub::PROGRESS_CHANNEL;
+use crate::video::video_recipe::Recipe;
+use deadpool_postgres::{Client, GenericClient};
+use std::time::Duration;
+use tokio_postgres::types::{Json, ToSql};
+use uuid::Uuid;
+
+pub const MAX_LISTED_JOBS: i64 = 100;
+
+const JOB_COLUMNS: &str = "id, user_id, media_id, recipe, callback_url, status, attempts, \
+                           result, error, failures, run_after, created_at, updated_at, \
+                           started_at, finished_at";
+
+pub struct JobRepository;
+
+impl JobRepository {
+  pub async fn create(
+    client: &Client,
+    user_id: i32,
+    media_id: Option<Uuid>,
+    recipe: &Recipe,
+    callback_url: Option<&str>,
+  ) -> Result<ConversionJob, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "INSERT INTO conversion_jobs (id, user_id, media_id, recipe, callback_url)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {}",
+        JOB_COLUMNS
+      ))
+      .await?;
+    let id = Uuid::new_v4();
+    let row =
+      client.query_one(&stmt, &[&id, &user_id, &media_id, &Json(recipe), &callback_url]).await?;
+    ConversionJob::try_from(row)
+  }
+
+  pub async fn find(client: &Client, user_id: i32, id: &Uuid) -> Result<ConversionJob, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "SELECT {} FROM conversion_jobs WHERE id = $1 AND user_id = $2",
+        JOB_COLUMNS
+      ))
+      .await?;
+    match client.query_opt(&stmt, &[id, &user_id]).await? {
+      Some(row) => ConversionJob::try_from(row),
+      None => Err(VideoError::JobNotFound),
+    }
+  }
+
+  // Newest first
+  pub async fn all(client: &Client, user_id: i32) -> Result<Vec<ConversionJob>, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "SELECT {} FROM conversion_jobs WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2",
+        JOB_COLUMNS
+      ))
+      .await?;
+    let rows = client.query(&stmt, &[&user_id, &MAX_LISTED_JOBS]).await?;
+    rows.into_iter().map(ConversionJob::try_from).collect()
+  }
+
+  // Takes a queued job whose retry is due, or a running one whose worker
+  // stopped renewing its lease (the server was restarted mid-job). Jobs of
+  // users already running as many as their tier allows wait. Higher classes
+  // go first, then users with the fewest running jobs, then the user served
+  // least recently, so users of a class take turns whatever they queued. Rows
+  // locked by another worker are skipped so workers never wait on each other,
+  // claims made at the same moment may go over a user's limit
+  pub async fn claim(
+    client: &Client,
+    lease: Duration,
+  ) -> Result<Option<ConversionJob>, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "WITH classes AS (
+           SELECT * FROM unnest($2::text[], $3::int[], $4::bigint[])
+             AS classes (tier, priority, max_running)
+         ), running AS (
+           SELECT user_id, count(*) AS jobs FROM conversion_jobs
+           WHERE status = 'running' AND locked_until >= now()
+           GROUP BY user_id
+         ), claimed AS (
+           UPDATE conversion_jobs
+           SET status = 'running', attempts = attempts + 1, error = NULL,
+               locked_until = now() + make_interval(secs => $1),
+               started_at = now(), updated_at = now()
+           WHERE id = (
+             SELECT job.id FROM conversion_jobs job
+             JOIN users ON users.id = job.user_id
+             JOIN classes
+               ON classes.tier = CASE WHEN users.is_admin THEN 'admin' ELSE users.tier END
+             LEFT JOIN running ON running.user_id = job.user_id
+             WHERE ((job.status = 'queued' AND job.run_after <= now())
+                 OR (job.status = 'running' AND job.locked_until < now()))
+               AND COALESCE(running.jobs, 0) < classes.max_running
+             ORDER BY classes.priority DESC, COALESCE(running.jobs, 0),
+                      users.last_job_claimed_at NULLS FIRST, job.created_at
+             LIMIT 1
+             FOR UPDATE OF job SKIP LOCKED
+           )
+           RETURNING *
+         ), served AS (
+           UPDATE users SET last_job_claimed_at = now()
+           FROM claimed WHERE users.id = claimed.user_id
+         )
+         SELECT {} FROM claimed",
+        JOB_COLUMNS
+      ))
+      .await?;
+    let tiers: Vec<&str> = UserTier::ALL.iter().map(|tier| tier.as_str()).collect();
+    let priorities: Vec<i32> = UserTier::ALL.iter().map(|tier| tier.priority()).collect();
+    let max_running: Vec<i64> = UserTier::ALL.iter().map(|tier| tier.max_running()).collect();
+    let params: [&(dyn ToSql + Sync); 4] =
+      [&lease.as_secs_f64(), &tiers, &priorities, &max_running];
+    match client.query_opt(&stmt, &params).await? {
+      Some(row) => ConversionJob::try_from(row).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  pub async fn tier(client: &Client, user_id: i32) -> Result<UserTier, VideoError> {
+    let stmt = client.prepare("SELECT tier, is_admin FROM users WHERE id = $1").await?;
+    let row = client.query_one(&stmt, &[&user_id]).await?;
+    let tier: String = row.try_get("tier")?;
+    Ok(UserTier::of(&tier, row.try_get("is_admin")?))
+  }
+
+  // What a new job of the user would join
+  pub async fn queue_load(client: &Client, user_id: i32) -> Result<QueueLoad, VideoError> {
+    let stmt = client
+      .prepare(
+        "SELECT users.tier, users.is_admin,
+           (SELECT count(*) FROM conversion_jobs
+            WHERE user_id = users.id AND status IN ('queued', 'running')) AS pending,
+           (SELECT count(*) FROM conversion_jobs WHERE status = 'queued') AS queued
+         FROM users WHERE users.id = $1",
+      )
+      .await?;
+    let row = client.query_one(&stmt, &[&user_id]).await?;
+    let tier: String = row.try_get("tier")?;
+    Ok(QueueLoad {
+      tier: UserTier::of(&tier, row.try_get("is_admin")?),
+      pending: row.try_get("pending")?,
+      queued: row.try_get("queued")?,
+    })
+  }
+
+  // Keeps a running job claimed, returns false once the claim was lost
+  pub async fn renew(
+    client: &Client,
+    id: &Uuid,
+    attempt: i32,
+    lease: Duration,
+  ) -> Result<bool, VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE conversion_jobs
+         SET locked_until = now() + make_interval(secs => $3), updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'running'",
+      )
+      .await?;
+    Ok(client.execute(&stmt, &[id, &attempt, &lease.as_secs_f64()]).await? > 0)
+  }
+
+  // Only the claim identified by `attempt` may finish the job, a worker whose
+  // lease expired and was taken over changes nothing
+  pub async fn succeed(
+    client: &impl GenericClient,
+    id: &Uuid,
+    attempt: i32,
+    result: &serde_json::Value,
+  ) -> Result<bool, VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE conversion_jobs
+         SET status = 'succeeded', result = $3, locked_until = NULL,
+             finished_at = now(), updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'running'",
+      )
+      .await?;
+    Ok(client.execute(&stmt, &[id, &attempt, &Json(result)]).await? > 0)
+  }
+
+  // Queues the job again after `delay`, for failures that may not happen twice
+  pub async fn retry(
+    client: &impl GenericClient,
+    id: &Uuid,
+    attempt: i32,
+    failure: &JobFailure,
+    delay: Duration,
+  ) -> Result<bool, VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE conversion_jobs
+         SET status = 'queued', error = $3, failures = failures || $4,
+             run_after = now() + make_interval(secs => $5), locked_until = NULL,
+             updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'running'",
+      )
+      .await?;
+    let delay = delay.as_secs_f64();
+    let failures = Json([failure]);
+    Ok(client.execute(&stmt, &[id, &attempt, &failure.errors[0], &failures, &delay]).await? > 0)
+  }
+
+  pub async fn dead_letter(
+    client: &impl GenericClient,
+    id: &Uuid,
+    attempt: i32,
+    failure: &JobFailure,
+  ) -> Result<bool, VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE conversion_jobs
+         SET status = 'dead_lettered', error = $3, failures = failures || $4,
+             locked_until = NULL, finished_at = now(), updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'running'",
+      )
+      .await?;
+    Ok(client.execute(&stmt, &[id, &attempt, &failure.errors[0], &Json([failure])]).await? > 0)
+  }
+
+  // Every user's, most recent first
+  pub async fn dead_lettered(client: &Client) -> Result<Vec<ConversionJob>, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "SELECT {} FROM conversion_jobs WHERE status = 'dead_lettered'
+         ORDER BY finished_at DESC LIMIT $1",
+        JOB_COLUMNS
+      ))
+      .await?;
+    let rows = client.query(&stmt, &[&MAX_LISTED_JOBS]).await?;
+    rows.into_iter().map(ConversionJob::try_from).collect()
+  }
+
+  // Starts a dead-lettered job over with every attempt, the failures of the
+  // previous runs are kept
+  pub async fn requeue(client: &Client, id: &Uuid) -> Result<ConversionJob, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "UPDATE conversion_jobs
+         SET status = 'queued', attempts = 0, error = NULL, run_after = now(),
+             started_at = NULL, finished_at = NULL, updated_at = now()
+         WHERE id = $1 AND status = 'dead_lettered'
+         RETURNING {}",
+        JOB_COLUMNS
+      ))
+      .await?;
+    match client.query_opt(&stmt, &[id]).await? {
+      Some(row) => ConversionJob::try_from(row),
+      None => match Self::status(client, id).await? {
+        Some(_) => Err(VideoError::NotDeadLettered),
+        None => Err(VideoError::JobNotFound),
+      },
+    }
+  }
+
+  // Queued and running jobs only. The worker of a running job stops once it
+  // hears of it, its attempt no longer matches a running row
+  pub async fn cancel(
+    client: &Client,
+    user_id: i32,
+    id: &Uuid,
+  ) -> Result<ConversionJob, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "UPDATE conversion_jobs
+         SET status = 'cancelled', locked_until = NULL, finished_at = now(), updated_at = now()
+         WHERE id = $1 AND user_id = $2 AND status IN ('queued', 'running')
+         RETURNING {}",
+        JOB_COLUMNS
+      ))
+      .await?;
+    match client.query_opt(&stmt, &[id, &user_id]).await? {
+      Some(row) => ConversionJob::try_from(row),
+      None => {
+        Self::find(client, user_id, id).await?;
+        Err(VideoError::JobFinished)
+      },
+    }
+  }
+
+  pub async fn status(client: &Client, id: &Uuid) -> Result<Option<JobStatus>, VideoError> {
+    let stmt = client.prepare("SELECT status FROM conversion_jobs WHERE id = $1").await?;
+    let row = client.query_opt(&stmt, &[id]).await?;
+    Ok(row.and_then(|row| JobStatus::parse(row.get("status"))))
+  }
+
+  // The user and status of the job writing the output `id`, outputs are named after their job
+  pub async fn owner(client: &Client, id: &Uuid) -> Result<Option<(i32, JobStatus)>, VideoError> {
+    let stmt = client.prepare("SELECT user_id, status FROM conversion_jobs WHERE id = $1").await?;
+    let row = client.query_opt(&stmt, &[id]).await?;
+    Ok(row.and_then(|row| Some((row.get("user_id"), JobStatus::parse(row.get("status"))?))))
+  }
+
+  // Every server listening on the channel relays the event to its own subscribers
+  pub async fn notify(client: &Client, event: &ProgressEvent) -> Result<(), VideoError> {
+    let stmt = client.prepare("SELECT pg_notify($1, $2)").await?;
+    client.execute(&stmt, &[&PROGRESS_CHANNEL, &serde_json::to_string(event)?]).await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,106 @@
+use crate::video::video_errors::VideoError;
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+
+pub const DEFAULT_MAX_QUEUED_JOBS: i64 = 1000;
+// Sent as `Retry-After` with submissions refused while the queue is full
+pub const QUEUE_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// Scheduling class of a user, from `users.tier` and `users.is_admin`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserTier {
+  Free,
+  Paid,
+  Admin,
+}
+
+impl UserTier {
+  pub const ALL: [UserTier; 3] = [UserTier::Free, UserTier::Paid, UserTier::Admin];
+
+  pub fn of(tier: &str, is_admin: bool) -> Self {
+    match (tier, is_admin) {
+      (_, true) => UserTier::Admin,
+      ("paid", false) => UserTier::Paid,
+      _ => UserTier::Free,
+    }
+  }
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      UserTier::Free => "free",
+      UserTier::Paid => "paid",
+      UserTier::Admin => "admin",
+    }
+  }
+
+  // Jobs of a higher class are claimed first, users of the same class take turns
+  pub fn priority(self) -> i32 {
+    match self {
+      UserTier::Free => 0,
+      UserTier::Paid => 1,
+      UserTier::Admin => 2,
+    }
+  }
+
+  // Jobs of a user run at once, the next ones wait in the queue
+  pub fn max_running(self) -> i64 {
+    match self {
+      UserTier::Free => 1,
+      UserTier::Paid => 4,
+      UserTier::Admin => 8,
+    }
+  }
+
+  // Estimated cost of a single job, see `CostEstimate`. Under two minutes of
+  // 720p at 10 fps for the free tier
+  pub fn max_cost(self) -> u64 {
+    match self {
+      UserTier::Free => 1_000,
+      UserTier::Paid => 10_000,
+      UserTier::Admin => 100_000,
+    }
+  }
+
+  // Queued or running jobs of a user, more are refused
+  pub fn max_pending(self) -> i64 {
+    match self {
+      UserTier::Free => 10,
+      UserTier::Paid => 100,
+      UserTier::Admin => 500,
+    }
+  }
+}
+
+// Queued jobs of every user, `MAX_QUEUED_JOBS` or 1000
+pub fn max_queued_jobs() -> i64 {
+  env::var("MAX_QUEUED_JOBS")
+    .ok()
+    .and_then(|value| value.parse::<i64>().ok())
+    .unwrap_or(DEFAULT_MAX_QUEUED_JOBS)
+}
+
+// The queue as a new job of a user finds it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLoad {
+  pub tier: UserTier,
+  pub pending: i64, // Queued or running jobs of the user
+  pub queued: i64,  // Queued jobs of every user
+}
+
+// Refuses a job while the user's share of the queue or the queue itself is full.
+// Submissions made at the same moment may go a few jobs over
+pub fn check_queue(load: &QueueLoad, max_queued: i64) -> Result<(), VideoError> {
+  let retry_after = QUEUE_RETRY_AFTER.as_secs();
+  if load.pending >= load.tier.max_pending() {
+    return Err(VideoError::QueueFull(
+      format!("at most {} jobs may be queued or running at once", load.tier.max_pending()),
+      retry_after,
+    ));
+  }
+  if load.queued >= max_queued {
+    return Err(VideoError::QueueFull("the job queue is full".to_string(), retry_after));
+  }
+  Ok(())
+}
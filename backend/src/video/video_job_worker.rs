@@ -0,0 +1,260 @@
+use crate::video::video_errors::{FailureKind, VideoError};
+use crate::video::video_job::{ConversionJob, JobFailure, JobStatus};
+use crate::video::video_job_repository::JobRepository;
+use crate::video::video_progress::{CancelToken, ProgressEvent, ProgressTracker, Stage};
+use crate::video::video_progress_hub::ProgressHub;
+use crate::video::video_render_cache::{find_render, recipe_key};
+use crate::video::video_render_cache_repository::RenderCacheRepository;
+use crate::video::video_service::VideoService;
+use crate::video::video_storage::MediaStorage;
+use crate::video::video_webhook::WebhookPayload;
+use crate::video::video_webhook_repository::WebhookRepository;
+use actix_web::web;
+use deadpool_postgres::Pool;
+use std::env;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub const DEFAULT_JOB_WORKERS: usize = 2;
+pub const MAX_JOB_WORKERS: usize = 32;
+// How long a claim lasts without being renewed, a job left by a stopped
+// server is picked up again once it runs out
+pub const JOB_LEASE: Duration = Duration::from_secs(60);
+pub const LEASE_RENEWAL: Duration = Duration::from_secs(20);
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Attempts at a job before it is dead-lettered, claims of a job whose worker
+// stopped count as well
+pub const MAX_ATTEMPTS: i32 = 5;
+pub const RETRY_BASE_DELAY: Duration = Duration::from_secs(10);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(600);
+
+// Number of jobs run at once by this server, `JOB_WORKERS` or 2
+pub fn job_workers() -> usize {
+  env::var("JOB_WORKERS")
+    .ok()
+    .and_then(|value| value.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_JOB_WORKERS)
+    .min(MAX_JOB_WORKERS)
+}
+
+// Wait before the next attempt at a job that failed, None when it should be
+// dead-lettered instead. Doubles with every attempt
+pub fn retry_delay(kind: FailureKind, attempt: i32) -> Option<Duration> {
+  match kind {
+    FailureKind::Permanent => None,
+    FailureKind::Transient if attempt >= MAX_ATTEMPTS => None,
+    FailureKind::Transient => {
+      let factor = 2u32.saturating_pow(attempt.max(1) as u32 - 1);
+      Some(RETRY_BASE_DELAY.saturating_mul(factor).min(MAX_RETRY_DELAY))
+    },
+  }
+}
+
+// Runs queued conversion jobs. Every server runs its own workers, the queue
+// lives in Postgres so they share it and jobs outlive a restart
+#[derive(Clone)]
+pub struct JobWorker {
+  pool: Pool,
+  storage: MediaStorage,
+  hub: ProgressHub, // Cancellations arrive with the progress events
+}
+
+impl JobWorker {
+  pub fn new(pool: Pool, storage: MediaStorage, hub: ProgressHub) -> Self {
+    JobWorker {
+      pool,
+      storage,
+      hub,
+    }
+  }
+
+  // Must be called from the server runtime
+  pub fn spawn(&self, count: usize) {
+    for _ in 0..count {
+      let worker = self.clone();
+      actix_web::rt::spawn(async move { worker.run().await });
+    }
+  }
+
+  async fn run(self) {
+    loop {
+      match self.run_next().await {
+        Ok(true) => continue,
+        Ok(false) => {},
+        // The database is unreachable, try again later
+        Err(err) => eprintln!("Conversion job worker: {}", err),
+      }
+      actix_web::rt::time::sleep(POLL_INTERVAL).await;
+    }
+  }
+
+  // Claims and runs one job, false when the queue is empty
+  pub async fn run_next(&self) -> Result<bool, VideoError> {
+    let job = {
+      let client = self.pool.get().await?;
+      JobRepository::claim(&client, JOB_LEASE).await?
+    };
+    match job {
+      Some(job) => {
+        self.process(job).await?;
+        Ok(true)
+      },
+      None => Ok(false),
+    }
+  }
+
+  async fn process(&self, job: ConversionJob) -> Result<(), VideoError> {
+    // Every earlier claim ended without a result, the job likely brings the
+    // server down
+    if job.attempts > MAX_ATTEMPTS {
+      let abandoned = job.attempts - 1;
+      return self.record(&job, Err(VideoError::Abandoned(abandoned)), None).await;
+    }
+    // A render already cached for the user's sources and recipe is answered
+    // without running. Jobs that cannot be keyed fail while running instead
+    let key = recipe_key(&self.storage, &job.recipe, job.media_id).await.ok();
+    if let Some(key) = &key {
+      if let Some(response) = find_render(&self.pool, key, job.user_id).await? {
+        return self.record(&job, Ok(response), None).await;
+      }
+    }
+    self.publish(&ProgressEvent::new(job.id, Stage::Running)).await;
+    // Events leave the conversion thread through the channel and are published
+    // from here, the tracker already keeps them few
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let mut notices = self.hub.subscribe();
+    let cancel = CancelToken::default();
+    let conversion = {
+      let cancel = cancel.clone();
+      let storage = self.storage.clone();
+      let job = job.clone();
+      async move {
+        let destination = storage.prepare_output(&job.id, job.recipe.output.format).await?;
+        let mut progress = ProgressTracker::new(job.id, move |event| {
+          let _ = sender.send(event);
+        })
+        .with_cancel(cancel);
+        let response = web::block(move || {
+          VideoService::run_recipe(
+            &storage,
+            job.id,
+            job.media_id,
+            &destination,
+            &job.recipe,
+            &mut progress,
+          )
+        })
+        .await??;
+        Ok(serde_json::json!(response))
+      }
+    };
+    tokio::pin!(conversion);
+
+    // The lease is renewed while the conversion runs
+    let mut renewal = actix_web::rt::time::interval(LEASE_RENEWAL);
+    renewal.tick().await;
+    let result = loop {
+      tokio::select! {
+        result = &mut conversion => break result,
+        Some(event) = events.recv() => self.publish(&event).await,
+        Ok(notice) = notices.recv() => {
+          if notice.job_id == job.id && notice.stage == Stage::Cancelled {
+            cancel.cancel();
+          }
+        },
+        // A missed renewal is retried on the next tick, the lease outlasts two
+        // of them. A lost claim stops the conversion, which also catches a
+        // cancellation whose notification never arrived
+        _ = renewal.tick() => {
+          if let Ok(client) = self.pool.get().await {
+            if let Ok(false) = JobRepository::renew(&client, &job.id, job.attempts, JOB_LEASE).await {
+              cancel.cancel();
+            }
+          }
+        },
+      }
+    };
+
+    self.record(&job, result, key.as_deref()).await
+  }
+
+  // A render that succeeded is cached under `key` for the user of the job. The
+  // status, the cache entry and the webhook are written in one transaction, a
+  // finished job always has its webhook
+  async fn record(
+    &self,
+    job: &ConversionJob,
+    result: Result<serde_json::Value, VideoError>,
+    key: Option<&str>,
+  ) -> Result<(), VideoError> {
+    let mut client = self.pool.get().await?;
+    let transaction = client.transaction().await?;
+    // Only a finished job gets a webhook, retries are not reported
+    let (remove_output, event, webhook) = match result {
+      Ok(result) => {
+        match JobRepository::succeed(&transaction, &job.id, job.attempts, &result).await? {
+          true => {
+            if let Some(key) = key {
+              let format = job.recipe.output.format;
+              let user_id = job.user_id;
+              RenderCacheRepository::insert(&transaction, key, &job.id, format, user_id, &result)
+                .await?;
+            }
+            (
+              false,
+              Some(ProgressEvent::new(job.id, Stage::Succeeded)),
+              Some(WebhookPayload::succeeded(job, result)),
+            )
+          },
+          false => (false, None, None),
+        }
+      },
+      // The job was cancelled or taken over, there is nothing to record
+      Err(VideoError::Cancelled) => (false, None, None),
+      Err(err) => {
+        let failure = JobFailure::new(job.attempts, &err);
+        let error = &failure.errors[0];
+        let (recorded, event, webhook) = match retry_delay(failure.kind, job.attempts) {
+          Some(delay) => (
+            JobRepository::retry(&transaction, &job.id, job.attempts, &failure, delay).await?,
+            ProgressEvent::retrying(job.id, error),
+            None,
+          ),
+          None => (
+            JobRepository::dead_letter(&transaction, &job.id, job.attempts, &failure).await?,
+            ProgressEvent::failed(job.id, error),
+            Some(WebhookPayload::failed(job, error)),
+          ),
+        };
+        (recorded, recorded.then_some(event), webhook.filter(|_| recorded))
+      },
+    };
+    if let (Some(url), Some(payload)) = (&job.callback_url, webhook) {
+      WebhookRepository::enqueue(&transaction, job.user_id, url, &payload).await?;
+    }
+    transaction.commit().await?;
+
+    // A retry starts over and a dead-lettered job has no output
+    if remove_output {
+      self.storage.remove_output(&job.id, job.recipe.output.format).await?;
+    }
+    match event {
+      Some(event) => JobRepository::notify(&client, &event).await?,
+      // The output of a cancelled job is never served. A worker that lost its
+      // claim to another one leaves the output to it
+      None => {
+        if JobRepository::status(&client, &job.id).await? == Some(JobStatus::Cancelled) {
+          self.storage.remove_output(&job.id, job.recipe.output.format).await?;
+        }
+      },
+    }
+    Ok(())
+  }
+
+  // Best effort, a lost event is soon followed by a newer one
+  async fn publish(&self, event: &ProgressEvent) {
+    if let Ok(client) = self.pool.get().await {
+      let _ = JobRepository::notify(&client, event).await;
+    }
+  }
+}
@@ -0,0 +1,297 @@
+use crate::video::video_canvas::{fit_to_canvas, parse_color, Rect, BLACK};
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_text::{draw_label, label_scale};
+use image::{imageops, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const MIN_CELLS: usize = 2;
+pub const MAX_CELLS: usize = 9;
+pub const MAX_GUTTER: u32 = 64;
+pub const MAX_LABEL_LENGTH: usize = 32;
+pub const DEFAULT_LAYOUT_WIDTH: u32 = 640;
+pub const DEFAULT_PIP_SCALE: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutKind {
+  #[default]
+  Grid,
+  Pip, // The second clip is inset over the first one
+}
+
+// What a clip shows once it is shorter than the longest one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FillMode {
+  #[default]
+  Loop,
+  Hold,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipPosition {
+  TopLeft,
+  TopRight,
+  BottomLeft,
+  #[default]
+  BottomRight,
+}
+
+// DTO for one clip of a layout, times are in seconds
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CellSpec {
+  pub source: Option<Uuid>, // Uploaded media, defaults to the media of the URL
+  pub start: Option<f64>,
+  pub end: Option<f64>,
+  pub label: Option<String>,
+}
+
+impl CellSpec {
+  pub fn start_ms(&self) -> Option<u64> {
+    self.start.map(|start| (start * 1000.0).round() as u64)
+  }
+
+  pub fn end_ms(&self) -> Option<u64> {
+    self.end.map(|end| (end * 1000.0).round() as u64)
+  }
+}
+
+// DTO placing several clips on one canvas, played in sync
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LayoutSpec {
+  #[serde(rename = "type", default)]
+  pub kind: LayoutKind,
+  pub columns: Option<u32>, // Grid: defaults to a square-ish grid
+  #[serde(default)]
+  pub gutter: u32, // Space between cells and around the canvas, the inset margin for pip
+  pub background: Option<String>,
+  #[serde(default)]
+  pub fill: FillMode,
+  #[serde(default)]
+  pub position: PipPosition,
+  pub scale: Option<f32>, // Pip: inset width relative to the canvas
+  pub cells: Vec<CellSpec>,
+}
+
+// The decoded frames of a cell
+pub struct LayoutClip {
+  pub frames: Vec<Frame>,
+  pub label: Option<String>,
+}
+
+impl LayoutSpec {
+  pub fn validate(&self) -> Result<(), VideoError> {
+    let count = self.cells.len();
+    if self.kind == LayoutKind::Pip && count != 2 {
+      return Err(VideoError::InvalidOption("pip layouts need exactly 2 cells".to_string()));
+    }
+    if !(MIN_CELLS..=MAX_CELLS).contains(&count) {
+      return Err(VideoError::InvalidOption(format!(
+        "layouts need between {} and {} cells",
+        MIN_CELLS, MAX_CELLS
+      )));
+    }
+    if let Some(columns) = self.columns {
+      if !(1..=count as u32).contains(&columns) {
+        return Err(VideoError::InvalidOption(format!("columns must be between 1 and {}", count)));
+      }
+    }
+    if self.gutter > MAX_GUTTER {
+      return Err(VideoError::InvalidOption(format!(
+        "gutter must be at most {} pixels",
+        MAX_GUTTER
+      )));
+    }
+    if let Some(scale) = self.scale {
+      if !(0.1..=0.5).contains(&scale) {
+        return Err(VideoError::InvalidOption("scale must be between 0.1 and 0.5".to_string()));
+      }
+    }
+    if let Some(color) = &self.background {
+      parse_color(color)?;
+    }
+
+    for cell in &self.cells {
+      let start = cell.start.unwrap_or(0.0);
+      if !start.is_finite() || start < 0.0 {
+        return Err(VideoError::InvalidOption("cell start must be positive".to_string()));
+      }
+      if cell.end.is_some_and(|end| !end.is_finite() || end <= start) {
+        return Err(VideoError::InvalidOption("cell end must be after its start".to_string()));
+      }
+      if cell.label.as_ref().is_some_and(|label| label.chars().count() > MAX_LABEL_LENGTH) {
+        return Err(VideoError::InvalidOption(format!(
+          "labels are limited to {} characters",
+          MAX_LABEL_LENGTH
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn columns(&self) -> u32 {
+    let count = self.cells.len() as u32;
+    self.columns.unwrap_or_else(|| (count as f64).sqrt().ceil() as u32).clamp(1, count.max(1))
+  }
+
+  pub fn rows(&self) -> u32 {
+    (self.cells.len() as u32).div_ceil(self.columns())
+  }
+
+  // Width each cell is decoded at for a canvas `width` pixels wide
+  pub fn decode_width(&self, index: usize, width: u32) -> u32 {
+    match (self.kind, index) {
+      (LayoutKind::Pip, 0) => width,
+      (LayoutKind::Pip, _) => (width as f32 * self.pip_scale()).round() as u32,
+      (LayoutKind::Grid, _) => {
+        let columns = self.columns();
+        width.saturating_sub(self.gutter * (columns + 1)) / columns
+      },
+    }
+    .max(2)
+  }
+
+  fn pip_scale(&self) -> f32 {
+    self.scale.unwrap_or(DEFAULT_PIP_SCALE)
+  }
+
+  fn background(&self) -> Rgba<u8> {
+    self.background.as_deref().and_then(|color| parse_color(color).ok()).unwrap_or(BLACK)
+  }
+
+  // Canvas size and the rect of every cell, cell heights follow the aspect ratio of the first clips
+  pub fn geometry(
+    &self,
+    clips: &[LayoutClip],
+    width: u32,
+  ) -> Result<((u32, u32), Vec<Rect>), VideoError> {
+    let aspect = |index: usize| -> Result<f64, VideoError> {
+      let frame =
+        clips.get(index).and_then(|clip| clip.frames.first()).ok_or(VideoError::NoFrames)?;
+      Ok(frame.height() as f64 / frame.width() as f64)
+    };
+
+    match self.kind {
+      LayoutKind::Grid => {
+        let (columns, rows) = (self.columns(), self.rows());
+        let cell_width = self.decode_width(0, width);
+        let cell_height = ((cell_width as f64 * aspect(0)?).round() as u32).max(2);
+        let canvas_width = columns * cell_width + (columns + 1) * self.gutter;
+        let canvas_height = rows * cell_height + (rows + 1) * self.gutter;
+
+        let rects = (0..clips.len() as u32)
+          .map(|index| Rect {
+            x: self.gutter + (index % columns) * (cell_width + self.gutter),
+            y: self.gutter + (index / columns) * (cell_height + self.gutter),
+            width: cell_width,
+            height: cell_height,
+          })
+          .collect();
+        Ok(((canvas_width, canvas_height), rects))
+      },
+      LayoutKind::Pip => {
+        let main_height = (((width as f64) * aspect(0)?).round() as u32).max(2) & !1;
+        let inset_width = self.decode_width(1, width).min(width);
+        let inset_height = ((inset_width as f64 * aspect(1)?).round() as u32).clamp(2, main_height);
+        let margin = self.gutter;
+        let left = margin.min(width - inset_width);
+        let right = width.saturating_sub(inset_width + margin);
+        let top = margin.min(main_height - inset_height);
+        let bottom = main_height.saturating_sub(inset_height + margin);
+        let (x, y) = match self.position {
+          PipPosition::TopLeft => (left, top),
+          PipPosition::TopRight => (right, top),
+          PipPosition::BottomLeft => (left, bottom),
+          PipPosition::BottomRight => (right, bottom),
+        };
+
+        let main = Rect {
+          x: 0,
+          y: 0,
+          width,
+          height: main_height,
+        };
+        let inset = Rect {
+          x,
+          y,
+          width: inset_width,
+          height: inset_height,
+        };
+        Ok(((width, main_height), vec![main, inset]))
+      },
+    }
+  }
+
+  // Play every clip on one timeline as long as the longest clip, one output frame every `interval_ms`
+  pub fn compose(
+    &self,
+    clips: Vec<LayoutClip>,
+    width: u32,
+    interval_ms: u32,
+  ) -> Result<Vec<Frame>, VideoError> {
+    let ((canvas_width, canvas_height), rects) = self.geometry(&clips, width)?;
+    let background = self.background();
+
+    // Fit and label every source frame once instead of once per output frame
+    let cells: Vec<Vec<Frame>> = clips
+      .into_iter()
+      .zip(&rects)
+      .map(|(clip, rect)| {
+        let scale = label_scale(rect.height);
+        clip
+          .frames
+          .into_iter()
+          .map(|mut frame| {
+            frame.image = fit_to_canvas(&frame.image, rect.width, rect.height, background);
+            if let Some(label) = &clip.label {
+              draw_label(&mut frame.image, label, 2 * scale, 2 * scale, scale);
+            }
+            frame
+          })
+          .collect()
+      })
+      .collect();
+
+    let duration_ms = cells.iter().map(|frames| clip_duration_ms(frames)).max().unwrap_or(0);
+    let interval_ms = interval_ms.max(1);
+    let frame_count = duration_ms.div_ceil(interval_ms as u64).max(1);
+
+    let mut output = Vec::with_capacity(frame_count as usize);
+    for index in 0..frame_count {
+      let time_ms = index * interval_ms as u64;
+      let mut canvas = RgbaImage::from_pixel(canvas_width, canvas_height, background);
+      for (frames, rect) in cells.iter().zip(&rects) {
+        if let Some(frame) = frame_at(frames, time_ms, self.fill) {
+          imageops::replace(&mut canvas, &frame.image, rect.x as i64, rect.y as i64);
+        }
+      }
+      output.push(Frame::new(canvas, time_ms as i64, interval_ms));
+    }
+    Ok(output)
+  }
+}
+
+fn clip_duration_ms(frames: &[Frame]) -> u64 {
+  frames.iter().map(|frame| frame.delay_ms as u64).sum()
+}
+
+// The frame on screen `time_ms` after the clip started
+pub fn frame_at(frames: &[Frame], time_ms: u64, fill: FillMode) -> Option<&Frame> {
+  let duration = clip_duration_ms(frames);
+  let time_ms = match fill {
+    FillMode::Loop if duration > 0 => time_ms % duration,
+    _ => time_ms,
+  };
+
+  let mut elapsed = 0u64;
+  for frame in frames {
+    elapsed += frame.delay_ms as u64;
+    if time_ms < elapsed {
+      return Some(frame);
+    }
+  }
+  frames.last()
+}
@@ -0,0 +1,158 @@
+use crate::video::video_errors::VideoError;
+use image::error::ImageError;
+use image::Limits;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Hard limits on untrusted uploads. Containers, codecs, resolution and duration
+// are checked when a file is opened, decoded frames are counted while decoding
+pub const MAX_SOURCE_DIMENSION: u32 = 8192;
+pub const MAX_SOURCE_PIXELS: u64 = 7680 * 4320; // 8K UHD
+pub const MAX_SOURCE_DURATION_MS: u64 = 15 * 60 * 1000;
+pub const MAX_DECODED_FRAMES: u64 = 30_000;
+pub const MAX_DECODED_PIXELS: u64 = 64_000_000_000; // About 30 000 frames of 1080p
+pub const DEFAULT_JOB_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Demuxer short names, ffmpeg reports some demuxers as a list ("mov,mp4,m4a,3gp,3g2,mj2")
+pub const ALLOWED_FORMATS: &[&str] = &["mov", "mp4", "matroska", "webm", "avi", "mpegts", "gif"];
+pub const ALLOWED_CODECS: &[&str] =
+  &["h264", "hevc", "vp8", "vp9", "av1", "mpeg4", "mpeg2video", "mjpeg", "prores", "gif"];
+
+pub fn check_container(format: &str, codec: &str) -> Result<(), VideoError> {
+  if !format.split(',').any(|name| ALLOWED_FORMATS.contains(&name)) {
+    return Err(VideoError::UnsupportedMedia(format!("container {:?} is not allowed", format)));
+  }
+  if !ALLOWED_CODECS.contains(&codec) {
+    return Err(VideoError::UnsupportedMedia(format!("codec {:?} is not allowed", codec)));
+  }
+  Ok(())
+}
+
+pub fn check_resolution(width: u32, height: u32) -> Result<(), VideoError> {
+  if width == 0 || height == 0 {
+    return Err(VideoError::InvalidMedia(format!("empty {}x{} picture", width, height)));
+  }
+  if width > MAX_SOURCE_DIMENSION
+    || height > MAX_SOURCE_DIMENSION
+    || width as u64 * height as u64 > MAX_SOURCE_PIXELS
+  {
+    return Err(VideoError::MediaTooLarge(format!(
+      "{}x{} is above the maximum resolution",
+      width, height
+    )));
+  }
+  Ok(())
+}
+
+// An unknown duration (0) is left to the frame and pixel limits
+pub fn check_duration(duration_ms: u64) -> Result<(), VideoError> {
+  match duration_ms > MAX_SOURCE_DURATION_MS {
+    true => Err(VideoError::MediaTooLarge(format!(
+      "{} seconds is above the maximum of {} seconds",
+      duration_ms / 1000,
+      MAX_SOURCE_DURATION_MS / 1000
+    ))),
+    false => Ok(()),
+  }
+}
+
+// Limits of the `image` decoders reading uploads, GIFs and overlay images
+pub fn image_limits() -> Limits {
+  let mut limits = Limits::default();
+  limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+  limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+  limits.max_alloc = Some(MAX_SOURCE_PIXELS * 4);
+  limits
+}
+
+// Broken or hostile images are the upload's fault, not a server error
+pub fn image_error(err: ImageError) -> VideoError {
+  match err {
+    ImageError::Limits(err) => VideoError::MediaTooLarge(err.to_string()),
+    err => VideoError::InvalidMedia(err.to_string()),
+  }
+}
+
+// Logical screen of a GIF, checked before any frame is decoded since every
+// frame is composed on a canvas of that size
+pub fn check_gif_screen(path: &Path) -> Result<(), VideoError> {
+  let mut header = [0u8; 10];
+  File::open(path)?
+    .read_exact(&mut header)
+    .map_err(|_| VideoError::InvalidMedia("truncated GIF header".to_string()))?;
+  let width = u16::from_le_bytes([header[6], header[7]]) as u32;
+  let height = u16::from_le_bytes([header[8], header[9]]) as u32;
+  check_resolution(width, height)
+}
+
+// Wall-clock time a conversion may take, `CONVERSION_TIMEOUT_SECS` or 5 minutes
+pub fn job_timeout() -> Duration {
+  env::var("CONVERSION_TIMEOUT_SECS")
+    .ok()
+    .and_then(|value| value.parse::<u64>().ok())
+    .filter(|&seconds| seconds > 0)
+    .map(Duration::from_secs)
+    .unwrap_or(DEFAULT_JOB_TIMEOUT)
+}
+
+pub fn job_deadline() -> Instant {
+  Instant::now() + job_timeout()
+}
+
+// Every stage of a conversion checks the deadline between frames
+pub fn check_deadline(deadline: Option<Instant>) -> Result<(), VideoError> {
+  match deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+    true => Err(VideoError::TimedOut(job_timeout().as_secs())),
+    false => Ok(()),
+  }
+}
+
+// Counts every decoded frame, sampled or not, and stops the decoder once a
+// limit or the deadline is reached
+#[derive(Debug, Clone)]
+pub struct DecodeGuard {
+  max_frames: u64,
+  max_pixels: u64,
+  deadline: Option<Instant>,
+  frames: u64,
+  pixels: u64,
+}
+
+impl DecodeGuard {
+  pub fn new(deadline: Option<Instant>) -> Self {
+    Self::with_limits(MAX_DECODED_FRAMES, MAX_DECODED_PIXELS, deadline)
+  }
+
+  pub fn with_limits(max_frames: u64, max_pixels: u64, deadline: Option<Instant>) -> Self {
+    DecodeGuard {
+      max_frames,
+      max_pixels,
+      deadline,
+      frames: 0,
+      pixels: 0,
+    }
+  }
+
+  // Resolution can change mid-stream, so every frame is checked on its own
+  pub fn admit(&mut self, width: u32, height: u32) -> Result<(), VideoError> {
+    check_resolution(width, height)?;
+    self.frames += 1;
+    self.pixels += width as u64 * height as u64;
+    if self.frames > self.max_frames {
+      return Err(VideoError::MediaTooLarge(format!(
+        "more than {} decoded frames",
+        self.max_frames
+      )));
+    }
+    if self.pixels > self.max_pixels {
+      return Err(VideoError::MediaTooLarge(format!(
+        "more than {} decoded pixels",
+        self.max_pixels
+      )));
+    }
+    check_deadline(self.deadline)
+  }
+}
@@ -0,0 +1,117 @@
+use crate::video::video_errors::VideoError;
+
+pub const MAX_LUT_SIZE: usize = 128;
+
+// A 3D colour lookup table parsed from an Adobe/Resolve `.cube` file
+#[derive(Debug, Clone)]
+pub struct Lut3d {
+  size: usize,
+  domain_min: [f32; 3],
+  domain_max: [f32; 3],
+  table: Vec<[f32; 3]>, // Red varies fastest, then green, then blue
+}
+
+impl Lut3d {
+  pub fn parse(content: &str) -> Result<Self, VideoError> {
+    let mut size = None;
+    let mut domain_min = [0.0; 3];
+    let mut domain_max = [1.0; 3];
+    let mut table = Vec::new();
+
+    for line in content.lines() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let mut parts = line.split_whitespace();
+      let keyword = parts.next().unwrap_or_default();
+      match keyword {
+        "TITLE" => {},
+        "LUT_1D_SIZE" => return Err(invalid("1D LUTs are not supported")),
+        "LUT_3D_SIZE" => {
+          let value: usize = parse_value(parts.next())?;
+          if !(2..=MAX_LUT_SIZE).contains(&value) {
+            return Err(invalid(&format!("LUT size must be between 2 and {}", MAX_LUT_SIZE)));
+          }
+          size = Some(value);
+          table.reserve(value * value * value);
+        },
+        "DOMAIN_MIN" => domain_min = parse_triplet(parts)?,
+        "DOMAIN_MAX" => domain_max = parse_triplet(parts)?,
+        // Resolve's form of the domain, the same range for every channel
+        "LUT_3D_INPUT_RANGE" => {
+          let (min, max): (f32, f32) = (parse_value(parts.next())?, parse_value(parts.next())?);
+          (domain_min, domain_max) = ([min; 3], [max; 3]);
+        },
+        // Other keywords, vendors add their own
+        _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {},
+        _ => table.push(parse_triplet(line.split_whitespace())?),
+      }
+    }
+
+    let size = size.ok_or_else(|| invalid("missing LUT_3D_SIZE"))?;
+    if table.len() != size * size * size {
+      return Err(invalid(&format!(
+        "expected {} entries, found {}",
+        size * size * size,
+        table.len()
+      )));
+    }
+    if (0..3).any(|channel| domain_max[channel] <= domain_min[channel]) {
+      return Err(invalid("DOMAIN_MAX must be greater than DOMAIN_MIN"));
+    }
+
+    Ok(Lut3d {
+      size,
+      domain_min,
+      domain_max,
+      table,
+    })
+  }
+
+  // Map an RGB colour through the table with trilinear interpolation
+  pub fn apply(&self, rgb: [u8; 3]) -> [u8; 3] {
+    let max_index = (self.size - 1) as f32;
+    let mut position = [0f32; 3];
+    for channel in 0..3 {
+      let value = rgb[channel] as f32 / 255.0;
+      let range = self.domain_max[channel] - self.domain_min[channel];
+      let normalized = ((value - self.domain_min[channel]) / range).clamp(0.0, 1.0);
+      position[channel] = normalized * max_index;
+    }
+
+    let lower = position.map(|p| p.floor() as usize);
+    let upper = lower.map(|l| (l + 1).min(self.size - 1));
+    let fraction =
+      [position[0] - lower[0] as f32, position[1] - lower[1] as f32, position[2] - lower[2] as f32];
+
+    let mut result = [0f32; 3];
+    for corner in 0..8 {
+      // Each bit of the corner picks the lower or upper sample on one axis
+      let side = |axis: usize| (corner >> axis) & 1;
+      let index = |axis: usize| [lower[axis], upper[axis]][side(axis)];
+      let weight: f32 =
+        (0..3).map(|axis| [1.0 - fraction[axis], fraction[axis]][side(axis)]).product();
+
+      let entry = self.table[index(0) + index(1) * self.size + index(2) * self.size * self.size];
+      for channel in 0..3 {
+        result[channel] += entry[channel] * weight;
+      }
+    }
+
+    result.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+  }
+}
+
+fn parse_value<T: std::str::FromStr>(value: Option<&str>) -> Result<T, VideoError> {
+  value.and_then(|value| value.parse().ok()).ok_or_else(|| invalid("malformed value"))
+}
+
+fn parse_triplet<'a>(mut parts: impl Iterator<Item = &'a str>) -> Result<[f32; 3], VideoError> {
+  Ok([parse_value(parts.next())?, parse_value(parts.next())?, parse_value(parts.next())?])
+}
+
+fn invalid(message: &str) -> VideoError {
+  VideoError::InvalidLut(message.to_string())
+}
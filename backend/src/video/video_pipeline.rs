@@ -0,0 +1,300 @@
+use crate::video::video_dedupe::{DedupeStats, Deduper};
+use crate::video::video_encoder::{quantize, EncoderOptions, GifWriter};
+use crate::video::video_errors::VideoError;
+use crate::video::video_filters::FilterChain;
+use crate::video::video_frame::Frame;
+use crate::video::video_frame_store::{write_frame, FrameStoreWriter};
+use crate::video::video_limits::check_deadline;
+use std::env;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+// Frames queued between the decoder and dedupe, a full queue blocks the stage before it
+pub const STAGE_CAPACITY: usize = 4;
+// Frames queued in front of and behind every worker
+pub const WORKER_CAPACITY: usize = 2;
+pub const MAX_WORKERS: usize = 64;
+
+// Most frames alive at once: the queues plus the frames held by the stages
+// themselves (decoder and dedupe hold one back to know its delay)
+pub fn frame_ceiling(capacity: usize, workers: usize) -> usize {
+  capacity + workers * (2 * WORKER_CAPACITY + 1) + 6
+}
+
+// Size of the worker pool, `CONVERSION_WORKERS` or one worker per core
+pub fn worker_count() -> usize {
+  env::var("CONVERSION_WORKERS")
+    .ok()
+    .and_then(|value| value.parse::<usize>().ok())
+    .filter(|&workers| workers > 0)
+    .or_else(|| thread::available_parallelism().ok().map(|cores| cores.get()))
+    .unwrap_or(1)
+    .min(MAX_WORKERS)
+}
+
+// Decode -> dedupe -> workers (scale, filters, quantize, store) -> encode. Decode,
+// dedupe and encode are sequential; frames are dealt to the workers in turn and
+// collected in the same order, so the output order never depends on which
+// worker is the fastest. Peak memory depends on the queue capacities and the
+// number of workers, not on the clip length. Every stage stops at the deadline
+// of the encoder options
+pub struct StreamingPipeline<'a> {
+  pub filters: &'a FilterChain,
+  pub dedupe_threshold: Option<f32>,
+  pub scale_width: Option<u32>, // Frames wider than this are scaled down by the workers
+  pub encoder: EncoderOptions,
+  pub capacity: usize,
+  pub workers: usize,
+}
+
+// What the encode stage wrote
+#[derive(Debug, Clone, Default)]
+pub struct StreamSummary {
+  pub width: u32,
+  pub height: u32,
+  pub frame_count: usize,
+  pub duration_ms: u64,
+  pub dedupe: Option<DedupeStats>,
+  pub peak_frames_in_flight: usize,
+}
+
+impl StreamingPipeline<'_> {
+  // Blocking: `source` pushes decoded frames into the sink it is given and
+  // fails with `StageClosed` once a later stage has stopped. Frames are also
+  // written to `frames_dir` when given, each worker writes the PNG of its own
+  // frames and the encode stage only records them
+  pub fn run<S, W>(
+    &self,
+    source: S,
+    writer: W,
+    frames_dir: Option<&Path>,
+  ) -> Result<StreamSummary, VideoError>
+  where
+    S: FnOnce(&mut dyn FnMut(Frame) -> Result<(), VideoError>) -> Result<(), VideoError> + Send,
+    W: Write + Send,
+  {
+    let store = frames_dir.map(FrameStoreWriter::create).transpose()?;
+    let gauge = Arc::new(FrameGauge::default());
+    let capacity = self.capacity.max(1);
+    let workers = self.workers.clamp(1, MAX_WORKERS);
+    let (decoded_tx, decoded_rx) = sync_channel::<Tracked>(capacity);
+    let (worker_inputs, input_queues): (Vec<_>, Vec<_>) =
+      (0..workers).map(|_| sync_channel::<Tracked>(WORKER_CAPACITY)).unzip();
+    let (worker_outputs, output_queues): (Vec<_>, Vec<_>) =
+      (0..workers).map(|_| sync_channel::<Quantized>(WORKER_CAPACITY)).unzip();
+
+    let (decoded, processed, worked, encoded) = thread::scope(|scope| {
+      let decode = scope.spawn(|| {
+        // Owned by the thread so the queue closes once the source returns
+        let sender = decoded_tx;
+        source(&mut |frame| {
+          let tracked = Tracked {
+            frame,
+            index: 0,
+            _ticket: FrameTicket::new(&gauge),
+          };
+          sender.send(tracked).map_err(|_| VideoError::StageClosed)
+        })
+      });
+      let process = scope.spawn(|| self.process(decoded_rx, worker_inputs));
+      let pool: Vec<_> = input_queues
+        .into_iter()
+        .zip(worker_outputs)
+        .map(|(input, output)| scope.spawn(move || self.work(input, output, frames_dir)))
+        .collect();
+      let encode = scope.spawn(|| encode(output_queues, writer, store, self.encoder.deadline));
+
+      let worked: Vec<_> = pool.into_iter().map(join).collect();
+      (join(decode), join(process), worked, join(encode))
+    });
+
+    // An upstream failure ends the stream early, so it wins over the result of
+    // the stages after it
+    decoded.or_else(closed)?;
+    let dedupe = processed.or_else(|err| closed(err).map(|_| None))?;
+    for result in worked {
+      result.or_else(closed)?;
+    }
+    let summary = StreamSummary {
+      dedupe,
+      peak_frames_in_flight: gauge.peak.load(Ordering::SeqCst),
+      ..encoded?
+    };
+    debug_assert!(summary.peak_frames_in_flight <= frame_ceiling(capacity, workers));
+    Ok(summary)
+  }
+
+  // A duplicate adds its delay to the kept frame, so kept frames are sent one
+  // step late. Kept frames are numbered, frame `n` goes to worker `n % workers`
+  fn process(
+    &self,
+    input: Receiver<Tracked>,
+    workers: Vec<SyncSender<Tracked>>,
+  ) -> Result<Option<DedupeStats>, VideoError> {
+    let mut deduper = self.dedupe_threshold.map(Deduper::new);
+    let mut pending: Option<Tracked> = None;
+    let mut sent = 0;
+    let mut send = |mut tracked: Tracked| {
+      let worker = &workers[sent % workers.len()];
+      tracked.index = sent;
+      sent += 1;
+      worker.send(tracked).map_err(|_| VideoError::StageClosed)
+    };
+
+    for tracked in input {
+      check_deadline(self.encoder.deadline)?;
+      if let Some(deduper) = deduper.as_mut() {
+        if deduper.is_duplicate(&tracked.frame) {
+          if let Some(kept) = pending.as_mut() {
+            kept.frame.delay_ms += tracked.frame.delay_ms;
+          }
+          continue;
+        }
+      }
+
+      // Without dedupe the delay of a frame never changes
+      let ready = match deduper {
+        Some(_) => pending.replace(tracked),
+        None => Some(tracked),
+      };
+      if let Some(ready) = ready {
+        send(ready)?;
+      }
+    }
+    if let Some(last) = pending {
+      send(last)?;
+    }
+    Ok(deduper.map(|deduper| deduper.stats()))
+  }
+
+  fn work(
+    &self,
+    input: Receiver<Tracked>,
+    output: SyncSender<Quantized>,
+    frames_dir: Option<&Path>,
+  ) -> Result<(), VideoError> {
+    for mut tracked in input {
+      check_deadline(self.encoder.deadline)?;
+      tracked.frame.scale_to_width(self.scale_width);
+      self.filters.apply_frame(&mut tracked.frame);
+      let gif_frame = quantize(&tracked.frame, &self.encoder)?;
+      if let Some(directory) = frames_dir {
+        write_frame(directory, tracked.index, &tracked.frame)?;
+      }
+      output
+        .send(Quantized {
+          tracked,
+          gif_frame,
+        })
+        .map_err(|_| VideoError::StageClosed)?;
+    }
+    Ok(())
+  }
+}
+
+// Reads the workers in the order frames were dealt to them, the stream ends at
+// the first worker that has nothing left
+fn encode<W: Write>(
+  workers: Vec<Receiver<Quantized>>,
+  writer: W,
+  mut store: Option<FrameStoreWriter>,
+  deadline: Option<Instant>,
+) -> Result<StreamSummary, VideoError> {
+  let mut gif = None;
+  let mut summary = StreamSummary::default();
+  let mut writer = Some(writer);
+  let mut failed = Ok(());
+
+  for Quantized {
+    tracked,
+    gif_frame,
+  } in (0..).map_while(|index| workers[index % workers.len()].recv().ok())
+  {
+    let frame = &tracked.frame;
+    if gif.is_none() {
+      let writer = writer.take().expect("the writer is only taken once");
+      gif = Some(GifWriter::new(writer, frame.width(), frame.height())?);
+      summary.width = frame.width();
+      summary.height = frame.height();
+    }
+    let written = check_deadline(deadline)
+      .and_then(|_| gif.as_mut().map_or(Ok(()), |gif| gif.write(gif_frame, frame.delay_ms)));
+    if let Err(err) = written {
+      failed = Err(err);
+      break;
+    }
+    if let Some(store) = store.as_mut() {
+      store.record(tracked.index, frame);
+    }
+    summary.frame_count += 1;
+    summary.duration_ms += frame.delay_ms as u64;
+  }
+
+  // Also reached when this or an earlier stage failed, the frames written so
+  // far still make a valid GIF once the trailer follows them
+  let finished = match gif {
+    Some(gif) => gif.finish().map(drop),
+    None => Err(VideoError::NoFrames),
+  };
+  failed?;
+  finished?;
+  if let Some(store) = store {
+    store.finish()?;
+  }
+  Ok(summary)
+}
+
+// `StageClosed` only means that a later stage failed, its own error is reported instead
+fn closed(err: VideoError) -> Result<(), VideoError> {
+  match err {
+    VideoError::StageClosed => Ok(()),
+    err => Err(err),
+  }
+}
+
+fn join<T>(handle: thread::ScopedJoinHandle<'_, Result<T, VideoError>>) -> Result<T, VideoError> {
+  match handle.join() {
+    Ok(result) => result,
+    Err(panic) => std::panic::resume_unwind(panic),
+  }
+}
+
+// A frame travelling through the stages with the ticket counting it in flight
+struct Tracked {
+  frame: Frame,
+  index: usize, // Position in the output, set by dedupe
+  _ticket: FrameTicket,
+}
+
+struct Quantized {
+  tracked: Tracked,
+  gif_frame: gif::Frame<'static>,
+}
+
+#[derive(Default)]
+struct FrameGauge {
+  current: AtomicUsize,
+  peak: AtomicUsize,
+}
+
+// Counts a decoded frame until it is written or merged into another one
+struct FrameTicket(Arc<FrameGauge>);
+
+impl FrameTicket {
+  fn new(gauge: &Arc<FrameGauge>) -> Self {
+    let current = gauge.current.fetch_add(1, Ordering::SeqCst) + 1;
+    gauge.peak.fetch_max(current, Ordering::SeqCst);
+    FrameTicket(Arc::clone(gauge))
+  }
+}
+
+impl Drop for FrameTicket {
+  fn drop(&mut self) {
+    self.0.current.fetch_sub(1, Ordering::SeqCst);
+  }
+}
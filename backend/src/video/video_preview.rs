@@ -0,0 +1,165 @@
+use crate::video::ffmpeg::{stream_frames, DecodeOptions};
+use crate::video::video_encoder::{quantize_fixed, GifWriter};
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use crate::video::video_gif::{is_gif, stream_gif};
+use actix_web::web::Bytes;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const PREVIEW_WIDTH: u32 = 240;
+pub const PREVIEW_FPS: u32 = 5;
+// Decoding stops once this is spent, the rest of a preview takes a few milliseconds
+pub const PREVIEW_BUDGET: Duration = Duration::from_millis(800);
+pub const PREVIEW_TTL: Duration = Duration::from_secs(60);
+pub const PREVIEW_CACHE_ENTRIES: usize = 64;
+
+// A low resolution GIF that is never saved, `truncated` when the budget ran out
+// before the end of the clip
+#[derive(Debug, Clone)]
+pub struct Preview {
+  pub bytes: Bytes,
+  pub frame_count: usize,
+  pub truncated: bool,
+}
+
+pub fn preview_fps(fps: u32) -> u32 {
+  fps.min(PREVIEW_FPS)
+}
+
+pub fn preview_width(width: Option<u32>) -> u32 {
+  width.map_or(PREVIEW_WIDTH, |width| width.min(PREVIEW_WIDTH))
+}
+
+// Time left to decode the sources of one preview
+pub struct PreviewBudget {
+  deadline: Instant,
+  truncated: bool,
+}
+
+impl PreviewBudget {
+  pub fn start() -> Self {
+    Self::with_duration(PREVIEW_BUDGET)
+  }
+
+  pub fn with_duration(duration: Duration) -> Self {
+    PreviewBudget {
+      deadline: Instant::now() + duration,
+      truncated: false,
+    }
+  }
+
+  pub fn is_spent(&self) -> bool {
+    Instant::now() >= self.deadline
+  }
+
+  // Blocking: the frames decoded before the deadline. The decoder gets the
+  // deadline too, so slow seeks and stretches where no frame is kept stop there
+  pub fn decode(
+    &mut self,
+    source: &Path,
+    options: &DecodeOptions,
+  ) -> Result<Vec<Frame>, VideoError> {
+    if self.is_spent() {
+      self.truncated = true;
+      return Ok(Vec::new());
+    }
+
+    let mut frames = Vec::new();
+    let deadline = self.deadline;
+    let options = &DecodeOptions {
+      deadline: Some(deadline),
+      ..options.clone()
+    };
+    let sink = |frame| {
+      frames.push(frame);
+      match Instant::now() >= deadline {
+        true => Err(VideoError::StageClosed),
+        false => Ok(()),
+      }
+    };
+    let decoded = match is_gif(source)? {
+      true => stream_gif(source, options, sink),
+      false => stream_frames(source, options, sink),
+    };
+    match decoded {
+      Ok(()) => Ok(frames),
+      Err(VideoError::StageClosed | VideoError::TimedOut(_)) => {
+        self.truncated = true;
+        Ok(frames)
+      },
+      Err(err) => Err(err),
+    }
+  }
+
+  // Scales the frames down to the preview width and encodes them with the fixed palette
+  pub fn encode(&self, frames: Vec<Frame>, width: u32) -> Result<Preview, VideoError> {
+    let mut writer = None;
+    let frame_count = frames.len();
+    for mut frame in frames {
+      frame.scale_to_width(Some(width));
+      let gif = match writer.as_mut() {
+        Some(gif) => gif,
+        None => writer.insert(GifWriter::new(Vec::new(), frame.width(), frame.height())?),
+      };
+      gif.write(quantize_fixed(&frame)?, frame.delay_ms)?;
+    }
+    let bytes = writer.ok_or(VideoError::NoFrames)?.finish()?;
+    Ok(Preview {
+      bytes: Bytes::from(bytes),
+      frame_count,
+      truncated: self.truncated,
+    })
+  }
+}
+
+// Recent previews by request, shared by every worker. Entries expire after
+// the TTL and the oldest one makes room when the cache is full
+pub struct PreviewCache {
+  ttl: Duration,
+  capacity: usize,
+  entries: Mutex<HashMap<String, (Instant, Preview)>>,
+}
+
+impl PreviewCache {
+  pub fn new(ttl: Duration, capacity: usize) -> Self {
+    PreviewCache {
+      ttl,
+      capacity: capacity.max(1),
+      entries: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn get(&self, key: &str) -> Option<Preview> {
+    let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match entries.get(key) {
+      Some((created, preview)) if created.elapsed() < self.ttl => Some(preview.clone()),
+      Some(_) => {
+        entries.remove(key);
+        None
+      },
+      None => None,
+    }
+  }
+
+  pub fn insert(&self, key: String, preview: Preview) {
+    let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    entries.retain(|_, (created, _)| created.elapsed() < self.ttl);
+    if entries.len() >= self.capacity && !entries.contains_key(&key) {
+      let oldest =
+        entries.iter().min_by_key(|(_, (created, _))| *created).map(|(key, _)| key.clone());
+      if let Some(oldest) = oldest {
+        entries.remove(&oldest);
+      }
+    }
+    entries.insert(key, (Instant::now(), preview));
+  }
+}
+
+impl Default for PreviewCache {
+  fn default() -> Self {
+    Self::new(PREVIEW_TTL, PREVIEW_CACHE_ENTRIES)
+  }
+}
@@ -0,0 +1,234 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_job::{ConversionJob, JobStatus};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Events of one stage are sent at most this often, stage changes go out at once
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+// Postgres refuses NOTIFY payloads above 8000 bytes
+pub const MAX_EVENT_ERROR_CHARS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+  Queued,
+  Running, // Claimed by a worker, nothing decoded yet
+  Decoding,
+  Processing,
+  Encoding,
+  Succeeded,
+  Failed,
+  Cancelled,
+}
+
+impl Stage {
+  pub fn is_finished(self) -> bool {
+    matches!(self, Stage::Succeeded | Stage::Failed | Stage::Cancelled)
+  }
+
+  // Share of the whole conversion done before and at the end of the stage,
+  // decoding is usually the longest part
+  fn span(self) -> (f64, f64) {
+    match self {
+      Stage::Queued | Stage::Running | Stage::Cancelled => (0.0, 0.0),
+      Stage::Decoding => (0.0, 0.5),
+      Stage::Processing => (0.5, 0.6),
+      Stage::Encoding => (0.6, 1.0),
+      Stage::Succeeded | Stage::Failed => (1.0, 1.0),
+    }
+  }
+}
+
+impl From<JobStatus> for Stage {
+  fn from(status: JobStatus) -> Self {
+    match status {
+      JobStatus::Queued => Stage::Queued,
+      JobStatus::Running => Stage::Running,
+      JobStatus::Succeeded => Stage::Succeeded,
+      JobStatus::DeadLettered => Stage::Failed,
+      JobStatus::Cancelled => Stage::Cancelled,
+    }
+  }
+}
+
+// Progress of one job. `frames_total` is estimated from the length of the
+// clips while decoding, 0 when unknown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressEvent {
+  pub job_id: Uuid,
+  pub stage: Stage,
+  pub frames_done: u64,
+  pub frames_total: u64,
+  pub eta_ms: Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub error: Option<String>,
+}
+
+impl ProgressEvent {
+  pub fn new(job_id: Uuid, stage: Stage) -> Self {
+    ProgressEvent {
+      job_id,
+      stage,
+      frames_done: 0,
+      frames_total: 0,
+      eta_ms: None,
+      error: None,
+    }
+  }
+
+  pub fn failed(job_id: Uuid, error: &str) -> Self {
+    Self::new(job_id, Stage::Failed).with_error(error)
+  }
+
+  // Back in the queue after a failure that may not happen again
+  pub fn retrying(job_id: Uuid, error: &str) -> Self {
+    Self::new(job_id, Stage::Queued).with_error(error)
+  }
+
+  fn with_error(self, error: &str) -> Self {
+    ProgressEvent {
+      error: Some(error.chars().take(MAX_EVENT_ERROR_CHARS).collect()),
+      ..self
+    }
+  }
+
+  // What is known of a job from its row, sent first to a new subscriber
+  pub fn from_job(job: &ConversionJob) -> Self {
+    match job.status {
+      JobStatus::DeadLettered => Self::failed(job.id, job.error.as_deref().unwrap_or_default()),
+      status => Self::new(job.id, status.into()),
+    }
+  }
+}
+
+// Set when a job is cancelled, the conversion checks it between frames
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+// Follows a blocking conversion and hands throttled events to `publish`. The
+// conversion also asks it whether to go on
+pub struct ProgressTracker {
+  job_id: Uuid,
+  started: Instant,
+  last_sent: Option<Instant>,
+  interval: Duration,
+  stage: Stage,
+  frames_done: u64,
+  frames_total: u64,
+  publish: Box<dyn FnMut(ProgressEvent) + Send>,
+  cancel: CancelToken,
+}
+
+impl ProgressTracker {
+  pub fn new(job_id: Uuid, publish: impl FnMut(ProgressEvent) + Send + 'static) -> Self {
+    Self::with_interval(job_id, PROGRESS_INTERVAL, publish)
+  }
+
+  pub fn with_interval(
+    job_id: Uuid,
+    interval: Duration,
+    publish: impl FnMut(ProgressEvent) + Send + 'static,
+  ) -> Self {
+    ProgressTracker {
+      job_id,
+      started: Instant::now(),
+      last_sent: None,
+      interval,
+      stage: Stage::Running,
+      frames_done: 0,
+      frames_total: 0,
+      publish: Box::new(publish),
+      cancel: CancelToken::default(),
+    }
+  }
+
+  pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+    self.cancel = cancel;
+    self
+  }
+
+  // For conversions nobody follows
+  pub fn none() -> Self {
+    Self::new(Uuid::nil(), |_| {})
+  }
+
+  pub fn start(&mut self, stage: Stage, frames_total: u64) {
+    self.stage = stage;
+    self.frames_done = 0;
+    self.frames_total = frames_total;
+    self.send();
+  }
+
+  pub fn advance(&mut self, frames: u64) {
+    if frames == 0 {
+      return;
+    }
+    self.frames_done += frames;
+    let due = self.last_sent.is_none_or(|sent| sent.elapsed() >= self.interval);
+    if due || self.frames_done == self.frames_total {
+      self.send();
+    }
+  }
+
+  pub fn set_done(&mut self, frames_done: u64) {
+    self.advance(frames_done.saturating_sub(self.frames_done));
+  }
+
+  // Called at frame boundaries, everything the conversion holds is dropped on
+  // the way out
+  pub fn checkpoint(&self) -> Result<(), VideoError> {
+    match self.cancel.is_cancelled() {
+      true => Err(VideoError::Cancelled),
+      false => Ok(()),
+    }
+  }
+
+  pub fn event(&self) -> ProgressEvent {
+    // The estimate is exceeded when a clip is longer than its container says
+    let frames_total = match self.frames_total {
+      0 => 0,
+      total => total.max(self.frames_done),
+    };
+    ProgressEvent {
+      frames_total,
+      frames_done: self.frames_done,
+      eta_ms: eta_ms(self.stage, self.frames_done, frames_total, self.started.elapsed()),
+      ..ProgressEvent::new(self.job_id, self.stage)
+    }
+  }
+
+  fn send(&mut self) {
+    self.last_sent = Some(Instant::now());
+    let event = self.event();
+    (self.publish)(event);
+  }
+}
+
+// Share of the conversion done, assuming the time spent so far is spread like
+// the stage weights. None until enough is done to tell
+pub fn eta_ms(stage: Stage, frames_done: u64, frames_total: u64, elapsed: Duration) -> Option<u64> {
+  let (from, to) = stage.span();
+  let within = match frames_total {
+    0 => 0.0,
+    total => frames_done.min(total) as f64 / total as f64,
+  };
+  let done = from + (to - from) * within;
+  match done {
+    done if done >= 1.0 => Some(0),
+    done if done < 0.01 => None,
+    done => Some((elapsed.as_millis() as f64 * (1.0 - done) / done) as u64),
+  }
+}
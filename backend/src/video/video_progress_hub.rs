@@ -0,0 +1,231 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_progress::ProgressEvent;
+use actix_http::ws::{CloseCode, CloseReason, OpCode, Parser, ProtocolError};
+use actix_web::error::PayloadError;
+use actix_web::web::{Bytes, BytesMut};
+use futures_util::{future, stream, Stream, StreamExt};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::timeout;
+use tokio_postgres::{AsyncMessage, NoTls};
+
+pub const PROGRESS_CHANNEL: &str = "job_progress";
+// Events kept for a slow subscriber before it misses some
+pub const HUB_CAPACITY: usize = 256;
+// Proxies close connections that stay silent for too long
+pub const KEEP_ALIVE: Duration = Duration::from_secs(15);
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Clients have nothing to send but pings and close frames
+pub const MAX_CLIENT_FRAME: usize = 4096;
+
+// Progress events of every job, whichever server runs it. Workers publish
+// with NOTIFY, the listener of each server hands them to its subscribers
+#[derive(Clone)]
+pub struct ProgressHub {
+  sender: broadcast::Sender<ProgressEvent>,
+}
+
+impl ProgressHub {
+  pub fn new(capacity: usize) -> Self {
+    let (sender, _) = broadcast::channel(capacity.max(1));
+    ProgressHub {
+      sender,
+    }
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+    self.sender.subscribe()
+  }
+
+  // Nobody following a job is not an error
+  pub fn publish(&self, event: ProgressEvent) {
+    let _ = self.sender.send(event);
+  }
+
+  // Must be called from the server runtime. The listener reconnects when its
+  // connection drops, events sent meanwhile are lost
+  pub fn listen(&self, config: tokio_postgres::Config) {
+    let hub = self.clone();
+    actix_web::rt::spawn(async move {
+      loop {
+        if let Err(err) = hub.relay_notifications(&config).await {
+          eprintln!("Job progress listener: {}", err);
+        }
+        actix_web::rt::time::sleep(RECONNECT_DELAY).await;
+      }
+    });
+  }
+
+  async fn relay_notifications(&self, config: &tokio_postgres::Config) -> Result<(), VideoError> {
+    let (client, mut connection) = config.connect(NoTls).await?;
+    let hub = self.clone();
+    // Notifications come out of the connection, which only makes progress while polled
+    let messages = actix_web::rt::spawn(async move {
+      while let Some(message) = future::poll_fn(|cx| connection.poll_message(cx)).await {
+        if let AsyncMessage::Notification(notification) = message? {
+          if let Ok(event) = serde_json::from_str(notification.payload()) {
+            hub.publish(event);
+          }
+        }
+      }
+      Ok::<_, tokio_postgres::Error>(())
+    });
+    client.batch_execute(&format!("LISTEN {}", PROGRESS_CHANNEL)).await?;
+    if let Ok(result) = messages.await {
+      result?;
+    }
+    Ok(())
+  }
+}
+
+impl Default for ProgressHub {
+  fn default() -> Self {
+    Self::new(HUB_CAPACITY)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Relay {
+  Event(ProgressEvent),
+  KeepAlive,
+}
+
+// `first` then the events of one job, up to the one that finishes it. A
+// subscriber that fell behind is dropped rather than left waiting for an event
+// it missed, SSE clients reconnect and start over from the job row
+pub fn job_events(
+  first: ProgressEvent,
+  receiver: broadcast::Receiver<ProgressEvent>,
+  keep_alive: Duration,
+) -> impl Stream<Item = Relay> {
+  let job_id = first.job_id;
+  stream::unfold(Some((Some(first), receiver)), move |state| async move {
+    let (pending, mut receiver) = state?;
+    let event = match pending {
+      Some(event) => event,
+      None => loop {
+        match timeout(keep_alive, receiver.recv()).await {
+          Err(_) => return Some((Relay::KeepAlive, Some((None, receiver)))),
+          Ok(Ok(event)) if event.job_id == job_id => break event,
+          Ok(Ok(_)) => continue,
+          Ok(Err(RecvError::Lagged(_))) | Ok(Err(RecvError::Closed)) => return None,
+        }
+      },
+    };
+    let next = match event.stage.is_finished() {
+      true => None,
+      false => Some((None, receiver)),
+    };
+    Some((Relay::Event(event), next))
+  })
+}
+
+pub fn sse_message(relay: &Relay) -> Bytes {
+  match relay {
+    Relay::Event(event) => {
+      Bytes::from(format!("event: progress\ndata: {}\n\n", serde_json::json!(event)))
+    },
+    Relay::KeepAlive => Bytes::from_static(b": keep-alive\n\n"),
+  }
+}
+
+pub fn sse_stream(
+  relay: impl Stream<Item = Relay>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+  relay.map(|relay| Ok(sse_message(&relay)))
+}
+
+// Server frames are never masked
+pub fn ws_message(relay: &Relay) -> Bytes {
+  let mut frame = BytesMut::new();
+  match relay {
+    Relay::Event(event) => {
+      let text = serde_json::json!(event).to_string();
+      Parser::write_message(&mut frame, text, OpCode::Text, true, false)
+    },
+    Relay::KeepAlive => Parser::write_message(&mut frame, b"", OpCode::Ping, true, false),
+  }
+  frame.freeze()
+}
+
+pub fn ws_close(code: CloseCode) -> Bytes {
+  let mut frame = BytesMut::new();
+  Parser::write_close(&mut frame, Some(CloseReason::from(code)), false);
+  frame.freeze()
+}
+
+// Answers the frames received so far: pongs for pings and a close frame when
+// the client closes. Messages from the client are ignored
+pub fn ws_answer(buffer: &mut BytesMut) -> Result<(Bytes, bool), ProtocolError> {
+  let mut answer = BytesMut::new();
+  while let Some((_, opcode, payload)) = Parser::parse(buffer, true, MAX_CLIENT_FRAME)? {
+    let payload = payload.unwrap_or_default();
+    match opcode {
+      OpCode::Ping => Parser::write_message(&mut answer, &payload, OpCode::Pong, true, false),
+      OpCode::Close => {
+        let reason = Parser::try_parse_close_payload(&payload)?;
+        Parser::write_close(&mut answer, reason, false);
+        return Ok((answer.freeze(), true));
+      },
+      _ => {},
+    }
+  }
+  Ok((answer.freeze(), false))
+}
+
+struct Socket<P, R> {
+  payload: P,
+  relay: R,
+  buffer: BytesMut,
+  closed: bool,
+}
+
+// Response body of a WebSocket: the relayed events as text frames, a close
+// frame once the job finished. Stops when the client leaves
+pub fn ws_stream<P, R>(payload: P, relay: R) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+  P: Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+  R: Stream<Item = Relay> + Unpin,
+{
+  let socket = Socket {
+    payload,
+    relay,
+    buffer: BytesMut::new(),
+    closed: false,
+  };
+  stream::unfold(socket, |mut socket| async move {
+    loop {
+      if socket.closed {
+        return None;
+      }
+      tokio::select! {
+        relay = socket.relay.next() => {
+          let frame = match relay {
+            Some(relay) => ws_message(&relay),
+            None => {
+              socket.closed = true;
+              ws_close(CloseCode::Normal)
+            },
+          };
+          return Some((Ok(frame), socket));
+        },
+        chunk = socket.payload.next() => {
+          let chunk = match chunk {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(_)) | None => return None,
+          };
+          socket.buffer.extend_from_slice(&chunk);
+          let (answer, closing) = match ws_answer(&mut socket.buffer) {
+            Ok(answer) => answer,
+            Err(_) => (ws_close(CloseCode::Protocol), true),
+          };
+          socket.closed = closing;
+          if !answer.is_empty() {
+            return Some((Ok(answer), socket));
+          }
+        },
+      }
+    }
+  })
+}
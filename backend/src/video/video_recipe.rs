@@ -0,0 +1,491 @@
+use crate::video::ffmpeg::output_size;
+use crate::video::video_canvas::{parse_color, Rect, BLACK};
+use crate::video::video_concat::{SegmentSpec, Transition, MAX_SEGMENTS};
+use crate::video::video_dedupe::{dedupe_frames, DedupeStats};
+use crate::video::video_dto::OutputFormat;
+use crate::video::video_errors::VideoError;
+use crate::video::video_filters::{FilterChain, FilterSpec};
+use crate::video::video_frame::Frame;
+use crate::video::video_frame_edit::MIN_DELAY_MS;
+use crate::video::video_limits::{image_error, image_limits};
+use crate::video::video_service::{DEFAULT_FPS, MAX_FPS, MAX_WIDTH};
+use crate::video::video_spritesheet::SpritesheetOptions;
+use crate::video::video_storage::MediaStorage;
+use crate::video::video_text::draw_label;
+use image::imageops::{self, FilterType};
+use image::{ImageReader, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const RECIPE_VERSION: u32 = 1;
+pub const MAX_RECIPE_OPERATIONS: usize = 64;
+pub const MAX_OVERLAY_TEXT: usize = 100;
+pub const MAX_TEXT_SCALE: u32 = 8;
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 10.0;
+
+// Versioned edit decision list, a recipe and its sources always give the same output
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+  pub version: u32,
+  pub sources: Vec<RecipeSource>, // Played one after the other
+  #[serde(default)]
+  pub operations: Vec<RecipeOperation>, // Applied to the joined frames in this order
+  #[serde(default)]
+  pub output: RecipeOutput,
+}
+
+// One trimmed clip, times are in seconds
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecipeSource {
+  pub media_id: Option<Uuid>, // The media the recipe is run on when omitted
+  pub start: Option<f64>,
+  pub end: Option<f64>,
+  #[serde(default)]
+  pub transition: Transition, // Into the next source, ignored on the last one
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum RecipeOperation {
+  Scale {
+    width: u32,
+    height: Option<u32>, // Keeps the aspect ratio when omitted
+  },
+  Crop {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+  },
+  Filter(FilterSpec),
+  Overlay(OverlaySpec),
+  Speed {
+    factor: f64, // 2.0 plays twice as fast
+  },
+  Dedupe {
+    threshold: f32,
+  },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OverlaySpec {
+  Text {
+    text: String,
+    x: u32,
+    y: u32,
+    scale: Option<u32>,
+  },
+  Image {
+    media_id: Uuid, // An uploaded PNG, its alpha channel is kept
+    x: u32,
+    y: u32,
+    opacity: Option<f32>,
+  },
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecipeOutput {
+  pub fps: Option<u32>,
+  #[serde(default)]
+  pub format: OutputFormat,
+  #[serde(default)]
+  pub spritesheet: SpritesheetOptions,
+  pub background: Option<String>, // Letterbox colour when sources differ in size
+}
+
+impl Recipe {
+  pub fn validate(&self) -> Result<(), VideoError> {
+    if self.version != RECIPE_VERSION {
+      return Err(VideoError::InvalidOption(format!(
+        "unsupported recipe version {}, expected {}",
+        self.version, RECIPE_VERSION
+      )));
+    }
+    if self.sources.is_empty() || self.sources.len() > MAX_SEGMENTS {
+      return Err(VideoError::InvalidOption(format!(
+        "a recipe needs between 1 and {} sources",
+        MAX_SEGMENTS
+      )));
+    }
+    for source in &self.sources {
+      source.segment().validate()?;
+    }
+    if self.operations.len() > MAX_RECIPE_OPERATIONS {
+      return Err(VideoError::InvalidOption(format!(
+        "at most {} operations are allowed",
+        MAX_RECIPE_OPERATIONS
+      )));
+    }
+    for operation in &self.operations {
+      operation.validate()?;
+    }
+    self.output.validate()
+  }
+
+  // A leading scale is done by the decoder so full size frames are never held
+  pub fn decode_width(&self) -> Option<u32> {
+    match self.operations.first() {
+      Some(RecipeOperation::Scale {
+        width,
+        height: None,
+      }) => Some(*width),
+      _ => None,
+    }
+  }
+}
+
+impl RecipeSource {
+  pub fn segment(&self) -> SegmentSpec {
+    SegmentSpec {
+      source: self.media_id,
+      start: self.start,
+      end: self.end,
+      transition: self.transition.clone(),
+    }
+  }
+}
+
+impl RecipeOperation {
+  fn validate(&self) -> Result<(), VideoError> {
+    match self {
+      RecipeOperation::Scale {
+        width,
+        height,
+      } => {
+        let sizes = [Some(*width), *height];
+        if sizes.iter().flatten().any(|size| !(2..=MAX_WIDTH).contains(size)) {
+          return Err(VideoError::InvalidOption(format!(
+            "scale sizes must be between 2 and {}",
+            MAX_WIDTH
+          )));
+        }
+      },
+      RecipeOperation::Crop {
+        width,
+        height,
+        ..
+      } => {
+        if *width == 0 || *height == 0 {
+          return Err(VideoError::InvalidOption("crop area must not be empty".to_string()));
+        }
+      },
+      RecipeOperation::Filter(_) => {}, // Checked when the filter is built
+      RecipeOperation::Overlay(OverlaySpec::Text {
+        text,
+        x,
+        y,
+        scale,
+      }) => {
+        check_position(*x, *y)?;
+        if text.is_empty() || text.chars().count() > MAX_OVERLAY_TEXT {
+          return Err(VideoError::InvalidOption(format!(
+            "overlay text must be between 1 and {} characters",
+            MAX_OVERLAY_TEXT
+          )));
+        }
+        if scale.is_some_and(|scale| !(1..=MAX_TEXT_SCALE).contains(&scale)) {
+          return Err(VideoError::InvalidOption(format!(
+            "overlay text scale must be between 1 and {}",
+            MAX_TEXT_SCALE
+          )));
+        }
+      },
+      RecipeOperation::Overlay(OverlaySpec::Image {
+        x,
+        y,
+        opacity,
+        ..
+      }) => {
+        check_position(*x, *y)?;
+        if opacity.is_some_and(|opacity| !(0.0..=1.0).contains(&opacity)) {
+          return Err(VideoError::InvalidOption(
+            "overlay opacity must be between 0.0 and 1.0".to_string(),
+          ));
+        }
+      },
+      RecipeOperation::Speed {
+        factor,
+      } => {
+        if !(MIN_SPEED..=MAX_SPEED).contains(factor) {
+          return Err(VideoError::InvalidOption(format!(
+            "speed factor must be between {} and {}",
+            MIN_SPEED, MAX_SPEED
+          )));
+        }
+      },
+      RecipeOperation::Dedupe {
+        threshold,
+      } => {
+        if !(0.0..=1.0).contains(threshold) {
+          return Err(VideoError::InvalidOption(
+            "dedupe threshold must be between 0.0 and 1.0".to_string(),
+          ));
+        }
+      },
+    }
+    Ok(())
+  }
+}
+
+// Positions are bounded like sizes so drawing never overflows
+fn check_position(x: u32, y: u32) -> Result<(), VideoError> {
+  match x > MAX_WIDTH || y > MAX_WIDTH {
+    true => Err(VideoError::InvalidOption(format!(
+      "overlay position must be between 0 and {}",
+      MAX_WIDTH
+    ))),
+    false => Ok(()),
+  }
+}
+
+impl RecipeOutput {
+  fn validate(&self) -> Result<(), VideoError> {
+    if self.fps.is_some_and(|fps| !(1..=MAX_FPS).contains(&fps)) {
+      return Err(VideoError::InvalidOption(format!("fps must be between 1 and {}", MAX_FPS)));
+    }
+    if self.format == OutputFormat::Spritesheet {
+      self.spritesheet.validate()?;
+    }
+    self.background()?;
+    Ok(())
+  }
+
+  pub fn fps(&self) -> u32 {
+    self.fps.unwrap_or(DEFAULT_FPS)
+  }
+
+  pub fn background(&self) -> Result<Rgba<u8>, VideoError> {
+    match &self.background {
+      Some(color) => parse_color(color),
+      None => Ok(BLACK),
+    }
+  }
+}
+
+// Operations with their filters built and overlay images loaded
+enum Step {
+  Scale(u32, Option<u32>),
+  Crop(Rect),
+  Filter(FilterChain),
+  Text {
+    text: String,
+    x: u32,
+    y: u32,
+    scale: u32,
+  },
+  Image {
+    image: RgbaImage,
+    x: u32,
+    y: u32,
+  },
+  Speed(f64),
+  Dedupe(f32),
+}
+
+pub struct RecipePipeline {
+  steps: Vec<Step>,
+}
+
+impl RecipePipeline {
+  // Blocking: LUT filters and image overlays are read from the storage
+  pub fn build(recipe: &Recipe, storage: &MediaStorage) -> Result<Self, VideoError> {
+    let mut steps = Vec::with_capacity(recipe.operations.len());
+    for operation in &recipe.operations {
+      steps.push(match operation {
+        RecipeOperation::Scale {
+          width,
+          height,
+        } => Step::Scale(*width, *height),
+        RecipeOperation::Crop {
+          x,
+          y,
+          width,
+          height,
+        } => Step::Crop(Rect {
+          x: *x,
+          y: *y,
+          width: *width,
+          height: *height,
+        }),
+        RecipeOperation::Filter(spec) => {
+          Step::Filter(FilterChain::build(std::slice::from_ref(spec), storage)?)
+        },
+        RecipeOperation::Overlay(OverlaySpec::Text {
+          text,
+          x,
+          y,
+          scale,
+        }) => Step::Text {
+          text: text.clone(),
+          x: *x,
+          y: *y,
+          scale: scale.unwrap_or(1),
+        },
+        RecipeOperation::Overlay(OverlaySpec::Image {
+          media_id,
+          x,
+          y,
+          opacity,
+        }) => Step::Image {
+          image: overlay_image(storage, media_id, opacity.unwrap_or(1.0))?,
+          x: *x,
+          y: *y,
+        },
+        RecipeOperation::Speed {
+          factor,
+        } => Step::Speed(*factor),
+        RecipeOperation::Dedupe {
+          threshold,
+        } => Step::Dedupe(*threshold),
+      });
+    }
+    Ok(RecipePipeline {
+      steps,
+    })
+  }
+
+  pub fn filter_names(&self) -> Vec<&'static str> {
+    self
+      .steps
+      .iter()
+      .filter_map(|step| match step {
+        Step::Filter(chain) => Some(chain.names()),
+        _ => None,
+      })
+      .flatten()
+      .collect()
+  }
+
+  // Runs every step in order, returns the statistics of the last dedupe step
+  pub fn apply(
+    &self,
+    mut frames: Vec<Frame>,
+  ) -> Result<(Vec<Frame>, Option<DedupeStats>), VideoError> {
+    let mut dedupe = None;
+    for step in &self.steps {
+      match step {
+        Step::Scale(width, height) => {
+          for frame in frames.iter_mut() {
+            let size = match height {
+              Some(height) => (*width, *height),
+              None => scaled_size(frame.width(), frame.height(), *width),
+            };
+            if size != frame.image.dimensions() {
+              frame.image = imageops::resize(&frame.image, size.0, size.1, FilterType::Triangle);
+            }
+          }
+        },
+        Step::Crop(rect) => {
+          for frame in frames.iter_mut() {
+            if rect.x + rect.width > frame.width() || rect.y + rect.height > frame.height() {
+              return Err(VideoError::InvalidOption(format!(
+                "crop area {}x{}+{}+{} is outside the {}x{} frames",
+                rect.width,
+                rect.height,
+                rect.x,
+                rect.y,
+                frame.width(),
+                frame.height()
+              )));
+            }
+            frame.image =
+              imageops::crop_imm(&frame.image, rect.x, rect.y, rect.width, rect.height).to_image();
+          }
+        },
+        Step::Filter(chain) => chain.apply(&mut frames),
+        Step::Text {
+          text,
+          x,
+          y,
+          scale,
+        } => {
+          for frame in frames.iter_mut() {
+            draw_label(&mut frame.image, text, *x, *y, *scale);
+          }
+        },
+        Step::Image {
+          image,
+          x,
+          y,
+        } => {
+          for frame in frames.iter_mut() {
+            imageops::overlay(&mut frame.image, image, *x as i64, *y as i64);
+          }
+        },
+        Step::Speed(factor) => frames = change_speed(frames, *factor),
+        Step::Dedupe(threshold) => {
+          let (kept, stats) = dedupe_frames(frames, *threshold);
+          frames = kept;
+          dedupe = Some(stats);
+        },
+      }
+    }
+    Ok((frames, dedupe))
+  }
+}
+
+// Width scaled to `width` with the height following the aspect ratio, like the decoder does
+pub fn scaled_size(source_width: u32, source_height: u32, width: u32) -> (u32, u32) {
+  match width < source_width {
+    true => output_size(source_width, source_height, Some(width)),
+    false => (width, (source_height as u64 * width as u64 / source_width as u64).max(1) as u32),
+  }
+}
+
+fn overlay_image(
+  storage: &MediaStorage,
+  media_id: &Uuid,
+  opacity: f32,
+) -> Result<RgbaImage, VideoError> {
+  let path = storage.media_path(media_id);
+  if !path.exists() {
+    return Err(VideoError::MediaNotFound);
+  }
+  // Uploads are stored without an extension
+  let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+  reader.limits(image_limits());
+  let mut image = reader.decode().map_err(image_error)?.to_rgba8();
+  for pixel in image.pixels_mut() {
+    pixel.0[3] = (pixel.0[3] as f32 * opacity).round() as u8;
+  }
+  Ok(image)
+}
+
+// Delays are divided by the factor, frames that would be shown for less than
+// the shortest GIF delay are dropped and their time given to the next frame
+pub fn change_speed(frames: Vec<Frame>, factor: f64) -> Vec<Frame> {
+  let mut output: Vec<Frame> = Vec::with_capacity(frames.len());
+  let mut dropped = None;
+  let mut pending = 0.0f64;
+  for mut frame in frames {
+    pending += frame.delay_ms as f64 / factor;
+    if pending < MIN_DELAY_MS as f64 {
+      dropped = Some(frame);
+      continue;
+    }
+    frame.delay_ms = pending.round() as u32;
+    pending = 0.0;
+    output.push(frame);
+  }
+  match (output.last_mut(), dropped) {
+    (Some(last), _) => last.delay_ms += pending.round() as u32,
+    // Too short to be shown at all, the animation keeps one frame
+    (None, Some(mut frame)) => {
+      frame.delay_ms = MIN_DELAY_MS;
+      output.push(frame);
+    },
+    (None, None) => {},
+  }
+
+  let mut pts_ms = 0i64;
+  for frame in output.iter_mut() {
+    frame.pts_ms = pts_ms;
+    pts_ms += frame.delay_ms as i64;
+  }
+  output
+}
@@ -0,0 +1,98 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_recipe::Recipe;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Client;
+use serde::Serialize;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::Json;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+pub const MAX_RECIPE_NAME: usize = 100;
+
+// A recipe saved by a user
+#[derive(Debug, Serialize)]
+pub struct SavedRecipe {
+  pub id: Uuid,
+  pub name: String,
+  pub recipe: Recipe,
+  pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<Row> for SavedRecipe {
+  type Error = VideoError;
+
+  // Recipes are stored in their canonical form, a row that no longer parses is an error
+  fn try_from(row: Row) -> Result<Self, Self::Error> {
+    Ok(SavedRecipe {
+      id: row.try_get("id")?,
+      name: row.try_get("name")?,
+      recipe: row.try_get::<_, Json<Recipe>>("recipe")?.0,
+      created_at: row.try_get("created_at")?,
+    })
+  }
+}
+
+// Names identify the recipes of a user
+pub fn validate_name(name: &str) -> Result<(), VideoError> {
+  if name.trim().is_empty() || name.chars().count() > MAX_RECIPE_NAME {
+    return Err(VideoError::InvalidOption(format!(
+      "recipe name must be between 1 and {} characters",
+      MAX_RECIPE_NAME
+    )));
+  }
+  Ok(())
+}
+
+pub struct RecipeRepository;
+
+impl RecipeRepository {
+  pub async fn create(
+    client: &Client,
+    user_id: i32,
+    name: &str,
+    recipe: &Recipe,
+  ) -> Result<SavedRecipe, VideoError> {
+    let stmt = client
+      .prepare(
+        "INSERT INTO recipes (id, user_id, name, recipe) VALUES ($1, $2, $3, $4)
+         RETURNING id, name, recipe, created_at",
+      )
+      .await?;
+    match client.query_one(&stmt, &[&Uuid::new_v4(), &user_id, &name, &Json(recipe)]).await {
+      Ok(row) => SavedRecipe::try_from(row),
+      Err(err) if err.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+        Err(VideoError::RecipeNameTaken(name.to_string()))
+      },
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub async fn find(client: &Client, user_id: i32, id: &Uuid) -> Result<SavedRecipe, VideoError> {
+    let stmt = client
+      .prepare("SELECT id, name, recipe, created_at FROM recipes WHERE id = $1 AND user_id = $2")
+      .await?;
+    match client.query_opt(&stmt, &[id, &user_id]).await? {
+      Some(row) => SavedRecipe::try_from(row),
+      None => Err(VideoError::RecipeNotFound),
+    }
+  }
+
+  pub async fn all(client: &Client, user_id: i32) -> Result<Vec<SavedRecipe>, VideoError> {
+    let stmt = client
+      .prepare(
+        "SELECT id, name, recipe, created_at FROM recipes WHERE user_id = $1 ORDER BY created_at",
+      )
+      .await?;
+    let rows = client.query(&stmt, &[&user_id]).await?;
+    rows.into_iter().map(SavedRecipe::try_from).collect()
+  }
+
+  pub async fn delete(client: &Client, user_id: i32, id: &Uuid) -> Result<(), VideoError> {
+    let stmt = client.prepare("DELETE FROM recipes WHERE id = $1 AND user_id = $2").await?;
+    match client.execute(&stmt, &[id, &user_id]).await? {
+      0 => Err(VideoError::RecipeNotFound),
+      _ => Ok(()),
+    }
+  }
+}
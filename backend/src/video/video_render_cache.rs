@@ -0,0 +1,254 @@
+use crate::video::video_dto::{ConversionRequest, OutputFormat};
+use crate::video::video_errors::VideoError;
+use crate::video::video_job::JobStatus;
+use crate::video::video_job_repository::JobRepository;
+use crate::video::video_recipe::Recipe;
+use crate::video::video_render_cache_repository::RenderCacheRepository;
+use crate::video::video_storage::MediaStorage;
+use deadpool_postgres::Pool;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+// Set to `hit` on recipe responses answered from the cache
+pub const CACHE_HEADER: &str = "X-Render-Cache";
+pub const RENDER_SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+// Changed when the same recipe would render differently, old entries are then
+// never found again
+const KEY_VERSION: &str = "render-cache/1";
+const CONVERSION_KEY_VERSION: &str = "conversion-cache/1";
+// Fields naming an upload, `source` in conversions and `media_id` in recipes
+const MEDIA_FIELDS: &[&str] = &["media_id", "source"];
+
+// Calls `replace` with every media a recipe or conversion reads and stores what
+// it returns in place of the id. Sources without a media read `media_id`
+fn replace_media(
+  value: &mut Value,
+  media_id: Option<Uuid>,
+  replace: &mut impl FnMut(Uuid) -> Result<String, VideoError>,
+) -> Result<(), VideoError> {
+  match value {
+    Value::Object(fields) => {
+      for (name, field) in fields.iter_mut() {
+        let is_media = MEDIA_FIELDS.contains(&name.as_str());
+        let id = match (is_media, &*field) {
+          (true, Value::Null) => media_id
+            .ok_or_else(|| VideoError::InvalidOption("a source media is required".to_string()))?,
+          (true, Value::String(id)) => Uuid::parse_str(id)
+            .map_err(|_| VideoError::InvalidOption(format!("invalid {} {:?}", name, id)))?,
+          _ => {
+            replace_media(field, media_id, replace)?;
+            continue;
+          },
+        };
+        *field = Value::String(replace(id)?);
+      }
+    },
+    Value::Array(items) => {
+      for item in items {
+        replace_media(item, media_id, replace)?;
+      }
+    },
+    _ => {},
+  }
+  Ok(())
+}
+
+fn media_of(value: &Value, media_id: Option<Uuid>) -> Result<Vec<Uuid>, VideoError> {
+  let mut media = Vec::new();
+  replace_media(&mut value.clone(), media_id, &mut |id| {
+    media.push(id);
+    Ok(id.to_string())
+  })?;
+  Ok(media)
+}
+
+// Uploads a recipe reads: its sources, LUTs and overlay images
+pub fn recipe_media(recipe: &Recipe, media_id: Option<Uuid>) -> Result<Vec<Uuid>, VideoError> {
+  media_of(&serde_json::to_value(recipe)?, media_id)
+}
+
+// SHA-256 of the JSON with every media replaced by the SHA-256 of its bytes
+fn canonical_key(
+  version: &str,
+  mut canonical: Value,
+  media_id: Option<Uuid>,
+  digests: &HashMap<Uuid, String>,
+) -> Result<String, VideoError> {
+  replace_media(&mut canonical, media_id, &mut |id| {
+    digests.get(&id).cloned().ok_or(VideoError::MediaNotFound)
+  })?;
+  let mut digest = Sha256::new();
+  digest.update(version.as_bytes());
+  digest.update(b"\n");
+  digest.update(serde_json::to_string(&canonical)?.as_bytes());
+  Ok(hex::encode(digest.finalize()))
+}
+
+// Keys of the JSON are sorted and defaults spelled out, so the same upload
+// sent twice or a field left out rather than given its default renders once
+pub fn render_key(
+  recipe: &Recipe,
+  media_id: Option<Uuid>,
+  digests: &HashMap<Uuid, String>,
+) -> Result<String, VideoError> {
+  canonical_key(KEY_VERSION, serde_json::to_value(recipe)?, media_id, digests)
+}
+
+async fn digests_of(
+  storage: &MediaStorage,
+  media: Vec<Uuid>,
+) -> Result<HashMap<Uuid, String>, VideoError> {
+  let mut digests = HashMap::new();
+  for id in media {
+    if let Entry::Vacant(entry) = digests.entry(id) {
+      entry.insert(storage.media_digest(&id).await?);
+    }
+  }
+  Ok(digests)
+}
+
+pub async fn recipe_key(
+  storage: &MediaStorage,
+  recipe: &Recipe,
+  media_id: Option<Uuid>,
+) -> Result<String, VideoError> {
+  let digests = digests_of(storage, recipe_media(recipe, media_id)?).await?;
+  render_key(recipe, media_id, &digests)
+}
+
+// `POST /media/{id}/gif` and `POST /gifs` are keyed like recipes, by the request
+// with its preset applied and its sources and LUTs replaced by their digests
+pub async fn conversion_key(
+  storage: &MediaStorage,
+  request: &ConversionRequest,
+  media_id: Option<Uuid>,
+) -> Result<String, VideoError> {
+  let canonical = serde_json::to_value(request)?;
+  let digests = digests_of(storage, media_of(&canonical, media_id)?).await?;
+  canonical_key(CONVERSION_KEY_VERSION, canonical, media_id, &digests)
+}
+
+// What dropping a reference to a cached output left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Release {
+  NotReferenced,         // Not cached, or the user holds no reference to it
+  Kept,                  // Other users still reference it
+  Removed(OutputFormat), // That was the last reference, its files must go
+}
+
+// Who answers for an output no reference of the user covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+  Shared,              // Cached, only the users referencing it may release it
+  Job(i32, JobStatus), // Written by a job of that user
+  Nobody,              // Anonymous renders, streamed GIFs and edited copies
+}
+
+impl Owner {
+  // Only the user of a finished job may remove its output, no one may remove
+  // an output that has no owner
+  pub fn may_delete(self, user_id: i32) -> Result<(), VideoError> {
+    match self {
+      Owner::Job(owner, _) if owner != user_id => Err(VideoError::NotGifOwner),
+      Owner::Job(_, JobStatus::Queued | JobStatus::Running) => Err(VideoError::JobNotFinished),
+      Owner::Job(..) => Ok(()),
+      Owner::Shared | Owner::Nobody => Err(VideoError::NotGifOwner),
+    }
+  }
+
+  // The user of a finished job edits its output, everyone else edits a copy
+  pub fn edits_in_place(self, user_id: i32) -> Result<bool, VideoError> {
+    match self {
+      Owner::Job(owner, _) if owner != user_id => Ok(false),
+      Owner::Job(_, JobStatus::Queued | JobStatus::Running) => Err(VideoError::JobNotFinished),
+      Owner::Job(..) => Ok(true),
+      Owner::Shared | Owner::Nobody => Ok(false),
+    }
+  }
+}
+
+// The response of the cached render of `key`, the user then references it
+pub async fn find_render(
+  pool: &Pool,
+  key: &str,
+  user_id: i32,
+) -> Result<Option<Value>, VideoError> {
+  let client = pool.get().await?;
+  RenderCacheRepository::find(&client, key, user_id).await
+}
+
+pub async fn store_render(
+  pool: &Pool,
+  key: &str,
+  output_id: &Uuid,
+  format: OutputFormat,
+  user_id: i32,
+  response: &Value,
+) -> Result<(), VideoError> {
+  let client = pool.get().await?;
+  RenderCacheRepository::insert(&client, key, output_id, format, user_id, response).await
+}
+
+// Drops the reference of a user, the files of the output are removed once no
+// one references it. The row goes first so a render found from now on is never
+// one whose files are being removed
+pub async fn release_render(
+  pool: &Pool,
+  storage: &MediaStorage,
+  user_id: i32,
+  output_id: &Uuid,
+) -> Result<Release, VideoError> {
+  let released = {
+    let mut client = pool.get().await?;
+    RenderCacheRepository::release(&mut client, user_id, output_id).await?
+  };
+  if let Release::Removed(format) = released {
+    storage.remove_output(output_id, format).await?;
+  }
+  Ok(released)
+}
+
+pub async fn output_owner(pool: &Pool, output_id: &Uuid) -> Result<Owner, VideoError> {
+  let client = pool.get().await?;
+  if RenderCacheRepository::exists(&client, output_id).await? {
+    return Ok(Owner::Shared);
+  }
+  Ok(match JobRepository::owner(&client, output_id).await? {
+    Some((user_id, status)) => Owner::Job(user_id, status),
+    None => Owner::Nobody,
+  })
+}
+
+// Renders whose last users were deleted have no references left, they go
+// with their files
+pub async fn sweep_renders(pool: &Pool, storage: &MediaStorage) -> Result<usize, VideoError> {
+  let orphans = {
+    let client = pool.get().await?;
+    RenderCacheRepository::delete_unreferenced(&client).await?
+  };
+  // The rows are gone, so every output is tried before reporting a failure
+  let mut swept = Ok(orphans.len());
+  for (output_id, format) in &orphans {
+    if let Err(err) = storage.remove_output(output_id, *format).await {
+      swept = Err(err);
+    }
+  }
+  swept
+}
+
+// Must be called from the server runtime. Every server runs it, each row is
+// only returned to the one deleting it
+pub fn spawn_render_sweep(pool: Pool, storage: MediaStorage) {
+  actix_web::rt::spawn(async move {
+    loop {
+      if let Err(err) = sweep_renders(&pool, &storage).await {
+        eprintln!("Render cache sweep: {}", err);
+      }
+      actix_web::rt::time::sleep(RENDER_SWEEP_INTERVAL).await;
+    }
+  });
+}
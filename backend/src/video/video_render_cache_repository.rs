@@ -0,0 +1,142 @@
+use crate::video::video_dto::OutputFormat;
+use crate::video::video_errors::VideoError;
+use crate::video::video_render_cache::Release;
+use deadpool_postgres::{Client, GenericClient};
+use tokio_postgres::types::Json;
+use uuid::Uuid;
+
+pub struct RenderCacheRepository;
+
+impl RenderCacheRepository {
+  // The row is locked while the reference is added so a release cannot remove
+  // it in between, a row removed first is not found
+  pub async fn find(
+    client: &Client,
+    key: &str,
+    user_id: i32,
+  ) -> Result<Option<serde_json::Value>, VideoError> {
+    let stmt = client
+      .prepare(
+        "WITH entry AS (
+           SELECT output_id, response FROM render_cache
+           WHERE key = $1
+           ORDER BY created_at
+           LIMIT 1
+           FOR SHARE
+         ), referenced AS (
+           INSERT INTO render_refs (output_id, user_id)
+           SELECT output_id, $2 FROM entry
+           ON CONFLICT DO NOTHING
+         )
+         SELECT response FROM entry",
+      )
+      .await?;
+    let row = client.query_opt(&stmt, &[&key, &user_id]).await?;
+    Ok(
+      row
+        .map(|row| row.try_get::<_, Json<serde_json::Value>>("response"))
+        .transpose()?
+        .map(|json| json.0),
+    )
+  }
+
+  // Caches a finished render, referenced by the user who ran it
+  pub async fn insert(
+    client: &impl GenericClient,
+    key: &str,
+    output_id: &Uuid,
+    format: OutputFormat,
+    user_id: i32,
+    response: &serde_json::Value,
+  ) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare(
+        "WITH entry AS (
+           INSERT INTO render_cache (output_id, key, format, response)
+           VALUES ($1, $2, $3, $4)
+           RETURNING output_id
+         )
+         INSERT INTO render_refs (output_id, user_id)
+         SELECT output_id, $5 FROM entry",
+      )
+      .await?;
+    client.execute(&stmt, &[output_id, &key, &format.as_str(), &Json(response), &user_id]).await?;
+    Ok(())
+  }
+
+  // Each step sees the references added while it waited for the lock, so the
+  // row is only removed when no one references it any more
+  pub async fn release(
+    client: &mut Client,
+    user_id: i32,
+    output_id: &Uuid,
+  ) -> Result<Release, VideoError> {
+    let transaction = client.transaction().await?;
+    let stmt = transaction
+      .prepare("SELECT format FROM render_cache WHERE output_id = $1 FOR UPDATE")
+      .await?;
+    let format: String = match transaction.query_opt(&stmt, &[output_id]).await? {
+      Some(row) => row.try_get("format")?,
+      None => return Ok(Release::NotReferenced),
+    };
+
+    let stmt =
+      transaction.prepare("DELETE FROM render_refs WHERE output_id = $1 AND user_id = $2").await?;
+    if transaction.execute(&stmt, &[output_id, &user_id]).await? == 0 {
+      return Ok(Release::NotReferenced);
+    }
+
+    let stmt = transaction
+      .prepare(
+        "DELETE FROM render_cache
+         WHERE output_id = $1
+           AND NOT EXISTS (SELECT 1 FROM render_refs WHERE output_id = $1)",
+      )
+      .await?;
+    let removed = transaction.execute(&stmt, &[output_id]).await? > 0;
+    transaction.commit().await?;
+
+    match removed {
+      true => OutputFormat::parse(&format)
+        .map(Release::Removed)
+        .ok_or_else(|| VideoError::InvalidOption(format!("unknown output format {:?}", format))),
+      false => Ok(Release::Kept),
+    }
+  }
+
+  // Rows a find holds are skipped, a row removed first is not found by it
+  pub async fn delete_unreferenced(
+    client: &Client,
+  ) -> Result<Vec<(Uuid, OutputFormat)>, VideoError> {
+    let stmt = client
+      .prepare(
+        "WITH orphans AS (
+           SELECT output_id FROM render_cache
+           WHERE NOT EXISTS (
+             SELECT 1 FROM render_refs WHERE render_refs.output_id = render_cache.output_id
+           )
+           FOR UPDATE SKIP LOCKED
+         )
+         DELETE FROM render_cache
+         WHERE output_id IN (SELECT output_id FROM orphans)
+         RETURNING output_id, format",
+      )
+      .await?;
+    let rows = client.query(&stmt, &[]).await?;
+    rows
+      .iter()
+      .map(|row| {
+        let format: String = row.try_get("format")?;
+        let format = OutputFormat::parse(&format).ok_or_else(|| {
+          VideoError::InvalidOption(format!("unknown output format {:?}", format))
+        })?;
+        Ok((row.try_get("output_id")?, format))
+      })
+      .collect()
+  }
+
+  pub async fn exists(client: &Client, output_id: &Uuid) -> Result<bool, VideoError> {
+    let stmt = client.prepare("SELECT 1 FROM render_cache WHERE output_id = $1").await?;
+    Ok(client.query_opt(&stmt, &[output_id]).await?.is_some())
+  }
+}
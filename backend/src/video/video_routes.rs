@@ -0,0 +1,71 @@
+use crate::video::video_controller::{
+  convert_media, create_gif, create_gif_from_recipe, delete_gif, delete_recipe, download_frames,
+  edit_gif_frames, get_contact_sheet, get_gif, get_gif_version, get_recipe, get_sheet_file,
+  gif_to_spritesheet, list_gif_versions, list_recipes, run_media_recipe, save_recipe, upload_media,
+};
+use crate::video::video_job_controller::{
+  cancel_job, create_job, estimate_job, get_job, job_events_socket, job_events_stream,
+  list_dead_letters, list_jobs, requeue_job,
+};
+use crate::video::video_webhook_controller::{
+  get_webhook_secret, list_webhook_deliveries, rotate_webhook_secret,
+};
+use actix_web::web;
+
+pub fn configure_video_routes(cfg: &mut web::ServiceConfig) {
+  cfg
+    .service(
+      web::scope("/media")
+        .route("", web::post().to(upload_media))
+        .route("/{id}/gif", web::post().to(convert_media))
+        .route("/{id}/recipe", web::post().to(run_media_recipe))
+        .route("/{id}/contact-sheet", web::get().to(get_contact_sheet)),
+    )
+    .service(
+      web::scope("/gifs")
+        .route("", web::post().to(create_gif))
+        .route("/recipe", web::post().to(create_gif_from_recipe))
+        .route("/{id}", web::get().to(get_gif))
+        .route("/{id}", web::delete().to(delete_gif))
+        .route("/{id}/frames.zip", web::get().to(download_frames))
+        .route("/{id}/frames/edits", web::post().to(edit_gif_frames))
+        .route("/{id}/versions", web::get().to(list_gif_versions))
+        .route("/{id}/versions/{version}", web::get().to(get_gif_version))
+        .route("/{id}/spritesheet", web::post().to(gif_to_spritesheet)),
+    )
+    .service(web::scope("/sheets").route("/{id}/{file}", web::get().to(get_sheet_file)))
+    .service(
+      web::scope("/jobs")
+        .route("", web::post().to(create_job))
+        .route("", web::get().to(list_jobs))
+        .route("/estimate", web::post().to(estimate_job))
+        .route("/{id}", web::get().to(get_job))
+        .route("/{id}", web::delete().to(cancel_job))
+        .route("/{id}/events", web::get().to(job_events_stream))
+        .route("/{id}/socket", web::get().to(job_events_socket)),
+    )
+    .service(
+      web::scope("/admin/jobs")
+        .route("/dead-letters", web::get().to(list_dead_letters))
+        .route("/{id}/requeue", web::post().to(requeue_job)),
+    );
+}
+
+// Mounted inside the `/users` scope, a second scope on that prefix would never be reached
+pub fn configure_recipe_routes(cfg: &mut web::ServiceConfig) {
+  cfg.service(
+    web::scope("/me/recipes")
+      .route("", web::get().to(list_recipes))
+      .route("", web::post().to(save_recipe))
+      .route("/{id}", web::get().to(get_recipe))
+      .route("/{id}", web::delete().to(delete_recipe)),
+  );
+}
+
+// Mounted inside the `/users` scope as well
+pub fn configure_webhook_routes(cfg: &mut web::ServiceConfig) {
+  cfg
+    .route("/me/webhook-secret", web::get().to(get_webhook_secret))
+    .route("/me/webhook-secret", web::post().to(rotate_webhook_secret))
+    .route("/me/webhook-deliveries", web::get().to(list_webhook_deliveries));
+}
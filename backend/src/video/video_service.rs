@@ -0,0 +1,906 @@
+use crate::video::ffmpeg::{
+  extract_frames, extract_frames_at, extract_frames_exact, output_size, probe, stream_frames,
+  DecodeOptions,
+};
+use crate::video::video_canvas::{parse_color, BLACK};
+use crate::video::video_concat::{concat_clips, Clip, MAX_SEGMENTS};
+use crate::video::video_contact_sheet::{self, sample_timestamps, SheetGrid};
+use crate::video::video_cost::CostEstimate;
+use crate::video::video_dedupe::dedupe_frames;
+use crate::video::video_dto::{
+  ConversionMode, ConversionRequest, ConversionResponse, GifVersionResponse, OutputFormat,
+  SheetFormat,
+};
+use crate::video::video_encoder::{
+  encode_gif, encode_gif_tracked, encode_within, EncoderOptions, DEFAULT_QUANTIZER_SPEED,
+  MAX_PALETTE_SIZE, MIN_PALETTE_SIZE,
+};
+use crate::video::video_errors::VideoError;
+use crate::video::video_filters::FilterChain;
+use crate::video::video_frame::{total_duration_ms, Frame};
+use crate::video::video_frame_edit::{apply_operations, FrameEditRequest};
+use crate::video::video_frame_store::{
+  create_manifest, latest_version, load_frames, manifest_path, read_manifest, write_frames,
+  FrameManifest,
+};
+use crate::video::video_gif::{decode_gif, is_gif, stream_gif};
+use crate::video::video_layout::{LayoutClip, LayoutSpec, DEFAULT_LAYOUT_WIDTH};
+use crate::video::video_limits::{check_deadline, job_deadline};
+use crate::video::video_pipeline::{worker_count, StreamingPipeline, STAGE_CAPACITY};
+use crate::video::video_preview::{
+  preview_fps, preview_width, Preview, PreviewBudget, PREVIEW_WIDTH,
+};
+use crate::video::video_progress::{ProgressTracker, Stage};
+use crate::video::video_recipe::{Recipe, RecipePipeline};
+use crate::video::video_spritesheet::{write_spritesheet, AtlasFile, SpritesheetOptions};
+use crate::video::video_storage::MediaStorage;
+use crate::video::video_stream::Tee;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use uuid::Uuid;
+
+pub const DEFAULT_FPS: u32 = 10;
+pub const MAX_FPS: u32 = 50; // GIF delays below 2 centiseconds are not honoured by browsers
+pub const MAX_WIDTH: u32 = 1920;
+pub const MAX_TIMELAPSE_FRAMES: u64 = 1500;
+pub const MIN_OUTPUT_BYTES: u64 = 1024;
+pub const MAX_OUTPUT_BYTES: u64 = 100 * 1024 * 1024;
+
+// Frames as they were written, the number of bytes written and the atlases of a sprite sheet
+type WrittenOutput = (Vec<Frame>, u64, Option<Vec<AtlasFile>>);
+
+// How the processed frames are written
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+  pub format: OutputFormat,
+  pub spritesheet: SpritesheetOptions,
+  pub encoder: EncoderOptions,
+  pub max_bytes: Option<u64>, // GIF only
+}
+
+pub struct VideoService;
+
+impl VideoService {
+  // Blocking: decodes, processes and encodes a whole video. `media_id` is the
+  // default source, segments and layout cells may pull clips from other uploads.
+  // `destination` is the GIF file, or the directory receiving sprite sheet atlases
+  pub fn convert(
+    storage: &MediaStorage,
+    id: Uuid,
+    media_id: Option<Uuid>,
+    destination: &Path,
+    request: &ConversionRequest,
+  ) -> Result<ConversionResponse, VideoError> {
+    Self::validate(request)?;
+    let filters = FilterChain::build(&request.filters, storage)?;
+    let deadline = job_deadline();
+    let output = Self::output(request, Some(deadline));
+
+    if Self::is_streamable(request, &output) {
+      let source = Self::source_path(storage, media_id)?;
+      let writer = BufWriter::new(File::create(destination)?);
+      return Self::stream_conversion(storage, id, &source, destination, writer, request, &filters);
+    }
+
+    let mut frames = match (&request.layout, request.mode, request.segments.is_empty()) {
+      (Some(layout), _, _) => Self::layout(storage, media_id, layout, request, deadline)?,
+      (None, ConversionMode::Normal, true) => {
+        let source = Self::source_path(storage, media_id)?;
+        Self::decode(&source, &Self::decode_options(request, deadline))?
+      },
+      (None, ConversionMode::Normal, false) => Self::concat(storage, media_id, request, deadline)?,
+      (None, ConversionMode::Timelapse, _) => {
+        Self::timelapse(&Self::source_path(storage, media_id)?, request, deadline)?
+      },
+    };
+
+    let mut dedupe = None;
+    if let Some(threshold) = request.dedupe_threshold {
+      let (kept, stats) = dedupe_frames(frames, threshold);
+      frames = kept;
+      dedupe = Some(stats);
+    }
+
+    check_deadline(Some(deadline))?;
+    filters.apply(&mut frames);
+
+    let response =
+      Self::save_output(storage, id, frames, destination, &output, &mut ProgressTracker::none())?;
+    Ok(ConversionResponse {
+      dedupe,
+      filters: filters.names(),
+      ..response
+    })
+  }
+
+  // Blocking: same as `convert`, the GIF is also written to `writer` frame by
+  // frame as it is encoded
+  pub fn convert_streaming<W: Write + Send>(
+    storage: &MediaStorage,
+    id: Uuid,
+    media_id: Uuid,
+    destination: &Path,
+    request: &ConversionRequest,
+    writer: W,
+  ) -> Result<ConversionResponse, VideoError> {
+    Self::check_streamable(request)?;
+    let filters = FilterChain::build(&request.filters, storage)?;
+    let source = Self::source_path(storage, Some(media_id))?;
+    let file = BufWriter::new(File::create(destination)?);
+    Self::stream_conversion(storage, id, &source, destination, Tee(file, writer), request, &filters)
+  }
+
+  fn check_streamable(request: &ConversionRequest) -> Result<(), VideoError> {
+    Self::validate(request)?;
+    match Self::is_streamable(request, &Self::output(request, None)) {
+      true => Ok(()),
+      false => Err(VideoError::InvalidOption(
+        "stream=true only applies to GIF conversions of one upload without max_bytes".to_string(),
+      )),
+    }
+  }
+
+  // Plain GIF conversions of one source go through the streaming pipeline so
+  // the whole clip is never held in memory. Sprite sheets, size limits and
+  // multi-clip conversions need every frame at once
+  fn is_streamable(request: &ConversionRequest, output: &OutputOptions) -> bool {
+    request.layout.is_none()
+      && request.segments.is_empty()
+      && request.mode == ConversionMode::Normal
+      && output.format == OutputFormat::Gif
+      && output.max_bytes.is_none()
+  }
+
+  fn stream_conversion<W: Write + Send>(
+    storage: &MediaStorage,
+    id: Uuid,
+    source: &Path,
+    destination: &Path,
+    writer: W,
+    request: &ConversionRequest,
+    filters: &FilterChain,
+  ) -> Result<ConversionResponse, VideoError> {
+    let deadline = job_deadline();
+    let animated_gif = is_gif(source)?;
+    // ffmpeg scales while converting to RGBA, GIF frames are scaled by the workers
+    let mut options = Self::decode_options(request, deadline);
+    let scale_width = match animated_gif {
+      true => options.width.take(),
+      false => None,
+    };
+    let pipeline = StreamingPipeline {
+      filters,
+      dedupe_threshold: request.dedupe_threshold,
+      scale_width,
+      encoder: Self::output(request, Some(deadline)).encoder,
+      capacity: STAGE_CAPACITY,
+      workers: worker_count(),
+    };
+    let summary = pipeline.run(
+      |sink| match animated_gif {
+        true => stream_gif(source, &options, sink),
+        false => stream_frames(source, &options, sink),
+      },
+      writer,
+      Some(&storage.frames_dir(&id)),
+    )?;
+
+    Ok(ConversionResponse {
+      id,
+      width: summary.width,
+      height: summary.height,
+      frame_count: summary.frame_count,
+      duration_ms: summary.duration_ms,
+      size_bytes: std::fs::metadata(destination)?.len(),
+      dedupe: summary.dedupe,
+      filters: filters.names(),
+      format: OutputFormat::Gif,
+      atlases: None,
+    })
+  }
+
+  // Blocking: the conversion at a reduced width and fps with a fixed palette,
+  // decoding stops when the preview budget is spent. Nothing is saved
+  pub fn preview(
+    storage: &MediaStorage,
+    media_id: Uuid,
+    request: &ConversionRequest,
+  ) -> Result<Preview, VideoError> {
+    Self::validate(request)?;
+    if request.layout.is_some()
+      || !request.segments.is_empty()
+      || request.mode != ConversionMode::Normal
+    {
+      return Err(VideoError::InvalidOption(
+        "preview=true only applies to normal conversions of one upload".to_string(),
+      ));
+    }
+    let filters = FilterChain::build(&request.filters, storage)?;
+    let source = Self::source_path(storage, Some(media_id))?;
+    let width = preview_width(request.width);
+    let options = DecodeOptions {
+      fps: Some(preview_fps(request.fps.unwrap_or(DEFAULT_FPS))),
+      width: Some(width),
+      ..Default::default()
+    };
+
+    let mut budget = PreviewBudget::start();
+    let mut frames = budget.decode(&source, &options)?;
+    if let Some(threshold) = request.dedupe_threshold {
+      frames = dedupe_frames(frames, threshold).0;
+    }
+    filters.apply(&mut frames);
+    budget.encode(frames, width)
+  }
+
+  // Blocking: the recipe with its output fps capped, sources left when the
+  // budget is spent are skipped. Operations run on frames of the usual size so
+  // crops and overlays land where they would in the full render
+  pub fn preview_recipe(
+    storage: &MediaStorage,
+    media_id: Option<Uuid>,
+    recipe: &Recipe,
+  ) -> Result<Preview, VideoError> {
+    let pipeline = Self::check_recipe(storage, recipe)?;
+    let options = DecodeOptions {
+      fps: Some(preview_fps(recipe.output.fps())),
+      width: recipe.decode_width(),
+      ..Default::default()
+    };
+
+    let mut budget = PreviewBudget::start();
+    let mut clips = Vec::with_capacity(recipe.sources.len());
+    for source in &recipe.sources {
+      let segment = source.segment();
+      let path = Self::source_path(storage, segment.source.or(media_id))?;
+      let options = DecodeOptions {
+        start_ms: segment.start_ms(),
+        end_ms: segment.end_ms(),
+        ..options.clone()
+      };
+      let frames = budget.decode(&path, &options)?;
+      if frames.is_empty() {
+        break;
+      }
+      clips.push(Clip {
+        frames,
+        transition: segment.transition,
+      });
+    }
+    let frames = concat_clips(clips, recipe.output.background()?)?;
+    let (frames, _) = pipeline.apply(frames)?;
+    budget.encode(frames, PREVIEW_WIDTH)
+  }
+
+  // Blocking: decodes the trimmed sources of a recipe, joins them and runs its
+  // operations in order. `media_id` is the source of clips that do not name one
+  pub fn run_recipe(
+    storage: &MediaStorage,
+    id: Uuid,
+    media_id: Option<Uuid>,
+    destination: &Path,
+    recipe: &Recipe,
+    progress: &mut ProgressTracker,
+  ) -> Result<ConversionResponse, VideoError> {
+    let pipeline = Self::check_recipe(storage, recipe)?;
+    let options = DecodeOptions {
+      fps: Some(recipe.output.fps()),
+      width: recipe.decode_width(),
+      deadline: Some(job_deadline()),
+      ..Default::default()
+    };
+
+    let mut sources = Vec::with_capacity(recipe.sources.len());
+    for source in &recipe.sources {
+      let segment = source.segment();
+      let path = Self::source_path(storage, segment.source.or(media_id))?;
+      let options = DecodeOptions {
+        start_ms: segment.start_ms(),
+        end_ms: segment.end_ms(),
+        ..options.clone()
+      };
+      sources.push((path, options, segment.transition));
+    }
+
+    let frames_total =
+      sources.iter().map(|(path, options, _)| Self::estimate_frames(path, options));
+    progress.start(Stage::Decoding, frames_total.sum());
+    let mut clips = Vec::with_capacity(sources.len());
+    for (path, options, transition) in sources {
+      let mut frames = Vec::new();
+      Self::decode_each(&path, &options, |frame| {
+        frames.push(frame);
+        progress.advance(1);
+        progress.checkpoint()
+      })?;
+      clips.push(Clip {
+        frames,
+        transition,
+      });
+    }
+    let frames = concat_clips(clips, recipe.output.background()?)?;
+    progress.start(Stage::Processing, frames.len() as u64);
+    let (frames, dedupe) = pipeline.apply(frames)?;
+    progress.checkpoint()?;
+
+    let output = OutputOptions {
+      format: recipe.output.format,
+      spritesheet: recipe.output.spritesheet.clone(),
+      encoder: EncoderOptions {
+        deadline: options.deadline,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let response = Self::save_output(storage, id, frames, destination, &output, progress)?;
+    Ok(ConversionResponse {
+      dedupe,
+      filters: pipeline.filter_names(),
+      ..response
+    })
+  }
+
+  // Blocking: cost of a recipe from the probes of its sources, nothing is decoded
+  pub fn estimate_recipe(
+    storage: &MediaStorage,
+    media_id: Option<Uuid>,
+    recipe: &Recipe,
+  ) -> Result<CostEstimate, VideoError> {
+    let mut sources = Vec::with_capacity(recipe.sources.len());
+    for source in &recipe.sources {
+      let path = Self::source_path(storage, source.media_id.or(media_id))?;
+      sources.push(probe(&path)?);
+    }
+    Ok(CostEstimate::of(recipe, &sources))
+  }
+
+  // Blocking: validates a recipe and builds its operations, LUTs and overlay
+  // images must exist
+  pub fn check_recipe(
+    storage: &MediaStorage,
+    recipe: &Recipe,
+  ) -> Result<RecipePipeline, VideoError> {
+    recipe.validate()?;
+    RecipePipeline::build(recipe, storage)
+  }
+
+  // Writes the GIF or the sprite sheet, GIF frames are kept for later edits
+  fn save_output(
+    storage: &MediaStorage,
+    id: Uuid,
+    frames: Vec<Frame>,
+    destination: &Path,
+    output: &OutputOptions,
+    progress: &mut ProgressTracker,
+  ) -> Result<ConversionResponse, VideoError> {
+    let (frames, size_bytes, atlases) = Self::write_output(frames, destination, output, progress)?;
+    if output.format == OutputFormat::Gif {
+      write_frames(&storage.frames_dir(&id), &frames)?;
+    }
+
+    Ok(ConversionResponse {
+      id,
+      width: frames[0].width(),
+      height: frames[0].height(),
+      frame_count: frames.len(),
+      duration_ms: total_duration_ms(&frames),
+      size_bytes,
+      dedupe: None,
+      filters: Vec::new(),
+      format: output.format,
+      atlases,
+    })
+  }
+
+  fn output(request: &ConversionRequest, deadline: Option<Instant>) -> OutputOptions {
+    OutputOptions {
+      format: request.format(),
+      spritesheet: request.spritesheet.clone(),
+      encoder: EncoderOptions {
+        colors: request.palette.unwrap_or(MAX_PALETTE_SIZE),
+        dithering: request.dithering.unwrap_or(false),
+        deadline,
+        ..Default::default()
+      },
+      max_bytes: request.max_bytes,
+    }
+  }
+
+  // Blocking: packs the frames of an existing GIF into a sprite sheet
+  pub fn spritesheet_from_gif(
+    id: Uuid,
+    source: &Path,
+    destination: &Path,
+    options: &SpritesheetOptions,
+  ) -> Result<ConversionResponse, VideoError> {
+    options.validate()?;
+    let frames = decode_gif(source, &DecodeOptions::default())?;
+    let output = OutputOptions {
+      format: OutputFormat::Spritesheet,
+      spritesheet: options.clone(),
+      ..Default::default()
+    };
+    let (frames, size_bytes, atlases) =
+      Self::write_output(frames, destination, &output, &mut ProgressTracker::none())?;
+
+    Ok(ConversionResponse {
+      id,
+      width: frames[0].width(),
+      height: frames[0].height(),
+      frame_count: frames.len(),
+      duration_ms: total_duration_ms(&frames),
+      size_bytes,
+      dedupe: None,
+      filters: Vec::new(),
+      format: OutputFormat::Spritesheet,
+      atlases,
+    })
+  }
+
+  // Blocking: frames of the latest version of a GIF, GIFs created before frames
+  // were stored are decoded once
+  pub fn frame_manifest(storage: &MediaStorage, gif_id: Uuid) -> Result<FrameManifest, VideoError> {
+    let directory = storage.frames_dir(&gif_id);
+    if let Some(version) = latest_version(&directory)? {
+      return read_manifest(&directory, version);
+    }
+
+    let gif = storage.gif_path(&gif_id);
+    if !gif.exists() {
+      return Err(VideoError::GifNotFound);
+    }
+    write_frames(&directory, &decode_gif(&gif, &DecodeOptions::default())?)
+  }
+
+  // Blocking: applies frame operations to a version and saves the result as a new version
+  pub fn edit_frames(
+    storage: &MediaStorage,
+    gif_id: Uuid,
+    request: &FrameEditRequest,
+  ) -> Result<GifVersionResponse, VideoError> {
+    let directory = storage.frames_dir(&gif_id);
+    let latest = Self::frame_manifest(storage, gif_id)?.version;
+    let base_version = request.base_version.unwrap_or(latest);
+    if !manifest_path(&directory, base_version).exists() {
+      return Err(VideoError::VersionNotFound(base_version));
+    }
+
+    let mut manifest = read_manifest(&directory, base_version)?;
+    apply_operations(&mut manifest.frames, &request.operations)?;
+    manifest.version = latest + 1;
+    create_manifest(&directory, &manifest)?;
+
+    let destination = storage.gif_version_path(&gif_id, manifest.version);
+    let encoded = Self::encode_version(&directory, &manifest, &destination);
+    if encoded.is_err() {
+      // Frees the version number for the next edit
+      let _ = std::fs::remove_file(manifest_path(&directory, manifest.version));
+    }
+
+    Ok(GifVersionResponse {
+      id: gif_id,
+      version: manifest.version,
+      frame_count: manifest.frames.len(),
+      duration_ms: manifest.frames.iter().map(|frame| frame.delay_ms as u64).sum(),
+      size_bytes: encoded?,
+    })
+  }
+
+  // Blocking: a copy of a GIF with its frames and versions under a new id.
+  // Files are hard linked where the filesystem allows, they are never
+  // rewritten in place
+  pub fn copy_gif(storage: &MediaStorage, gif_id: Uuid) -> Result<Uuid, VideoError> {
+    Self::frame_manifest(storage, gif_id)?;
+    let id = Uuid::new_v4();
+    Self::link_files(&storage.frames_dir(&gif_id), &storage.frames_dir(&id))?;
+    Self::link_files(&storage.versions_dir(&gif_id), &storage.versions_dir(&id))?;
+    Self::link_file(&storage.gif_path(&gif_id), &storage.gif_path(&id))?;
+    Ok(id)
+  }
+
+  fn link_files(from: &Path, to: &Path) -> Result<(), VideoError> {
+    let entries = match std::fs::read_dir(from) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+      Err(err) => return Err(err.into()),
+    };
+    std::fs::create_dir_all(to)?;
+    for entry in entries {
+      let entry = entry?;
+      if entry.file_type()?.is_file() {
+        Self::link_file(&entry.path(), &to.join(entry.file_name()))?;
+      }
+    }
+    Ok(())
+  }
+
+  // Copied when the files are on another filesystem
+  fn link_file(from: &Path, to: &Path) -> Result<(), VideoError> {
+    if std::fs::hard_link(from, to).is_err() {
+      std::fs::copy(from, to)?;
+    }
+    Ok(())
+  }
+
+  fn encode_version(
+    directory: &Path,
+    manifest: &FrameManifest,
+    destination: &Path,
+  ) -> Result<u64, VideoError> {
+    if let Some(parent) = destination.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    let frames = load_frames(directory, manifest)?;
+
+    // Renamed once complete so readers never see a partial GIF
+    let partial = destination.with_extension("gif.partial");
+    encode_gif(&frames, BufWriter::new(File::create(&partial)?), DEFAULT_QUANTIZER_SPEED)?;
+    std::fs::rename(&partial, destination)?;
+    Ok(std::fs::metadata(destination)?.len())
+  }
+
+  // Blocking: every saved version of a GIF, oldest first
+  pub fn list_versions(
+    storage: &MediaStorage,
+    gif_id: Uuid,
+  ) -> Result<Vec<GifVersionResponse>, VideoError> {
+    let directory = storage.frames_dir(&gif_id);
+    let latest = Self::frame_manifest(storage, gif_id)?.version;
+
+    let mut versions = Vec::new();
+    for version in 1..=latest {
+      let Ok(manifest) = read_manifest(&directory, version) else {
+        continue; // An edit that failed to encode
+      };
+      let Ok(metadata) = std::fs::metadata(storage.gif_version_path(&gif_id, version)) else {
+        continue; // An edit still being encoded
+      };
+      versions.push(GifVersionResponse {
+        id: gif_id,
+        version,
+        frame_count: manifest.frames.len(),
+        duration_ms: manifest.frames.iter().map(|frame| frame.delay_ms as u64).sum(),
+        size_bytes: metadata.len(),
+      });
+    }
+    Ok(versions)
+  }
+
+  // Blocking: path of the latest GIF version that finished encoding
+  pub fn latest_gif(storage: &MediaStorage, gif_id: Uuid) -> Result<PathBuf, VideoError> {
+    let latest = latest_version(&storage.frames_dir(&gif_id))?.unwrap_or(1);
+    (1..=latest)
+      .rev()
+      .map(|version| storage.gif_version_path(&gif_id, version))
+      .find(|path| path.exists())
+      .ok_or(VideoError::GifNotFound)
+  }
+
+  // Blocking: renders a contact sheet once and serves it from `cache` afterwards
+  pub fn contact_sheet(
+    source: &Path,
+    cache: &Path,
+    grid: &SheetGrid,
+    format: SheetFormat,
+  ) -> Result<Vec<u8>, VideoError> {
+    if let Ok(bytes) = std::fs::read(cache) {
+      return Ok(bytes);
+    }
+
+    let info = probe(source)?;
+    let timestamps = sample_timestamps(info.duration_ms, grid.count());
+    let frames = extract_frames_exact(source, &timestamps, Some(grid.tile_width))?;
+    let bytes = video_contact_sheet::encode(video_contact_sheet::render(&frames, grid)?, format)?;
+
+    // Written aside then renamed so concurrent requests never read a partial file
+    if let Some(directory) = cache.parent() {
+      std::fs::create_dir_all(directory)?;
+    }
+    let partial = cache.with_extension(format!("{}.partial", Uuid::new_v4()));
+    std::fs::write(&partial, &bytes)?;
+    std::fs::rename(&partial, cache)?;
+    Ok(bytes)
+  }
+
+  // Blocking: only probes the video, tiles are computed like the sheet decoder scales them
+  pub fn thumbnails_track(
+    source: &Path,
+    grid: &SheetGrid,
+    image_url: &str,
+  ) -> Result<String, VideoError> {
+    let info = probe(source)?;
+    let tile_size = output_size(info.width, info.height, Some(grid.tile_width));
+    Ok(video_contact_sheet::webvtt(info.duration_ms, grid, tile_size, image_url))
+  }
+
+  // Runs the Encoding stage, every frame is marked done once the output is written
+  fn write_output(
+    frames: Vec<Frame>,
+    destination: &Path,
+    output: &OutputOptions,
+    progress: &mut ProgressTracker,
+  ) -> Result<WrittenOutput, VideoError> {
+    let frame_count = frames.len() as u64;
+    progress.start(Stage::Encoding, frame_count);
+    let written = Self::encode_output(frames, destination, output, progress)?;
+    progress.set_done(frame_count);
+    Ok(written)
+  }
+
+  fn encode_output(
+    frames: Vec<Frame>,
+    destination: &Path,
+    output: &OutputOptions,
+    progress: &mut ProgressTracker,
+  ) -> Result<WrittenOutput, VideoError> {
+    match (output.format, output.max_bytes) {
+      (OutputFormat::Gif, Some(max_bytes)) => {
+        let (frames, bytes) = encode_within(frames, &output.encoder, max_bytes)?;
+        std::fs::write(destination, &bytes)?;
+        Ok((frames, bytes.len() as u64, None))
+      },
+      (OutputFormat::Gif, None) => {
+        let writer = BufWriter::new(File::create(destination)?);
+        encode_gif_tracked(&frames, writer, &output.encoder, |written| {
+          progress.set_done(written as u64);
+          progress.checkpoint()
+        })?;
+        Ok((frames, std::fs::metadata(destination)?.len(), None))
+      },
+      (OutputFormat::Spritesheet, _) => {
+        let files = write_spritesheet(&frames, &output.spritesheet, destination)?;
+        let mut size_bytes = 0;
+        for file in &files {
+          size_bytes += std::fs::metadata(destination.join(&file.image))?.len();
+          size_bytes += std::fs::metadata(destination.join(&file.data))?.len();
+        }
+        Ok((frames, size_bytes, Some(files)))
+      },
+    }
+  }
+
+  // Same as `decode`, every frame goes to `sink` as soon as it is decoded
+  fn decode_each(
+    source: &Path,
+    options: &DecodeOptions,
+    sink: impl FnMut(Frame) -> Result<(), VideoError>,
+  ) -> Result<(), VideoError> {
+    match is_gif(source)? {
+      true => stream_gif(source, options, sink),
+      false => stream_frames(source, options, sink),
+    }
+  }
+
+  // Frames a clip should give at the sampling rate, 0 when its length is unknown
+  fn estimate_frames(source: &Path, options: &DecodeOptions) -> u64 {
+    let info = match probe(source) {
+      Ok(info) => info,
+      Err(_) => return 0,
+    };
+    let end_ms = options.end_ms.map_or(info.duration_ms, |end| end.min(info.duration_ms));
+    let length_ms = end_ms.saturating_sub(options.start_ms.unwrap_or(0));
+    let fps = options.fps.map_or(info.fps, f64::from);
+    (length_ms as f64 * fps / 1000.0).round() as u64
+  }
+
+  // Animated GIF uploads keep their own frames and delays, anything else goes through ffmpeg
+  fn decode(source: &Path, options: &DecodeOptions) -> Result<Vec<Frame>, VideoError> {
+    match is_gif(source)? {
+      true => decode_gif(source, options),
+      false => extract_frames(source, options),
+    }
+  }
+
+  fn source_path(storage: &MediaStorage, media_id: Option<Uuid>) -> Result<PathBuf, VideoError> {
+    let media_id = media_id
+      .ok_or_else(|| VideoError::InvalidOption("a source media is required".to_string()))?;
+    let path = storage.media_path(&media_id);
+    match path.exists() {
+      true => Ok(path),
+      false => Err(VideoError::MediaNotFound),
+    }
+  }
+
+  // Every decode of a conversion shares the deadline taken when it started
+  fn decode_options(request: &ConversionRequest, deadline: Instant) -> DecodeOptions {
+    DecodeOptions {
+      fps: Some(request.fps.unwrap_or(DEFAULT_FPS)),
+      width: request.width,
+      deadline: Some(deadline),
+      ..Default::default()
+    }
+  }
+
+  // Decode every segment at the same fps and join them with their transitions
+  fn concat(
+    storage: &MediaStorage,
+    media_id: Option<Uuid>,
+    request: &ConversionRequest,
+    deadline: Instant,
+  ) -> Result<Vec<Frame>, VideoError> {
+    let background = match &request.background {
+      Some(color) => parse_color(color)?,
+      None => BLACK,
+    };
+
+    let options = Self::decode_options(request, deadline);
+    let mut clips = Vec::with_capacity(request.segments.len());
+    for segment in &request.segments {
+      let source = Self::source_path(storage, segment.source.or(media_id))?;
+      let options = DecodeOptions {
+        start_ms: segment.start_ms(),
+        end_ms: segment.end_ms(),
+        ..options.clone()
+      };
+      clips.push(Clip {
+        frames: Self::decode(&source, &options)?,
+        transition: segment.transition.clone(),
+      });
+    }
+    concat_clips(clips, background)
+  }
+
+  // Decode every cell at its cell size and play them side by side
+  fn layout(
+    storage: &MediaStorage,
+    media_id: Option<Uuid>,
+    layout: &LayoutSpec,
+    request: &ConversionRequest,
+    deadline: Instant,
+  ) -> Result<Vec<Frame>, VideoError> {
+    let width = request.width.unwrap_or(DEFAULT_LAYOUT_WIDTH);
+    let fps = request.fps.unwrap_or(DEFAULT_FPS);
+
+    let mut clips = Vec::with_capacity(layout.cells.len());
+    for (index, cell) in layout.cells.iter().enumerate() {
+      let source = Self::source_path(storage, cell.source.or(media_id))?;
+      let options = DecodeOptions {
+        fps: Some(fps),
+        width: Some(layout.decode_width(index, width)),
+        start_ms: cell.start_ms(),
+        end_ms: cell.end_ms(),
+        deadline: Some(deadline),
+      };
+      clips.push(LayoutClip {
+        frames: Self::decode(&source, &options)?,
+        label: cell.label.clone(),
+      });
+    }
+    layout.compose(clips, width, 1000 / fps)
+  }
+
+  // Sample the whole video at a fixed interval and play it back at the output fps
+  fn timelapse(
+    source: &Path,
+    request: &ConversionRequest,
+    deadline: Instant,
+  ) -> Result<Vec<Frame>, VideoError> {
+    let fps = request.fps.unwrap_or(DEFAULT_FPS);
+    let duration_ms = probe(source)?.duration_ms;
+    let timestamps =
+      timelapse_timestamps(duration_ms, fps, request.every, request.target_duration)?;
+
+    let mut frames = extract_frames_at(source, &timestamps, request.width, Some(deadline))?;
+    for frame in frames.iter_mut() {
+      frame.delay_ms = 1000 / fps;
+    }
+    Ok(frames)
+  }
+
+  pub fn validate(request: &ConversionRequest) -> Result<(), VideoError> {
+    if let Some(fps) = request.fps {
+      if !(1..=MAX_FPS).contains(&fps) {
+        return Err(VideoError::InvalidOption(format!("fps must be between 1 and {}", MAX_FPS)));
+      }
+    }
+    if let Some(width) = request.width {
+      if !(2..=MAX_WIDTH).contains(&width) {
+        return Err(VideoError::InvalidOption(format!(
+          "width must be between 2 and {}",
+          MAX_WIDTH
+        )));
+      }
+    }
+    if let Some(threshold) = request.dedupe_threshold {
+      if !(0.0..=1.0).contains(&threshold) {
+        return Err(VideoError::InvalidOption(
+          "dedupe_threshold must be between 0.0 and 1.0".to_string(),
+        ));
+      }
+    }
+    if request.segments.len() > MAX_SEGMENTS {
+      return Err(VideoError::InvalidOption(format!(
+        "at most {} segments are allowed",
+        MAX_SEGMENTS
+      )));
+    }
+    for segment in &request.segments {
+      segment.validate()?;
+    }
+    if let Some(color) = &request.background {
+      parse_color(color)?;
+    }
+    if request.format() == OutputFormat::Spritesheet {
+      request.spritesheet.validate()?;
+    }
+    if let Some(palette) = request.palette {
+      if !(MIN_PALETTE_SIZE..=MAX_PALETTE_SIZE).contains(&palette) {
+        return Err(VideoError::InvalidOption(format!(
+          "palette must be between {} and {} colours",
+          MIN_PALETTE_SIZE, MAX_PALETTE_SIZE
+        )));
+      }
+    }
+    if let Some(max_bytes) = request.max_bytes {
+      if !(MIN_OUTPUT_BYTES..=MAX_OUTPUT_BYTES).contains(&max_bytes) {
+        return Err(VideoError::InvalidOption(format!(
+          "max_bytes must be between {} and {}",
+          MIN_OUTPUT_BYTES, MAX_OUTPUT_BYTES
+        )));
+      }
+      if request.format() != OutputFormat::Gif {
+        return Err(VideoError::InvalidOption("max_bytes only applies to GIF output".to_string()));
+      }
+    }
+    if let Some(layout) = &request.layout {
+      if !request.segments.is_empty() || request.mode == ConversionMode::Timelapse {
+        return Err(VideoError::InvalidOption(
+          "layouts cannot be combined with segments or timelapse".to_string(),
+        ));
+      }
+      layout.validate()?;
+    }
+    if request.mode == ConversionMode::Timelapse {
+      if !request.segments.is_empty() {
+        return Err(VideoError::InvalidOption(
+          "timelapse cannot be combined with segments".to_string(),
+        ));
+      }
+      match (request.every, request.target_duration) {
+        (Some(every), None) if every > 0.0 => {},
+        (None, Some(target)) if target > 0.0 => {},
+        _ => {
+          return Err(VideoError::InvalidOption(
+            "timelapse needs a positive every or target_duration, but not both".to_string(),
+          ))
+        },
+      }
+    }
+    Ok(())
+  }
+}
+
+// Source timestamps sampled by a timelapse, either every N seconds or spread
+// evenly so the GIF lasts target_duration seconds at the output fps
+pub fn timelapse_timestamps(
+  duration_ms: u64,
+  fps: u32,
+  every: Option<f64>,
+  target_duration: Option<f64>,
+) -> Result<Vec<u64>, VideoError> {
+  let interval_ms = match (every, target_duration) {
+    (Some(every), _) => every * 1000.0,
+    (None, Some(target)) => {
+      let frame_count = (target * fps as f64).round().max(1.0);
+      duration_ms as f64 / frame_count
+    },
+    (None, None) => {
+      return Err(VideoError::InvalidOption("missing timelapse interval".to_string()))
+    },
+  };
+
+  if interval_ms < 1.0 {
+    return Err(VideoError::InvalidOption("timelapse interval is too small".to_string()));
+  }
+  let frame_count = (duration_ms as f64 / interval_ms).ceil() as u64;
+  if frame_count > MAX_TIMELAPSE_FRAMES {
+    return Err(VideoError::InvalidOption(format!(
+      "timelapse would produce {} frames, the maximum is {}",
+      frame_count, MAX_TIMELAPSE_FRAMES
+    )));
+  }
+
+  Ok((0..frame_count.max(1)).map(|index| (index as f64 * interval_ms) as u64).collect())
+}
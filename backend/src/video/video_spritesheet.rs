@@ -0,0 +1,377 @@
+use crate::video::video_canvas::Rect;
+use crate::video::video_errors::VideoError;
+use crate::video::video_frame::Frame;
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const DEFAULT_ATLAS_SIZE: u32 = 2048;
+pub const MAX_ATLAS_SIZE: u32 = 8192;
+pub const MAX_PADDING: u32 = 16;
+pub const DEFAULT_PADDING: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Packing {
+  #[default]
+  Grid,
+  Tight, // Transparent borders are trimmed and sprites packed on shelves
+}
+
+// DTO for sprite sheet output
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SpritesheetOptions {
+  #[serde(default)]
+  pub packing: Packing,
+  pub max_size: Option<u32>, // Width and height limit of one atlas, extra frames go to new atlases
+  pub padding: Option<u32>,  // Space between sprites
+  pub columns: Option<u32>,  // Grid only
+}
+
+impl SpritesheetOptions {
+  pub fn validate(&self) -> Result<(), VideoError> {
+    if !(64..=MAX_ATLAS_SIZE).contains(&self.max_size()) {
+      return Err(VideoError::InvalidOption(format!(
+        "max_size must be between 64 and {}",
+        MAX_ATLAS_SIZE
+      )));
+    }
+    if self.padding() > MAX_PADDING {
+      return Err(VideoError::InvalidOption(format!(
+        "padding must be at most {} pixels",
+        MAX_PADDING
+      )));
+    }
+    if self.columns == Some(0) {
+      return Err(VideoError::InvalidOption("columns must be positive".to_string()));
+    }
+    Ok(())
+  }
+
+  fn max_size(&self) -> u32 {
+    self.max_size.unwrap_or(DEFAULT_ATLAS_SIZE)
+  }
+
+  fn padding(&self) -> u32 {
+    self.padding.unwrap_or(DEFAULT_PADDING)
+  }
+}
+
+// One frame placed in an atlas
+#[derive(Debug, Clone)]
+pub struct Sprite {
+  pub index: usize, // Position of the frame in the animation
+  pub frame: Rect,  // Area of the atlas holding the sprite
+  pub offset: Rect, // Area of the original frame the sprite was trimmed to
+  pub source_size: (u32, u32),
+  pub duration_ms: u32,
+}
+
+pub struct Atlas {
+  pub image: RgbaImage,
+  pub sprites: Vec<Sprite>,
+}
+
+// Summary of a written atlas
+#[derive(Debug, Serialize)]
+pub struct AtlasFile {
+  pub image: String,
+  pub data: String,
+  pub width: u32,
+  pub height: u32,
+  pub frame_count: usize,
+}
+
+// Pack frames into as many atlases as needed
+pub fn pack(frames: &[Frame], options: &SpritesheetOptions) -> Result<Vec<Atlas>, VideoError> {
+  if frames.is_empty() {
+    return Err(VideoError::NoFrames);
+  }
+  let max_size = options.max_size();
+  if frames.iter().any(|frame| frame.width() > max_size || frame.height() > max_size) {
+    return Err(VideoError::InvalidOption(format!(
+      "frames do not fit in a {}x{} atlas",
+      max_size, max_size
+    )));
+  }
+
+  let sprites = match options.packing {
+    Packing::Grid => grid_layout(frames, options),
+    Packing::Tight => shelf_layout(frames, options),
+  };
+  Ok(sprites.into_iter().map(|sprites| render_atlas(frames, sprites)).collect())
+}
+
+// Every frame gets a cell the size of the largest frame
+fn grid_layout(frames: &[Frame], options: &SpritesheetOptions) -> Vec<Vec<Sprite>> {
+  let (max_size, padding) = (options.max_size(), options.padding());
+  let cell_width = frames.iter().map(Frame::width).max().unwrap_or(1);
+  let cell_height = frames.iter().map(Frame::height).max().unwrap_or(1);
+
+  let fit_columns = ((max_size + padding) / (cell_width + padding)).max(1);
+  let fit_rows = ((max_size + padding) / (cell_height + padding)).max(1);
+  let square = (frames.len() as f64).sqrt().ceil() as u32;
+  let columns = options.columns.unwrap_or(square).clamp(1, fit_columns);
+  let per_atlas = (columns * fit_rows) as usize;
+
+  let mut atlases = Vec::new();
+  for (atlas_index, chunk) in frames.chunks(per_atlas).enumerate() {
+    let sprites = chunk
+      .iter()
+      .enumerate()
+      .map(|(position, frame)| {
+        let (column, row) = (position as u32 % columns, position as u32 / columns);
+        let full = full_rect(frame);
+        Sprite {
+          index: atlas_index * per_atlas + position,
+          frame: Rect {
+            x: column * (cell_width + padding),
+            y: row * (cell_height + padding),
+            ..full
+          },
+          offset: full,
+          source_size: (frame.width(), frame.height()),
+          duration_ms: frame.delay_ms,
+        }
+      })
+      .collect();
+    atlases.push(sprites);
+  }
+  atlases
+}
+
+// Trimmed sprites sorted by height and placed left to right on shelves
+fn shelf_layout(frames: &[Frame], options: &SpritesheetOptions) -> Vec<Vec<Sprite>> {
+  let (max_size, padding) = (options.max_size(), options.padding());
+  let trimmed: Vec<Rect> = frames.iter().map(opaque_bounds).collect();
+
+  // A roughly square atlas unless the frames need more room
+  let area: u64 =
+    trimmed.iter().map(|rect| (rect.width + padding) as u64 * (rect.height + padding) as u64).sum();
+  let widest = trimmed.iter().map(|rect| rect.width).max().unwrap_or(1);
+  let atlas_width = ((area as f64).sqrt().ceil() as u32).clamp(widest, max_size);
+
+  let mut order: Vec<usize> = (0..frames.len()).collect();
+  order.sort_by_key(|&index| (std::cmp::Reverse(trimmed[index].height), index));
+
+  let mut atlases: Vec<Vec<Sprite>> = vec![Vec::new()];
+  let (mut x, mut y, mut shelf_height) = (0u32, 0u32, 0u32);
+  for index in order {
+    let rect = trimmed[index];
+    if x > 0 && x + rect.width > atlas_width {
+      x = 0;
+      y += shelf_height + padding;
+      shelf_height = 0;
+    }
+    if y + rect.height > max_size {
+      atlases.push(Vec::new());
+      (x, y, shelf_height) = (0, 0, 0);
+    }
+
+    let frame = &frames[index];
+    atlases.last_mut().expect("at least one atlas").push(Sprite {
+      index,
+      frame: Rect {
+        x,
+        y,
+        ..rect
+      },
+      offset: rect,
+      source_size: (frame.width(), frame.height()),
+      duration_ms: frame.delay_ms,
+    });
+    x += rect.width + padding;
+    shelf_height = shelf_height.max(rect.height);
+  }
+
+  // Descriptors list frames in animation order
+  for sprites in atlases.iter_mut() {
+    sprites.sort_by_key(|sprite| sprite.index);
+  }
+  atlases
+}
+
+fn full_rect(frame: &Frame) -> Rect {
+  Rect {
+    x: 0,
+    y: 0,
+    width: frame.width(),
+    height: frame.height(),
+  }
+}
+
+// Smallest area holding every non transparent pixel, fully transparent frames keep one pixel
+pub fn opaque_bounds(frame: &Frame) -> Rect {
+  let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+  for (x, y, pixel) in frame.image.enumerate_pixels() {
+    if pixel.0[3] > 0 {
+      left = left.min(x);
+      top = top.min(y);
+      right = right.max(x);
+      bottom = bottom.max(y);
+    }
+  }
+
+  match left {
+    u32::MAX => Rect {
+      x: 0,
+      y: 0,
+      width: 1,
+      height: 1,
+    },
+    _ => Rect {
+      x: left,
+      y: top,
+      width: right - left + 1,
+      height: bottom - top + 1,
+    },
+  }
+}
+
+fn render_atlas(frames: &[Frame], sprites: Vec<Sprite>) -> Atlas {
+  let width = sprites.iter().map(|sprite| sprite.frame.x + sprite.frame.width).max().unwrap_or(1);
+  let height = sprites.iter().map(|sprite| sprite.frame.y + sprite.frame.height).max().unwrap_or(1);
+
+  let mut image = RgbaImage::new(width, height);
+  for sprite in &sprites {
+    let source = &frames[sprite.index].image;
+    let Rect {
+      x,
+      y,
+      width,
+      height,
+    } = sprite.offset;
+    let cropped = imageops::crop_imm(source, x, y, width, height).to_image();
+    imageops::replace(&mut image, &cropped, sprite.frame.x as i64, sprite.frame.y as i64);
+  }
+
+  Atlas {
+    image,
+    sprites,
+  }
+}
+
+// TexturePacker "JSON (Array)" descriptor, durations follow the Aseprite extension
+#[derive(Debug, Serialize)]
+pub struct Descriptor {
+  pub frames: Vec<DescriptorFrame>,
+  pub meta: DescriptorMeta,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DescriptorFrame {
+  pub filename: String,
+  pub frame: DescriptorRect,
+  pub rotated: bool,
+  pub trimmed: bool,
+  pub sprite_source_size: DescriptorRect,
+  pub source_size: DescriptorSize,
+  pub duration: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DescriptorRect {
+  pub x: u32,
+  pub y: u32,
+  pub w: u32,
+  pub h: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DescriptorSize {
+  pub w: u32,
+  pub h: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DescriptorMeta {
+  pub app: &'static str,
+  pub version: &'static str,
+  pub image: String,
+  pub format: &'static str,
+  pub size: DescriptorSize,
+  pub scale: &'static str,
+}
+
+impl From<Rect> for DescriptorRect {
+  fn from(rect: Rect) -> Self {
+    DescriptorRect {
+      x: rect.x,
+      y: rect.y,
+      w: rect.width,
+      h: rect.height,
+    }
+  }
+}
+
+pub fn descriptor(atlas: &Atlas, image_name: &str) -> Descriptor {
+  let frames = atlas
+    .sprites
+    .iter()
+    .map(|sprite| DescriptorFrame {
+      filename: format!("frame_{:04}", sprite.index),
+      frame: sprite.frame.into(),
+      rotated: false,
+      trimmed: (sprite.offset.width, sprite.offset.height) != sprite.source_size,
+      sprite_source_size: sprite.offset.into(),
+      source_size: DescriptorSize {
+        w: sprite.source_size.0,
+        h: sprite.source_size.1,
+      },
+      duration: sprite.duration_ms,
+    })
+    .collect();
+
+  Descriptor {
+    frames,
+    meta: DescriptorMeta {
+      app: "rust_to_gif",
+      version: "1.0",
+      image: image_name.to_string(),
+      format: "RGBA8888",
+      size: DescriptorSize {
+        w: atlas.image.width(),
+        h: atlas.image.height(),
+      },
+      scale: "1",
+    },
+  }
+}
+
+// Write `atlas_N.png` and `atlas_N.json` for every atlas into `directory`
+pub fn write_spritesheet(
+  frames: &[Frame],
+  options: &SpritesheetOptions,
+  directory: &Path,
+) -> Result<Vec<AtlasFile>, VideoError> {
+  let atlases = pack(frames, options)?;
+
+  let mut files = Vec::with_capacity(atlases.len());
+  for (index, atlas) in atlases.iter().enumerate() {
+    let image = format!("atlas_{}.png", index);
+    let data = format!("atlas_{}.json", index);
+    atlas.image.save(directory.join(&image))?;
+    let json = serde_json::to_vec_pretty(&descriptor(atlas, &image))?;
+    std::fs::write(directory.join(&data), json)?;
+
+    files.push(AtlasFile {
+      image,
+      data,
+      width: atlas.image.width(),
+      height: atlas.image.height(),
+      frame_count: atlas.sprites.len(),
+    });
+  }
+  Ok(files)
+}
+
+// Files a sprite sheet directory may serve
+pub fn is_atlas_file(name: &str) -> bool {
+  let Some(stem) = name.strip_suffix(".png").or_else(|| name.strip_suffix(".json")) else {
+    return false;
+  };
+  stem
+    .strip_prefix("atlas_")
+    .is_some_and(|number| !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()))
+}
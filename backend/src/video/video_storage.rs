@@ -0,0 +1,197 @@
+use crate::video::video_dto::OutputFormat;
+use crate::video::video_errors::VideoError;
+use actix_web::web;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+const DIGEST_BUFFER: usize = 64 * 1024;
+
+// Filesystem storage for uploaded media and generated GIFs
+#[derive(Debug, Clone)]
+pub struct MediaStorage {
+  root: PathBuf,
+}
+
+impl MediaStorage {
+  pub fn new(root: impl Into<PathBuf>) -> Self {
+    MediaStorage {
+      root: root.into(),
+    }
+  }
+
+  pub fn from_env() -> Self {
+    Self::new(env::var("STORAGE_DIR").unwrap_or_else(|_| "storage".to_string()))
+  }
+
+  pub fn media_path(&self, id: &Uuid) -> PathBuf {
+    self.root.join("media").join(id.to_string())
+  }
+
+  // SHA-256 of an upload, kept next to it
+  pub fn media_digest_path(&self, id: &Uuid) -> PathBuf {
+    self.root.join("media").join(format!("{}.sha256", id))
+  }
+
+  pub fn gif_path(&self, id: &Uuid) -> PathBuf {
+    self.root.join("gifs").join(format!("{}.gif", id))
+  }
+
+  // Version 1 is the GIF of the conversion, later versions come from frame edits
+  pub fn gif_version_path(&self, id: &Uuid, version: u32) -> PathBuf {
+    match version {
+      0 | 1 => self.gif_path(id),
+      _ => self.versions_dir(id).join(format!("v{}.gif", version)),
+    }
+  }
+
+  pub fn versions_dir(&self, id: &Uuid) -> PathBuf {
+    self.root.join("gifs").join(id.to_string())
+  }
+
+  // PNG frames and manifests of a generated GIF
+  pub fn frames_dir(&self, id: &Uuid) -> PathBuf {
+    self.root.join("frames").join(id.to_string())
+  }
+
+  pub fn sheet_dir(&self, id: &Uuid) -> PathBuf {
+    self.root.join("sheets").join(id.to_string())
+  }
+
+  pub fn contact_sheet_path(&self, id: &Uuid, key: &str, extension: &str) -> PathBuf {
+    self.root.join("contact-sheets").join(id.to_string()).join(format!("{}.{}", key, extension))
+  }
+
+  // Stream an upload to disk without holding it in memory, with the SHA-256
+  // of its content
+  pub async fn save_upload(
+    &self,
+    mut payload: web::Payload,
+  ) -> Result<(Uuid, u64, String), VideoError> {
+    let id = Uuid::new_v4();
+    let path = self.media_path(&id);
+    fs::create_dir_all(self.root.join("media")).await?;
+
+    let mut file = fs::File::create(&path).await?;
+    let mut size = 0u64;
+    let mut digest = Sha256::new();
+
+    while let Some(chunk) = payload.next().await {
+      let chunk = match chunk {
+        Ok(chunk) => chunk,
+        Err(err) => {
+          drop(file);
+          let _ = fs::remove_file(&path).await;
+          return Err(VideoError::IoError(std::io::Error::other(err.to_string())));
+        },
+      };
+      size += chunk.len() as u64;
+      digest.update(&chunk);
+      file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    let digest = hex::encode(digest.finalize());
+    fs::write(self.media_digest_path(&id), &digest).await?;
+    Ok((id, size, digest))
+  }
+
+  // SHA-256 of an upload. Uploads saved before digests were kept are hashed
+  // once
+  pub async fn media_digest(&self, id: &Uuid) -> Result<String, VideoError> {
+    let digest_path = self.media_digest_path(id);
+    if let Ok(digest) = fs::read_to_string(&digest_path).await {
+      return Ok(digest);
+    }
+    let mut file = match fs::File::open(self.media_path(id)).await {
+      Ok(file) => file,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+        return Err(VideoError::MediaNotFound)
+      },
+      Err(err) => return Err(err.into()),
+    };
+    let mut digest = Sha256::new();
+    let mut buffer = vec![0u8; DIGEST_BUFFER];
+    loop {
+      let read = file.read(&mut buffer).await?;
+      if read == 0 {
+        break;
+      }
+      digest.update(&buffer[..read]);
+    }
+    let digest = hex::encode(digest.finalize());
+    fs::write(&digest_path, &digest).await?;
+    Ok(digest)
+  }
+
+  pub async fn find_media(&self, id: &Uuid) -> Result<PathBuf, VideoError> {
+    let path = self.media_path(id);
+    match fs::try_exists(&path).await? {
+      true => Ok(path),
+      false => Err(VideoError::MediaNotFound),
+    }
+  }
+
+  pub async fn prepare_gif(&self, id: &Uuid) -> Result<PathBuf, VideoError> {
+    fs::create_dir_all(self.root.join("gifs")).await?;
+    Ok(self.gif_path(id))
+  }
+
+  // Drops a GIF with its frames and edited versions
+  // Whether anything of the GIF `id` is left on disk
+  pub async fn has_gif(&self, id: &Uuid) -> Result<bool, VideoError> {
+    for path in [self.gif_path(id), self.frames_dir(id), self.versions_dir(id)] {
+      if fs::try_exists(path).await? {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+
+  pub async fn remove_gif(&self, id: &Uuid) -> Result<(), VideoError> {
+    for result in [
+      fs::remove_file(self.gif_path(id)).await,
+      fs::remove_dir_all(self.frames_dir(id)).await,
+      fs::remove_dir_all(self.versions_dir(id)).await,
+    ] {
+      match result {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {},
+      }
+    }
+    Ok(())
+  }
+
+  // Drops what a cancelled job wrote so far
+  pub async fn remove_output(&self, id: &Uuid, format: OutputFormat) -> Result<(), VideoError> {
+    match format {
+      OutputFormat::Gif => self.remove_gif(id).await,
+      OutputFormat::Spritesheet => match fs::remove_dir_all(self.sheet_dir(id)).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+      },
+    }
+  }
+
+  // GIF file or sprite sheet directory receiving the output
+  pub async fn prepare_output(
+    &self,
+    id: &Uuid,
+    format: OutputFormat,
+  ) -> Result<PathBuf, VideoError> {
+    match format {
+      OutputFormat::Gif => self.prepare_gif(id).await,
+      OutputFormat::Spritesheet => self.prepare_sheet(id).await,
+    }
+  }
+
+  pub async fn prepare_sheet(&self, id: &Uuid) -> Result<PathBuf, VideoError> {
+    let directory = self.sheet_dir(id);
+    fs::create_dir_all(&directory).await?;
+    Ok(directory)
+  }
+}
@@ -0,0 +1,80 @@
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream, StreamExt};
+use std::convert::Infallible;
+use std::io::{self, Write};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+// Chunks waiting for the client, a slow client slows the encoder down
+pub const STREAM_CHUNKS: usize = 8;
+
+// Collects what the encoder writes and hands it to the response on every
+// flush, the GIF writer flushes once per frame. Blocking, for the encoding thread
+pub struct ChunkWriter {
+  buffer: Vec<u8>,
+  sender: Sender<Bytes>,
+  closed: bool, // The client went away
+}
+
+impl ChunkWriter {
+  pub fn new(sender: Sender<Bytes>) -> Self {
+    ChunkWriter {
+      buffer: Vec::new(),
+      sender,
+      closed: false,
+    }
+  }
+}
+
+impl Write for ChunkWriter {
+  // Never fails so the encoder can always write its trailer, the error is
+  // reported by the next flush
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if !self.closed {
+      self.buffer.extend_from_slice(buf);
+    }
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    if self.closed {
+      return Err(io::ErrorKind::BrokenPipe.into());
+    }
+    if self.buffer.is_empty() {
+      return Ok(());
+    }
+    let chunk = Bytes::from(std::mem::take(&mut self.buffer));
+    if self.sender.blocking_send(chunk).is_err() {
+      self.closed = true;
+      return Err(io::ErrorKind::BrokenPipe.into());
+    }
+    Ok(())
+  }
+}
+
+// Writes everything to both writers, used to keep the streamed GIF on disk
+pub struct Tee<A: Write, B: Write>(pub A, pub B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.write_all(buf)?;
+    self.1.write_all(buf)?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.0.flush()?;
+    self.1.flush()
+  }
+}
+
+// Response body, the first chunk was already received to pick the status
+pub fn gif_chunks(
+  first: Bytes,
+  receiver: Receiver<Bytes>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+  let rest = stream::unfold(receiver, |mut receiver| async move {
+    let chunk = receiver.recv().await?;
+    Some((Ok(chunk), receiver))
+  });
+  stream::once(async move { Ok(first) }).chain(rest)
+}
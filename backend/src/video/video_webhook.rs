@@ -0,0 +1,354 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_job::{ConversionJob, JobStatus};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde::Serialize;
+use sha2::Sha256;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_postgres::types::Json;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+pub const DELIVERY_HEADER: &str = "X-Webhook-Id";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const MAX_CALLBACK_URL_LENGTH: usize = 2048;
+// Receivers are expected to answer at once and do their work later
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 10; // Over about 3 hours
+pub const DELIVERY_BASE_DELAY: Duration = Duration::from_secs(30);
+pub const MAX_DELIVERY_DELAY: Duration = Duration::from_secs(3600);
+// Part of a refused delivery's response kept with it
+pub const MAX_RESPONSE_CHARS: usize = 500;
+// Lets callbacks reach loopback and private addresses, for receivers on the
+// same machine or network during development. Never set in production
+pub const PRIVATE_CALLBACKS_ENV: &str = "WEBHOOK_ALLOW_PRIVATE_HOSTS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WebhookEvent {
+  #[serde(rename = "job.succeeded")]
+  JobSucceeded,
+  #[serde(rename = "job.failed")]
+  JobFailed, // Dead-lettered, retries of a job are not reported
+}
+
+impl WebhookEvent {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      WebhookEvent::JobSucceeded => "job.succeeded",
+      WebhookEvent::JobFailed => "job.failed",
+    }
+  }
+
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "job.succeeded" => Some(WebhookEvent::JobSucceeded),
+      "job.failed" => Some(WebhookEvent::JobFailed),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+  Pending, // Not sent yet, or waiting for a retry until `next_attempt_at`
+  Delivered,
+  Failed, // Refused on every attempt
+}
+
+impl DeliveryStatus {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "pending" => Some(DeliveryStatus::Pending),
+      "delivered" => Some(DeliveryStatus::Delivered),
+      "failed" => Some(DeliveryStatus::Failed),
+      _ => None,
+    }
+  }
+}
+
+// Body of a webhook. `id` is the id of the delivery, the same on every attempt
+// so receivers can drop the ones they already handled
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookPayload {
+  pub id: Uuid,
+  pub event: WebhookEvent,
+  pub job_id: Uuid,
+  pub status: JobStatus,
+  pub attempts: i32,
+  pub result: Option<serde_json::Value>, // The conversion response of a succeeded job
+  pub error: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl WebhookPayload {
+  pub fn succeeded(job: &ConversionJob, result: serde_json::Value) -> Self {
+    Self::new(job, WebhookEvent::JobSucceeded, JobStatus::Succeeded, Some(result), None)
+  }
+
+  pub fn failed(job: &ConversionJob, error: &str) -> Self {
+    let error = Some(error.to_string());
+    Self::new(job, WebhookEvent::JobFailed, JobStatus::DeadLettered, None, error)
+  }
+
+  fn new(
+    job: &ConversionJob,
+    event: WebhookEvent,
+    status: JobStatus,
+    result: Option<serde_json::Value>,
+    error: Option<String>,
+  ) -> Self {
+    WebhookPayload {
+      id: Uuid::new_v4(),
+      event,
+      job_id: job.id,
+      status,
+      attempts: job.attempts,
+      result,
+      error,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+// One webhook of a job and how sending it went
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+  pub id: Uuid,
+  pub job_id: Uuid,
+  pub url: String,
+  pub event: WebhookEvent,
+  pub payload: serde_json::Value,
+  pub status: DeliveryStatus,
+  pub attempts: i32,
+  pub response_status: Option<i32>, // Of the last attempt, none when the receiver was unreachable
+  pub error: Option<String>,
+  pub next_attempt_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+  pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<Row> for WebhookDelivery {
+  type Error = VideoError;
+
+  fn try_from(row: Row) -> Result<Self, Self::Error> {
+    let event: String = row.try_get("event")?;
+    let status: String = row.try_get("status")?;
+    Ok(WebhookDelivery {
+      id: row.try_get("id")?,
+      job_id: row.try_get("job_id")?,
+      url: row.try_get("url")?,
+      event: WebhookEvent::parse(&event)
+        .ok_or_else(|| VideoError::InvalidOption(format!("unknown webhook event {:?}", event)))?,
+      payload: row.try_get::<_, Json<serde_json::Value>>("payload")?.0,
+      status: DeliveryStatus::parse(&status).ok_or_else(|| {
+        VideoError::InvalidOption(format!("unknown delivery status {:?}", status))
+      })?,
+      attempts: row.try_get("attempts")?,
+      response_status: row.try_get("response_status")?,
+      error: row.try_get("error")?,
+      next_attempt_at: row.try_get("next_attempt_at")?,
+      created_at: row.try_get("created_at")?,
+      updated_at: row.try_get("updated_at")?,
+      delivered_at: row.try_get("delivered_at")?,
+    })
+  }
+}
+
+// Absolute http or https URL to a public host, credentials belong in the
+// signature. The host is resolved, every address it has must be public
+pub async fn check_callback_url(url: &str, allow_private: bool) -> Result<(), VideoError> {
+  if url.len() > MAX_CALLBACK_URL_LENGTH {
+    return Err(VideoError::InvalidOption(format!(
+      "callback_url is longer than {} characters",
+      MAX_CALLBACK_URL_LENGTH
+    )));
+  }
+  let parsed = Url::parse(url)
+    .map_err(|err| VideoError::InvalidOption(format!("callback_url is not a URL: {}", err)))?;
+  if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+    return Err(VideoError::InvalidOption("callback_url must be an http or https URL".to_string()));
+  }
+  if !parsed.username().is_empty() || parsed.password().is_some() {
+    return Err(VideoError::InvalidOption("callback_url must not hold credentials".to_string()));
+  }
+  // IPv6 hosts keep their brackets
+  let host = parsed.host_str().unwrap_or_default();
+  match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+    Ok(ip) => check_address(ip, allow_private),
+    Err(_) => {
+      let port = parsed.port_or_known_default().unwrap_or(80);
+      resolve_public(host, port, allow_private).await.map(|_| ())
+    },
+  }
+}
+
+// `WEBHOOK_ALLOW_PRIVATE_HOSTS=true`
+pub fn private_callbacks_allowed() -> bool {
+  env::var(PRIVATE_CALLBACKS_ENV).map(|value| value == "true").unwrap_or(false)
+}
+
+// Loopback, private, link-local, unspecified and multicast addresses belong to
+// the server's own networks, where a callback could reach internal services
+pub fn is_internal_address(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      let [first, second, ..] = ip.octets();
+      ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || first == 0
+        || (first == 100 && second & 0xC0 == 64) // Shared address space, 100.64.0.0/10
+    },
+    IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+      Some(ip) => is_internal_address(IpAddr::V4(ip)),
+      None => {
+        let first = ip.segments()[0];
+        ip.is_loopback()
+          || ip.is_unspecified()
+          || ip.is_multicast()
+          || first & 0xFE00 == 0xFC00 // Unique local, fc00::/7
+          || first & 0xFFC0 == 0xFE80 // Link-local, fe80::/10
+      },
+    },
+  }
+}
+
+fn check_address(ip: IpAddr, allow_private: bool) -> Result<(), VideoError> {
+  if !allow_private && is_internal_address(ip) {
+    return Err(VideoError::InvalidOption(
+      "callback_url must not point to a private or local address".to_string(),
+    ));
+  }
+  Ok(())
+}
+
+async fn resolve_public(
+  host: &str,
+  port: u16,
+  allow_private: bool,
+) -> Result<Vec<SocketAddr>, VideoError> {
+  let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+    .await
+    .map_err(|err| VideoError::InvalidOption(format!("callback_url host not found: {}", err)))?
+    .collect();
+  if addresses.is_empty() {
+    return Err(VideoError::InvalidOption("callback_url host has no address".to_string()));
+  }
+  for address in &addresses {
+    check_address(address.ip(), allow_private)?;
+  }
+  Ok(addresses)
+}
+
+// Resolver of the webhook client. A host checked when its job was queued can
+// resolve to another address by the time it is called, so the addresses are
+// checked again right before connecting to them
+struct PublicResolver {
+  allow_private: bool,
+}
+
+impl Resolve for PublicResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let host = name.as_str().to_string();
+    let allow_private = self.allow_private;
+    Box::pin(async move {
+      let addresses = resolve_public(&host, 0, allow_private).await?;
+      Ok(Box::new(addresses.into_iter()) as Addrs)
+    })
+  }
+}
+
+pub fn generate_secret() -> String {
+  let mut bytes = [0u8; 32];
+  OsRng.fill_bytes(&mut bytes);
+  format!("whsec_{}", hex::encode(bytes))
+}
+
+// HMAC-SHA256 of `{timestamp}.{body}` with the secret of the user. The
+// timestamp is signed so a captured webhook cannot be replayed later
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+  mac.update(timestamp.to_string().as_bytes());
+  mac.update(b".");
+  mac.update(body);
+  format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// Wait before sending a refused webhook again, None once it was tried often
+// enough. Doubles with every attempt
+pub fn delivery_delay(attempt: i32) -> Option<Duration> {
+  if attempt >= MAX_DELIVERY_ATTEMPTS {
+    return None;
+  }
+  let factor = 2u32.saturating_pow(attempt.max(1) as u32 - 1);
+  Some(DELIVERY_BASE_DELAY.saturating_mul(factor).min(MAX_DELIVERY_DELAY))
+}
+
+// Redirects are not followed, the signed request must reach the URL the user gave
+pub fn webhook_client(allow_private: bool) -> reqwest::Client {
+  reqwest::Client::builder()
+    .timeout(DELIVERY_TIMEOUT)
+    .redirect(Policy::none())
+    .dns_resolver(Arc::new(PublicResolver {
+      allow_private,
+    }))
+    .user_agent(concat!("rust_to_gif-webhooks/", env!("CARGO_PKG_VERSION")))
+    .build()
+    .expect("Failed to create the webhook client")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryOutcome {
+  Delivered(u16),
+  Refused(u16, String), // Any other status, with the start of the response
+  Unreachable(String),
+}
+
+pub async fn send_webhook(
+  http: &reqwest::Client,
+  delivery: &WebhookDelivery,
+  secret: &str,
+  allow_private: bool,
+) -> DeliveryOutcome {
+  // Checked again, addresses in the URL itself never reach the resolver
+  if let Err(err) = check_callback_url(&delivery.url, allow_private).await {
+    return DeliveryOutcome::Unreachable(err.to_string());
+  }
+  let body = delivery.payload.to_string();
+  let timestamp = Utc::now().timestamp();
+  let request = http
+    .post(&delivery.url)
+    .header(CONTENT_TYPE, "application/json")
+    .header(DELIVERY_HEADER, delivery.id.to_string())
+    .header(EVENT_HEADER, delivery.event.as_str())
+    .header(TIMESTAMP_HEADER, timestamp.to_string())
+    .header(SIGNATURE_HEADER, sign(secret, timestamp, body.as_bytes()))
+    .body(body);
+  let response = match request.send().await {
+    Ok(response) => response,
+    Err(err) => return DeliveryOutcome::Unreachable(err.to_string()),
+  };
+  let status = response.status();
+  if status.is_success() {
+    return DeliveryOutcome::Delivered(status.as_u16());
+  }
+  let text = response.text().await.unwrap_or_default();
+  DeliveryOutcome::Refused(status.as_u16(), text.chars().take(MAX_RESPONSE_CHARS).collect())
+}
@@ -0,0 +1,59 @@
+use crate::auth::auth_user::CurrentUser;
+use crate::common::responses::ApiResponse;
+use crate::video::video_controller::video_error_response;
+use crate::video::video_dto::{WebhookDeliveriesQuery, WebhookSecretResponse};
+use crate::video::video_webhook_repository::WebhookRepository;
+use actix_web::{web, Responder};
+use deadpool_postgres::Pool;
+
+// Receivers check the `X-Webhook-Signature` of every webhook with this secret
+pub async fn get_webhook_secret(user: CurrentUser, pool: web::Data<Pool>) -> impl Responder {
+  let secret = match pool.get().await {
+    Ok(client) => WebhookRepository::secret(&client, user.id).await,
+    Err(err) => Err(err.into()),
+  };
+  match secret {
+    Ok(secret) => ApiResponse::success(
+      "Webhook secret retrieved",
+      Some(serde_json::json!(WebhookSecretResponse {
+        secret
+      })),
+    ),
+    Err(err) => video_error_response(err),
+  }
+}
+
+// The previous secret stops working at once, including for retries of
+// webhooks sent before
+pub async fn rotate_webhook_secret(user: CurrentUser, pool: web::Data<Pool>) -> impl Responder {
+  let secret = match pool.get().await {
+    Ok(client) => WebhookRepository::rotate_secret(&client, user.id).await,
+    Err(err) => Err(err.into()),
+  };
+  match secret {
+    Ok(secret) => ApiResponse::success(
+      "Webhook secret rotated",
+      Some(serde_json::json!(WebhookSecretResponse {
+        secret
+      })),
+    ),
+    Err(err) => video_error_response(err),
+  }
+}
+
+pub async fn list_webhook_deliveries(
+  user: CurrentUser,
+  query: web::Query<WebhookDeliveriesQuery>,
+  pool: web::Data<Pool>,
+) -> impl Responder {
+  let deliveries = match pool.get().await {
+    Ok(client) => WebhookRepository::all(&client, user.id, query.job_id).await,
+    Err(err) => Err(err.into()),
+  };
+  match deliveries {
+    Ok(deliveries) => {
+      ApiResponse::success("Webhook deliveries retrieved", Some(serde_json::json!(deliveries)))
+    },
+    Err(err) => video_error_response(err),
+  }
+}
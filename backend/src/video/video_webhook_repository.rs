@@ -0,0 +1,170 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_webhook::{generate_secret, WebhookDelivery, WebhookPayload};
+use deadpool_postgres::{Client, GenericClient};
+use std::time::Duration;
+use tokio_postgres::types::Json;
+use uuid::Uuid;
+
+pub const MAX_LISTED_DELIVERIES: i64 = 100;
+
+const DELIVERY_COLUMNS: &str = "id, job_id, url, event, payload, status, attempts, \
+                                response_status, error, next_attempt_at, created_at, updated_at, \
+                                delivered_at";
+
+pub struct WebhookRepository;
+
+impl WebhookRepository {
+  // The secret of the user, created the first time it is needed
+  pub async fn secret(client: &Client, user_id: i32) -> Result<String, VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE users SET webhook_secret = COALESCE(webhook_secret, $2)
+         WHERE id = $1 RETURNING webhook_secret",
+      )
+      .await?;
+    let row = client.query_one(&stmt, &[&user_id, &generate_secret()]).await?;
+    Ok(row.try_get("webhook_secret")?)
+  }
+
+  // Webhooks already queued are signed with the new secret when they are sent
+  pub async fn rotate_secret(client: &Client, user_id: i32) -> Result<String, VideoError> {
+    let stmt = client
+      .prepare("UPDATE users SET webhook_secret = $2 WHERE id = $1 RETURNING webhook_secret")
+      .await?;
+    let row = client.query_one(&stmt, &[&user_id, &generate_secret()]).await?;
+    Ok(row.try_get("webhook_secret")?)
+  }
+
+  pub async fn enqueue(
+    client: &impl GenericClient,
+    user_id: i32,
+    url: &str,
+    payload: &WebhookPayload,
+  ) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare(
+        "INSERT INTO webhook_deliveries (id, job_id, user_id, url, event, payload)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+      )
+      .await?;
+    client
+      .execute(
+        &stmt,
+        &[&payload.id, &payload.job_id, &user_id, &url, &payload.event.as_str(), &Json(payload)],
+      )
+      .await?;
+    Ok(())
+  }
+
+  // Newest first, of one job when `job_id` is given
+  pub async fn all(
+    client: &Client,
+    user_id: i32,
+    job_id: Option<Uuid>,
+  ) -> Result<Vec<WebhookDelivery>, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "SELECT {} FROM webhook_deliveries
+         WHERE user_id = $1 AND ($2::UUID IS NULL OR job_id = $2)
+         ORDER BY created_at DESC LIMIT $3",
+        DELIVERY_COLUMNS
+      ))
+      .await?;
+    let rows = client.query(&stmt, &[&user_id, &job_id, &MAX_LISTED_DELIVERIES]).await?;
+    rows.into_iter().map(WebhookDelivery::try_from).collect()
+  }
+
+  // Takes the oldest due delivery with the secret of its user. It is not due
+  // again before `lease` runs out, so a server stopped mid-delivery only
+  // delays it
+  pub async fn claim(
+    client: &Client,
+    lease: Duration,
+  ) -> Result<Option<(WebhookDelivery, Option<String>)>, VideoError> {
+    let stmt = client
+      .prepare(&format!(
+        "WITH claimed AS (
+           UPDATE webhook_deliveries
+           SET attempts = attempts + 1, next_attempt_at = now() + make_interval(secs => $1),
+               updated_at = now()
+           WHERE id = (
+             SELECT id FROM webhook_deliveries
+             WHERE status = 'pending' AND next_attempt_at <= now()
+             ORDER BY next_attempt_at
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+           )
+           RETURNING {}, user_id
+         )
+         SELECT claimed.*, users.webhook_secret
+         FROM claimed JOIN users ON users.id = claimed.user_id",
+        DELIVERY_COLUMNS
+      ))
+      .await?;
+    match client.query_opt(&stmt, &[&lease.as_secs_f64()]).await? {
+      Some(row) => {
+        let secret = row.try_get("webhook_secret")?;
+        Ok(Some((WebhookDelivery::try_from(row)?, secret)))
+      },
+      None => Ok(None),
+    }
+  }
+
+  // Only the claim identified by `attempt` records its outcome
+  pub async fn delivered(
+    client: &Client,
+    id: &Uuid,
+    attempt: i32,
+    response_status: i32,
+  ) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE webhook_deliveries
+         SET status = 'delivered', response_status = $3, error = NULL,
+             delivered_at = now(), updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'pending'",
+      )
+      .await?;
+    client.execute(&stmt, &[id, &attempt, &response_status]).await?;
+    Ok(())
+  }
+
+  pub async fn retry(
+    client: &Client,
+    id: &Uuid,
+    attempt: i32,
+    response_status: Option<i32>,
+    error: &str,
+    delay: Duration,
+  ) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE webhook_deliveries
+         SET response_status = $3, error = $4,
+             next_attempt_at = now() + make_interval(secs => $5), updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'pending'",
+      )
+      .await?;
+    let delay = delay.as_secs_f64();
+    client.execute(&stmt, &[id, &attempt, &response_status, &error, &delay]).await?;
+    Ok(())
+  }
+
+  pub async fn fail(
+    client: &Client,
+    id: &Uuid,
+    attempt: i32,
+    response_status: Option<i32>,
+    error: &str,
+  ) -> Result<(), VideoError> {
+    let stmt = client
+      .prepare(
+        "UPDATE webhook_deliveries
+         SET status = 'failed', response_status = $3, error = $4, updated_at = now()
+         WHERE id = $1 AND attempts = $2 AND status = 'pending'",
+      )
+      .await?;
+    client.execute(&stmt, &[id, &attempt, &response_status, &error]).await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,107 @@
+use crate::video::video_errors::VideoError;
+use crate::video::video_webhook::{
+  delivery_delay, private_callbacks_allowed, send_webhook, webhook_client, DeliveryOutcome,
+  DELIVERY_TIMEOUT,
+};
+use crate::video::video_webhook_repository::WebhookRepository;
+use deadpool_postgres::Pool;
+use std::env;
+use std::time::Duration;
+
+pub const DELIVERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Outlasts a delivery that runs into its timeout
+pub const DELIVERY_LEASE: Duration = Duration::from_secs(3 * DELIVERY_TIMEOUT.as_secs());
+pub const DEFAULT_WEBHOOK_WORKERS: usize = 4;
+pub const MAX_WEBHOOK_WORKERS: usize = 32;
+
+// Number of webhooks sent at once by this server, `WEBHOOK_WORKERS` or 4. A
+// slow receiver only holds up one of them
+pub fn webhook_workers() -> usize {
+  env::var("WEBHOOK_WORKERS")
+    .ok()
+    .and_then(|value| value.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_WEBHOOK_WORKERS)
+    .min(MAX_WEBHOOK_WORKERS)
+}
+
+// Sends the webhooks of finished jobs. Like the conversion jobs, deliveries
+// are queued in Postgres and shared by every server
+#[derive(Clone)]
+pub struct WebhookWorker {
+  pool: Pool,
+  http: reqwest::Client,
+  allow_private: bool,
+}
+
+impl WebhookWorker {
+  pub fn new(pool: Pool) -> Self {
+    let allow_private = private_callbacks_allowed();
+    WebhookWorker {
+      pool,
+      http: webhook_client(allow_private),
+      allow_private,
+    }
+  }
+
+  // Must be called from the server runtime. Each worker claims its own
+  // deliveries, so they run side by side
+  pub fn spawn(&self, count: usize) {
+    for _ in 0..count {
+      let worker = self.clone();
+      actix_web::rt::spawn(async move { worker.run().await });
+    }
+  }
+
+  async fn run(self) {
+    loop {
+      match self.deliver_next().await {
+        Ok(true) => continue,
+        Ok(false) => {},
+        Err(err) => eprintln!("Webhook worker: {}", err),
+      }
+      actix_web::rt::time::sleep(DELIVERY_POLL_INTERVAL).await;
+    }
+  }
+
+  // Claims and sends one webhook, false when none is due
+  pub async fn deliver_next(&self) -> Result<bool, VideoError> {
+    let claimed = {
+      let client = self.pool.get().await?;
+      WebhookRepository::claim(&client, DELIVERY_LEASE).await?
+    };
+    let (delivery, secret) = match claimed {
+      Some(claimed) => claimed,
+      None => return Ok(false),
+    };
+    let outcome = match secret {
+      Some(secret) => send_webhook(&self.http, &delivery, &secret, self.allow_private).await,
+      // Never sent unsigned, the secret is created with the job
+      None => DeliveryOutcome::Unreachable("The user has no webhook secret".to_string()),
+    };
+
+    let client = self.pool.get().await?;
+    let (response_status, error) = match outcome {
+      DeliveryOutcome::Delivered(status) => {
+        let status = i32::from(status);
+        WebhookRepository::delivered(&client, &delivery.id, delivery.attempts, status).await?;
+        return Ok(true);
+      },
+      DeliveryOutcome::Refused(status, body) => {
+        (Some(i32::from(status)), format!("Receiver answered {}: {}", status, body))
+      },
+      DeliveryOutcome::Unreachable(error) => (None, error),
+    };
+    match delivery_delay(delivery.attempts) {
+      Some(delay) => {
+        let attempt = delivery.attempts;
+        WebhookRepository::retry(&client, &delivery.id, attempt, response_status, &error, delay)
+          .await?
+      },
+      None => {
+        WebhookRepository::fail(&client, &delivery.id, delivery.attempts, response_status, &error)
+          .await?
+      },
+    }
+    Ok(true)
+  }
+}
@@ -0,0 +1,160 @@
+use crate::video::video_errors::VideoError;
+use actix_web::web::Bytes;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use futures_util::{stream, Stream};
+use std::path::PathBuf;
+
+// Writes a ZIP archive piece by piece so it can be streamed, entries are
+// stored without compression since PNG data is already compressed
+pub struct ZipWriter {
+  offset: u64,
+  entries: Vec<CentralEntry>,
+  dos_time: u16,
+  dos_date: u16,
+}
+
+struct CentralEntry {
+  name: String,
+  crc: u32,
+  size: u32,
+  offset: u32,
+}
+
+pub const MAX_ENTRIES: usize = u16::MAX as usize;
+pub const MAX_ARCHIVE_SIZE: u64 = u32::MAX as u64; // Without ZIP64 records
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const VERSION: u16 = 20; // 2.0, the first version with folders and stored entries
+const UTF8_NAMES: u16 = 1 << 11;
+
+impl ZipWriter {
+  pub fn new(modified: NaiveDateTime) -> Self {
+    ZipWriter {
+      offset: 0,
+      entries: Vec::new(),
+      dos_time: ((modified.hour() << 11) | (modified.minute() << 5) | (modified.second() / 2))
+        as u16,
+      dos_date: (((modified.year().clamp(1980, 2107) - 1980) as u32) << 9
+        | (modified.month() << 5)
+        | modified.day()) as u16,
+    }
+  }
+
+  // Local header followed by the data of one file
+  pub fn entry(&mut self, name: &str, data: &[u8]) -> Result<Vec<u8>, VideoError> {
+    if self.entries.len() >= MAX_ENTRIES {
+      return Err(VideoError::ArchiveTooLarge("more than 65535 entries"));
+    }
+    let header_size = 30 + name.len() as u64;
+    if self.offset + header_size + data.len() as u64 > MAX_ARCHIVE_SIZE {
+      return Err(VideoError::ArchiveTooLarge("more than 4 GiB"));
+    }
+
+    let entry = CentralEntry {
+      name: name.to_string(),
+      crc: crc32fast::hash(data),
+      size: data.len() as u32,
+      offset: self.offset as u32,
+    };
+
+    let mut bytes = Vec::with_capacity(header_size as usize + data.len());
+    put_u32(&mut bytes, LOCAL_HEADER_SIGNATURE);
+    put_u16(&mut bytes, VERSION);
+    put_u16(&mut bytes, UTF8_NAMES);
+    put_u16(&mut bytes, 0); // Stored
+    put_u16(&mut bytes, self.dos_time);
+    put_u16(&mut bytes, self.dos_date);
+    put_u32(&mut bytes, entry.crc);
+    put_u32(&mut bytes, entry.size); // Compressed size
+    put_u32(&mut bytes, entry.size);
+    put_u16(&mut bytes, name.len() as u16);
+    put_u16(&mut bytes, 0); // Extra field length
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.extend_from_slice(data);
+
+    self.offset += bytes.len() as u64;
+    self.entries.push(entry);
+    Ok(bytes)
+  }
+
+  // Central directory and end record closing the archive
+  pub fn finish(self) -> Result<Vec<u8>, VideoError> {
+    let mut bytes = Vec::new();
+    for entry in &self.entries {
+      put_u32(&mut bytes, CENTRAL_HEADER_SIGNATURE);
+      put_u16(&mut bytes, VERSION); // Made by
+      put_u16(&mut bytes, VERSION); // Needed to extract
+      put_u16(&mut bytes, UTF8_NAMES);
+      put_u16(&mut bytes, 0);
+      put_u16(&mut bytes, self.dos_time);
+      put_u16(&mut bytes, self.dos_date);
+      put_u32(&mut bytes, entry.crc);
+      put_u32(&mut bytes, entry.size);
+      put_u32(&mut bytes, entry.size);
+      put_u16(&mut bytes, entry.name.len() as u16);
+      put_u16(&mut bytes, 0); // Extra field length
+      put_u16(&mut bytes, 0); // Comment length
+      put_u16(&mut bytes, 0); // Disk number
+      put_u16(&mut bytes, 0); // Internal attributes
+      put_u32(&mut bytes, 0); // External attributes
+      put_u32(&mut bytes, entry.offset);
+      bytes.extend_from_slice(entry.name.as_bytes());
+    }
+
+    if self.offset + bytes.len() as u64 > MAX_ARCHIVE_SIZE {
+      return Err(VideoError::ArchiveTooLarge("more than 4 GiB"));
+    }
+    let count = self.entries.len() as u16;
+    let directory_size = bytes.len() as u32;
+    put_u32(&mut bytes, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    put_u16(&mut bytes, 0); // This disk
+    put_u16(&mut bytes, 0); // Disk with the central directory
+    put_u16(&mut bytes, count);
+    put_u16(&mut bytes, count);
+    put_u32(&mut bytes, directory_size);
+    put_u32(&mut bytes, self.offset as u32);
+    put_u16(&mut bytes, 0); // Comment length
+    Ok(bytes)
+  }
+}
+
+fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+  bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+  bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+pub enum ZipSource {
+  File(PathBuf),
+  Data(Vec<u8>),
+}
+
+// Stream an archive one entry at a time, only the file being added is held in memory
+pub fn zip_stream(
+  entries: Vec<(String, ZipSource)>,
+  modified: NaiveDateTime,
+) -> impl Stream<Item = Result<Bytes, VideoError>> {
+  let state = Some((ZipWriter::new(modified), entries.into_iter()));
+  stream::unfold(state, |state| async move {
+    let (mut writer, mut entries) = state?;
+    let Some((name, source)) = entries.next() else {
+      return Some((writer.finish().map(Bytes::from), None));
+    };
+
+    let data = match source {
+      ZipSource::File(path) => match tokio::fs::read(&path).await {
+        Ok(data) => data,
+        Err(err) => return Some((Err(err.into()), None)),
+      },
+      ZipSource::Data(data) => data,
+    };
+    match writer.entry(&name, &data) {
+      Ok(bytes) => Some((Ok(Bytes::from(bytes)), Some((writer, entries)))),
+      Err(err) => Some((Err(err), None)),
+    }
+  })
+}